@@ -0,0 +1,83 @@
+use crate::audio::config;
+use crate::audio::errors::AudioAnalysisError;
+use rayon::prelude::*;
+
+// --- Rating Scale ---
+// Window levels (dBFS RMS) mapped onto the level half of the rating
+const LEVEL_FLOOR_DB: f32 = -30.0;
+const LEVEL_CEILING_DB: f32 = -6.0;
+// First-difference to signal RMS ratio mapped onto the brightness half
+const BRIGHTNESS_FLOOR: f32 = 0.05;
+const BRIGHTNESS_CEILING: f32 = 0.6;
+const LEVEL_WEIGHT: f32 = 0.6;
+
+// --- Private Helper Functions ---
+
+fn normalize(value: f32, floor: f32, ceiling: f32) -> f32 {
+    ((value - floor) / (ceiling - floor)).clamp(0.0, 1.0)
+}
+
+/// RMS of a window and of its first difference. The difference acts as a
+/// cheap high-pass, so its share of the level tracks how bright the mix is.
+fn window_level_and_brightness(window: &[f32]) -> (f32, f32) {
+    let len = window.len() as f32;
+    let rms = (window.iter().map(|&x| x * x).sum::<f32>() / len).sqrt();
+    let diff_rms = (window
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).powi(2))
+        .sum::<f32>()
+        / len)
+        .sqrt();
+    (rms, diff_rms)
+}
+
+// --- Public Calculation Function ---
+
+/// Rate a track's energy from 1 (calm) to 10 (peak time) from pre-decoded mono
+/// samples, combining the typical window level with spectral brightness.
+///
+/// Silent windows are skipped so intros and breakdowns don't drag the rating down.
+pub(crate) fn analyze_energy(samples: &[f32], sample_rate: f32) -> Result<f32, AudioAnalysisError> {
+    if samples.is_empty() {
+        return Err(AudioAnalysisError::EmptySamples);
+    }
+    if sample_rate <= 0.0 {
+        return Err(AudioAnalysisError::InvalidSampleRate(sample_rate));
+    }
+
+    let window_len = (sample_rate * config::ENERGY_WINDOW_MS as f32 / 1000.0) as usize;
+    if window_len < 2 || samples.len() < window_len {
+        return Err(AudioAnalysisError::InsufficientSamples {
+            required: window_len.max(2),
+            actual: samples.len(),
+        });
+    }
+
+    let mut windows: Vec<(f32, f32)> = samples
+        .par_chunks_exact(window_len)
+        .map(window_level_and_brightness)
+        .filter(|&(rms, _)| rms > 1e-4)
+        .collect();
+    if windows.is_empty() {
+        return Err(AudioAnalysisError::SilentAudio);
+    }
+
+    // Median window level: robust against a few very loud or quiet sections
+    windows.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let median_rms = windows[windows.len() / 2].0;
+    let level_db = 20.0 * median_rms.log10();
+
+    let brightness = windows.iter().map(|&(rms, diff_rms)| diff_rms / rms).sum::<f32>()
+        / windows.len() as f32;
+
+    let score = LEVEL_WEIGHT * normalize(level_db, LEVEL_FLOOR_DB, LEVEL_CEILING_DB)
+        + (1.0 - LEVEL_WEIGHT) * normalize(brightness, BRIGHTNESS_FLOOR, BRIGHTNESS_CEILING);
+    let energy = 1.0 + score * 9.0;
+    log::debug!(
+        "Energy Analysis: level {:.1} dBFS, brightness {:.3} -> {:.1}",
+        level_db,
+        brightness,
+        energy
+    );
+    Ok(energy)
+}
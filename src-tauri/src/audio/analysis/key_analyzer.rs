@@ -0,0 +1,230 @@
+use crate::audio::config;
+use crate::audio::errors::AudioAnalysisError;
+use crate::audio::types::KeyAnalysis;
+use rayon::prelude::*;
+use rustfft::{FftPlanner, num_complex::Complex, num_traits::Zero};
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+// --- Key Profiles (Krumhansl-Kessler) ---
+const MAJOR_PROFILE: [f32; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f32; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+const PITCH_CLASS_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Camelot wheel numbers for major keys, indexed by tonic pitch class (C = 0).
+const CAMELOT_MAJOR: [u8; 12] = [8, 3, 10, 5, 12, 7, 2, 9, 4, 11, 6, 1];
+/// Camelot wheel numbers for minor keys, indexed by tonic pitch class (C = 0).
+const CAMELOT_MINOR: [u8; 12] = [5, 12, 7, 2, 9, 4, 11, 6, 1, 8, 3, 10];
+
+// --- Private Helper Functions ---
+
+fn downsample_averaged(samples: &[f32], factor: usize) -> Vec<f32> {
+    if factor <= 1 {
+        return samples.to_vec();
+    }
+    // Averaging each group doubles as a crude anti-alias filter
+    let inv_factor = 1.0 / factor as f32;
+    samples
+        .chunks_exact(factor)
+        .map(|chunk| chunk.iter().sum::<f32>() * inv_factor)
+        .collect()
+}
+
+/// Map each FFT bin in the analysed range to its pitch class.
+fn bin_pitch_classes(frame_size: usize, sample_rate: f32) -> Vec<(usize, usize)> {
+    let freq_per_bin = sample_rate / frame_size as f32;
+    (1..frame_size / 2)
+        .filter_map(|k| {
+            let freq = k as f32 * freq_per_bin;
+            if !(config::KEY_MIN_FREQ_HZ..=config::KEY_MAX_FREQ_HZ).contains(&freq) {
+                return None;
+            }
+            let midi_note = 69.0 + 12.0 * (freq / 440.0).log2();
+            Some((k, (midi_note.round() as i32).rem_euclid(12) as usize))
+        })
+        .collect()
+}
+
+fn compute_chromagram(samples: &[f32], sample_rate: f32) -> [f32; 12] {
+    let frame_size = config::KEY_FRAME_SIZE;
+    let hop_size = config::KEY_HOP_SIZE;
+
+    let mut planner = FftPlanner::new();
+    let fft = planner.plan_fft_forward(frame_size);
+    let hann_window: Arc<Vec<f32>> = Arc::new(
+        (0..frame_size)
+            .map(|i| 0.5 * (1.0 - (2.0 * PI * i as f32 / (frame_size - 1) as f32).cos()))
+            .collect(),
+    );
+    let bin_map = bin_pitch_classes(frame_size, sample_rate);
+    let num_frames = (samples.len() - frame_size) / hop_size + 1;
+
+    (0..num_frames)
+        .into_par_iter()
+        .map(|i| {
+            let start = i * hop_size;
+            let frame = &samples[start..start + frame_size];
+
+            let mut buffer: Vec<Complex<f32>> = vec![Complex::zero(); frame_size];
+            for (j, (&s, &w)) in frame.iter().zip(hann_window.iter()).enumerate() {
+                buffer[j] = Complex { re: s * w, im: 0.0 };
+            }
+            fft.process(&mut buffer);
+
+            let mut chroma = [0.0f32; 12];
+            for &(k, pitch_class) in &bin_map {
+                chroma[pitch_class] += buffer[k].norm();
+            }
+
+            // Normalize per frame so loud sections don't dominate the estimate
+            let frame_max = chroma.iter().copied().fold(0.0f32, f32::max);
+            if frame_max > 1e-6 {
+                chroma.iter_mut().for_each(|c| *c /= frame_max);
+            }
+            chroma
+        })
+        .reduce(
+            || [0.0f32; 12],
+            |mut acc, chroma| {
+                for (a, c) in acc.iter_mut().zip(chroma.iter()) {
+                    *a += c;
+                }
+                acc
+            },
+        )
+}
+
+fn pearson_correlation(a: &[f32; 12], b: &[f32; 12]) -> f32 {
+    let mean_a = a.iter().sum::<f32>() / 12.0;
+    let mean_b = b.iter().sum::<f32>() / 12.0;
+    let mut covariance = 0.0;
+    let mut var_a = 0.0;
+    let mut var_b = 0.0;
+    for i in 0..12 {
+        let da = a[i] - mean_a;
+        let db = b[i] - mean_b;
+        covariance += da * db;
+        var_a += da * da;
+        var_b += db * db;
+    }
+    let denominator = (var_a * var_b).sqrt();
+    if denominator > 1e-9 {
+        covariance / denominator
+    } else {
+        0.0
+    }
+}
+
+fn rotated_profile(profile: &[f32; 12], tonic: usize) -> [f32; 12] {
+    let mut rotated = [0.0f32; 12];
+    for (pitch_class, value) in rotated.iter_mut().enumerate() {
+        *value = profile[(pitch_class + 12 - tonic) % 12];
+    }
+    rotated
+}
+
+// --- Public Calculation Function ---
+
+/// Parse a key written by us or another application into its tonic pitch
+/// class (C = 0) and mode. Accepts "A minor", "Am", "Abm", "C#", "F# major"
+/// and Camelot codes like "8A".
+pub(crate) fn parse_key(key: &str) -> Option<(usize, bool)> {
+    let key = key.trim();
+    if let Some(letter) = key.chars().last().filter(|c| matches!(c, 'A' | 'B' | 'a' | 'b'))
+        && let Ok(number) = key[..key.len() - 1].parse::<u8>()
+    {
+        let table = if letter.eq_ignore_ascii_case(&'a') { &CAMELOT_MINOR } else { &CAMELOT_MAJOR };
+        let tonic = table.iter().position(|&n| n == number)?;
+        return Some((tonic, letter.eq_ignore_ascii_case(&'a')));
+    }
+
+    let mut chars = key.chars();
+    let root = chars.next()?.to_ascii_uppercase();
+    let natural = PITCH_CLASS_NAMES.iter().position(|name| name.starts_with(root) && name.len() == 1)?;
+    let rest = chars.as_str();
+    let (tonic, rest) = if let Some(rest) = rest.strip_prefix(['#', '♯']) {
+        ((natural + 1) % 12, rest)
+    } else if let Some(rest) = rest.strip_prefix(['b', '♭']) {
+        ((natural + 11) % 12, rest)
+    } else {
+        (natural, rest)
+    };
+    let is_minor = match rest.trim().to_ascii_lowercase().as_str() {
+        "" | "maj" | "major" => false,
+        "m" | "min" | "minor" => true,
+        _ => return None,
+    };
+    Some((tonic, is_minor))
+}
+
+/// Build the key name and Camelot code for a tonic pitch class (C = 0).
+pub(crate) fn key_names(tonic: usize, is_minor: bool) -> (String, String) {
+    let tonic = tonic % 12;
+    if is_minor {
+        (
+            format!("{} minor", PITCH_CLASS_NAMES[tonic]),
+            format!("{}A", CAMELOT_MINOR[tonic]),
+        )
+    } else {
+        (
+            format!("{} major", PITCH_CLASS_NAMES[tonic]),
+            format!("{}B", CAMELOT_MAJOR[tonic]),
+        )
+    }
+}
+
+/// Estimate the musical key from pre-decoded mono samples using a chromagram
+/// matched against Krumhansl-Kessler key profiles.
+pub(crate) fn analyze_key(
+    samples: &[f32],
+    sample_rate: f32,
+) -> Result<KeyAnalysis, AudioAnalysisError> {
+    if samples.is_empty() {
+        return Err(AudioAnalysisError::EmptySamples);
+    }
+    if sample_rate <= 0.0 {
+        return Err(AudioAnalysisError::InvalidSampleRate(sample_rate));
+    }
+
+    let factor = config::KEY_DOWNSAMPLE_FACTOR;
+    let processed = downsample_averaged(samples, factor);
+    let effective_sample_rate = sample_rate / factor as f32;
+    if processed.len() < config::KEY_FRAME_SIZE {
+        return Err(AudioAnalysisError::InsufficientSamples {
+            required: config::KEY_FRAME_SIZE * factor,
+            actual: samples.len(),
+        });
+    }
+
+    let chroma = compute_chromagram(&processed, effective_sample_rate);
+    if chroma.iter().all(|&c| c <= 1e-6) {
+        return Err(AudioAnalysisError::SilentAudio);
+    }
+
+    let mut best = (0usize, false, f32::MIN);
+    for tonic in 0..12 {
+        for (is_minor, profile) in [(false, &MAJOR_PROFILE), (true, &MINOR_PROFILE)] {
+            let score = pearson_correlation(&chroma, &rotated_profile(profile, tonic));
+            if score > best.2 {
+                best = (tonic, is_minor, score);
+            }
+        }
+    }
+
+    let (tonic, is_minor, confidence) = best;
+    let (key, camelot) = key_names(tonic, is_minor);
+    log::debug!("Key Analysis: Estimated {} ({}), confidence {:.3}", key, camelot, confidence);
+
+    Ok(KeyAnalysis {
+        key,
+        camelot,
+        confidence,
+    })
+}
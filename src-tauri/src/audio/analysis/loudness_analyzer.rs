@@ -0,0 +1,122 @@
+use crate::audio::config;
+use crate::audio::errors::AudioAnalysisError;
+use crate::audio::types::LoudnessAnalysis;
+use biquad::{Biquad, Coefficients, DirectForm1, ToHertz, Type};
+use rayon::prelude::*;
+
+// --- K-Weighting Filter Parameters (ITU-R BS.1770) ---
+const K_SHELF_FREQ_HZ: f32 = 1681.97;
+const K_SHELF_GAIN_DB: f32 = 4.0;
+const K_SHELF_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+const K_HIGHPASS_FREQ_HZ: f32 = 38.13;
+const K_HIGHPASS_Q: f32 = 0.5;
+
+// --- Private Helper Functions ---
+
+fn k_weight(samples: &[f32], sample_rate: f32) -> Result<Vec<f32>, AudioAnalysisError> {
+    let shelf_coeffs = Coefficients::<f32>::from_params(
+        Type::HighShelf(K_SHELF_GAIN_DB),
+        sample_rate.hz(),
+        K_SHELF_FREQ_HZ.hz(),
+        K_SHELF_Q,
+    )
+    .map_err(|e| AudioAnalysisError::FilterSetup(format!("K-weighting shelf: {:?}", e)))?;
+    let highpass_coeffs = Coefficients::<f32>::from_params(
+        Type::HighPass,
+        sample_rate.hz(),
+        K_HIGHPASS_FREQ_HZ.hz(),
+        K_HIGHPASS_Q,
+    )
+    .map_err(|e| AudioAnalysisError::FilterSetup(format!("K-weighting highpass: {:?}", e)))?;
+
+    let mut shelf = DirectForm1::<f32>::new(shelf_coeffs);
+    let mut highpass = DirectForm1::<f32>::new(highpass_coeffs);
+
+    // IIR filtering is inherently sequential
+    Ok(samples
+        .iter()
+        .map(|&s| highpass.run(shelf.run(s)))
+        .collect())
+}
+
+fn mean_square_to_lufs(mean_square: f64) -> f32 {
+    (-0.691 + 10.0 * mean_square.max(1e-12).log10()) as f32
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+// --- Public Calculation Function ---
+
+/// Measure gated integrated loudness and sample peak from pre-decoded mono samples.
+///
+/// Follows the BS.1770 block/gate scheme on the mono downmix, so values can differ
+/// slightly from a multichannel meter on wide stereo material.
+pub(crate) fn analyze_loudness(
+    samples: &[f32],
+    sample_rate: f32,
+) -> Result<LoudnessAnalysis, AudioAnalysisError> {
+    if samples.is_empty() {
+        return Err(AudioAnalysisError::EmptySamples);
+    }
+    if sample_rate <= 0.0 {
+        return Err(AudioAnalysisError::InvalidSampleRate(sample_rate));
+    }
+
+    let block_len = (sample_rate * config::LOUDNESS_BLOCK_MS as f32 / 1000.0) as usize;
+    let step_len = (sample_rate * config::LOUDNESS_STEP_MS as f32 / 1000.0) as usize;
+    if block_len == 0 || step_len == 0 || samples.len() < block_len {
+        return Err(AudioAnalysisError::InsufficientSamples {
+            required: block_len,
+            actual: samples.len(),
+        });
+    }
+
+    let weighted = k_weight(samples, sample_rate)?;
+    let num_blocks = (weighted.len() - block_len) / step_len + 1;
+
+    let block_powers: Vec<f64> = (0..num_blocks)
+        .into_par_iter()
+        .map(|i| {
+            let start = i * step_len;
+            let block = &weighted[start..start + block_len];
+            block.iter().map(|&x| (x as f64) * (x as f64)).sum::<f64>() / block_len as f64
+        })
+        .collect();
+
+    // Absolute gate
+    let absolute_gated: Vec<f64> = block_powers
+        .into_iter()
+        .filter(|&p| mean_square_to_lufs(p) > config::LOUDNESS_ABSOLUTE_GATE_LUFS)
+        .collect();
+    if absolute_gated.is_empty() {
+        return Err(AudioAnalysisError::SilentAudio);
+    }
+
+    // Relative gate
+    let relative_threshold =
+        mean_square_to_lufs(mean(&absolute_gated)) + config::LOUDNESS_RELATIVE_GATE_LU;
+    let relative_gated: Vec<f64> = absolute_gated
+        .iter()
+        .copied()
+        .filter(|&p| mean_square_to_lufs(p) > relative_threshold)
+        .collect();
+    let integrated_lufs = if relative_gated.is_empty() {
+        mean_square_to_lufs(mean(&absolute_gated))
+    } else {
+        mean_square_to_lufs(mean(&relative_gated))
+    };
+
+    let peak = samples
+        .par_iter()
+        .map(|&x| x.abs())
+        .reduce(|| 0.0f32, f32::max);
+    let peak_dbfs = 20.0 * peak.max(1e-9).log10();
+
+    Ok(LoudnessAnalysis {
+        integrated_lufs,
+        peak_dbfs,
+        replay_gain_db: config::REPLAYGAIN_REFERENCE_LUFS - integrated_lufs,
+    })
+}
@@ -1,2 +1,5 @@
 pub mod bpm_analyzer;
-pub mod volume_analyzer; 
\ No newline at end of file
+pub mod energy_analyzer;
+pub mod key_analyzer;
+pub mod loudness_analyzer;
+pub mod volume_analyzer;
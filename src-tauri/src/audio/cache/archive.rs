@@ -0,0 +1,335 @@
+use super::{fingerprint, index, storage, waveform};
+use super::{AnalyzerInfo, CacheError, CacheResult, CachedTrackData, IndexEntry};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+// --- Portable Cache Archive ---
+//
+// Layout (little endian):
+//   magic "ODJA" | version u16 | manifest_len u32 | manifest JSON
+//   per record: hash (64 ASCII hex bytes) | entry_len u32 | entry JSON
+//               | waveform_len u32 | waveform bytes (0 when absent)
+//
+// The manifest maps root-relative paths to content hashes, so the archive can
+// be imported under a different library root. Records are streamed one at a
+// time on both ends; nothing holds the whole archive in memory.
+
+const ARCHIVE_MAGIC: &[u8; 4] = b"ODJA";
+const ARCHIVE_FORMAT_VERSION: u16 = 1;
+const HASH_LEN: usize = 64;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ArchiveManifest {
+    /// Library root on the exporting machine, for information only.
+    library_root: String,
+    entries: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ManifestEntry {
+    /// '/'-separated path relative to the library root.
+    relative_path: String,
+    content_hash: String,
+}
+
+enum TrackCheck {
+    Valid(PathBuf),
+    Missing,
+    Mismatched,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportReport {
+    pub tracks: usize,
+    pub entries: usize,
+    /// Indexed tracks outside the library root, which can't be made portable.
+    pub skipped_outside_root: usize,
+    pub archive_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub imported: usize,
+    pub already_cached: usize,
+    /// Tracks whose archived entry or waveform is corrupted and was left out.
+    pub skipped: usize,
+    /// Tracks in the archive that don't exist under the local library root.
+    pub missing: Vec<String>,
+    /// Tracks whose local audio doesn't match the archived content hash.
+    pub mismatched: Vec<String>,
+}
+
+fn write_blob(writer: &mut impl Write, bytes: &[u8]) -> std::io::Result<()> {
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(bytes)
+}
+
+/// Reads a length-prefixed blob. The buffer grows with the bytes actually
+/// read, so a corrupted length can't allocate more than the archive holds.
+fn read_blob(reader: &mut impl Read) -> CacheResult<Vec<u8>> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as u64;
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < len {
+        return Err(CacheError::EntryCorrupted(format!(
+            "Cache archive claims a {} byte record but ends after {}",
+            len,
+            bytes.len()
+        )));
+    }
+    Ok(bytes)
+}
+
+/// Reads the next record's hash, or `None` at a clean end of archive.
+fn read_record_hash(reader: &mut impl Read) -> CacheResult<Option<String>> {
+    let mut hash = [0u8; HASH_LEN];
+    let mut filled = 0;
+    while filled < HASH_LEN {
+        match reader.read(&mut hash[filled..])? {
+            0 if filled == 0 => return Ok(None),
+            0 => {
+                return Err(CacheError::EntryCorrupted(
+                    "Cache archive ends in the middle of a record".to_string(),
+                ));
+            }
+            n => filled += n,
+        }
+    }
+    String::from_utf8(hash.to_vec())
+        .map(Some)
+        .map_err(|_| CacheError::EntryCorrupted("Cache archive has an invalid record hash".to_string()))
+}
+
+/// Writes every indexed entry under `library_root` to a portable archive.
+pub fn export_cache(cache_dir: &Path, library_root: &Path, archive_path: &Path) -> CacheResult<ExportReport> {
+    index::flush_index(cache_dir)?;
+    let snapshot = index::open_index(cache_dir)?.snapshot()?;
+    let mut report = ExportReport::default();
+
+    let mut entries = Vec::new();
+    let mut hashes = HashSet::new();
+    for (path, entry) in &snapshot.entries {
+        match storage::relative_to_root(path, library_root) {
+            Some(relative_path) => {
+                hashes.insert(entry.content_hash.clone());
+                entries.push(ManifestEntry {
+                    relative_path,
+                    content_hash: entry.content_hash.clone(),
+                });
+            }
+            None => report.skipped_outside_root += 1,
+        }
+    }
+    entries.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+    report.tracks = entries.len();
+
+    let manifest = ArchiveManifest {
+        library_root: library_root.to_string_lossy().to_string(),
+        entries,
+    };
+
+    let temp_file = archive_path.with_extension("tmp");
+    {
+        let mut writer = BufWriter::new(File::create(&temp_file)?);
+        writer.write_all(ARCHIVE_MAGIC)?;
+        writer.write_all(&ARCHIVE_FORMAT_VERSION.to_le_bytes())?;
+        write_blob(&mut writer, &serde_json::to_vec(&manifest)?)?;
+
+        for hash in &hashes {
+            let entry_bytes = match fs::read(cache_dir.join(format!("{}.json", hash))) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    log::warn!("Skipping cache entry {} in export: {}", hash, e);
+                    continue;
+                }
+            };
+            let waveform_bytes = fs::read(cache_dir.join(format!("{}.wave", hash))).unwrap_or_default();
+
+            writer.write_all(hash.as_bytes())?;
+            write_blob(&mut writer, &entry_bytes)?;
+            write_blob(&mut writer, &waveform_bytes)?;
+            report.entries += 1;
+        }
+        writer.flush()?;
+    }
+    fs::rename(&temp_file, archive_path)?;
+
+    report.archive_bytes = fs::metadata(archive_path)?.len();
+    log::info!(
+        "Exported {} cache entries for {} tracks to {}",
+        report.entries,
+        report.tracks,
+        archive_path.display()
+    );
+    Ok(report)
+}
+
+/// Imports an archive under `library_root`. Each track is rebased onto the
+/// local root and only accepted if its local audio hashes to the archived
+/// content hash; entries already in the local cache are kept as they are.
+pub fn import_cache(cache_dir: &Path, library_root: &Path, archive_path: &Path) -> CacheResult<ImportReport> {
+    let mut reader = BufReader::new(File::open(archive_path)?);
+
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != ARCHIVE_MAGIC {
+        return Err(CacheError::EntryCorrupted(format!(
+            "{} is not a cache archive",
+            archive_path.display()
+        )));
+    }
+    let mut version = [0u8; 2];
+    reader.read_exact(&mut version)?;
+    let version = u16::from_le_bytes(version);
+    if version != ARCHIVE_FORMAT_VERSION {
+        return Err(CacheError::EntryCorrupted(format!(
+            "Unsupported cache archive version {}",
+            version
+        )));
+    }
+    let manifest: ArchiveManifest = serde_json::from_slice(&read_blob(&mut reader)?)?;
+    log::info!(
+        "Importing {} tracks exported from {}",
+        manifest.entries.len(),
+        manifest.library_root
+    );
+
+    // Validate every track against its local file before touching the cache
+    let checked: Vec<(ManifestEntry, TrackCheck)> = manifest
+        .entries
+        .into_par_iter()
+        .map(|entry| {
+            let local_path = storage::resolve_relative(library_root, &entry.relative_path);
+            let check = if !local_path.exists() {
+                TrackCheck::Missing
+            } else {
+                match fingerprint::compute_content_hash(&local_path) {
+                    Ok(hash) if hash == entry.content_hash => TrackCheck::Valid(local_path),
+                    _ => TrackCheck::Mismatched,
+                }
+            };
+            (entry, check)
+        })
+        .collect();
+
+    let mut report = ImportReport::default();
+    let mut accepted: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for (entry, check) in checked {
+        match check {
+            TrackCheck::Valid(local_path) => accepted.entry(entry.content_hash).or_default().push(local_path),
+            TrackCheck::Missing => report.missing.push(entry.relative_path),
+            TrackCheck::Mismatched => report.mismatched.push(entry.relative_path),
+        }
+    }
+
+    fs::create_dir_all(cache_dir)?;
+    let handle = index::open_index(cache_dir)?;
+
+    while let Some(hash) = read_record_hash(&mut reader)? {
+        let entry_bytes = read_blob(&mut reader)?;
+        let waveform_bytes = read_blob(&mut reader)?;
+        let Some(local_paths) = accepted.get(&hash) else {
+            continue;
+        };
+
+        let already_cached = storage::load_cached_data(cache_dir, &hash).is_ok();
+        if already_cached {
+            report.already_cached += local_paths.len();
+        } else {
+            let mut cached_data: CachedTrackData = match serde_json::from_slice(&entry_bytes) {
+                Ok(cached_data) => cached_data,
+                Err(e) => {
+                    log::warn!("Skipping corrupted archived entry {}: {}", hash, e);
+                    report.skipped += local_paths.len();
+                    continue;
+                }
+            };
+            // Size and mtime differ between machines; the hash was just verified
+            let source_path = local_paths[0].clone();
+            fingerprint::refresh_file_metadata(&source_path.to_string_lossy(), &mut cached_data.fingerprint)?;
+            cached_data.relative_path = storage::relative_to_root(&source_path, library_root);
+            cached_data.source_path = Some(source_path);
+
+            if !waveform_bytes.is_empty() {
+                let analysis = match waveform::decode_waveform(&mut waveform_bytes.as_slice(), waveform_bytes.len() as u64) {
+                    Ok(analysis) => analysis,
+                    Err(e) => {
+                        log::warn!("Skipping archived entry {} with a corrupted waveform: {}", hash, e);
+                        report.skipped += local_paths.len();
+                        continue;
+                    }
+                };
+                waveform::save_waveform_data(cache_dir, &hash, &analysis)?;
+            }
+            storage::save_cached_data(cache_dir, &hash, &cached_data)?;
+            report.imported += local_paths.len();
+        }
+
+        let analyzer_signature = storage::load_cached_data(cache_dir, &hash)?
+            .analyzer
+            .as_ref()
+            .map(AnalyzerInfo::signature)
+            .unwrap_or_default();
+        handle.update(|index| {
+            for path in local_paths {
+                index
+                    .entries
+                    .entry(path.clone())
+                    .or_insert_with(|| IndexEntry::new(hash.clone(), analyzer_signature.clone()));
+            }
+        })?;
+    }
+    handle.flush()?;
+
+    log::info!(
+        "Cache import: {} imported, {} already cached, {} skipped, {} missing, {} mismatched",
+        report.imported,
+        report.already_cached,
+        report.skipped,
+        report.missing.len(),
+        report.mismatched.len()
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blob_round_trips() {
+        let mut bytes = Vec::new();
+        write_blob(&mut bytes, b"entry").unwrap();
+        write_blob(&mut bytes, b"").unwrap();
+        let mut reader = bytes.as_slice();
+        assert_eq!(read_blob(&mut reader).unwrap(), b"entry");
+        assert!(read_blob(&mut reader).unwrap().is_empty());
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn oversized_blob_length_is_an_error() {
+        let mut bytes = u32::MAX.to_le_bytes().to_vec();
+        bytes.extend_from_slice(b"short");
+        let result = read_blob(&mut bytes.as_slice());
+        assert!(matches!(result, Err(CacheError::EntryCorrupted(_))));
+    }
+
+    #[test]
+    fn record_hash_reports_a_clean_end() {
+        assert!(read_record_hash(&mut &b""[..]).unwrap().is_none());
+        assert!(read_record_hash(&mut &b"abc"[..]).is_err());
+        let hash = "0".repeat(HASH_LEN);
+        assert_eq!(read_record_hash(&mut hash.as_bytes()).unwrap(), Some(hash));
+    }
+}
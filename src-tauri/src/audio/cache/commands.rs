@@ -1,15 +1,59 @@
-use std::path::PathBuf;
-use super::{storage, index};
+use std::path::{Path, PathBuf};
+use tauri::{Emitter, Manager};
+use super::{archive, eviction, storage, index, verify};
+use super::archive::{ExportReport, ImportReport};
+use super::verify::VerifyReport;
+use super::eviction::EvictionReport;
+use super::storage::CacheLocation;
 
+fn ensure_central(app_handle: &tauri::AppHandle, music_path: &Path) -> Result<PathBuf, String> {
+    let app_data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to resolve app data directory: {}", e))?;
+    let cache_dir = storage::ensure_central_cache_directory(&app_data_dir, music_path)
+        .map_err(|e| e.to_string())?;
+
+    // Pull in a cache left behind by the in-folder layout
+    let legacy_dir = storage::in_folder_cache_dir(music_path);
+    if legacy_dir.exists()
+        && !storage::is_migrated(&legacy_dir)
+        && let Err(e) = storage::migrate_cache_directory(&legacy_dir, &cache_dir)
+    {
+        log::warn!("Failed to migrate in-folder cache {}: {}", legacy_dir.display(), e);
+    }
+
+    Ok(cache_dir)
+}
+
+/// Resolves (and creates) the cache directory for a music folder. Defaults to the
+/// central per-user cache; `InFolder` falls back to central when the folder is
+/// not writable.
 #[tauri::command(async)]
-pub fn ensure_cache_directory(music_dir: String) -> Result<String, String> {
+pub fn ensure_cache_directory(
+    app_handle: tauri::AppHandle,
+    music_dir: String,
+    location: Option<CacheLocation>,
+) -> Result<String, String> {
     let music_path = PathBuf::from(music_dir);
-    
-    match storage::ensure_cache_directory(&music_path) {
+
+    let result = match location.unwrap_or_default() {
+        CacheLocation::Central => ensure_central(&app_handle, &music_path),
+        CacheLocation::InFolder => storage::ensure_cache_directory(&music_path).or_else(|e| {
+            log::warn!(
+                "In-folder cache unavailable for {} ({}), using central cache",
+                music_path.display(),
+                e
+            );
+            ensure_central(&app_handle, &music_path)
+        }),
+    };
+
+    match result {
         Ok(cache_dir) => Ok(cache_dir.to_string_lossy().to_string()),
         Err(e) => {
             log::warn!("Failed to create cache directory: {}", e);
-            Err(e.to_string())
+            Err(e)
         }
     }
 }
@@ -64,6 +108,102 @@ pub fn rebuild_cache_index(cache_dir: String) -> Result<usize, String> {
     }
 }
 
+#[tauri::command(async)]
+pub fn refresh_stale_cache_entries(cache_dir: String) -> Result<usize, String> {
+    let cache_path = PathBuf::from(cache_dir);
+    
+    match super::refresh_stale_entries(&cache_path) {
+        Ok(refreshed) => {
+            log::info!("Refreshed {} stale cache entries", refreshed);
+            Ok(refreshed)
+        }
+        Err(e) => {
+            log::warn!("Failed to refresh stale cache entries: {}", e);
+            Err(e.to_string())
+        }
+    }
+}
+
+/// Sets the cache size budget in bytes (0 = unlimited) and evicts down to it.
+#[tauri::command(async)]
+pub fn set_cache_size_limit(cache_dir: String, size_limit_bytes: u64) -> Result<EvictionReport, String> {
+    let cache_path = PathBuf::from(cache_dir);
+
+    eviction::set_size_limit(&cache_path, size_limit_bytes).map_err(|e| {
+        log::warn!("Failed to set cache size limit: {}", e);
+        e.to_string()
+    })
+}
+
+#[tauri::command(async)]
+pub fn evict_cache(cache_dir: String) -> Result<EvictionReport, String> {
+    let cache_path = PathBuf::from(cache_dir);
+
+    eviction::enforce_size_limit(&cache_path).map_err(|e| {
+        log::warn!("Cache eviction failed: {}", e);
+        e.to_string()
+    })
+}
+
+/// Pins (or unpins) tracks so their cache entries survive eviction.
+#[tauri::command(async)]
+pub fn pin_cache_entries(cache_dir: String, paths: Vec<String>, pinned: bool) -> Result<usize, String> {
+    let cache_path = PathBuf::from(cache_dir);
+    let file_paths: Vec<PathBuf> = paths.into_iter().map(PathBuf::from).collect();
+
+    eviction::set_pinned(&cache_path, &file_paths, pinned).map_err(|e| {
+        log::warn!("Failed to update cache pins: {}", e);
+        e.to_string()
+    })
+}
+
+/// Exports the cache of a library to a portable archive with root-relative paths.
+#[tauri::command(async)]
+pub fn export_cache_archive(
+    cache_dir: String,
+    music_dir: String,
+    archive_path: String,
+) -> Result<ExportReport, String> {
+    let cache_path = PathBuf::from(cache_dir);
+
+    archive::export_cache(&cache_path, Path::new(&music_dir), Path::new(&archive_path)).map_err(|e| {
+        log::warn!("Cache export failed: {}", e);
+        e.to_string()
+    })
+}
+
+/// Imports a cache archive, rebasing its paths onto `music_dir`.
+#[tauri::command(async)]
+pub fn import_cache_archive(
+    cache_dir: String,
+    music_dir: String,
+    archive_path: String,
+) -> Result<ImportReport, String> {
+    let cache_path = PathBuf::from(cache_dir);
+
+    archive::import_cache(&cache_path, Path::new(&music_dir), Path::new(&archive_path)).map_err(|e| {
+        log::warn!("Cache import failed: {}", e);
+        e.to_string()
+    })
+}
+
+/// Rehashes and re-decodes every cached track, repairing or removing entries
+/// that don't match their audio. Emits `cache://verify-progress` per track.
+#[tauri::command(async)]
+pub fn verify_cache(app_handle: tauri::AppHandle, cache_dir: String) -> Result<VerifyReport, String> {
+    let cache_path = PathBuf::from(cache_dir);
+
+    let on_progress = |progress: verify::VerifyProgress| {
+        if let Err(e) = app_handle.emit("cache://verify-progress", progress) {
+            log::warn!("Failed to emit cache verify progress: {}", e);
+        }
+    };
+    verify::verify_cache(&cache_path, on_progress).map_err(|e| {
+        log::warn!("Cache verification failed: {}", e);
+        e.to_string()
+    })
+}
+
 #[tauri::command(async)]
 pub fn clear_cache(cache_dir: String) -> Result<(), String> {
     let cache_path = PathBuf::from(cache_dir);
@@ -82,9 +222,12 @@ pub fn clear_cache(cache_dir: String) -> Result<(), String> {
                 }
             }
             
-            // Create a new empty index
-            let empty_index = super::CacheIndex::default();
-            match index::save_index(&cache_path, &empty_index) {
+            // Create a new empty index (the size limit and pins are settings, not data)
+            let cleared = index::open_index(&cache_path).and_then(|handle| {
+                let empty_index = handle.snapshot()?.emptied();
+                handle.replace(empty_index)
+            });
+            match cleared {
                 Ok(()) => {
                     log::info!("Cache cleared successfully");
                     Ok(())
@@ -0,0 +1,192 @@
+use super::{index, storage, CacheError, CacheResult};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, RwLock};
+use std::time::UNIX_EPOCH;
+
+/// Default size budget for a cache directory (2 GiB).
+const DEFAULT_CACHE_SIZE_LIMIT_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+/// Eviction trims down to this fraction of the limit, so the next few analyses
+/// don't immediately trigger another pass.
+const EVICTION_TARGET_RATIO: f64 = 0.9;
+
+/// Tracks in any playlist or crate, pinned in every cache directory on top
+/// of each index's own pins. The playlist store keeps the set current.
+static PLAYLIST_TRACKS: LazyLock<RwLock<HashSet<PathBuf>>> = LazyLock::new(|| RwLock::new(HashSet::new()));
+
+pub(crate) fn default_size_limit() -> u64 {
+    DEFAULT_CACHE_SIZE_LIMIT_BYTES
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EvictionReport {
+    pub evicted_entries: usize,
+    pub freed_bytes: u64,
+    pub cache_size_bytes: u64,
+    pub size_limit_bytes: u64,
+    pub pinned_bytes: u64,
+}
+
+struct EntryUsage {
+    hash: String,
+    size: u64,
+    last_accessed: u64,
+    pinned: bool,
+}
+
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+fn modified_secs(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Size, recency and pin state of every entry on disk. Several paths can share
+/// one entry (duplicate files), so an entry's recency is that of its most
+/// recently used path and it is pinned if any of its paths is, or is in a
+/// playlist.
+fn entry_usage(cache_dir: &Path) -> CacheResult<Vec<EntryUsage>> {
+    let snapshot = index::open_index(cache_dir)?.snapshot()?;
+    let playlist_tracks = PLAYLIST_TRACKS
+        .read()
+        .map_err(|e| CacheError::LockPoisoned(format!("playlist tracks: {}", e)))?;
+
+    let mut by_hash: HashMap<&str, (u64, bool)> = HashMap::new();
+    for (path, entry) in &snapshot.entries {
+        let usage = by_hash.entry(entry.content_hash.as_str()).or_insert((0, false));
+        usage.0 = usage.0.max(entry.last_accessed);
+        usage.1 |= snapshot.pinned.contains(path) || playlist_tracks.contains(path);
+    }
+
+    let usage = storage::list_cache_files(cache_dir)?
+        .into_iter()
+        .map(|hash| {
+            let json_file = cache_dir.join(format!("{}.json", hash));
+            let size = file_size(&json_file) + file_size(&cache_dir.join(format!("{}.wave", hash)));
+            // Unindexed entries (source not found on rebuild) fall back to the
+            // entry's write time, which puts them near the front of the queue
+            let (last_accessed, pinned) = by_hash
+                .get(hash.as_str())
+                .copied()
+                .unwrap_or_else(|| (modified_secs(&json_file), false));
+            EntryUsage {
+                hash,
+                size,
+                last_accessed,
+                pinned,
+            }
+        })
+        .collect();
+    Ok(usage)
+}
+
+/// Evicts least recently used, unpinned entries until the cache fits its size
+/// limit. Does nothing when the limit is 0 (unlimited) or already met.
+pub fn enforce_size_limit(cache_dir: &Path) -> CacheResult<EvictionReport> {
+    let handle = index::open_index(cache_dir)?;
+    let size_limit_bytes = handle.snapshot()?.size_limit_bytes;
+    let mut cache_size_bytes = storage::get_cache_size(cache_dir)?;
+
+    let mut usage = entry_usage(cache_dir)?;
+    let mut report = EvictionReport {
+        size_limit_bytes,
+        pinned_bytes: usage.iter().filter(|u| u.pinned).map(|u| u.size).sum(),
+        ..EvictionReport::default()
+    };
+
+    if size_limit_bytes == 0 || cache_size_bytes <= size_limit_bytes {
+        report.cache_size_bytes = cache_size_bytes;
+        return Ok(report);
+    }
+
+    let target = (size_limit_bytes as f64 * EVICTION_TARGET_RATIO) as u64;
+    usage.retain(|u| !u.pinned);
+    usage.sort_by_key(|u| u.last_accessed);
+
+    let mut evicted = HashSet::new();
+    for entry in usage {
+        if cache_size_bytes <= target {
+            break;
+        }
+        cache_size_bytes = cache_size_bytes.saturating_sub(entry.size);
+        report.freed_bytes += entry.size;
+        evicted.insert(entry.hash);
+    }
+
+    // Unindex first so no lookup resolves to a file that is about to disappear
+    handle.update(|index| {
+        index
+            .entries
+            .retain(|_, entry| !evicted.contains(&entry.content_hash));
+    })?;
+    handle.flush()?;
+
+    for hash in &evicted {
+        match storage::delete_cached_data(cache_dir, hash) {
+            Ok(()) => report.evicted_entries += 1,
+            Err(e) => log::warn!("Failed to evict cache entry {}: {}", hash, e),
+        }
+    }
+
+    report.cache_size_bytes = cache_size_bytes;
+    if cache_size_bytes > size_limit_bytes {
+        log::warn!(
+            "Cache at {} is still over its {} byte limit after eviction ({} bytes pinned)",
+            cache_dir.display(),
+            size_limit_bytes,
+            report.pinned_bytes
+        );
+    }
+    log::info!(
+        "Evicted {} cache entries ({} bytes) from {}",
+        report.evicted_entries,
+        report.freed_bytes,
+        cache_dir.display()
+    );
+    Ok(report)
+}
+
+/// Sets the cache's size limit (0 = unlimited) and evicts down to it.
+pub fn set_size_limit(cache_dir: &Path, size_limit_bytes: u64) -> CacheResult<EvictionReport> {
+    let handle = index::open_index(cache_dir)?;
+    handle.update(|index| index.size_limit_bytes = size_limit_bytes)?;
+    handle.flush()?;
+    enforce_size_limit(cache_dir)
+}
+
+/// Replaces the tracks pinned for being in a playlist.
+pub fn set_playlist_tracks(paths: impl IntoIterator<Item = PathBuf>) {
+    match PLAYLIST_TRACKS.write() {
+        Ok(mut tracks) => *tracks = paths.into_iter().collect(),
+        Err(e) => log::warn!("Failed to update pinned playlist tracks: {}", e),
+    }
+}
+
+/// Pins or unpins source paths. Paths need not be analyzed yet; a pin applies
+/// to whatever entry the path maps to. Returns the number of pins changed.
+pub fn set_pinned(cache_dir: &Path, paths: &[PathBuf], pinned: bool) -> CacheResult<usize> {
+    let handle = index::open_index(cache_dir)?;
+    let changed = handle.update(|index| {
+        paths
+            .iter()
+            .filter(|path| {
+                if pinned {
+                    index.pinned.insert((*path).clone())
+                } else {
+                    index.pinned.remove(*path)
+                }
+            })
+            .count()
+    })?;
+    handle.flush()?;
+    Ok(changed)
+}
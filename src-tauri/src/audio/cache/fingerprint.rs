@@ -1,22 +1,22 @@
-use super::{AudioFingerprint, CacheError, CacheResult};
-use std::fs::File;
-use std::io::{BufReader, Read};
+use super::{AudioFingerprint, CacheResult};
 use std::path::Path;
 use std::time::SystemTime;
 
+/// Hashes the audio payload of a file (compressed packets only, no tags).
 pub fn compute_content_hash(file_path: &Path) -> CacheResult<String> {
-    let file = File::open(file_path)?;
-    let mut reader = BufReader::new(file);
-    let mut buffer = vec![0; 64 * 1024]; // 64KB buffer
-
-    let bytes_read = reader.read(&mut buffer)?;
-    buffer.truncate(bytes_read);
-
-    let hash = blake3::hash(&buffer);
-    Ok(hash.to_hex().to_string())
+    let path_str = file_path.to_string_lossy();
+    Ok(crate::audio::decoding::hash_audio_payload(&path_str)?)
 }
 
-pub fn create_fingerprint(file_path: &str) -> CacheResult<AudioFingerprint> {
+/// Builds a fingerprint for a file whose content hash, duration and sample rate
+/// are already known from the analysis decode, so fingerprinting never reads the
+/// audio again.
+pub fn create_fingerprint(
+    file_path: &str,
+    content_hash: String,
+    duration_ms: u64,
+    sample_rate: u32,
+) -> CacheResult<AudioFingerprint> {
     let path = Path::new(file_path);
 
     // Get file metadata
@@ -24,54 +24,62 @@ pub fn create_fingerprint(file_path: &str) -> CacheResult<AudioFingerprint> {
     let file_size = metadata.len();
     let last_modified = metadata.modified()?;
 
-    // Compute content hash
-    let content_hash = compute_content_hash(path)?;
-
-    // Get audio metadata using existing decoding
-    let (samples, sample_rate) = crate::audio::decoding::decode_file_to_mono_samples(file_path)
-        .map_err(|e| {
-            CacheError::EntryCorrupted(format!("Failed to decode audio for fingerprint: {}", e))
-        })?;
-
-    let duration_ms = if sample_rate > 0.0 && !samples.is_empty() {
-        ((samples.len() as f64 / sample_rate as f64) * 1000.0) as u64
-    } else {
-        0
-    };
-
     Ok(AudioFingerprint {
         content_hash,
         duration_ms,
-        sample_rate: sample_rate as u32,
+        sample_rate,
         file_size,
         last_modified,
     })
 }
 
+/// Whether the file's size and modification time still match the fingerprint.
+pub fn file_metadata_matches(file_path: &str, cached_fingerprint: &AudioFingerprint) -> bool {
+    match std::fs::metadata(file_path) {
+        Ok(metadata) => {
+            metadata.len() == cached_fingerprint.file_size
+                && metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH)
+                    == cached_fingerprint.last_modified
+        }
+        Err(_) => false,
+    }
+}
+
+/// Updates the fingerprint's size and modification time from the file on disk,
+/// after a tag edit has been confirmed not to touch the audio.
+pub fn refresh_file_metadata(
+    file_path: &str,
+    fingerprint: &mut AudioFingerprint,
+) -> CacheResult<()> {
+    let metadata = std::fs::metadata(file_path)?;
+    fingerprint.file_size = metadata.len();
+    fingerprint.last_modified = metadata.modified()?;
+    Ok(())
+}
+
 pub fn validate_cache_entry(
     file_path: &str,
     cached_fingerprint: &AudioFingerprint,
 ) -> CacheResult<bool> {
     let path = Path::new(file_path);
 
-    // Quick file metadata check
-    let metadata = match std::fs::metadata(path) {
-        Ok(meta) => meta,
-        Err(_) => return Ok(false), // File doesn't exist
-    };
-
-    let file_size = metadata.len();
-    let last_modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    if !path.exists() {
+        return Ok(false); // File doesn't exist
+    }
 
-    // Check if file size or modification time changed
-    if file_size != cached_fingerprint.file_size
-        || last_modified != cached_fingerprint.last_modified
-    {
-        log::debug!("File metadata changed for: {}", file_path);
-        return Ok(false);
+    // Quick file metadata check
+    if file_metadata_matches(file_path, cached_fingerprint) {
+        return Ok(true);
     }
 
-    // If metadata is the same, we can trust the cache
-    // For extra validation, we could rehash, but that's expensive
-    Ok(true)
+    // Size or modification time changed. That is usually a tag edit, which leaves
+    // the audio payload (and so the content hash) untouched.
+    log::debug!("File metadata changed for: {}, rehashing audio payload", file_path);
+    match compute_content_hash(path) {
+        Ok(content_hash) => Ok(content_hash == cached_fingerprint.content_hash),
+        Err(e) => {
+            log::debug!("Failed to rehash {}: {}", file_path, e);
+            Ok(false)
+        }
+    }
 }
@@ -1,11 +1,72 @@
-use super::{CacheIndex, CacheResult};
+use super::{
+    AnalyzerInfo, CacheError, CacheIndex, CacheResult, CachedTrackData, IndexEntry,
+    CACHE_INDEX_VERSION,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::{BufReader, BufWriter};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
 const INDEX_FILE_NAME: &str = "index.json";
+/// Flush the index after this many unsaved updates...
+const INDEX_FLUSH_BATCH_SIZE: usize = 64;
+/// ...or once this much time has passed since the last flush.
+const INDEX_FLUSH_INTERVAL: Duration = Duration::from_secs(2);
 
-pub fn load_index(cache_dir: &Path) -> CacheResult<CacheIndex> {
+/// Open index owners, keyed by canonical cache directory.
+static OPEN_INDEXES: LazyLock<Mutex<HashMap<PathBuf, Arc<IndexHandle>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Version 1 index: path -> content hash, no analyzer information.
+#[derive(Deserialize)]
+struct CacheIndexV1 {
+    entries: HashMap<PathBuf, String>,
+}
+
+/// Upgrades a raw index document of any known version to the current format.
+fn migrate_index(raw: serde_json::Value) -> CacheResult<CacheIndex> {
+    let version = raw.get("version").and_then(|v| v.as_u64()).unwrap_or(1) as u32;
+
+    match version {
+        CACHE_INDEX_VERSION => Ok(serde_json::from_value(raw)?),
+        1 => {
+            let legacy: CacheIndexV1 = serde_json::from_value(raw)?;
+            log::info!(
+                "Migrating cache index from version 1 to {} ({} entries)",
+                CACHE_INDEX_VERSION,
+                legacy.entries.len()
+            );
+            let entries = legacy
+                .entries
+                .into_iter()
+                .map(|(path, content_hash)| {
+                    (
+                        path,
+                        IndexEntry {
+                            content_hash,
+                            // Unknown analyzer: treated as stale until re-analyzed
+                            analyzer_signature: String::new(),
+                            last_accessed: 0,
+                        },
+                    )
+                })
+                .collect();
+            Ok(CacheIndex {
+                entries,
+                ..CacheIndex::default()
+            })
+        }
+        newer => Err(CacheError::EntryCorrupted(format!(
+            "Cache index version {} is newer than supported version {}",
+            newer, CACHE_INDEX_VERSION
+        ))),
+    }
+}
+
+fn read_index_file(cache_dir: &Path) -> CacheResult<CacheIndex> {
     let index_file = cache_dir.join(INDEX_FILE_NAME);
 
     if !index_file.exists() {
@@ -16,19 +77,38 @@ pub fn load_index(cache_dir: &Path) -> CacheResult<CacheIndex> {
     let file = File::open(&index_file)?;
     let reader = BufReader::new(file);
 
-    match serde_json::from_reader::<_, CacheIndex>(reader) {
+    let raw = match serde_json::from_reader::<_, serde_json::Value>(reader) {
+        Ok(raw) => raw,
+        Err(e) => {
+            log::warn!("Cache index corrupted ({}), rebuilding...", e);
+            let index = scan_cache_files(cache_dir)?;
+            write_index_file(cache_dir, &index)?;
+            return Ok(index);
+        }
+    };
+
+    let needs_migration = raw.get("version").and_then(|v| v.as_u64())
+        != Some(CACHE_INDEX_VERSION as u64);
+
+    match migrate_index(raw) {
         Ok(index) => {
+            if needs_migration {
+                write_index_file(cache_dir, &index)?;
+            }
             log::debug!("Loaded cache index with {} entries", index.entries.len());
             Ok(index)
         }
-        Err(e) => {
+        Err(CacheError::Serde(e)) => {
             log::warn!("Cache index corrupted ({}), rebuilding...", e);
-            rebuild_index(cache_dir)
+            let index = scan_cache_files(cache_dir)?;
+            write_index_file(cache_dir, &index)?;
+            Ok(index)
         }
+        Err(e) => Err(e),
     }
 }
 
-pub fn save_index(cache_dir: &Path, index: &CacheIndex) -> CacheResult<()> {
+fn write_index_file(cache_dir: &Path, index: &CacheIndex) -> CacheResult<()> {
     let index_file = cache_dir.join(INDEX_FILE_NAME);
     let temp_file = cache_dir.join(format!("{}.tmp", INDEX_FILE_NAME));
 
@@ -37,11 +117,12 @@ pub fn save_index(cache_dir: &Path, index: &CacheIndex) -> CacheResult<()> {
         fs::create_dir_all(cache_dir)?;
     }
 
-    // Write to temporary file first (atomic operation)
+    // Write to temporary file first (atomic operation). Compact JSON: the index
+    // is rewritten on every batch flush and nobody reads it by hand.
     {
         let file = File::create(&temp_file)?;
         let writer = BufWriter::new(file);
-        serde_json::to_writer_pretty(writer, index)?;
+        serde_json::to_writer(writer, index)?;
     }
 
     // Atomic rename
@@ -51,68 +132,319 @@ pub fn save_index(cache_dir: &Path, index: &CacheIndex) -> CacheResult<()> {
     Ok(())
 }
 
-pub fn rebuild_index(cache_dir: &Path) -> CacheResult<CacheIndex> {
-    log::info!("Rebuilding cache index from cached files...");
+// --- Index Owner ---
+
+struct IndexState {
+    index: CacheIndex,
+    pending_writes: usize,
+    last_flush: Instant,
+}
+
+/// Single in-process owner of a cache directory's index.
+///
+/// Every read and write goes through this handle, so parallel analysis workers
+/// serialize on one lock instead of racing on `index.json`. Updates are held in
+/// memory and flushed in batches.
+pub struct IndexHandle {
+    cache_dir: PathBuf,
+    state: Mutex<IndexState>,
+}
+
+impl IndexHandle {
+    fn lock(&self) -> CacheResult<MutexGuard<'_, IndexState>> {
+        self.state
+            .lock()
+            .map_err(|e| CacheError::LockPoisoned(format!("cache index: {}", e)))
+    }
+
+    fn flush_locked(&self, state: &mut IndexState) -> CacheResult<()> {
+        write_index_file(&self.cache_dir, &state.index)?;
+        state.pending_writes = 0;
+        state.last_flush = Instant::now();
+        Ok(())
+    }
+
+    pub fn get(&self, path: &Path) -> CacheResult<Option<IndexEntry>> {
+        Ok(self.lock()?.index.entries.get(path).cloned())
+    }
+
+    pub fn entry_count(&self) -> CacheResult<usize> {
+        Ok(self.lock()?.index.entries.len())
+    }
+
+    /// A copy of the current index, including unflushed updates.
+    pub fn snapshot(&self) -> CacheResult<CacheIndex> {
+        Ok(self.lock()?.index.clone())
+    }
+
+    pub fn insert(&self, path: PathBuf, entry: IndexEntry) -> CacheResult<()> {
+        self.update(|index| {
+            index.entries.insert(path, entry);
+        })
+    }
+
+    /// Records a cache hit for LRU eviction.
+    pub fn touch(&self, path: &Path) -> CacheResult<()> {
+        let now = super::unix_now();
+        self.update(|index| {
+            if let Some(entry) = index.entries.get_mut(path) {
+                entry.last_accessed = now;
+            }
+        })
+    }
+
+    /// Applies a mutation under the index lock and flushes if a batch is due.
+    pub fn update<T>(&self, mutate: impl FnOnce(&mut CacheIndex) -> T) -> CacheResult<T> {
+        let mut state = self.lock()?;
+        let result = mutate(&mut state.index);
+        state.pending_writes += 1;
+
+        if state.pending_writes >= INDEX_FLUSH_BATCH_SIZE
+            || state.last_flush.elapsed() >= INDEX_FLUSH_INTERVAL
+        {
+            self.flush_locked(&mut state)?;
+        }
+        Ok(result)
+    }
+
+    /// Replaces the whole index and writes it immediately.
+    pub fn replace(&self, index: CacheIndex) -> CacheResult<()> {
+        let mut state = self.lock()?;
+        state.index = index;
+        self.flush_locked(&mut state)
+    }
+
+    /// Writes any pending updates to disk.
+    pub fn flush(&self) -> CacheResult<()> {
+        let mut state = self.lock()?;
+        if state.pending_writes > 0 {
+            self.flush_locked(&mut state)?;
+        }
+        Ok(())
+    }
+}
+
+/// The key of `cache_dir`'s handle: its canonical path, so every spelling
+/// of the directory shares one handle. A directory that doesn't exist yet is
+/// keyed by its nearest existing ancestor's canonical path plus the rest,
+/// which is what it canonicalizes to once it is created.
+fn index_key(cache_dir: &Path) -> PathBuf {
+    let mut missing = Vec::new();
+    let mut existing = cache_dir;
+    loop {
+        let lookup = if existing.as_os_str().is_empty() { Path::new(".") } else { existing };
+        if let Ok(canonical) = fs::canonicalize(lookup) {
+            return missing.iter().rev().fold(canonical, |path, name| path.join(name));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            _ => return cache_dir.to_path_buf(),
+        }
+    }
+}
+
+/// Returns the owner of `cache_dir`'s index, loading it from disk on first use.
+pub fn open_index(cache_dir: &Path) -> CacheResult<Arc<IndexHandle>> {
+    let key = index_key(cache_dir);
+
+    let mut open_indexes = OPEN_INDEXES
+        .lock()
+        .map_err(|e| CacheError::LockPoisoned(format!("open indexes: {}", e)))?;
+
+    if let Some(handle) = open_indexes.get(&key) {
+        return Ok(handle.clone());
+    }
+
+    let index = read_index_file(cache_dir)?;
+    let handle = Arc::new(IndexHandle {
+        cache_dir: cache_dir.to_path_buf(),
+        state: Mutex::new(IndexState {
+            index,
+            pending_writes: 0,
+            last_flush: Instant::now(),
+        }),
+    });
+    open_indexes.insert(key, handle.clone());
+    Ok(handle)
+}
+
+/// Flushes and forgets `cache_dir`'s index handle, e.g. once its entries
+/// moved elsewhere. The next `open_index` reads it from disk again.
+pub fn close_index(cache_dir: &Path) -> CacheResult<()> {
+    let key = index_key(cache_dir);
+    let handle = OPEN_INDEXES
+        .lock()
+        .map_err(|e| CacheError::LockPoisoned(format!("open indexes: {}", e)))?
+        .remove(&key);
+    match handle {
+        Some(handle) => handle.flush(),
+        None => Ok(()),
+    }
+}
+
+/// Flushes pending updates for one cache directory.
+pub fn flush_index(cache_dir: &Path) -> CacheResult<()> {
+    open_index(cache_dir)?.flush()
+}
+
+/// Flushes pending updates for every open index (called on shutdown).
+pub fn flush_all_indexes() {
+    let handles: Vec<Arc<IndexHandle>> = match OPEN_INDEXES.lock() {
+        Ok(open_indexes) => open_indexes.values().cloned().collect(),
+        Err(e) => {
+            log::warn!("Failed to lock open cache indexes for flush: {}", e);
+            return;
+        }
+    };
+
+    for handle in handles {
+        if let Err(e) = handle.flush() {
+            log::warn!(
+                "Failed to flush cache index for {}: {}",
+                handle.cache_dir.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Finds the audio file a cache entry was made from: its stored absolute path, or
+/// its root-relative path under the current library root if the library moved.
+pub(crate) fn locate_source(cached_data: &CachedTrackData, library_root: Option<&Path>) -> Option<PathBuf> {
+    if let Some(source_path) = cached_data.source_path.as_ref().filter(|p| p.exists()) {
+        return Some(source_path.clone());
+    }
+    let root = library_root?;
+    let relative_path = cached_data.relative_path.as_ref()?;
+    let candidate = super::storage::resolve_relative(root, relative_path);
+    candidate.exists().then_some(candidate)
+}
 
-    let index = CacheIndex::default();
+/// Builds an index from the entry files on disk. Each entry's source file is
+/// re-validated before it is indexed; unreadable entries and entries whose
+/// source has changed are removed. Entries whose source cannot be found are
+/// left on disk unindexed, since the file may be on an unmounted drive.
+fn scan_cache_files(cache_dir: &Path) -> CacheResult<CacheIndex> {
+    let mut index = CacheIndex::default();
 
     if !cache_dir.exists() {
         log::debug!("Cache directory doesn't exist, returning empty index");
         return Ok(index);
     }
 
+    let library_root = super::storage::library_root_for_cache_dir(cache_dir);
+
     // Get all cache files
     let cache_hashes = super::storage::list_cache_files(cache_dir)?;
 
     for hash in cache_hashes {
         // Try to load the cached data to validate it
-        match super::storage::load_cached_data(cache_dir, &hash) {
-            Ok(_cached_data) => {
-                // Extract the original file path from the cached data
-                // We need to reverse-engineer this from the cache structure
-                // Since we don't store the original path in the cached data,
-                // we'll need to rely on the current file system state
-
-                // For now, we'll skip adding to index if we can't determine the path
-                // This will cause cache misses until files are re-analyzed
-                log::debug!("Found valid cache file for hash: {}", hash);
-            }
+        let cached_data = match super::storage::load_cached_data(cache_dir, &hash) {
+            Ok(cached_data) => cached_data,
             Err(e) => {
                 log::warn!("Invalid cache file for hash {}: {}. Removing.", hash, e);
                 if let Err(remove_err) = super::storage::delete_cached_data(cache_dir, &hash) {
                     log::warn!("Failed to remove invalid cache file: {}", remove_err);
                 }
+                continue;
+            }
+        };
+
+        let Some(source_path) = locate_source(&cached_data, library_root.as_deref()) else {
+            log::debug!("Source file for cache entry {} not found; leaving it unindexed", hash);
+            continue;
+        };
+
+        let source_str = source_path.to_string_lossy();
+        match super::fingerprint::validate_cache_entry(&source_str, &cached_data.fingerprint) {
+            Ok(true) => {
+                let analyzer_signature = cached_data
+                    .analyzer
+                    .as_ref()
+                    .map(AnalyzerInfo::signature)
+                    .unwrap_or_default();
+                let last_accessed = cached_data
+                    .cached_at
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default();
+                index.entries.insert(
+                    source_path,
+                    IndexEntry {
+                        content_hash: hash,
+                        analyzer_signature,
+                        last_accessed,
+                    },
+                );
+            }
+            Ok(false) => {
+                log::debug!("Source file changed since cache entry {} was written. Removing.", hash);
+                if let Err(remove_err) = super::storage::delete_cached_data(cache_dir, &hash) {
+                    log::warn!("Failed to remove outdated cache file: {}", remove_err);
+                }
+            }
+            Err(e) => {
+                log::warn!("Failed to validate cache entry {}: {}", hash, e);
             }
         }
     }
 
-    // Save the rebuilt index
-    save_index(cache_dir, &index)?;
+    Ok(index)
+}
+
+pub fn rebuild_index(cache_dir: &Path) -> CacheResult<CacheIndex> {
+    log::info!("Rebuilding cache index from cached files...");
+
+    let mut index = scan_cache_files(cache_dir)?;
+
+    // Install and save the rebuilt index, keeping the size limit and pins
+    let handle = open_index(cache_dir)?;
+    let previous = handle.snapshot()?;
+    index.size_limit_bytes = previous.size_limit_bytes;
+    index.pinned = previous.pinned;
+    handle.replace(index.clone())?;
 
     log::info!("Rebuilt cache index with {} entries", index.entries.len());
     Ok(index)
 }
 
 pub fn cleanup_orphaned_cache(cache_dir: &Path, current_files: &[PathBuf]) -> CacheResult<()> {
-    let mut index = load_index(cache_dir)?;
-    let mut removed_count = 0;
+    let handle = open_index(cache_dir)?;
 
     // Create a set of current files for fast lookup
     let current_files_set: std::collections::HashSet<_> = current_files.iter().collect();
 
-    // Find entries in index that no longer exist in the file system
-    let mut to_remove = Vec::new();
-    for (path, hash) in &index.entries {
-        if !current_files_set.contains(&path) {
-            to_remove.push((path.clone(), hash.clone()));
-        }
-    }
-
-    // Remove orphaned entries
-    for (path, hash) in to_remove {
-        index.entries.remove(&path);
+    // Remove index entries that no longer exist in the file system
+    let removed = handle.update(|index| {
+        let to_remove: Vec<PathBuf> = index
+            .entries
+            .keys()
+            .filter(|path| !current_files_set.contains(path))
+            .cloned()
+            .collect();
+        let mut removed: Vec<(PathBuf, String)> = to_remove
+            .into_iter()
+            .filter_map(|path| index.entries.remove(&path).map(|entry| (path, entry.content_hash)))
+            .collect();
+        // Copies of a file share one cache file; keep it while any copy is left
+        let referenced: std::collections::HashSet<&str> = index
+            .entries
+            .values()
+            .map(|entry| entry.content_hash.as_str())
+            .collect();
+        removed.retain(|(_, hash)| !referenced.contains(hash.as_str()));
+        removed.sort_by(|a, b| a.1.cmp(&b.1));
+        removed.dedup_by(|a, b| a.1 == b.1);
+        removed
+    })?;
+    handle.flush()?;
 
-        // Also remove the cache file
+    // Also remove the cache files nothing refers to anymore
+    let mut removed_count = 0;
+    for (path, hash) in removed {
         if let Err(e) = super::storage::delete_cached_data(cache_dir, &hash) {
             log::warn!("Failed to remove orphaned cache file {}: {}", hash, e);
         } else {
@@ -122,7 +454,6 @@ pub fn cleanup_orphaned_cache(cache_dir: &Path, current_files: &[PathBuf]) -> Ca
     }
 
     if removed_count > 0 {
-        save_index(cache_dir, &index)?;
         log::info!("Cleaned up {} orphaned cache entries", removed_count);
     }
 
@@ -130,8 +461,19 @@ pub fn cleanup_orphaned_cache(cache_dir: &Path, current_files: &[PathBuf]) -> Ca
 }
 
 pub fn get_cache_stats(cache_dir: &Path) -> CacheResult<(usize, u64)> {
-    let index = load_index(cache_dir)?;
+    let entry_count = open_index(cache_dir)?.entry_count()?;
     let cache_size = super::storage::get_cache_size(cache_dir)?;
 
-    Ok((index.entries.len(), cache_size))
+    Ok((entry_count, cache_size))
+}
+
+/// Paths whose cache entries were produced by a different analyzer version or parameters.
+pub fn stale_entries(index: &CacheIndex) -> Vec<PathBuf> {
+    let current_signature = AnalyzerInfo::current().signature();
+    index
+        .entries
+        .iter()
+        .filter(|(_, entry)| entry.analyzer_signature != current_signature)
+        .map(|(path, _)| path.clone())
+        .collect()
 }
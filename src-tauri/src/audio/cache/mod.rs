@@ -1,13 +1,22 @@
-use crate::audio::types::TrackBasicMetadata;
+use crate::audio::config;
+use crate::audio::errors::AudioProcessorError;
+use crate::audio::types::{
+    AudioAnalysis, KeyAnalysis, LoudnessAnalysis, TrackAnalysis, TrackBasicMetadata,
+};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::time::SystemTime;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+pub mod archive;
 pub mod commands;
+pub mod eviction;
 pub mod fingerprint;
 pub mod index;
 pub mod storage;
+pub mod verify;
+pub mod waveform;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -19,29 +28,153 @@ pub struct AudioFingerprint {
     pub last_modified: SystemTime,
 }
 
+/// The analyzer version and parameters that produced a cached result.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyzerInfo {
+    pub version: u32,
+    pub bpm_frame_size: usize,
+    pub bpm_hop_size: usize,
+    pub bpm_downsample_factor: usize,
+    pub bpm_min: f32,
+    pub bpm_max: f32,
+    pub waveform_frame_size: usize,
+    pub waveform_hop_size: usize,
+    pub key_frame_size: usize,
+    pub key_hop_size: usize,
+    pub key_downsample_factor: usize,
+    pub loudness_block_ms: u32,
+    pub loudness_step_ms: u32,
+    #[serde(default)]
+    pub energy_window_ms: u32,
+}
+
+impl AnalyzerInfo {
+    /// Analyzer info for the algorithms compiled into this build.
+    pub fn current() -> Self {
+        Self {
+            version: config::ANALYZER_VERSION,
+            bpm_frame_size: config::BPM_FRAME_SIZE,
+            bpm_hop_size: config::BPM_HOP_SIZE,
+            bpm_downsample_factor: config::BPM_DOWNSAMPLE_FACTOR,
+            bpm_min: config::BPM_MIN,
+            bpm_max: config::BPM_MAX,
+            waveform_frame_size: config::WAVEFORM_FRAME_SIZE,
+            waveform_hop_size: config::WAVEFORM_HOP_SIZE,
+            key_frame_size: config::KEY_FRAME_SIZE,
+            key_hop_size: config::KEY_HOP_SIZE,
+            key_downsample_factor: config::KEY_DOWNSAMPLE_FACTOR,
+            loudness_block_ms: config::LOUDNESS_BLOCK_MS,
+            loudness_step_ms: config::LOUDNESS_STEP_MS,
+            energy_window_ms: config::ENERGY_WINDOW_MS,
+        }
+    }
+
+    /// Short stable signature of this info, stored in the index so stale entries
+    /// can be found without opening every cache file.
+    pub fn signature(&self) -> String {
+        let serialized = serde_json::to_vec(self).unwrap_or_default();
+        blake3::hash(&serialized).to_hex()[..16].to_string()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CachedTrackData {
     pub fingerprint: AudioFingerprint,
+    /// Missing on entries written before analyzer versioning; those are always stale.
+    #[serde(default)]
+    pub analyzer: Option<AnalyzerInfo>,
+    /// Absolute path of the analyzed file when it was cached.
+    #[serde(default)]
+    pub source_path: Option<PathBuf>,
+    /// Path relative to the library root, `/`-separated.
+    #[serde(default)]
+    pub relative_path: Option<String>,
     pub bpm_analysis: TrackBasicMetadata,
+    #[serde(default)]
+    pub loudness_analysis: Option<LoudnessAnalysis>,
+    #[serde(default)]
+    pub key_analysis: Option<KeyAnalysis>,
+    #[serde(default)]
+    pub energy: Option<f32>,
     pub cached_at: SystemTime,
 }
 
+impl CachedTrackData {
+    /// Whether this entry was produced by the current analyzer version and parameters.
+    pub fn is_current(&self) -> bool {
+        self.analyzer.as_ref() == Some(&AnalyzerInfo::current())
+    }
+}
+
+/// Current on-disk index format. Older formats are migrated when the index is opened.
+pub const CACHE_INDEX_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexEntry {
+    pub content_hash: String,
+    /// `AnalyzerInfo::signature` of the entry; empty when unknown (migrated entries).
+    #[serde(default)]
+    pub analyzer_signature: String,
+    /// Unix seconds of the last cache hit or write; drives LRU eviction.
+    #[serde(default)]
+    pub last_accessed: u64,
+}
+
+impl IndexEntry {
+    pub fn new(content_hash: String, analyzer_signature: String) -> Self {
+        Self {
+            content_hash,
+            analyzer_signature,
+            last_accessed: unix_now(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CacheIndex {
     pub version: u32,
-    pub entries: HashMap<PathBuf, String>, // path -> content_hash
+    pub entries: HashMap<PathBuf, IndexEntry>,
+    /// Size budget for the cache directory in bytes; 0 disables eviction.
+    #[serde(default = "eviction::default_size_limit")]
+    pub size_limit_bytes: u64,
+    /// Source paths whose entries are never evicted (e.g. playlist tracks).
+    #[serde(default)]
+    pub pinned: HashSet<PathBuf>,
 }
 
 impl Default for CacheIndex {
     fn default() -> Self {
         Self {
-            version: 1,
+            version: CACHE_INDEX_VERSION,
+            entries: HashMap::new(),
+            size_limit_bytes: eviction::default_size_limit(),
+            pinned: HashSet::new(),
+        }
+    }
+}
+
+impl CacheIndex {
+    /// An empty index that keeps this index's size limit and pins.
+    pub fn emptied(&self) -> Self {
+        Self {
             entries: HashMap::new(),
+            size_limit_bytes: self.size_limit_bytes,
+            pinned: self.pinned.clone(),
+            ..Self::default()
         }
     }
 }
 
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
 pub type CacheResult<T> = Result<T, CacheError>;
 
 #[derive(Debug, thiserror::Error)]
@@ -60,12 +193,20 @@ pub enum CacheError {
 
     #[error("Cache entry invalid or corrupted: {0}")]
     EntryCorrupted(String),
+
+    #[error("Cache lock poisoned: {0}")]
+    LockPoisoned(String),
+
+    #[error("Audio read error: {0}")]
+    AudioRead(#[from] crate::audio::errors::AudioDecodingError),
 }
 
+/// Returns cached BPM metadata for a file, or runs the full single-decode analysis
+/// job on a miss and stores every analyzer's result in one cache entry.
 pub fn analyze_bpm_with_cache(
     file_path: &str,
     cache_dir: Option<&PathBuf>,
-) -> Result<TrackBasicMetadata, Box<dyn std::error::Error>> {
+) -> Result<TrackBasicMetadata, AudioProcessorError> {
     if let Some(cache_dir) = cache_dir {
         // Try cache first
         match try_bpm_cache_lookup(file_path, cache_dir) {
@@ -86,70 +227,500 @@ pub fn analyze_bpm_with_cache(
         }
     }
 
-    // Fallback to regular BPM analysis
-    let metadata = match crate::audio::processor::get_track_basic_metadata_internal(file_path) {
-        Ok(meta) => meta,
-        Err(e) => return Err(Box::new(e)),
-    };
+    // Fallback to a full analysis job (one decode, all analyzers)
+    let analysis = crate::audio::processor::analyze_track_internal(file_path)?;
 
-    // Cache the BPM result if caching is enabled
+    // Cache the analysis result if caching is enabled
+    if let Some(cache_dir) = cache_dir
+        && let Err(e) = cache_analysis_result(file_path, cache_dir, &analysis)
+    {
+        log::warn!("Failed to cache analysis result for {}: {}", file_path, e);
+    }
+
+    Ok(analysis.metadata)
+}
+
+/// Returns cached BPM metadata and waveform for a file, or runs the full
+/// single-decode analysis job on a miss and caches both.
+pub fn analyze_complete_with_cache(
+    file_path: &str,
+    cache_dir: Option<&PathBuf>,
+) -> Result<(TrackBasicMetadata, AudioAnalysis), AudioProcessorError> {
     if let Some(cache_dir) = cache_dir {
-        if let Err(e) = cache_bpm_result(file_path, cache_dir, &metadata) {
-            log::warn!("Failed to cache BPM result for {}: {}", file_path, e);
+        match try_complete_cache_lookup(file_path, cache_dir) {
+            Ok(Some(result)) => {
+                log::info!("Waveform cache hit for: {}", file_path);
+                return Ok(result);
+            }
+            Ok(None) => {
+                log::debug!("Waveform cache miss for: {}", file_path);
+            }
+            Err(e) => {
+                log::warn!(
+                    "Waveform cache lookup failed for {}: {}. Proceeding without cache.",
+                    file_path,
+                    e
+                );
+            }
         }
     }
 
-    Ok(metadata)
+    let analysis = crate::audio::processor::analyze_track_internal(file_path)?;
+
+    if let Some(cache_dir) = cache_dir
+        && let Err(e) = cache_analysis_result(file_path, cache_dir, &analysis)
+    {
+        log::warn!("Failed to cache analysis result for {}: {}", file_path, e);
+    }
+
+    Ok((analysis.metadata, analysis.waveform))
 }
 
-fn try_bpm_cache_lookup(
-    file_path: &str,
-    cache_dir: &PathBuf,
-) -> CacheResult<Option<TrackBasicMetadata>> {
-    // Load index
-    let index = index::load_index(cache_dir)?;
+/// Returns a file's cache entry if it is indexed, current and unchanged on disk.
+/// Never hashes or decodes, so it is cheap enough to call for every file in a
+/// library scan; anything that needs a closer look is reported as a miss.
+pub fn peek_cached_entry(file_path: &Path, cache_dir: &Path) -> Option<CachedTrackData> {
+    let entry = index::open_index(cache_dir).ok()?.get(file_path).ok()??;
+    let cached_data = storage::load_cached_data(cache_dir, &entry.content_hash).ok()?;
+    let unchanged = cached_data.is_current()
+        && fingerprint::file_metadata_matches(&file_path.to_string_lossy(), &cached_data.fingerprint);
+    unchanged.then_some(cached_data)
+}
+
+/// Replaces the BPM and first beat of a file's current cache entry, e.g. with
+/// a grid prepared in other DJ software. Returns false if the file has no
+/// current entry.
+pub fn apply_beat_grid(file_path: &Path, cache_dir: &Path, bpm: f32, first_beat_sec: f32) -> CacheResult<bool> {
+    let Some(mut cached_data) = peek_cached_entry(file_path, cache_dir) else {
+        return Ok(false);
+    };
+    cached_data.bpm_analysis.bpm = Some(bpm);
+    cached_data.bpm_analysis.first_beat_sec = Some(first_beat_sec);
+    let hash = cached_data.fingerprint.content_hash.clone();
+    storage::save_cached_data(cache_dir, &hash, &cached_data)?;
+    Ok(true)
+}
+
+/// Brings a file's cache entry up to date with its new size and modification
+/// time after we rewrote only its tags, so the entry stays valid without
+/// hashing the audio again. Only call this for entries that were current
+/// before the write. Returns false if the file has no entry.
+pub fn refresh_after_tag_write(file_path: &Path, cache_dir: &Path) -> CacheResult<bool> {
+    let Some(entry) = index::open_index(cache_dir)?.get(file_path)? else {
+        return Ok(false);
+    };
+    let mut cached_data = storage::load_cached_data(cache_dir, &entry.content_hash)?;
+    fingerprint::refresh_file_metadata(&file_path.to_string_lossy(), &mut cached_data.fingerprint)?;
+    storage::save_cached_data(cache_dir, &entry.content_hash, &cached_data)?;
+    Ok(true)
+}
+
+/// Points the entries of files that moved (same audio, new path) at their
+/// new paths: the index key, pins, and the stored source path, relative path
+/// and file metadata. Returns how many entries moved.
+pub fn relocate_entries(cache_dir: &Path, moves: &HashMap<PathBuf, PathBuf>) -> CacheResult<usize> {
+    if moves.is_empty() {
+        return Ok(0);
+    }
+    let handle = index::open_index(cache_dir)?;
+    let moved: Vec<(PathBuf, String)> = handle.update(|index| {
+        let mut moved = Vec::new();
+        for (from, to) in moves {
+            let Some(entry) = index.entries.remove(from) else {
+                continue;
+            };
+            if index.pinned.remove(from) {
+                index.pinned.insert(to.clone());
+            }
+            moved.push((to.clone(), entry.content_hash.clone()));
+            index.entries.insert(to.clone(), entry);
+        }
+        moved
+    })?;
+
+    let library_root = storage::library_root_for_cache_dir(cache_dir);
+    for (to, hash) in &moved {
+        let mut cached_data = storage::load_cached_data(cache_dir, hash)?;
+        fingerprint::refresh_file_metadata(&to.to_string_lossy(), &mut cached_data.fingerprint)?;
+        cached_data.relative_path = library_root
+            .as_deref()
+            .and_then(|root| storage::relative_to_root(to, root));
+        cached_data.source_path = Some(to.clone());
+        storage::save_cached_data(cache_dir, hash, &cached_data)?;
+    }
+    index::flush_index(cache_dir)?;
+    Ok(moved.len())
+}
 
+/// Re-analyzes every cached track whose entry was produced by an outdated analyzer.
+/// Entries for files that no longer exist are left for `cleanup_cache`.
+pub fn refresh_stale_entries(cache_dir: &PathBuf) -> CacheResult<usize> {
+    let index = index::open_index(cache_dir)?.snapshot()?;
+    let stale_paths = index::stale_entries(&index);
+    log::info!("Refreshing {} stale cache entries", stale_paths.len());
+
+    let refreshed = stale_paths
+        .par_iter()
+        .filter(|path| path.exists())
+        .filter(|path| {
+            let file_path = path.to_string_lossy();
+            match analyze_bpm_with_cache(&file_path, Some(cache_dir)) {
+                Ok(_) => true,
+                Err(e) => {
+                    log::warn!("Failed to refresh stale cache entry for {}: {}", file_path, e);
+                    false
+                }
+            }
+        })
+        .count();
+
+    index::flush_index(cache_dir)?;
+    Ok(refreshed)
+}
+
+/// Looks up and validates the cache entry for a file, returning its hash and data.
+fn try_cache_lookup(
+    file_path: &str,
+    cache_dir: &Path,
+) -> CacheResult<Option<(String, CachedTrackData)>> {
     // Check if we have a cache entry
     let path_buf = PathBuf::from(file_path);
-    if let Some(cached_hash) = index.entries.get(&path_buf) {
-        // Load cached data
-        if let Ok(cached_data) = storage::load_cached_data(cache_dir, cached_hash) {
-            // Validate cache entry
-            if fingerprint::validate_cache_entry(file_path, &cached_data.fingerprint)? {
-                return Ok(Some(cached_data.bpm_analysis));
-            } else {
-                log::debug!("BPM cache entry invalid for: {}", file_path);
+    let index_handle = index::open_index(cache_dir)?;
+    let Some(entry) = index_handle.get(&path_buf)? else {
+        return adopt_moved_entry(file_path, cache_dir);
+    };
+
+    // Load cached data
+    if let Ok(mut cached_data) = storage::load_cached_data(cache_dir, &entry.content_hash) {
+        // Results from an older analyzer are re-analyzed lazily on request
+        if !cached_data.is_current() {
+            log::debug!("Cache entry produced by an outdated analyzer for: {}", file_path);
+            return Ok(None);
+        }
+        // Validate cache entry
+        if fingerprint::validate_cache_entry(file_path, &cached_data.fingerprint)? {
+            // A tag edit changed size/mtime but not the audio; store the new
+            // metadata so the next lookup takes the fast path again
+            if !fingerprint::file_metadata_matches(file_path, &cached_data.fingerprint) {
+                fingerprint::refresh_file_metadata(file_path, &mut cached_data.fingerprint)?;
+                storage::save_cached_data(cache_dir, &entry.content_hash, &cached_data)?;
+                log::debug!("Refreshed cache fingerprint after tag edit for: {}", file_path);
             }
+            index_handle.touch(&path_buf)?;
+            return Ok(Some((entry.content_hash, cached_data)));
+        } else {
+            log::debug!("Cache entry invalid for: {}", file_path);
         }
     }
 
     Ok(None)
 }
 
-fn cache_bpm_result(
+/// Handles a path the index doesn't know. The file may have been moved or
+/// renamed; since the audio content hash is the cache key, an existing entry
+/// for the same audio is adopted under the new path instead of re-analyzing.
+fn adopt_moved_entry(
+    file_path: &str,
+    cache_dir: &Path,
+) -> CacheResult<Option<(String, CachedTrackData)>> {
+    let content_hash = fingerprint::compute_content_hash(Path::new(file_path))?;
+    let mut cached_data = match storage::load_cached_data(cache_dir, &content_hash) {
+        Ok(cached_data) => cached_data,
+        Err(CacheError::EntryNotFound(_)) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if !cached_data.is_current() {
+        return Ok(None);
+    }
+
+    let source_path = PathBuf::from(file_path);
+    let previous_path = cached_data.source_path.replace(source_path.clone());
+    log::info!(
+        "Adopting cache entry for moved file: {:?} -> {}",
+        previous_path,
+        file_path
+    );
+    fingerprint::refresh_file_metadata(file_path, &mut cached_data.fingerprint)?;
+    cached_data.relative_path = relative_path_in_library(cache_dir, &source_path);
+    storage::save_cached_data(cache_dir, &content_hash, &cached_data)?;
+
+    let analyzer_signature = AnalyzerInfo::current().signature();
+    index::open_index(cache_dir)?.update(|index| {
+        // Drop the old mapping only if the old file is really gone (not a duplicate)
+        if let Some(previous_path) = previous_path.filter(|p| *p != source_path && !p.exists()) {
+            index.entries.remove(&previous_path);
+        }
+        index
+            .entries
+            .insert(source_path, IndexEntry::new(content_hash.clone(), analyzer_signature));
+    })?;
+
+    Ok(Some((content_hash, cached_data)))
+}
+
+fn relative_path_in_library(cache_dir: &Path, source_path: &Path) -> Option<String> {
+    storage::library_root_for_cache_dir(cache_dir)
+        .and_then(|root| storage::relative_to_root(source_path, &root))
+}
+
+fn try_bpm_cache_lookup(
+    file_path: &str,
+    cache_dir: &Path,
+) -> CacheResult<Option<TrackBasicMetadata>> {
+    Ok(try_cache_lookup(file_path, cache_dir)?.map(|(_, cached_data)| cached_data.bpm_analysis))
+}
+
+fn try_complete_cache_lookup(
+    file_path: &str,
+    cache_dir: &Path,
+) -> CacheResult<Option<(TrackBasicMetadata, AudioAnalysis)>> {
+    let Some((hash, cached_data)) = try_cache_lookup(file_path, cache_dir)? else {
+        return Ok(None);
+    };
+
+    // Entries written before waveform caching have no side file; treat as a miss
+    match waveform::load_waveform_data(cache_dir, &hash) {
+        Ok(waveform_data) => Ok(Some((cached_data.bpm_analysis, waveform_data))),
+        Err(e) => {
+            log::debug!("No usable cached waveform for {}: {}", file_path, e);
+            Ok(None)
+        }
+    }
+}
+
+fn cache_analysis_result(
     file_path: &str,
     cache_dir: &PathBuf,
-    metadata: &TrackBasicMetadata,
+    analysis: &TrackAnalysis,
 ) -> CacheResult<()> {
-    // Create fingerprint
-    let fingerprint = fingerprint::create_fingerprint(file_path)?;
+    // Create fingerprint from the already-decoded analysis (no second decode)
+    let duration_ms = analysis
+        .metadata
+        .duration_seconds
+        .map(|secs| (secs * 1000.0) as u64)
+        .unwrap_or(0);
+    let fingerprint = fingerprint::create_fingerprint(
+        file_path,
+        analysis.content_hash.clone(),
+        duration_ms,
+        analysis.sample_rate,
+    )?;
 
     // Create cached data
+    let analyzer = AnalyzerInfo::current();
+    let analyzer_signature = analyzer.signature();
+    let source_path = PathBuf::from(file_path);
+    let relative_path = relative_path_in_library(cache_dir, &source_path);
     let cached_data = CachedTrackData {
         fingerprint: fingerprint.clone(),
-        bpm_analysis: metadata.clone(),
+        analyzer: Some(analyzer),
+        source_path: Some(source_path.clone()),
+        relative_path,
+        bpm_analysis: analysis.metadata.clone(),
+        loudness_analysis: analysis.loudness.clone(),
+        key_analysis: analysis.key.clone(),
+        energy: analysis.energy,
         cached_at: SystemTime::now(),
     };
 
-    // Save to cache
+    // Save to cache (waveform first, so a JSON entry always has its side file)
+    waveform::save_waveform_data(cache_dir, &fingerprint.content_hash, &analysis.waveform)?;
     storage::save_cached_data(cache_dir, &fingerprint.content_hash, &cached_data)?;
 
-    // Update index
-    let mut index = index::load_index(cache_dir).unwrap_or_default();
-    index
-        .entries
-        .insert(PathBuf::from(file_path), fingerprint.content_hash);
-    index::save_index(cache_dir, &index)?;
+    // Update index (the index owner serializes concurrent workers and batches writes)
+    index::open_index(cache_dir)?.insert(
+        source_path,
+        IndexEntry::new(fingerprint.content_hash, analyzer_signature),
+    )?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+    use std::io::Write;
+
+    const TEST_SAMPLE_RATE: u32 = 22050;
+
+    /// Writes a mono 16-bit WAV click track at 120 BPM. `seed` shifts the click pitch
+    /// so every file has distinct content and therefore its own cache entry.
+    fn write_click_track(path: &Path, seed: usize) {
+        let num_samples = TEST_SAMPLE_RATE as usize * 6;
+        let beat_interval = TEST_SAMPLE_RATE as usize / 2;
+        let click_len = 1000;
+        let freq = 800.0 + seed as f32 * 10.0;
+
+        let samples: Vec<i16> = (0..num_samples)
+            .map(|i| {
+                let pos = i % beat_interval;
+                if pos < click_len {
+                    let envelope = 1.0 - pos as f32 / click_len as f32;
+                    let phase = 2.0 * std::f32::consts::PI * freq * i as f32 / TEST_SAMPLE_RATE as f32;
+                    (phase.sin() * envelope * 0.8 * i16::MAX as f32) as i16
+                } else {
+                    0
+                }
+            })
+            .collect();
+
+        let data_len = (samples.len() * 2) as u32;
+        let mut bytes = Vec::with_capacity(44 + data_len as usize);
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        bytes.extend_from_slice(&1u16.to_le_bytes()); // mono
+        bytes.extend_from_slice(&TEST_SAMPLE_RATE.to_le_bytes());
+        bytes.extend_from_slice(&(TEST_SAMPLE_RATE * 2).to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_len.to_le_bytes());
+        for sample in samples {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+
+        File::create(path).unwrap().write_all(&bytes).unwrap();
+    }
+
+    #[test]
+    fn parallel_analysis_keeps_every_index_entry() {
+        let root = std::env::temp_dir().join(format!("open-dj-index-test-{}", std::process::id()));
+        let music_dir = root.join("music");
+        let cache_dir = root.join("cache");
+        fs::create_dir_all(&music_dir).unwrap();
+
+        let paths: Vec<String> = (0..48)
+            .map(|i| {
+                let path = music_dir.join(format!("track_{:02}.wav", i));
+                write_click_track(&path, i);
+                path.to_string_lossy().to_string()
+            })
+            .collect();
+
+        let failures = paths
+            .par_iter()
+            .filter(|path| analyze_bpm_with_cache(path, Some(&cache_dir)).is_err())
+            .count();
+        assert_eq!(failures, 0);
+
+        index::flush_index(&cache_dir).unwrap();
+
+        // Both the index owner and the file on disk must hold every entry
+        let handle = index::open_index(&cache_dir).unwrap();
+        assert_eq!(handle.entry_count().unwrap(), paths.len());
+
+        let on_disk: CacheIndex =
+            serde_json::from_reader(File::open(cache_dir.join("index.json")).unwrap()).unwrap();
+        assert_eq!(on_disk.entries.len(), paths.len());
+        for path in &paths {
+            assert!(on_disk.entries.contains_key(&PathBuf::from(path)));
+        }
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn every_spelling_of_a_cache_dir_shares_one_index() {
+        let root = std::env::temp_dir().join(format!("open-dj-index-key-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("real")).unwrap();
+        std::os::unix::fs::symlink(root.join("real"), root.join("link")).unwrap();
+
+        // Opened through a symlink before the directory exists, then directly after
+        let before = index::open_index(&root.join("link").join("cache")).unwrap();
+        fs::create_dir_all(root.join("real").join("cache")).unwrap();
+        let direct = index::open_index(&root.join("real").join("cache")).unwrap();
+        let relative = index::open_index(&root.join("real").join("..").join("link").join("cache")).unwrap();
+        assert!(std::sync::Arc::ptr_eq(&before, &direct));
+        assert!(std::sync::Arc::ptr_eq(&before, &relative));
+
+        index::close_index(&root.join("link").join("cache")).unwrap();
+        let reopened = index::open_index(&root.join("real").join("cache")).unwrap();
+        assert!(!std::sync::Arc::ptr_eq(&before, &reopened));
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn verify_leaves_temp_files_of_running_saves_alone() {
+        let cache_dir = std::env::temp_dir().join(format!("open-dj-stale-temp-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&cache_dir);
+        fs::create_dir_all(&cache_dir).unwrap();
+
+        let fresh = storage::unique_temp_file(&cache_dir, "fresh.json");
+        let stale = storage::unique_temp_file(&cache_dir, "stale.json");
+        fs::write(&fresh, b"{}").unwrap();
+        fs::write(&stale, b"{}").unwrap();
+        let two_hours_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(2 * 60 * 60);
+        File::options().write(true).open(&stale).unwrap().set_modified(two_hours_ago).unwrap();
+
+        verify::verify_cache(&cache_dir, |_| {}).unwrap();
+        assert!(fresh.exists());
+        assert!(!stale.exists());
+
+        index::close_index(&cache_dir).unwrap();
+        let _ = fs::remove_dir_all(&cache_dir);
+    }
+
+    #[test]
+    fn cleanup_keeps_entries_other_copies_still_use() {
+        let root = std::env::temp_dir().join(format!("open-dj-orphan-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let music_dir = root.join("music");
+        let cache_dir = root.join("cache");
+        fs::create_dir_all(&music_dir).unwrap();
+
+        let original = music_dir.join("original.wav");
+        let copy = music_dir.join("copy.wav");
+        write_click_track(&original, 0);
+        fs::copy(&original, &copy).unwrap();
+        analyze_bpm_with_cache(&original.to_string_lossy(), Some(&cache_dir)).unwrap();
+        analyze_bpm_with_cache(&copy.to_string_lossy(), Some(&cache_dir)).unwrap();
+        let hash = fingerprint::compute_content_hash(&original).unwrap();
+
+        // The original is gone, the copy still needs the shared entry
+        index::cleanup_orphaned_cache(&cache_dir, std::slice::from_ref(&copy)).unwrap();
+        assert!(storage::load_cached_data(&cache_dir, &hash).is_ok());
+
+        index::cleanup_orphaned_cache(&cache_dir, &[]).unwrap();
+        assert!(storage::load_cached_data(&cache_dir, &hash).is_err());
+
+        index::close_index(&cache_dir).unwrap();
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn import_skips_entries_with_a_corrupted_waveform() {
+        let root = std::env::temp_dir().join(format!("open-dj-archive-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let music_dir = root.join("music");
+        let export_dir = root.join("export-cache");
+        let import_dir = root.join("import-cache");
+        let archive_path = root.join("library.odjcache");
+        fs::create_dir_all(&music_dir).unwrap();
+
+        let good = music_dir.join("good.wav");
+        let bad = music_dir.join("bad.wav");
+        write_click_track(&good, 0);
+        write_click_track(&bad, 1);
+        analyze_bpm_with_cache(&good.to_string_lossy(), Some(&export_dir)).unwrap();
+        analyze_bpm_with_cache(&bad.to_string_lossy(), Some(&export_dir)).unwrap();
+        let bad_hash = fingerprint::compute_content_hash(&bad).unwrap();
+        fs::write(export_dir.join(format!("{}.wave", bad_hash)), b"not a waveform").unwrap();
+        archive::export_cache(&export_dir, &music_dir, &archive_path).unwrap();
+
+        let report = archive::import_cache(&import_dir, &music_dir, &archive_path).unwrap();
+        assert_eq!(report.imported, 1);
+        assert_eq!(report.skipped, 1);
+        assert!(storage::load_cached_data(&import_dir, &bad_hash).is_err());
+
+        index::close_index(&export_dir).unwrap();
+        index::close_index(&import_dir).unwrap();
+        let _ = fs::remove_dir_all(&root);
+    }
+}
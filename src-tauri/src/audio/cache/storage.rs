@@ -1,20 +1,156 @@
 use std::fs::{self, File};
 use std::io::{BufReader, BufWriter};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+use serde::{Deserialize, Serialize};
 use super::{CachedTrackData, CacheResult, CacheError};
 
-pub fn ensure_cache_directory(music_dir: &Path) -> CacheResult<PathBuf> {
-    let cache_dir = music_dir.join(".open-dj").join("cache").join("metadata");
-    
+const CACHE_DIR_COMPONENTS: [&str; 3] = [".open-dj", "cache", "metadata"];
+const CENTRAL_CACHE_DIR_NAME: &str = "cache";
+const LIBRARY_MARKER_FILE_NAME: &str = "library.json";
+const MIGRATION_MARKER_FILE_NAME: &str = "migrated.json";
+
+/// Where a library's cache lives.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CacheLocation {
+    /// Per-user cache under the app data directory, keyed by the library path.
+    #[default]
+    Central,
+    /// `.open-dj/cache/metadata` inside the music folder itself.
+    InFolder,
+}
+
+/// Records which library a central cache directory belongs to, since that
+/// can't be derived from its path.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LibraryMarker {
+    library_root: PathBuf,
+}
+
+/// Left in a cache directory whose entries were copied into another, so they
+/// are only merged once.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MigrationMarker {
+    migrated_to: PathBuf,
+}
+
+/// `.open-dj/cache/metadata` inside the music folder.
+pub fn in_folder_cache_dir(music_dir: &Path) -> PathBuf {
+    CACHE_DIR_COMPONENTS
+        .iter()
+        .fold(music_dir.to_path_buf(), |dir, component| dir.join(component))
+}
+
+/// `<app data>/cache/<hash of absolute library path>/metadata`.
+pub fn central_cache_dir(app_data_dir: &Path, music_dir: &Path) -> PathBuf {
+    let absolute = fs::canonicalize(music_dir).unwrap_or_else(|_| music_dir.to_path_buf());
+    let key = blake3::hash(absolute.to_string_lossy().as_bytes()).to_hex();
+    app_data_dir
+        .join(CENTRAL_CACHE_DIR_NAME)
+        .join(&key[..16])
+        .join("metadata")
+}
+
+fn create_cache_directory(cache_dir: &Path) -> CacheResult<()> {
     if !cache_dir.exists() {
-        fs::create_dir_all(&cache_dir)
+        fs::create_dir_all(cache_dir)
             .map_err(|e| CacheError::DirectoryCreation(format!("Failed to create cache directory: {}", e)))?;
         log::info!("Created cache directory: {}", cache_dir.display());
     }
-    
+    Ok(())
+}
+
+pub fn ensure_cache_directory(music_dir: &Path) -> CacheResult<PathBuf> {
+    let cache_dir = in_folder_cache_dir(music_dir);
+    create_cache_directory(&cache_dir)?;
+    // In use again, so its entries are worth merging the next time
+    let marker_file = cache_dir.join(MIGRATION_MARKER_FILE_NAME);
+    if marker_file.exists() {
+        fs::remove_file(&marker_file)?;
+    }
+    Ok(cache_dir)
+}
+
+/// Whether `cache_dir`'s entries were already copied into another cache.
+pub fn is_migrated(cache_dir: &Path) -> bool {
+    cache_dir.join(MIGRATION_MARKER_FILE_NAME).exists()
+}
+
+pub fn ensure_central_cache_directory(app_data_dir: &Path, music_dir: &Path) -> CacheResult<PathBuf> {
+    let cache_dir = central_cache_dir(app_data_dir, music_dir);
+    create_cache_directory(&cache_dir)?;
+
+    let marker_file = cache_dir.join(LIBRARY_MARKER_FILE_NAME);
+    if !marker_file.exists() {
+        let marker = LibraryMarker {
+            library_root: fs::canonicalize(music_dir).unwrap_or_else(|_| music_dir.to_path_buf()),
+        };
+        let writer = BufWriter::new(File::create(&marker_file)?);
+        serde_json::to_writer_pretty(writer, &marker)?;
+    }
+
     Ok(cache_dir)
 }
 
+/// Copies every entry (JSON and waveform side file) and index mapping from one
+/// cache directory into another. Entries already present in the destination
+/// are kept. The source is left in place and marked as migrated; nothing is
+/// deleted. Returns the number of entries copied over.
+pub fn migrate_cache_directory(from_dir: &Path, to_dir: &Path) -> CacheResult<usize> {
+    if !from_dir.exists() || from_dir == to_dir {
+        return Ok(0);
+    }
+    create_cache_directory(to_dir)?;
+
+    let mut migrated = 0;
+    for hash in list_cache_files(from_dir)? {
+        for file_name in [format!("{}.json", hash), format!("{}.wave", hash)] {
+            let source = from_dir.join(&file_name);
+            let destination = to_dir.join(&file_name);
+            if source.exists() && !destination.exists() {
+                fs::copy(&source, &destination)?;
+            }
+        }
+        migrated += 1;
+    }
+
+    let source_index = super::index::open_index(from_dir)?.snapshot()?;
+    // Nothing uses the source any more; drop its handle rather than keep it open
+    super::index::close_index(from_dir)?;
+    let destination = super::index::open_index(to_dir)?;
+    destination.update(|index| {
+        for (path, entry) in source_index.entries {
+            index.entries.entry(path).or_insert(entry);
+        }
+        index.pinned.extend(source_index.pinned);
+    })?;
+    destination.flush()?;
+
+    // Best effort: the source may be on a read-only mount, in which case it
+    // is merged again next time, keeping what the destination already has
+    let marker = MigrationMarker {
+        migrated_to: to_dir.to_path_buf(),
+    };
+    let written = File::create(from_dir.join(MIGRATION_MARKER_FILE_NAME))
+        .map_err(CacheError::from)
+        .and_then(|file| Ok(serde_json::to_writer_pretty(BufWriter::new(file), &marker)?));
+    if let Err(e) = written {
+        log::warn!("Migrated cache but could not mark {}: {}", from_dir.display(), e);
+    }
+
+    log::info!(
+        "Migrated {} cache entries from {} to {}",
+        migrated,
+        from_dir.display(),
+        to_dir.display()
+    );
+    Ok(migrated)
+}
+
 pub fn load_cached_data(cache_dir: &Path, hash: &str) -> CacheResult<CachedTrackData> {
     let cache_file = cache_dir.join(format!("{}.json", hash));
     
@@ -31,9 +167,18 @@ pub fn load_cached_data(cache_dir: &Path, hash: &str) -> CacheResult<CachedTrack
     Ok(cached_data)
 }
 
+/// A temp file name next to `file_name` that no other writer in this process
+/// uses, so concurrent saves of one entry can't rename each other's half
+/// written files. Ends in `.tmp` for `cleanup_temp_files`.
+pub(crate) fn unique_temp_file(cache_dir: &Path, file_name: &str) -> PathBuf {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    cache_dir.join(format!("{}.{}-{}.tmp", file_name, std::process::id(), n))
+}
+
 pub fn save_cached_data(cache_dir: &Path, hash: &str, data: &CachedTrackData) -> CacheResult<()> {
     let cache_file = cache_dir.join(format!("{}.json", hash));
-    let temp_file = cache_dir.join(format!("{}.json.tmp", hash));
+    let temp_file = unique_temp_file(cache_dir, &format!("{}.json", hash));
     
     // Ensure cache directory exists
     if !cache_dir.exists() {
@@ -62,6 +207,9 @@ pub fn delete_cached_data(cache_dir: &Path, hash: &str) -> CacheResult<()> {
         log::debug!("Deleted cache file for hash: {}", hash);
     }
     
+    // The waveform side file belongs to the same entry
+    super::waveform::delete_waveform_data(cache_dir, hash)?;
+    
     Ok(())
 }
 
@@ -76,11 +224,12 @@ pub fn list_cache_files(cache_dir: &Path) -> CacheResult<Vec<String>> {
         let entry = entry?;
         let path = entry.path();
         
-        if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
-            if file_name.ends_with(".json") && !file_name.ends_with(".tmp") {
-                let hash = file_name.trim_end_matches(".json");
-                hashes.push(hash.to_string());
-            }
+        // Only `{hash}.json` entries; skips index.json, library.json and temp files
+        if let Some(file_name) = path.file_name().and_then(|n| n.to_str())
+            && let Some(hash) = file_name.strip_suffix(".json")
+            && is_content_hash(hash)
+        {
+            hashes.push(hash.to_string());
         }
     }
     
@@ -112,16 +261,89 @@ pub fn cleanup_temp_files(cache_dir: &Path) -> CacheResult<()> {
         let entry = entry?;
         let path = entry.path();
         
-        if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
-            if file_name.ends_with(".tmp") {
-                if let Err(e) = fs::remove_file(&path) {
-                    log::warn!("Failed to remove temp file {}: {}", path.display(), e);
-                } else {
-                    log::debug!("Cleaned up temp file: {}", path.display());
-                }
+        if let Some(file_name) = path.file_name().and_then(|n| n.to_str())
+            && file_name.ends_with(".tmp")
+        {
+            if let Err(e) = fs::remove_file(&path) {
+                log::warn!("Failed to remove temp file {}: {}", path.display(), e);
+            } else {
+                log::debug!("Cleaned up temp file: {}", path.display());
             }
         }
     }
     
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Like `cleanup_temp_files`, but only removes temp files that haven't been
+/// touched for `older_than`, leaving those of saves still in flight alone.
+pub fn cleanup_stale_temp_files(cache_dir: &Path, older_than: Duration) -> CacheResult<()> {
+    if !cache_dir.exists() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(cache_dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if !path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.ends_with(".tmp")) {
+            continue;
+        }
+        let age = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+        if age.is_some_and(|age| age >= older_than) {
+            if let Err(e) = fs::remove_file(&path) {
+                log::warn!("Failed to remove stale temp file {}: {}", path.display(), e);
+            } else {
+                log::debug!("Cleaned up stale temp file: {}", path.display());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn is_content_hash(name: &str) -> bool {
+    name.len() == 64 && name.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// The music library root a cache directory belongs to, if it can be determined:
+/// the enclosing folder for in-folder caches, or the recorded root for central ones.
+pub fn library_root_for_cache_dir(cache_dir: &Path) -> Option<PathBuf> {
+    in_folder_library_root(cache_dir).or_else(|| {
+        let file = File::open(cache_dir.join(LIBRARY_MARKER_FILE_NAME)).ok()?;
+        let marker: LibraryMarker = serde_json::from_reader(BufReader::new(file)).ok()?;
+        Some(marker.library_root)
+    })
+}
+
+fn in_folder_library_root(cache_dir: &Path) -> Option<PathBuf> {
+    let mut root = cache_dir;
+    for component in CACHE_DIR_COMPONENTS.iter().rev() {
+        if root.file_name().and_then(|n| n.to_str()) != Some(*component) {
+            return None;
+        }
+        root = root.parent()?;
+    }
+    Some(root.to_path_buf())
+}
+
+/// Path of `file_path` relative to `root`, with `/` separators so it is portable.
+pub fn relative_to_root(file_path: &Path, root: &Path) -> Option<String> {
+    let relative = file_path.strip_prefix(root).ok()?;
+    let components: Vec<String> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    Some(components.join("/"))
+}
+
+/// Resolves a `/`-separated root-relative path against a library root.
+pub fn resolve_relative(root: &Path, relative_path: &str) -> PathBuf {
+    relative_path
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .fold(root.to_path_buf(), |path, part| path.join(part))
+}
@@ -0,0 +1,228 @@
+use super::{cache_analysis_result, fingerprint, index, storage, waveform};
+use super::CacheResult;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Allowed difference between the cached and re-decoded duration.
+const DURATION_TOLERANCE_MS: u64 = 5;
+
+/// Temp files untouched for this long belong to saves that will never finish.
+const STALE_TEMP_FILE_AGE: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyReport {
+    pub checked: usize,
+    pub valid: usize,
+    /// Entries re-analyzed or refreshed in place.
+    pub repaired: Vec<String>,
+    /// Entries deleted because they could not be repaired.
+    pub removed: Vec<String>,
+    /// Index entries whose source file is missing (left untouched; the drive may be unmounted).
+    pub missing_sources: Vec<String>,
+    /// Entry and waveform files no index entry or source file refers to.
+    pub orphans_removed: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyProgress {
+    pub checked: usize,
+    pub total: usize,
+    pub path: String,
+}
+
+enum Outcome {
+    Valid,
+    Repaired,
+    Removed,
+    MissingSource,
+}
+
+fn duration_matches(decoded_ms: u64, cached_ms: u64) -> bool {
+    decoded_ms.abs_diff(cached_ms) <= DURATION_TOLERANCE_MS
+}
+
+/// Re-decodes one source file and checks its entry against the real audio.
+/// Any mismatch is repaired by re-analyzing from the decode just done.
+fn verify_entry(cache_dir: &PathBuf, path: &Path, hash: &str) -> Outcome {
+    if !path.exists() {
+        return Outcome::MissingSource;
+    }
+    let file_path = path.to_string_lossy();
+
+    let (samples, sample_rate, content_hash) =
+        match crate::audio::processor::decode_and_hash_for_analysis(&file_path) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                log::warn!("Verify: cannot decode {}: {}", file_path, e);
+                return Outcome::Removed;
+            }
+        };
+    let decoded_ms = if sample_rate > 0.0 {
+        (samples.len() as f64 / sample_rate as f64 * 1000.0) as u64
+    } else {
+        0
+    };
+
+    let cached_data = storage::load_cached_data(cache_dir, hash).ok().filter(|data| {
+        content_hash == hash
+            && data.fingerprint.content_hash == hash
+            && duration_matches(decoded_ms, data.fingerprint.duration_ms)
+            && waveform::load_waveform_data(cache_dir, hash).is_ok()
+    });
+
+    if let Some(mut cached_data) = cached_data {
+        if fingerprint::file_metadata_matches(&file_path, &cached_data.fingerprint) {
+            return Outcome::Valid;
+        }
+        // Audio verified; only the size/mtime snapshot is out of date
+        return match fingerprint::refresh_file_metadata(&file_path, &mut cached_data.fingerprint)
+            .and_then(|()| storage::save_cached_data(cache_dir, hash, &cached_data))
+        {
+            Ok(()) => Outcome::Repaired,
+            Err(e) => {
+                log::warn!("Verify: failed to refresh fingerprint for {}: {}", file_path, e);
+                Outcome::Removed
+            }
+        };
+    }
+
+    log::info!("Verify: cache entry for {} does not match its audio, re-analyzing", file_path);
+    let repaired = crate::audio::processor::analyze_decoded_samples(
+        &file_path,
+        &samples,
+        sample_rate,
+        content_hash,
+    )
+    .map_err(|e| e.to_string())
+    .and_then(|analysis| {
+        cache_analysis_result(&file_path, cache_dir, &analysis).map_err(|e| e.to_string())
+    });
+    match repaired {
+        Ok(()) => Outcome::Repaired,
+        Err(e) => {
+            log::warn!("Verify: failed to re-analyze {}: {}", file_path, e);
+            Outcome::Removed
+        }
+    }
+}
+
+/// Rehashes and re-decodes every cached source file, repairing entries that
+/// don't match their audio and removing those that can't be repaired, then
+/// deletes entry files nothing refers to. `on_progress` is called after each
+/// track.
+pub fn verify_cache(
+    cache_dir: &PathBuf,
+    on_progress: impl Fn(VerifyProgress) + Sync,
+) -> CacheResult<VerifyReport> {
+    storage::cleanup_stale_temp_files(cache_dir, STALE_TEMP_FILE_AGE)?;
+    let handle = index::open_index(cache_dir)?;
+    let snapshot = handle.snapshot()?;
+
+    let total = snapshot.entries.len();
+    let checked = AtomicUsize::new(0);
+    let report = Mutex::new(VerifyReport::default());
+
+    snapshot.entries.par_iter().for_each(|(path, entry)| {
+        let outcome = verify_entry(cache_dir, path, &entry.content_hash);
+        let display_path = path.to_string_lossy().to_string();
+
+        if matches!(outcome, Outcome::Removed)
+            && let Err(e) = handle.update(|index| index.entries.remove(path))
+        {
+            log::warn!("Verify: failed to unindex {}: {}", display_path, e);
+        }
+        if let Ok(mut report) = report.lock() {
+            report.checked += 1;
+            match outcome {
+                Outcome::Valid => report.valid += 1,
+                Outcome::Repaired => report.repaired.push(display_path.clone()),
+                Outcome::Removed => report.removed.push(display_path.clone()),
+                Outcome::MissingSource => report.missing_sources.push(display_path.clone()),
+            }
+        }
+
+        on_progress(VerifyProgress {
+            checked: checked.fetch_add(1, Ordering::Relaxed) + 1,
+            total,
+            path: display_path,
+        });
+    });
+    handle.flush()?;
+
+    let mut report = report
+        .into_inner()
+        .map_err(|e| super::CacheError::LockPoisoned(format!("verify report: {}", e)))?;
+    let unrepairable: HashSet<PathBuf> = report.removed.iter().map(PathBuf::from).collect();
+    report.orphans_removed = remove_orphans(cache_dir, &unrepairable)?;
+
+    log::info!(
+        "Cache verify: {} checked, {} valid, {} repaired, {} removed, {} missing sources, {} orphans removed",
+        report.checked,
+        report.valid,
+        report.repaired.len(),
+        report.removed.len(),
+        report.missing_sources.len(),
+        report.orphans_removed
+    );
+    Ok(report)
+}
+
+/// Deletes entries the index no longer references, plus waveform files without
+/// an entry. An unreferenced entry is kept only while its recorded source
+/// exists, isn't indexed under another entry and didn't just fail verification,
+/// since a lookup of that file will adopt it again.
+fn remove_orphans(cache_dir: &Path, unrepairable: &HashSet<PathBuf>) -> CacheResult<usize> {
+    let snapshot = index::open_index(cache_dir)?.snapshot()?;
+    let referenced: HashSet<&str> = snapshot
+        .entries
+        .values()
+        .map(|entry| entry.content_hash.as_str())
+        .collect();
+    let library_root = storage::library_root_for_cache_dir(cache_dir);
+
+    let mut removed = 0;
+    let entry_hashes: HashSet<String> = storage::list_cache_files(cache_dir)?.into_iter().collect();
+    for hash in &entry_hashes {
+        if referenced.contains(hash.as_str()) {
+            continue;
+        }
+        let adoptable = storage::load_cached_data(cache_dir, hash)
+            .ok()
+            .and_then(|data| index::locate_source(&data, library_root.as_deref()))
+            .is_some_and(|source| {
+                !snapshot.entries.contains_key(&source) && !unrepairable.contains(&source)
+            });
+        if adoptable {
+            continue;
+        }
+        match storage::delete_cached_data(cache_dir, hash) {
+            Ok(()) => removed += 1,
+            Err(e) => log::warn!("Verify: failed to remove orphaned entry {}: {}", hash, e),
+        }
+    }
+
+    for dir_entry in fs::read_dir(cache_dir)? {
+        let path = dir_entry?.path();
+        let is_orphan = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_suffix(".wave"))
+            .is_some_and(|hash| !entry_hashes.contains(hash));
+        if is_orphan {
+            match fs::remove_file(&path) {
+                Ok(()) => removed += 1,
+                Err(e) => log::warn!("Verify: failed to remove orphaned waveform {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    Ok(removed)
+}
@@ -0,0 +1,252 @@
+use super::{storage, CacheError, CacheResult};
+use crate::audio::types::{AudioAnalysis, WaveBin};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+// --- Binary Waveform Side File ---
+//
+// Layout (little endian):
+//   magic "ODJW" | version u16 | max_band_energy f32 | level_count u32
+//   per level: bin_count u32, then bin_count * (low u16, mid u16, high u16)
+//
+// Band energies are quantized to u16 relative to `max_band_energy`, which is
+// all the renderer needs and keeps a typical track around 150 KB.
+
+const WAVEFORM_MAGIC: &[u8; 4] = b"ODJW";
+const WAVEFORM_FORMAT_VERSION: u16 = 1;
+const WAVEFORM_FILE_EXTENSION: &str = "wave";
+/// Magic, version, max_band_energy and level_count.
+const WAVEFORM_HEADER_LEN: u64 = 14;
+const LEVEL_HEADER_LEN: u64 = 4;
+const BIN_LEN: u64 = 6;
+
+fn waveform_file_path(cache_dir: &Path, hash: &str) -> PathBuf {
+    cache_dir.join(format!("{}.{}", hash, WAVEFORM_FILE_EXTENSION))
+}
+
+fn quantize(value: f32, max_energy: f32) -> u16 {
+    ((value / max_energy).clamp(0.0, 1.0) * u16::MAX as f32).round() as u16
+}
+
+fn dequantize(value: u16, max_energy: f32) -> f32 {
+    value as f32 / u16::MAX as f32 * max_energy
+}
+
+fn read_u16(reader: &mut impl Read) -> std::io::Result<u16> {
+    let mut buf = [0u8; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_f32(reader: &mut impl Read) -> std::io::Result<f32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(f32::from_le_bytes(buf))
+}
+
+/// Encodes waveform levels into the compact binary format.
+pub fn encode_waveform(writer: &mut impl Write, analysis: &AudioAnalysis) -> std::io::Result<()> {
+    let max_energy = analysis.max_band_energy.max(f32::EPSILON);
+
+    writer.write_all(WAVEFORM_MAGIC)?;
+    writer.write_all(&WAVEFORM_FORMAT_VERSION.to_le_bytes())?;
+    writer.write_all(&max_energy.to_le_bytes())?;
+    writer.write_all(&(analysis.levels.len() as u32).to_le_bytes())?;
+
+    for level in &analysis.levels {
+        writer.write_all(&(level.len() as u32).to_le_bytes())?;
+        for bin in level {
+            writer.write_all(&quantize(bin.low, max_energy).to_le_bytes())?;
+            writer.write_all(&quantize(bin.mid, max_energy).to_le_bytes())?;
+            writer.write_all(&quantize(bin.high, max_energy).to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Fails unless `count` items of `item_len` bytes fit in what is left of the
+/// data, so a corrupted count can't make us allocate more than the file holds.
+fn take_items(remaining: &mut u64, count: usize, item_len: u64, what: &str) -> CacheResult<()> {
+    let needed = (count as u64).saturating_mul(item_len);
+    if needed > *remaining {
+        return Err(CacheError::EntryCorrupted(format!(
+            "Waveform data claims {} {} but only {} bytes remain",
+            count, what, remaining
+        )));
+    }
+    *remaining -= needed;
+    Ok(())
+}
+
+/// Decodes waveform levels from the compact binary format. `data_len` is the
+/// size of the encoded data; counts that don't fit in it are rejected.
+pub fn decode_waveform(reader: &mut impl Read, data_len: u64) -> CacheResult<AudioAnalysis> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != WAVEFORM_MAGIC {
+        return Err(CacheError::EntryCorrupted(
+            "Waveform file has an invalid header".to_string(),
+        ));
+    }
+
+    let version = read_u16(reader)?;
+    if version != WAVEFORM_FORMAT_VERSION {
+        return Err(CacheError::EntryCorrupted(format!(
+            "Unsupported waveform format version {}",
+            version
+        )));
+    }
+
+    let max_band_energy = read_f32(reader)?;
+    let level_count = read_u32(reader)? as usize;
+    let mut remaining = data_len.saturating_sub(WAVEFORM_HEADER_LEN);
+    // Level headers are taken up front; their bins as each level is read
+    take_items(&mut remaining, level_count, LEVEL_HEADER_LEN, "levels")?;
+    let mut levels = Vec::with_capacity(level_count);
+
+    for _ in 0..level_count {
+        let bin_count = read_u32(reader)? as usize;
+        take_items(&mut remaining, bin_count, BIN_LEN, "bins")?;
+        let mut bins = Vec::with_capacity(bin_count);
+        for _ in 0..bin_count {
+            bins.push(WaveBin {
+                low: dequantize(read_u16(reader)?, max_band_energy),
+                mid: dequantize(read_u16(reader)?, max_band_energy),
+                high: dequantize(read_u16(reader)?, max_band_energy),
+            });
+        }
+        levels.push(bins);
+    }
+
+    Ok(AudioAnalysis {
+        levels,
+        max_band_energy,
+    })
+}
+
+pub fn save_waveform_data(cache_dir: &Path, hash: &str, analysis: &AudioAnalysis) -> CacheResult<()> {
+    let waveform_file = waveform_file_path(cache_dir, hash);
+    let temp_file = storage::unique_temp_file(cache_dir, &format!("{}.{}", hash, WAVEFORM_FILE_EXTENSION));
+
+    // Ensure cache directory exists
+    if !cache_dir.exists() {
+        fs::create_dir_all(cache_dir)?;
+    }
+
+    // Write to temporary file first (atomic operation)
+    {
+        let file = File::create(&temp_file)?;
+        let mut writer = BufWriter::new(file);
+        encode_waveform(&mut writer, analysis)?;
+        writer.flush()?;
+    }
+
+    // Atomic rename
+    fs::rename(&temp_file, &waveform_file)?;
+
+    log::debug!("Cached waveform data for hash: {}", hash);
+    Ok(())
+}
+
+pub fn load_waveform_data(cache_dir: &Path, hash: &str) -> CacheResult<AudioAnalysis> {
+    let waveform_file = waveform_file_path(cache_dir, hash);
+
+    if !waveform_file.exists() {
+        return Err(CacheError::EntryNotFound(hash.to_string()));
+    }
+
+    let file = File::open(&waveform_file)?;
+    let data_len = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+
+    decode_waveform(&mut reader, data_len).map_err(|e| {
+        CacheError::EntryCorrupted(format!(
+            "Failed to decode waveform file {}: {}",
+            waveform_file.display(),
+            e
+        ))
+    })
+}
+
+pub fn delete_waveform_data(cache_dir: &Path, hash: &str) -> CacheResult<()> {
+    let waveform_file = waveform_file_path(cache_dir, hash);
+
+    if waveform_file.exists() {
+        fs::remove_file(&waveform_file)?;
+        log::debug!("Deleted waveform file for hash: {}", hash);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_analysis() -> AudioAnalysis {
+        let level = |len: usize| {
+            (0..len)
+                .map(|i| WaveBin {
+                    low: i as f32,
+                    mid: i as f32 / 2.0,
+                    high: 1.0,
+                })
+                .collect()
+        };
+        AudioAnalysis {
+            levels: vec![level(8), level(4), level(0)],
+            max_band_energy: 7.0,
+        }
+    }
+
+    fn encoded(analysis: &AudioAnalysis) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        encode_waveform(&mut bytes, analysis).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn waveform_round_trips() {
+        let analysis = sample_analysis();
+        let bytes = encoded(&analysis);
+        let decoded = decode_waveform(&mut bytes.as_slice(), bytes.len() as u64).unwrap();
+
+        assert_eq!(decoded.max_band_energy, analysis.max_band_energy);
+        let lens: Vec<usize> = decoded.levels.iter().map(Vec::len).collect();
+        assert_eq!(lens, vec![8, 4, 0]);
+        for (bin, original) in decoded.levels[0].iter().zip(&analysis.levels[0]) {
+            // Quantized to u16 steps of max_band_energy
+            assert!((bin.low - original.low).abs() < 1e-3);
+            assert!((bin.mid - original.mid).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn oversized_counts_are_rejected_before_allocating() {
+        let bytes = encoded(&sample_analysis());
+
+        let mut huge_levels = bytes.clone();
+        huge_levels[10..14].copy_from_slice(&u32::MAX.to_le_bytes());
+        let result = decode_waveform(&mut huge_levels.as_slice(), huge_levels.len() as u64);
+        assert!(matches!(result, Err(CacheError::EntryCorrupted(_))));
+
+        let mut huge_bins = bytes.clone();
+        huge_bins[14..18].copy_from_slice(&u32::MAX.to_le_bytes());
+        let result = decode_waveform(&mut huge_bins.as_slice(), huge_bins.len() as u64);
+        assert!(matches!(result, Err(CacheError::EntryCorrupted(_))));
+    }
+
+    #[test]
+    fn truncated_data_is_an_error() {
+        let bytes = encoded(&sample_analysis());
+        let truncated = &bytes[..bytes.len() - 3];
+        assert!(decode_waveform(&mut &truncated[..], truncated.len() as u64).is_err());
+    }
+}
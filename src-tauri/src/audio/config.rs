@@ -25,6 +25,10 @@ pub const AUDIO_BUFFER_CHAN_SIZE: usize = 64; // Increased buffer size for bette
 pub const DEFAULT_MONO_SAMPLE_CAPACITY: usize = 1024 * 512;
 
 // --- Audio Analysis Performance Constants ---
+/// Version of the analysis algorithms. Bump whenever an analyzer changes in a way
+/// that should invalidate cached results; parameter changes are detected automatically.
+pub const ANALYZER_VERSION: u32 = 2;
+
 /// FFT frame size for BPM analysis - optimized for performance vs accuracy
 pub const BPM_FRAME_SIZE: usize = 1024;
 /// Hop size for BPM analysis - 25% overlap for good time resolution
@@ -37,6 +41,31 @@ pub const WAVEFORM_FRAME_SIZE: usize = 1024;
 /// Hop size for waveform analysis - 50% overlap for smooth waveform
 pub const WAVEFORM_HOP_SIZE: usize = WAVEFORM_FRAME_SIZE / 2;
 
+/// FFT frame size for key analysis - large frames for fine pitch resolution at low frequencies
+pub const KEY_FRAME_SIZE: usize = 8192;
+/// Hop size for key analysis - 50% overlap, key is a whole-track property
+pub const KEY_HOP_SIZE: usize = KEY_FRAME_SIZE / 2;
+/// Downsampling factor for key analysis (harmonic content above ~5 kHz is not needed)
+pub const KEY_DOWNSAMPLE_FACTOR: usize = 4;
+/// Lowest frequency folded into the chromagram
+pub const KEY_MIN_FREQ_HZ: f32 = 55.0;
+/// Highest frequency folded into the chromagram
+pub const KEY_MAX_FREQ_HZ: f32 = 2000.0;
+
+/// Loudness measurement block length (EBU R128 momentary window)
+pub const LOUDNESS_BLOCK_MS: u32 = 400;
+/// Loudness block step - 75% overlap between blocks
+pub const LOUDNESS_STEP_MS: u32 = 100;
+/// Blocks quieter than this are ignored entirely
+pub const LOUDNESS_ABSOLUTE_GATE_LUFS: f32 = -70.0;
+/// Blocks this far below the ungated loudness are ignored
+pub const LOUDNESS_RELATIVE_GATE_LU: f32 = -10.0;
+/// Target loudness used when deriving a ReplayGain value
+pub const REPLAYGAIN_REFERENCE_LUFS: f32 = -18.0;
+
+/// Window length for the energy rating's level and brightness measurements
+pub const ENERGY_WINDOW_MS: u32 = 1000;
+
 // -- Initial Values --
 pub const INITIAL_TRIM_GAIN: f32 = 1.0;
 
@@ -1,28 +1,40 @@
 use crate::audio::config::DEFAULT_MONO_SAMPLE_CAPACITY;
 
 use super::errors::AudioDecodingError;
+use super::types::TrackTags;
 use std::fs::File;
+use std::path::Path;
 use symphonia::core::{
     audio::SampleBuffer,
-    codecs::{CODEC_TYPE_NULL, DecoderOptions},
+    codecs::{CODEC_TYPE_NULL, CodecParameters, DecoderOptions},
     errors::Error as SymphoniaError,
-    formats::FormatOptions,
+    formats::{FormatOptions, FormatReader},
     io::MediaSourceStream,
-    meta::MetadataOptions,
-    probe::Hint,
+    meta::{MetadataOptions, MetadataRevision, StandardTagKey},
+    probe::{Hint, ProbeResult},
 };
 
-/// Decodes an audio file to mono f32 samples.
-pub(crate) fn decode_file_to_mono_samples(
-    path: &str,
-) -> Result<(Vec<f32>, f32), AudioDecodingError> {
+/// File extensions the enabled symphonia formats can open (lowercase).
+pub(crate) const SUPPORTED_EXTENSIONS: &[&str] = &["mp3", "flac", "wav", "wave", "aac"];
+
+/// Whether a path has an extension the decoder supports.
+pub(crate) fn is_supported_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| SUPPORTED_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+fn probe_file(path: &str) -> Result<ProbeResult, AudioDecodingError> {
     let file = File::open(path).map_err(|e| AudioDecodingError::FileOpenError {
         path: path.to_string(),
         source: e,
     })?;
     let mss = MediaSourceStream::new(Box::new(file), Default::default());
-    let hint = Hint::new();
-    let probed = symphonia::default::get_probe()
+    let mut hint = Hint::new();
+    if let Some(ext) = Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(ext);
+    }
+    symphonia::default::get_probe()
         .format(
             &hint,
             mss,
@@ -32,8 +44,60 @@ pub(crate) fn decode_file_to_mono_samples(
         .map_err(|e| AudioDecodingError::FormatError {
             path: path.to_string(),
             source: e,
-        })?;
-    let mut format = probed.format;
+        })
+}
+
+fn apply_tags(tags: &mut TrackTags, revision: &MetadataRevision) {
+    for tag in revision.tags() {
+        let value = tag.value.to_string().trim().to_string();
+        if value.is_empty() {
+            continue;
+        }
+        let slot = match tag.std_key {
+            Some(StandardTagKey::TrackTitle) => &mut tags.title,
+            Some(StandardTagKey::Artist) => &mut tags.artist,
+            Some(StandardTagKey::Album) => &mut tags.album,
+            Some(StandardTagKey::Genre) => &mut tags.genre,
+            Some(StandardTagKey::Label) => &mut tags.label,
+            Some(StandardTagKey::Comment) => &mut tags.comment,
+            Some(StandardTagKey::Date) | Some(StandardTagKey::ReleaseDate) => &mut tags.date,
+            Some(StandardTagKey::Bpm) => {
+                tags.bpm = value.parse::<f32>().ok().filter(|bpm| *bpm > 0.0);
+                continue;
+            }
+            // symphonia has no standard key for the musical key
+            None if matches!(
+                tag.key.to_ascii_uppercase().as_str(),
+                "TKEY" | "INITIALKEY" | "KEY"
+            ) =>
+            {
+                &mut tags.key
+            }
+            _ => continue,
+        };
+        *slot = Some(value);
+    }
+}
+
+/// Reads a file's tags without decoding any audio. In-stream metadata (e.g.
+/// FLAC Vorbis comments) overrides container metadata read during the probe.
+pub(crate) fn read_tags(path: &str) -> Result<TrackTags, AudioDecodingError> {
+    let mut probed = probe_file(path)?;
+    let mut tags = TrackTags::default();
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        apply_tags(&mut tags, revision);
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        apply_tags(&mut tags, revision);
+    }
+    Ok(tags)
+}
+
+/// Opens a file and selects the track that decoding and content hashing both use.
+fn open_default_track(
+    path: &str,
+) -> Result<(Box<dyn FormatReader>, u32, CodecParameters), AudioDecodingError> {
+    let format = probe_file(path)?.format;
     let track = format
         .tracks()
         .iter()
@@ -42,21 +106,83 @@ pub(crate) fn decode_file_to_mono_samples(
             path: path.to_string(),
         })?;
     let track_id = track.id;
+    let codec_params = track.codec_params.clone();
+    Ok((format, track_id, codec_params))
+}
+
+/// Hashes the compressed packet data of the audio track, without decoding it.
+///
+/// Tags (ID3, Vorbis comments, artwork) live outside the packets, so the hash
+/// survives metadata edits while still distinguishing different audio.
+pub(crate) fn hash_audio_payload(path: &str) -> Result<String, AudioDecodingError> {
+    let (mut format, track_id, _) = open_default_track(path)?;
+    let mut hasher = blake3::Hasher::new();
+    let mut packet_count: u64 = 0;
+    loop {
+        match format.next_packet() {
+            Ok(packet) => {
+                if packet.track_id() != track_id {
+                    continue;
+                }
+                hasher.update(packet.buf());
+                packet_count += 1;
+            }
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break;
+            }
+            Err(SymphoniaError::ResetRequired) => {
+                break;
+            }
+            Err(e) => {
+                return Err(AudioDecodingError::PacketReadIoError {
+                    path: path.to_string(),
+                    source: e,
+                });
+            }
+        }
+    }
+    if packet_count == 0 {
+        return Err(AudioDecodingError::NoSamplesDecoded {
+            path: path.to_string(),
+        });
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Decodes an audio file to mono f32 samples.
+pub(crate) fn decode_file_to_mono_samples(
+    path: &str,
+) -> Result<(Vec<f32>, f32), AudioDecodingError> {
+    decode_to_mono_samples_internal(path, None)
+}
+
+/// Decodes an audio file to mono f32 samples and hashes its audio payload in the
+/// same pass. The hash matches `hash_audio_payload` for the same file.
+pub(crate) fn decode_file_to_mono_samples_hashed(
+    path: &str,
+) -> Result<(Vec<f32>, f32, String), AudioDecodingError> {
+    let mut hasher = blake3::Hasher::new();
+    let (samples, sample_rate) = decode_to_mono_samples_internal(path, Some(&mut hasher))?;
+    Ok((samples, sample_rate, hasher.finalize().to_hex().to_string()))
+}
+
+fn decode_to_mono_samples_internal(
+    path: &str,
+    mut payload_hasher: Option<&mut blake3::Hasher>,
+) -> Result<(Vec<f32>, f32), AudioDecodingError> {
+    let (mut format, track_id, codec_params) = open_default_track(path)?;
     let sample_rate =
-        track
-            .codec_params
+        codec_params
             .sample_rate
             .ok_or_else(|| AudioDecodingError::MissingSampleRate {
                 path: path.to_string(),
             })? as f32;
-    let channels = track
-        .codec_params
+    let channels = codec_params
         .channels
         .ok_or_else(|| AudioDecodingError::MissingChannelInfo {
             path: path.to_string(),
         })?
         .count();
-    let codec_params = track.codec_params.clone();
     let mut decoder = symphonia::default::get_codecs()
         .make(&codec_params, &DecoderOptions::default())
         .map_err(|e| AudioDecodingError::DecoderCreationError {
@@ -76,6 +202,9 @@ pub(crate) fn decode_file_to_mono_samples(
                 if packet.track_id() != track_id {
                     continue;
                 }
+                if let Some(hasher) = payload_hasher.as_deref_mut() {
+                    hasher.update(packet.buf());
+                }
                 match decoder.decode(&packet) {
                     Ok(audio_buf) => {
                         if sample_buf.is_none() {
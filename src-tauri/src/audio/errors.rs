@@ -11,6 +11,15 @@ pub enum AudioAnalysisError {
     /// Cannot calculate RMS from empty samples.
     #[error("Cannot calculate RMS from empty samples")]
     EmptySamples,
+    /// Not enough samples for a single analysis frame or block.
+    #[error("Not enough samples for analysis: need {required}, got {actual}")]
+    InsufficientSamples { required: usize, actual: usize },
+    /// The signal contains no usable energy (digital silence or below the gate).
+    #[error("Audio is silent or below the analysis gate")]
+    SilentAudio,
+    /// Failed to set up an analysis filter.
+    #[error("Failed to set up analysis filter: {0}")]
+    FilterSetup(String),
 }
 
 /// Errors that can occur during BPM analysis and detection.
@@ -207,6 +216,24 @@ pub enum AudioProcessorError {
         path: String,
         source: AudioAnalysisError,
     },
+    /// Loudness analysis failed during analysis.
+    #[error("Loudness analysis failed for '{path}': {source}")]
+    AnalysisLoudnessError {
+        path: String,
+        source: AudioAnalysisError,
+    },
+    /// Key analysis failed during analysis.
+    #[error("Key analysis failed for '{path}': {source}")]
+    AnalysisKeyError {
+        path: String,
+        source: AudioAnalysisError,
+    },
+    /// Energy analysis failed during analysis.
+    #[error("Energy analysis failed for '{path}': {source}")]
+    AnalysisEnergyError {
+        path: String,
+        source: AudioAnalysisError,
+    },
     /// Invalid data for duration calculation.
     #[error(
         "Invalid data (empty samples or zero sample rate) for duration calculation for '{path}'."
@@ -89,8 +89,8 @@ pub fn run_audio_thread<R: Runtime>(app_handle: AppHandle<R>, mut receiver: mpsc
                             AudioThreadCommand::InitDeck(deck_id) => {
                                 handlers::audio_thread_handle_init(&deck_id, &mut local_deck_states, &app_handle)
                             }
-                            AudioThreadCommand::LoadTrack { deck_id, path, original_bpm, first_beat_sec, output_device_name } => {
-                                handlers::audio_thread_handle_load(deck_id, path, original_bpm, first_beat_sec, output_device_name, &mut local_deck_states, &cpal_device, &app_handle).await
+                            AudioThreadCommand::LoadTrack { deck_id, path, original_bpm, first_beat_sec, playable_range, output_device_name } => {
+                                handlers::audio_thread_handle_load(deck_id, path, original_bpm, first_beat_sec, playable_range, output_device_name, &mut local_deck_states, &cpal_device, &app_handle).await
                             }
                             AudioThreadCommand::Play(deck_id) => {
                                 handlers::audio_thread_handle_play(&deck_id, &mut local_deck_states, &app_handle)
@@ -102,7 +102,7 @@ pub fn run_audio_thread<R: Runtime>(app_handle: AppHandle<R>, mut receiver: mpsc
                                 handlers::audio_thread_handle_seek(&deck_id, position_seconds, &mut local_deck_states, &app_handle)
                             }
                             AudioThreadCommand::SetFaderLevel { deck_id, level } => {
-                                handlers::audio_thread_handle_set_fader_level(&deck_id, level, &mut local_deck_states)
+                                handlers::audio_thread_handle_set_fader_level(&deck_id, level, &mut local_deck_states, &app_handle)
                             }
                             AudioThreadCommand::SetTrimGain { deck_id, gain } => {
                                 handlers::audio_thread_handle_set_trim_gain(&deck_id, gain, &mut local_deck_states)
@@ -13,6 +13,8 @@ pub enum AudioThreadCommand {
         path: String,
         original_bpm: Option<f32>,
         first_beat_sec: Option<f32>,
+        /// Start and optional end in seconds, for CUE sheet tracks.
+        playable_range: Option<(f64, Option<f64>)>,
         output_device_name: Option<String>,
     },
     Play(String),
@@ -76,16 +78,25 @@ pub async fn load_track(
     path: String,
     original_bpm: Option<f32>,
     first_beat_sec: Option<f32>,
+    start_seconds: Option<f64>,
+    end_seconds: Option<f64>,
     app_state: State<'_, AppState>,
     _device_store: State<'_, AudioDeviceStore>,
 ) -> Result<(), String> {
     log::info!(
-        "CMD: Load track '{}' for deck: {}. BPM: {:?}, First Beat: {:?}",
+        "CMD: Load track '{}' for deck: {}. BPM: {:?}, First Beat: {:?}, Range: {:?}-{:?}",
         path,
         deck_id,
         original_bpm,
-        first_beat_sec
+        first_beat_sec,
+        start_seconds,
+        end_seconds
     );
+    // A CUE sheet track: the range of the parent file to play
+    let playable_range = match (start_seconds, end_seconds) {
+        (None, None) => None,
+        (start, end) => Some((start.unwrap_or(0.0), end)),
+    };
 
     // Master output always uses the default device
     let output_device_name = if deck_id == "A" || deck_id == "B" {
@@ -103,6 +114,7 @@ pub async fn load_track(
             path,
             original_bpm,
             first_beat_sec,
+            playable_range,
             output_device_name,
         })
         .await
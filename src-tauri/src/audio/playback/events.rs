@@ -46,6 +46,8 @@ pub struct PlaybackLoadEventPayload {
     pub deck_id: String,
     pub duration: f64,
     pub cue_point_seconds: Option<f64>,
+    /// Start and end in seconds of a loaded CUE sheet track.
+    pub playable_range: Option<(f64, f64)>,
     pub original_bpm: Option<f32>,
     pub first_beat_sec: Option<f32>,
 }
@@ -133,6 +135,7 @@ pub(crate) fn emit_load_update_event<R: Runtime>(
     deck_id: &str,
     duration: f64,
     cue_point_seconds: Option<f64>,
+    playable_range: Option<(f64, f64)>,
     original_bpm: Option<f32>,
     first_beat_sec: Option<f32>,
 ) {
@@ -140,6 +143,7 @@ pub(crate) fn emit_load_update_event<R: Runtime>(
         deck_id: deck_id.to_string(),
         duration,
         cue_point_seconds,
+        playable_range,
         original_bpm,
         first_beat_sec,
     };
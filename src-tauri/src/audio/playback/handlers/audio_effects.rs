@@ -1,9 +1,10 @@
 use super::*;
 
-pub(crate) fn audio_thread_handle_set_fader_level(
+pub(crate) fn audio_thread_handle_set_fader_level<R: Runtime>(
     deck_id: &str,
     level: f32,
     local_states: &mut HashMap<String, AudioThreadDeckState>,
+    app_handle: &AppHandle<R>,
 ) -> Result<(), PlaybackError> {
     let state = local_states
         .get_mut(deck_id)
@@ -17,6 +18,7 @@ pub(crate) fn audio_thread_handle_set_fader_level(
         deck_id,
         clamped_level
     );
+    history_deck_activity(deck_id, state, app_handle);
     Ok(())
 }
 
@@ -80,8 +82,10 @@ pub(crate) fn audio_thread_handle_set_cue<R: Runtime>(
         );
         return Ok(());
     }
-    let cue_duration =
-        Duration::from_secs_f64(position_seconds.max(0.0).min(state.duration.as_secs_f64()));
+    let (range_start, range_end) = state
+        .playable_range
+        .unwrap_or((0.0, state.duration.as_secs_f64()));
+    let cue_duration = Duration::from_secs_f64(position_seconds.max(range_start).min(range_end));
     state.cue_point = Some(cue_duration);
     log::info!(
         "Audio Thread: Set cue point for deck '{}' to {:.2}s",
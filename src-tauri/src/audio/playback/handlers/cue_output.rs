@@ -127,6 +127,7 @@ impl CueOutputManager {
             
             self.is_active.store(true, Ordering::Relaxed);
             
+            #[cfg(target_os = "macos")]
             if let Some(ref mut audio_unit) = self.audio_unit {
                 audio_unit.start().map_err(|e| {
                     PlaybackError::OutputStreamInitError(format!("Failed to start CoreAudio unit: {}", e))
@@ -0,0 +1,149 @@
+use super::*;
+use crate::library::history::{DeckActivity, HistoryStore};
+use crate::library::store::LibraryStore;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use tauri::Manager;
+
+/// What the audio thread tells the play history, in the order it happened.
+enum HistoryReport {
+    TrackLoaded {
+        deck_id: String,
+        path: PathBuf,
+        range_start: Option<f64>,
+        duration: f64,
+    },
+    DeckActivity {
+        deck_id: String,
+        activity: DeckActivity,
+    },
+}
+
+/// Carries history reports from the audio thread to a worker thread, which
+/// updates the history, counts plays in the library and refreshes smart
+/// playlists, so none of that holds up audio commands.
+pub(crate) struct HistoryReporter {
+    /// `None` if the worker couldn't be started; reports are then dropped.
+    report_tx: Option<Sender<HistoryReport>>,
+}
+
+impl HistoryReporter {
+    pub(crate) fn spawn<R: Runtime>(app_handle: AppHandle<R>) -> Self {
+        let (report_tx, report_rx) = mpsc::channel::<HistoryReport>();
+        let spawned = std::thread::Builder::new()
+            .name("history-reporter".to_string())
+            .spawn(move || {
+                for report in report_rx {
+                    handle_report(report, &app_handle);
+                }
+            });
+        match spawned {
+            Ok(_) => HistoryReporter {
+                report_tx: Some(report_tx),
+            },
+            Err(e) => {
+                log::error!("Failed to start history reporter thread, plays will not be logged: {}", e);
+                HistoryReporter { report_tx: None }
+            }
+        }
+    }
+
+    /// Queues a report for the worker. Never blocks on the history.
+    fn send(&self, report: HistoryReport) {
+        if let Some(report_tx) = &self.report_tx {
+            // Only fails once the worker is gone
+            let _ = report_tx.send(report);
+        }
+    }
+}
+
+fn handle_report<R: Runtime>(report: HistoryReport, app_handle: &AppHandle<R>) {
+    let Some(history) = app_handle.try_state::<HistoryStore>() else {
+        return;
+    };
+    match report {
+        HistoryReport::TrackLoaded {
+            deck_id,
+            path,
+            range_start,
+            duration,
+        } => {
+            if let Err(e) = history.track_loaded(&deck_id, &path, range_start, duration) {
+                log::warn!("History: Failed to log load on deck '{}': {}", deck_id, e);
+            }
+        }
+        HistoryReport::DeckActivity { deck_id, activity } => {
+            let played = match history.deck_activity(&deck_id, activity) {
+                Ok(played) => played,
+                Err(e) => {
+                    log::warn!("History: Failed to log deck '{}': {}", deck_id, e);
+                    return;
+                }
+            };
+            if let Some(path) = played {
+                count_play(&path, app_handle);
+            }
+        }
+    }
+}
+
+/// Counts a play in the library and moves smart playlists along with it.
+fn count_play<R: Runtime>(path: &std::path::Path, app_handle: &AppHandle<R>) {
+    let Some(library) = app_handle.try_state::<LibraryStore>() else {
+        return;
+    };
+    match library.record_play(path) {
+        Ok(track) => {
+            crate::library::commands::refresh_smart_playlists_after_change(app_handle, Some(&track));
+        }
+        Err(crate::library::LibraryError::TrackNotFound(_)) => {}
+        Err(e) => log::warn!("History: Failed to count play of {}: {}", path.display(), e),
+    }
+}
+
+/// Tells the play history about a newly loaded track.
+pub(crate) fn history_track_loaded<R: Runtime>(
+    deck_id: &str,
+    path: &str,
+    state: &mut AudioThreadDeckState,
+    app_handle: &AppHandle<R>,
+) {
+    state.history_reported_playing = false;
+    let Some(reporter) = app_handle.try_state::<HistoryReporter>() else {
+        return;
+    };
+    let (range_start, duration) = match state.playable_range {
+        Some((start, end)) => (Some(start), end - start),
+        None => (None, state.duration.as_secs_f64()),
+    };
+    reporter.send(HistoryReport::TrackLoaded {
+        deck_id: deck_id.to_string(),
+        path: PathBuf::from(path),
+        range_start,
+        duration,
+    });
+}
+
+/// Reports a deck's play, fader and pitch state to the play history. A
+/// track reaching the master for the first time also counts as a play in
+/// the library.
+pub(crate) fn history_deck_activity<R: Runtime>(
+    deck_id: &str,
+    state: &mut AudioThreadDeckState,
+    app_handle: &AppHandle<R>,
+) {
+    let playing = state.is_playing.load(Ordering::Relaxed);
+    state.history_reported_playing = playing;
+    let Some(reporter) = app_handle.try_state::<HistoryReporter>() else {
+        return;
+    };
+    let activity = DeckActivity {
+        playing,
+        fader_level: state.channel_fader_level.load(Ordering::Relaxed),
+        pitch_rate: state.target_pitch_rate.load(Ordering::Relaxed),
+    };
+    reporter.send(HistoryReport::DeckActivity {
+        deck_id: deck_id.to_string(),
+        activity,
+    });
+}
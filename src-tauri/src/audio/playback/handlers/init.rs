@@ -58,6 +58,7 @@ pub(crate) fn audio_thread_handle_init<R: Runtime>(
         current_trim_gain: Arc::new(AtomicF32::new(initial_linear_trim_gain)),
         target_trim_gain: Arc::new(AtomicF32::new(initial_linear_trim_gain)),
         cue_point: None,
+        playable_range: None,
         current_pitch_rate: Arc::new(AtomicF32::new(initial_pitch_val)),
         target_pitch_rate: Arc::new(AtomicF32::new(initial_pitch_val)),
         last_ui_pitch_rate: Some(1.0),
@@ -81,13 +82,14 @@ pub(crate) fn audio_thread_handle_init<R: Runtime>(
         read_head_at_last_playback_instant: Arc::new(Mutex::new(None)),
         seek_fade_state: Arc::new(Mutex::new(None)),
         channel_fader_level: Arc::new(AtomicF32::new(1.0f32)),
+        history_reported_playing: false,
         last_pitch_event_time: Arc::new(Mutex::new(None)),
         last_emit_frame: Arc::new(AtomicU64::new(0u64)),
     };
     local_states.insert(deck_id.to_string(), deck_state);
     log::info!("Audio Thread: Initialized deck '{}' for CPAL", deck_id);
 
-    emit_load_update_event(app_handle, deck_id, 0.0, None, None, None);
+    emit_load_update_event(app_handle, deck_id, 0.0, None, None, None, None);
     emit_status_update_event(app_handle, deck_id, false);
     emit_sync_status_update_event(app_handle, deck_id, false, false);
     emit_pitch_tick_event(app_handle, deck_id, 1.0);
@@ -3,12 +3,14 @@ pub mod track;
 pub mod playback;
 pub mod audio_effects;
 pub mod cue_output;
+pub mod history;
 
 pub(crate) use init::*;
 pub(crate) use track::*;
 pub(crate) use playback::*;
 pub(crate) use audio_effects::*;
 pub(crate) use cue_output::*;
+pub(crate) use history::*;
 
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
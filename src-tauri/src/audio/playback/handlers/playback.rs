@@ -64,6 +64,9 @@ pub(crate) fn audio_thread_handle_play<R: Runtime>(
         }
     }
     
+    if let Some(state) = local_states.get_mut(deck_id) {
+        history_deck_activity(deck_id, state, app_handle);
+    }
     emit_status_update_event(app_handle, deck_id, true);
     Ok(())
 }
@@ -100,6 +103,7 @@ pub(crate) fn audio_thread_handle_pause<R: Runtime>(
         deck_id,
         current_idx
     );
+    history_deck_activity(deck_id, state, app_handle);
     
     // If this is deck B, stop cue output
     if deck_id == "B" {
@@ -161,6 +165,14 @@ pub(crate) fn audio_thread_handle_seek<R: Runtime>(
     } else {
         target_sample_index = target_sample_index.max(0);
     }
+    // Loaded CUE sheet tracks can't be left by seeking
+    if let Some((start, end)) = state.playable_range {
+        // Both bounds stay inside the file, even for a range starting at its end
+        let last_sample = total_samples - 1;
+        let start_sample = ((start * sample_rate_f64).round() as usize).min(last_sample);
+        let end_sample = ((end * sample_rate_f64).round() as usize).clamp(start_sample, last_sample);
+        target_sample_index = target_sample_index.clamp(start_sample, end_sample);
+    }
     state.current_sample_read_head.store(target_sample_index as f64, Ordering::Relaxed);
     *state.seek_fade_state.lock().map_err(|_| {
         PlaybackError::LogicalStateLockError(format!(
@@ -5,6 +5,7 @@ pub(crate) async fn audio_thread_handle_load<R: Runtime>(
     path: String,
     original_bpm: Option<f32>,
     first_beat_sec: Option<f32>,
+    playable_range: Option<(f64, Option<f64>)>,
     output_device_name: Option<String>,
     local_states: &mut HashMap<String, AudioThreadDeckState>,
     cpal_device: &Device,
@@ -235,6 +236,15 @@ pub(crate) async fn audio_thread_handle_load<R: Runtime>(
             let app_handle_clone_for_callback = app_handle.clone();
             let deck_id_clone_for_callback = deck_id.clone();
             let track_total_samples = samples_arc.len();
+            // A CUE sheet track plays from its start to where the next one begins
+            let playable_range = playable_range.map(|(start, end)| {
+                let total_seconds = duration_val.as_secs_f64();
+                let start = start.clamp(0.0, total_seconds);
+                (start, end.unwrap_or(total_seconds).clamp(start, total_seconds))
+            });
+            let playable_end_sample = playable_range.map_or(track_total_samples, |(_, end)| {
+                ((end * rate as f64) as usize).min(track_total_samples)
+            });
             let stream_output_channels = cpal_channels;
 
             let last_eq_params_mut = deck_state.last_eq_params.clone();
@@ -452,7 +462,7 @@ pub(crate) async fn audio_thread_handle_load<R: Runtime>(
                     let read_head_floor = current_read_head.floor();
                     let idx_floor = read_head_floor as usize;
 
-                    if idx_floor >= track_total_samples.saturating_sub(3) {
+                    if idx_floor >= playable_end_sample.saturating_sub(3) {
                         if is_playing {
                             is_playing_arc.store(false, Ordering::Relaxed);
                             log::info!(
@@ -569,6 +579,7 @@ pub(crate) async fn audio_thread_handle_load<R: Runtime>(
             deck_state.output_sample_rate = Some(stream_config.sample_rate.0);
             deck_state.duration = duration_val;
             deck_state.cue_point = None;
+            deck_state.playable_range = playable_range;
             deck_state.original_bpm = original_bpm;
             deck_state.first_beat_sec = first_beat_sec;
 
@@ -598,6 +609,16 @@ pub(crate) async fn audio_thread_handle_load<R: Runtime>(
             deck_state.target_pitch_rate_for_bpm_match = 1.0;
             deck_state.pll_integral_error = 0.0;
 
+            // Start a CUE sheet track at its beginning, with the cue point there
+            let range_start = playable_range.map(|(start, _)| start);
+            if let Some(start) = range_start {
+                audio_thread_handle_seek(&deck_id, start, local_states, app_handle)?;
+                audio_thread_handle_set_cue(&deck_id, start, local_states, app_handle)?;
+            }
+            if let Some(deck_state) = local_states.get_mut(&deck_id) {
+                history_track_loaded(&deck_id, &path, deck_state, app_handle);
+            }
+
             log::info!(
                 "Audio Thread: Track '{}' loaded and CPAL stream built for deck '{}' with config: {:?}, {} channels, {} Hz",
                 path,
@@ -610,7 +631,8 @@ pub(crate) async fn audio_thread_handle_load<R: Runtime>(
                 app_handle,
                 &deck_id,
                 duration_val.as_secs_f64(),
-                None,
+                range_start,
+                playable_range,
                 original_bpm,
                 first_beat_sec,
             );
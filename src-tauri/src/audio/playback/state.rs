@@ -97,6 +97,9 @@ pub(crate) struct AudioThreadDeckState {
     pub(crate) target_trim_gain: Arc<AtomicF32>,
     /// Optional cue point for the deck.
     pub(crate) cue_point: Option<Duration>,
+    /// Start and end in seconds when a CUE sheet track is loaded; playback,
+    /// seeks and cue points stay inside it.
+    pub(crate) playable_range: Option<(f64, f64)>,
     /// Current pitch rate (smoothed).
     pub(crate) current_pitch_rate: Arc<AtomicF32>,
     /// Target pitch rate (for smoothing).
@@ -135,6 +138,9 @@ pub(crate) struct AudioThreadDeckState {
     pub pll_integral_error: f32,
     /// Channel fader level (0.0 to 1.0), controlled by individual deck faders.
     pub(crate) channel_fader_level: Arc<AtomicF32>,
+    /// Play state last reported to the play history, to catch the deck
+    /// stopping by itself at the end of the track.
+    pub(crate) history_reported_playing: bool,
     // --- Precise Timing Fields (Phase 5) ---
     /// Output sample rate of the audio device (set on stream creation).
     pub(crate) output_sample_rate: Option<u32>,
@@ -1,4 +1,5 @@
 use super::events::emit_status_update_event;
+use super::handlers::history_deck_activity;
 use super::state::AudioThreadDeckState;
 use super::sync;
 use crate::audio::config;
@@ -48,6 +49,13 @@ pub(crate) fn process_time_slice_updates<R: Runtime>(
             pll_times.insert(deck_id.clone(), (current_time, track_ended));
         }
     }
+    // Decks that stopped by themselves at the end of the track leave the master
+    for (deck_id, deck_state) in local_states.iter_mut() {
+        if deck_state.history_reported_playing && !deck_state.is_playing.load(Ordering::Relaxed) {
+            history_deck_activity(deck_id, deck_state, app_handle);
+        }
+    }
+
     // Process PLL sync corrections
     let pitch_corrections = sync::calculate_pll_pitch_updates(local_states, &pll_times)?;
 
@@ -1,8 +1,8 @@
-use crate::audio::types::TrackBasicMetadata;
+use crate::audio::types::{AudioAnalysis, TrackAnalysis, TrackBasicMetadata};
 use crate::audio::errors::AudioProcessorError;
 use rayon::prelude::*;
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{LazyLock, RwLock};
 
 // --- New Struct for Basic Metadata ---
 
@@ -29,104 +29,186 @@ fn log_and_convert_to_option<T, E: std::fmt::Display>(
     }
 }
 
-/// Optimized function that decodes once and calculates all metadata
-fn get_track_metadata_and_samples_internal(
-    path: &str,
-) -> Result<(TrackBasicMetadata, Arc<Vec<f32>>, f32), AudioProcessorError> {
-    log::info!(
-        "Metadata Intern: Starting optimized metadata analysis for: {}",
-        path
-    );
-    
-    // Decode once and reuse for all analysis
-    let (samples, sample_rate) = crate::audio::decoding::decode_file_to_mono_samples(path)
-        .map_err(|e| AudioProcessorError::AnalysisDecodingError {
+type AnalysisListener = Box<dyn Fn(&str, &TrackAnalysis) + Send + Sync>;
+
+/// Callbacks run after every successful track analysis.
+static ANALYSIS_LISTENERS: LazyLock<RwLock<Vec<AnalysisListener>>> =
+    LazyLock::new(|| RwLock::new(Vec::new()));
+
+/// Registers a callback that receives every fresh analysis result, whichever
+/// command or background job produced it (e.g. to update the library database).
+pub fn add_analysis_listener(listener: impl Fn(&str, &TrackAnalysis) + Send + Sync + 'static) {
+    match ANALYSIS_LISTENERS.write() {
+        Ok(mut listeners) => listeners.push(Box::new(listener)),
+        Err(e) => log::error!("Failed to register analysis listener: {}", e),
+    }
+}
+
+fn notify_analysis_listeners(path: &str, analysis: &TrackAnalysis) {
+    if let Ok(listeners) = ANALYSIS_LISTENERS.read() {
+        for listener in listeners.iter() {
+            listener(path, analysis);
+        }
+    }
+}
+
+/// Decodes a file to mono samples. Each analysis job calls this exactly once.
+fn decode_for_analysis(path: &str) -> Result<(Vec<f32>, f32), AudioProcessorError> {
+    crate::audio::decoding::decode_file_to_mono_samples(path).map_err(|e| {
+        AudioProcessorError::AnalysisDecodingError {
             path: path.to_string(),
             source: e,
-        })?;
-    
-    let samples_arc = Arc::new(samples);
-    
-    let duration_result = if sample_rate > 0.0 && !samples_arc.is_empty() {
-        Ok(samples_arc.len() as f64 / sample_rate as f64)
+        }
+    })
+}
+
+/// Like `decode_for_analysis`, also hashing the audio payload for the cache key.
+pub(crate) fn decode_and_hash_for_analysis(path: &str) -> Result<(Vec<f32>, f32, String), AudioProcessorError> {
+    crate::audio::decoding::decode_file_to_mono_samples_hashed(path).map_err(|e| {
+        AudioProcessorError::AnalysisDecodingError {
+            path: path.to_string(),
+            source: e,
+        }
+    })
+}
+
+/// Runs every analyzer (BPM, waveform, loudness, key) over one decoded buffer.
+///
+/// BPM and waveform failures fail the job; loudness and key are best-effort and
+/// stored as `None` when they cannot be computed.
+pub(crate) fn analyze_decoded_samples(
+    path: &str,
+    samples: &[f32],
+    sample_rate: f32,
+    content_hash: String,
+) -> Result<TrackAnalysis, AudioProcessorError> {
+    let duration_result = if sample_rate > 0.0 && !samples.is_empty() {
+        Ok(samples.len() as f64 / sample_rate as f64)
     } else {
         log::warn!(
-            "Metadata Intern: Cannot calculate duration for '{}' due to zero sample rate or empty samples.",
+            "Analysis Intern: Cannot calculate duration for '{}' due to zero sample rate or empty samples.",
             path
         );
         Err(AudioProcessorError::InvalidDataForDurationCalculation {
             path: path.to_string(),
         })
     };
-    
-    let (bpm, first_beat_sec) = crate::audio::analysis::bpm_analyzer::analyze_bpm(&samples_arc, sample_rate)
-        .map_err(|e| AudioProcessorError::AnalysisBpmError {
+
+    // The analyzers are independent, so run them side by side on the shared buffer
+    let (bpm_result, (waveform_result, (loudness_result, (key_result, energy_result)))) = rayon::join(
+        || crate::audio::analysis::bpm_analyzer::analyze_bpm(samples, sample_rate),
+        || {
+            rayon::join(
+                || {
+                    crate::audio::analysis::volume_analyzer::calculate_rms_intervals(
+                        samples,
+                        sample_rate,
+                    )
+                },
+                || {
+                    rayon::join(
+                        || {
+                            crate::audio::analysis::loudness_analyzer::analyze_loudness(
+                                samples,
+                                sample_rate,
+                            )
+                        },
+                        || {
+                            rayon::join(
+                                || crate::audio::analysis::key_analyzer::analyze_key(samples, sample_rate),
+                                || {
+                                    crate::audio::analysis::energy_analyzer::analyze_energy(
+                                        samples,
+                                        sample_rate,
+                                    )
+                                },
+                            )
+                        },
+                    )
+                },
+            )
+        },
+    );
+
+    let (bpm, first_beat_sec) = bpm_result.map_err(|e| AudioProcessorError::AnalysisBpmError {
+        path: path.to_string(),
+        source: e,
+    })?;
+    let waveform = waveform_result
+        .map_err(|e| AudioProcessorError::AnalysisVolumeError {
             path: path.to_string(),
             source: e,
+        })
+        .map(|(levels, max_band_energy)| AudioAnalysis {
+            levels,
+            max_band_energy,
         })?;
-    
-    let final_duration = log_and_convert_to_option(duration_result, path, "Duration");
-    let final_bpm = Some(bpm);
-    let final_first_beat_sec = Some(first_beat_sec);
-    
+    let loudness = log_and_convert_to_option(
+        loudness_result.map_err(|e| AudioProcessorError::AnalysisLoudnessError {
+            path: path.to_string(),
+            source: e,
+        }),
+        path,
+        "Loudness",
+    );
+    let key = log_and_convert_to_option(
+        key_result.map_err(|e| AudioProcessorError::AnalysisKeyError {
+            path: path.to_string(),
+            source: e,
+        }),
+        path,
+        "Key",
+    );
+    let energy = log_and_convert_to_option(
+        energy_result.map_err(|e| AudioProcessorError::AnalysisEnergyError {
+            path: path.to_string(),
+            source: e,
+        }),
+        path,
+        "Energy",
+    );
+
     let metadata = TrackBasicMetadata {
-        duration_seconds: final_duration,
-        bpm: final_bpm,
-        first_beat_sec: final_first_beat_sec,
+        duration_seconds: log_and_convert_to_option(duration_result, path, "Duration"),
+        bpm: Some(bpm),
+        first_beat_sec: Some(first_beat_sec),
+    };
+
+    let analysis = TrackAnalysis {
+        metadata,
+        waveform,
+        loudness,
+        key,
+        energy,
+        sample_rate: sample_rate as u32,
+        content_hash,
     };
-    
-    Ok((metadata, samples_arc, sample_rate))
+    notify_analysis_listeners(path, &analysis);
+    Ok(analysis)
 }
 
-/// Decodes audio and calculates basic metadata (duration, BPM).
-pub fn get_track_basic_metadata_internal(
-    path: &str,
-) -> Result<TrackBasicMetadata, AudioProcessorError> {
-    let (metadata, _, _) = get_track_metadata_and_samples_internal(path)?;
-    Ok(metadata)
+/// Decodes a track once and runs the full analysis pipeline on it.
+pub fn analyze_track_internal(path: &str) -> Result<TrackAnalysis, AudioProcessorError> {
+    log::info!("Analysis Intern: Starting single-decode analysis for: {}", path);
+    let (samples, sample_rate, content_hash) = decode_and_hash_for_analysis(path)?;
+    analyze_decoded_samples(path, &samples, sample_rate, content_hash)
 }
 
 /// Decodes audio and calculates full volume analysis (WaveBin levels).
-fn get_track_volume_analysis_internal(
-    path: &str,
-) -> Result<crate::audio::types::AudioAnalysis, AudioProcessorError> {
+fn get_track_volume_analysis_internal(path: &str) -> Result<AudioAnalysis, AudioProcessorError> {
     log::info!("Volume Intern: Starting volume analysis for: {}", path);
-    let (samples, sample_rate) = crate::audio::decoding::decode_file_to_mono_samples(path)
-        .map_err(|e| AudioProcessorError::AnalysisDecodingError {
-            path: path.to_string(),
-            source: e,
-        })?;
+    let (samples, sample_rate) = decode_for_analysis(path)?;
     crate::audio::analysis::volume_analyzer::calculate_rms_intervals(&samples, sample_rate)
         .map_err(|e| AudioProcessorError::AnalysisVolumeError {
             path: path.to_string(),
             source: e,
         })
-        .map(|(levels, max_band_energy)| crate::audio::types::AudioAnalysis {
+        .map(|(levels, max_band_energy)| AudioAnalysis {
             levels,
             max_band_energy,
         })
 }
 
-/// Optimized function for when both metadata and volume analysis are needed
-pub fn get_track_complete_analysis_internal(
-    path: &str,
-) -> Result<(TrackBasicMetadata, crate::audio::types::AudioAnalysis), AudioProcessorError> {
-    log::info!("Complete Intern: Starting complete analysis for: {}", path);
-    let (metadata, samples_arc, sample_rate) = get_track_metadata_and_samples_internal(path)?;
-    
-    let volume_analysis = crate::audio::analysis::volume_analyzer::calculate_rms_intervals(&samples_arc, sample_rate)
-        .map_err(|e| AudioProcessorError::AnalysisVolumeError {
-            path: path.to_string(),
-            source: e,
-        })
-        .map(|(levels, max_band_energy)| crate::audio::types::AudioAnalysis {
-            levels,
-            max_band_energy,
-        })?;
-    
-    Ok((metadata, volume_analysis))
-}
-
 // --- Batch Command (To be modified next) ---
 
 #[tauri::command(async)]
@@ -147,22 +229,15 @@ pub fn analyze_features_batch_with_cache(
         cache_dir.is_some()
     );
 
-    let cache_path = cache_dir.map(|dir| std::path::PathBuf::from(dir));
+    let cache_path = cache_dir.map(std::path::PathBuf::from);
 
     let results: HashMap<String, Result<TrackBasicMetadata, String>> = paths
         .par_iter()
         .map(|path| {
-            let analysis_result = if let Some(ref cache_dir) = cache_path {
-                match crate::audio::cache::analyze_bpm_with_cache(path, Some(cache_dir)) {
-                    Ok(metadata) => Ok(metadata),
-                    Err(e) => {
-                        log::warn!("BPM cache analysis failed for {}: {}. Falling back to direct analysis.", path, e);
-                        get_track_basic_metadata_internal(path)
-                    }
-                }
-            } else {
-                get_track_basic_metadata_internal(path)
-            };
+            // Cache failures already fall back to direct analysis inside the cache layer,
+            // so an error here is a genuine analysis failure and is not retried.
+            let analysis_result =
+                crate::audio::cache::analyze_bpm_with_cache(path, cache_path.as_ref());
 
             match analysis_result {
                 Ok(metadata) => (path.clone(), Ok(metadata)),
@@ -174,15 +249,75 @@ pub fn analyze_features_batch_with_cache(
         })
         .collect();
 
+    if let Some(ref cache_dir) = cache_path {
+        if let Err(e) = crate::audio::cache::index::flush_index(cache_dir) {
+            log::warn!("Failed to flush cache index after batch: {}", e);
+        }
+        if let Err(e) = crate::audio::cache::eviction::enforce_size_limit(cache_dir) {
+            log::warn!("Cache eviction after batch failed: {}", e);
+        }
+    }
+
     log::info!("Metadata Batch CMD: Finished batch BPM analysis.");
     results
 }
 
+// --- Batch Command for Metadata and Waveforms ---
+
+#[tauri::command(async)]
+pub fn analyze_features_and_waveforms_batch_with_cache(
+    paths: Vec<String>,
+    cache_dir: Option<String>,
+) -> HashMap<String, Result<(TrackBasicMetadata, AudioAnalysis), String>> {
+    log::info!(
+        "Complete Batch CMD: Starting batch analysis for {} files (cache: {})",
+        paths.len(),
+        cache_dir.is_some()
+    );
+
+    let cache_path = cache_dir.map(std::path::PathBuf::from);
+
+    let results: HashMap<String, Result<(TrackBasicMetadata, AudioAnalysis), String>> = paths
+        .par_iter()
+        .map(|path| {
+            match crate::audio::cache::analyze_complete_with_cache(path, cache_path.as_ref()) {
+                Ok(result) => (path.clone(), Ok(result)),
+                Err(e) => {
+                    log::error!("Complete analysis failed for path '{}': {}", path, e);
+                    (path.clone(), Err(e.to_string()))
+                }
+            }
+        })
+        .collect();
+
+    if let Some(ref cache_dir) = cache_path {
+        if let Err(e) = crate::audio::cache::index::flush_index(cache_dir) {
+            log::warn!("Failed to flush cache index after batch: {}", e);
+        }
+        if let Err(e) = crate::audio::cache::eviction::enforce_size_limit(cache_dir) {
+            log::warn!("Cache eviction after batch failed: {}", e);
+        }
+    }
+
+    log::info!("Complete Batch CMD: Finished batch analysis.");
+    results
+}
+
 // --- New Command for On-Demand Volume Analysis ---
 #[tauri::command(async)]
-pub fn get_track_volume_analysis(path: String) -> Result<crate::audio::types::AudioAnalysis, String> {
-    log::info!("Volume CMD: Request for: {}", path);
-    get_track_volume_analysis_internal(&path).map_err(|e| {
+pub fn get_track_volume_analysis(
+    path: String,
+    cache_dir: Option<String>,
+) -> Result<AudioAnalysis, String> {
+    log::info!("Volume CMD: Request for: {} (cache: {})", path, cache_dir.is_some());
+    let result = match cache_dir.map(std::path::PathBuf::from) {
+        Some(cache_path) => {
+            crate::audio::cache::analyze_complete_with_cache(&path, Some(&cache_path))
+                .map(|(_, waveform)| waveform)
+        }
+        None => get_track_volume_analysis_internal(&path),
+    };
+    result.map_err(|e| {
         log::error!("Volume CMD: Error for path '{}': {}", path, e);
         e.to_string()
     })
@@ -192,12 +327,12 @@ pub fn get_track_volume_analysis(path: String) -> Result<crate::audio::types::Au
 #[tauri::command(async)]
 pub fn get_track_complete_analysis(
     path: String,
-) -> Result<(TrackBasicMetadata, crate::audio::types::AudioAnalysis), String> {
-    log::info!("Complete CMD: Request for: {}", path);
-    get_track_complete_analysis_internal(&path).map_err(|e| {
+    cache_dir: Option<String>,
+) -> Result<(TrackBasicMetadata, AudioAnalysis), String> {
+    log::info!("Complete CMD: Request for: {} (cache: {})", path, cache_dir.is_some());
+    let cache_path = cache_dir.map(std::path::PathBuf::from);
+    crate::audio::cache::analyze_complete_with_cache(&path, cache_path.as_ref()).map_err(|e| {
         log::error!("Complete CMD: Error for path '{}': {}", path, e);
         e.to_string()
     })
 }
-
-// --- Batch Analysis with Complete Data ---
@@ -59,6 +59,68 @@ pub struct WaveBin {
     pub high: f32,
 }
 
+/// Loudness measurement for a track (EBU R128 style, mono).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LoudnessAnalysis {
+    /// Gated integrated loudness in LUFS.
+    pub integrated_lufs: f32,
+    /// Sample peak in dBFS.
+    pub peak_dbfs: f32,
+    /// Gain in dB that brings the track to the ReplayGain reference level.
+    pub replay_gain_db: f32,
+}
+
+/// Musical key estimate for a track.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyAnalysis {
+    /// Key name, e.g. "A minor" or "C# major".
+    pub key: String,
+    /// Camelot wheel notation, e.g. "8A".
+    pub camelot: String,
+    /// Correlation of the chromagram with the winning key profile (-1.0 to 1.0).
+    pub confidence: f32,
+}
+
+/// Results of every analyzer run over a single decode of a track.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackAnalysis {
+    /// Duration, BPM and first beat.
+    pub metadata: TrackBasicMetadata,
+    /// Band waveform levels.
+    pub waveform: AudioAnalysis,
+    /// Loudness measurement, if it could be computed.
+    pub loudness: Option<LoudnessAnalysis>,
+    /// Key estimate, if it could be computed.
+    pub key: Option<KeyAnalysis>,
+    /// Energy rating from 1 (calm) to 10 (peak time), if it could be computed.
+    pub energy: Option<f32>,
+    /// Sample rate of the decoded audio.
+    pub sample_rate: u32,
+    /// Hash of the compressed audio payload, computed during the same decode.
+    pub content_hash: String,
+}
+
+/// Tags read from a file's container metadata (ID3v2, Vorbis comments, ...).
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackTags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub genre: Option<String>,
+    pub label: Option<String>,
+    pub comment: Option<String>,
+    /// Release date as written in the tag (often just a year).
+    pub date: Option<String>,
+    /// BPM from the tag (TBPM / BPM), not our analysis.
+    pub bpm: Option<f32>,
+    /// Key from the tag (TKEY / INITIALKEY), in whatever notation the tagger used.
+    pub key: Option<String>,
+}
+
 // --- Audio Thread Commands ---
 
 // --- Event Payloads for Frontend ---
@@ -1,4 +1,5 @@
 mod audio;
+mod library;
 
 use audio::config::AUDIO_BUFFER_CHAN_SIZE;
 use audio::playback::state::AppState;
@@ -43,6 +44,50 @@ pub fn run() {
                 }
             }
 
+            let (library_store, playlist_store, cue_store, history_store) = match app.path().app_data_dir() {
+                Ok(app_data_dir) => {
+                    let library_dir = app_data_dir.join("library");
+                    (
+                        library::store::LibraryStore::open(&library_dir),
+                        library::playlists::PlaylistStore::open(&library_dir),
+                        library::cues::CueStore::open(&library_dir),
+                        library::history::HistoryStore::open(&library_dir),
+                    )
+                }
+                Err(e) => {
+                    log::error!("Failed to resolve app data directory, library will not be saved: {}", e);
+                    (
+                        library::store::LibraryStore::new(),
+                        library::playlists::PlaylistStore::new(),
+                        library::cues::CueStore::new(),
+                        library::history::HistoryStore::new(),
+                    )
+                }
+            };
+            app.manage(library_store);
+            app.manage(playlist_store);
+            app.manage(cue_store);
+            app.manage(history_store);
+            // Play history and play counts are kept up to date off the audio thread
+            app.manage(audio::playback::handlers::history::HistoryReporter::spawn(app_handle.clone()));
+            // Fresh analysis results from any command or background job land in the library
+            let app_handle_for_library = app_handle.clone();
+            audio::processor::add_analysis_listener(move |path, analysis| {
+                let store = app_handle_for_library.state::<library::store::LibraryStore>();
+                let path = std::path::Path::new(path);
+                match store.record_analysis(path, analysis) {
+                    Ok(true) => {
+                        // Smart playlists follow analysis as it finishes
+                        if let Ok(Some(track)) = store.get_track(path) {
+                            library::commands::refresh_smart_playlists_after_change(&app_handle_for_library, Some(&track));
+                        }
+                    }
+                    Ok(false) => {}
+                    Err(e) => log::warn!("Failed to store analysis of {} in library: {}", path.display(), e),
+                }
+            });
+            app.manage(library::watcher::LibraryWatcher::new());
+
             // Initialize cue output manager
             if let Err(e) = audio::playback::handlers::cue_output::init_cue_output_manager() {
                 log::error!("Failed to initialize cue output manager: {}", e);
@@ -63,12 +108,20 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             audio::processor::analyze_features_batch,
             audio::processor::analyze_features_batch_with_cache,
+            audio::processor::analyze_features_and_waveforms_batch_with_cache,
             audio::processor::get_track_volume_analysis,
             audio::processor::get_track_complete_analysis,
             audio::cache::commands::ensure_cache_directory,
             audio::cache::commands::get_cache_stats,
             audio::cache::commands::cleanup_cache,
             audio::cache::commands::rebuild_cache_index,
+            audio::cache::commands::refresh_stale_cache_entries,
+            audio::cache::commands::set_cache_size_limit,
+            audio::cache::commands::evict_cache,
+            audio::cache::commands::pin_cache_entries,
+            audio::cache::commands::export_cache_archive,
+            audio::cache::commands::import_cache_archive,
+            audio::cache::commands::verify_cache,
             audio::cache::commands::clear_cache,
             audio::playback::commands::init_player,
             audio::playback::commands::load_track,
@@ -86,11 +139,60 @@ pub fn run() {
             audio::devices::commands::get_audio_devices,
             audio::devices::commands::set_cue_output_device,
             audio::devices::commands::refresh_audio_devices,
-            audio::devices::commands::set_cue_deck
+            audio::devices::commands::set_cue_deck,
+            library::commands::scan_library,
+            library::commands::get_library_tracks,
+            library::commands::query_library,
+            library::commands::update_track_user_fields,
+            library::commands::record_track_play,
+            library::commands::suggest_next_tracks,
+            library::commands::get_playlists,
+            library::commands::create_playlist,
+            library::commands::rename_playlist,
+            library::commands::delete_playlist,
+            library::commands::move_playlist,
+            library::commands::create_smart_playlist,
+            library::commands::update_smart_playlist,
+            library::commands::refresh_smart_playlists,
+            library::commands::add_tracks_to_playlist,
+            library::commands::remove_tracks_from_playlist,
+            library::commands::reorder_playlist_tracks,
+            library::commands::import_playlist_file,
+            library::commands::export_playlist_file,
+            library::commands::get_track_cues,
+            library::commands::set_track_cues,
+            library::commands::get_history_sessions,
+            library::commands::end_history_session,
+            library::commands::delete_history_session,
+            library::commands::export_history_session,
+            library::exchange::commands::import_rekordbox_xml,
+            library::exchange::commands::export_rekordbox_xml,
+            library::exchange::commands::import_traktor_nml,
+            library::exchange::commands::export_traktor_nml,
+            library::exchange::commands::import_itunes_library,
+            library::exchange::commands::import_serato_crates,
+            library::exchange::commands::export_serato_crates,
+            library::exchange::commands::write_serato_markers,
+            library::exchange::commands::export_rekordbox_device,
+            library::commands::relocate_missing_tracks,
+            library::commands::write_analysis_tags,
+            library::commands::watch_library,
+            library::commands::unwatch_library
         ])
         .on_window_event(move |window, event| {
             if let WindowEvent::CloseRequested { api, .. } = event {
                 log::info!("Window close requested. Sending Shutdown command to audio thread.");
+                audio::cache::index::flush_all_indexes();
+                if let Err(e) = window.app_handle().state::<library::store::LibraryStore>().flush() {
+                    log::error!("Failed to save library database: {}", e);
+                }
+                let history_store = window.app_handle().state::<library::history::HistoryStore>();
+                if let Err(e) = history_store.end_session() {
+                    log::error!("Failed to close history session: {}", e);
+                }
+                if let Err(e) = history_store.flush() {
+                    log::error!("Failed to save play history: {}", e);
+                }
                 // Prevent the window from closing immediately
                 api.prevent_close();
 
@@ -0,0 +1,301 @@
+//! Writing analysis results into the files' own tags, so other software and
+//! players see them: BPM, key, ReplayGain and the library comment, as ID3v2
+//! frames in MP3s and Vorbis comments in FLACs.
+
+use super::cues::CueStore;
+use super::exchange::short_key_name;
+use super::tag_files::flac::{self, FlacMetadata};
+use super::tag_files::id3v2::{self, Id3Tag};
+use super::{LibraryResult, LibraryTrack};
+use crate::audio::cache;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Which values to write. Nothing is written unless the caller opts in.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AnalysisTagFields {
+    pub bpm: bool,
+    pub key: bool,
+    /// Track gain and peak from the loudness analysis.
+    pub replay_gain: bool,
+    /// The comment edited in the library.
+    pub comment: bool,
+}
+
+/// A tag field, whatever each format calls it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TagField {
+    Bpm,
+    Key,
+    ReplayGainTrackGain,
+    ReplayGainTrackPeak,
+    Comment,
+}
+
+impl TagField {
+    /// Vorbis comment field name. TXXX descriptions use the same names.
+    fn vorbis_name(self) -> &'static str {
+        match self {
+            TagField::Bpm => "BPM",
+            TagField::Key => "INITIALKEY",
+            TagField::ReplayGainTrackGain => "REPLAYGAIN_TRACK_GAIN",
+            TagField::ReplayGainTrackPeak => "REPLAYGAIN_TRACK_PEAK",
+            TagField::Comment => "COMMENT",
+        }
+    }
+}
+
+/// A value a write changes, or in a dry run would change.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagChange {
+    pub path: PathBuf,
+    pub field: TagField,
+    /// The value currently in the file.
+    pub old_value: Option<String>,
+    pub new_value: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalysisTagReport {
+    pub dry_run: bool,
+    /// Files written, or that a dry run would write.
+    pub written: usize,
+    /// Files whose tags already hold every value.
+    pub unchanged: usize,
+    pub changes: Vec<TagChange>,
+    /// Tracks without a value for any requested field, e.g. not analyzed yet.
+    pub without_values: Vec<PathBuf>,
+    /// Files in formats we don't write tags to.
+    pub unsupported: Vec<PathBuf>,
+    /// Files that couldn't be read or written, with the reason.
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+/// A track's values to write, before each format's formatting.
+#[derive(Debug, Default)]
+struct TagValues {
+    bpm: Option<f32>,
+    key: Option<String>,
+    /// Track gain in dB and sample peak in dBFS.
+    replay_gain: Option<(f32, f32)>,
+    comment: Option<String>,
+}
+
+impl TagValues {
+    /// The requested values of a track. A stored grid's BPM overrides the
+    /// analyzed one, as in every other export.
+    fn for_track(track: &LibraryTrack, grid_bpm: Option<f32>, fields: AnalysisTagFields) -> Self {
+        let analysis = track.analysis.as_ref();
+        TagValues {
+            bpm: grid_bpm
+                .or_else(|| analysis?.metadata.bpm)
+                .filter(|&bpm| fields.bpm && bpm > 0.0),
+            key: analysis
+                .and_then(|a| a.key.as_ref())
+                .filter(|_| fields.key)
+                .map(|k| short_key_name(&k.key)),
+            replay_gain: analysis
+                .and_then(|a| a.loudness.as_ref())
+                .filter(|_| fields.replay_gain)
+                .map(|l| (l.replay_gain_db, l.peak_dbfs)),
+            comment: track
+                .user
+                .comment
+                .as_deref()
+                .map(str::trim)
+                .filter(|comment| fields.comment && !comment.is_empty())
+                .map(str::to_string),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bpm.is_none() && self.key.is_none() && self.replay_gain.is_none() && self.comment.is_none()
+    }
+
+    /// The values as written into `file`, in field order.
+    fn formatted(&self, file: &TagFile) -> Vec<(TagField, String)> {
+        let mut values = Vec::new();
+        values.extend(self.bpm.map(|bpm| (TagField::Bpm, file.format_bpm(bpm))));
+        values.extend(self.key.clone().map(|key| (TagField::Key, key)));
+        if let Some((gain_db, peak_dbfs)) = self.replay_gain {
+            values.push((TagField::ReplayGainTrackGain, format!("{:.2} dB", gain_db)));
+            // ReplayGain peaks are linear, 1.0 being full scale
+            let peak = 10f32.powf(peak_dbfs / 20.0);
+            values.push((TagField::ReplayGainTrackPeak, format!("{:.6}", peak)));
+        }
+        values.extend(self.comment.clone().map(|comment| (TagField::Comment, comment)));
+        values
+    }
+}
+
+enum TagFile {
+    Mp3(Id3Tag),
+    Flac(FlacMetadata),
+}
+
+impl TagFile {
+    /// Opens the tags of formats we write to; `None` for others.
+    fn open(path: &Path) -> LibraryResult<Option<Self>> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+        Ok(match extension.as_deref() {
+            Some("mp3") => Some(TagFile::Mp3(id3v2::read_tag(path)?)),
+            Some("flac") => Some(TagFile::Flac(flac::read_metadata(path)?)),
+            _ => None,
+        })
+    }
+
+    /// ID3v2 defines the tempo as a whole number; Vorbis comments
+    /// keep the decimals.
+    fn format_bpm(&self, bpm: f32) -> String {
+        match self {
+            TagFile::Flac(_) => {
+                let formatted = format!("{:.2}", bpm);
+                formatted.trim_end_matches('0').trim_end_matches('.').to_string()
+            }
+            _ => format!("{}", bpm.round() as i16),
+        }
+    }
+
+    fn get(&self, field: TagField) -> Option<String> {
+        match self {
+            TagFile::Mp3(tag) => match field {
+                TagField::Bpm => tag.text("TBPM"),
+                TagField::Key => tag.text("TKEY"),
+                TagField::Comment => tag.comment(),
+                _ => tag.user_text(field.vorbis_name()),
+            },
+            TagFile::Flac(metadata) => metadata.comment(field.vorbis_name()).map(str::to_string),
+        }
+    }
+
+    fn set(&mut self, field: TagField, value: &str) {
+        match self {
+            TagFile::Mp3(tag) => match field {
+                TagField::Bpm => tag.set_text("TBPM", value),
+                TagField::Key => tag.set_text("TKEY", value),
+                TagField::Comment => tag.set_comment(value),
+                _ => tag.set_user_text(field.vorbis_name(), value),
+            },
+            TagFile::Flac(metadata) => metadata.set_comment(field.vorbis_name(), Some(value.to_string())),
+        }
+    }
+
+    fn save(&self, path: &Path) -> LibraryResult<()> {
+        match self {
+            TagFile::Mp3(tag) => id3v2::write_tag(path, tag),
+            TagFile::Flac(metadata) => flac::write_metadata(path, metadata),
+        }
+    }
+}
+
+/// Compares one file's tags with `values` and, unless `dry_run`, writes the
+/// ones that differ. Returns the changes, or `None` for formats we don't
+/// write tags to.
+fn update_file(path: &Path, values: &TagValues, dry_run: bool) -> LibraryResult<Option<Vec<TagChange>>> {
+    let Some(mut file) = TagFile::open(path)? else {
+        return Ok(None);
+    };
+    let mut changes = Vec::new();
+    for (field, new_value) in values.formatted(&file) {
+        let old_value = file.get(field);
+        if old_value.as_deref().map(str::trim) == Some(new_value.as_str()) {
+            continue;
+        }
+        file.set(field, &new_value);
+        changes.push(TagChange {
+            path: path.to_path_buf(),
+            field,
+            old_value,
+            new_value,
+        });
+    }
+    if !dry_run && !changes.is_empty() {
+        file.save(path)?;
+    }
+    Ok(Some(changes))
+}
+
+/// Writes the requested analysis values into the tags of `tracks`. With
+/// `dry_run` files are only read, and the report lists what a write would
+/// change. Cache entries of rewritten files are kept valid.
+pub fn write_analysis_tags(
+    tracks: &[LibraryTrack],
+    cue_store: &CueStore,
+    fields: AnalysisTagFields,
+    dry_run: bool,
+    cache_dir: Option<&Path>,
+) -> LibraryResult<AnalysisTagReport> {
+    enum Outcome {
+        Changed(Vec<TagChange>),
+        Unchanged,
+        WithoutValues(PathBuf),
+        Unsupported(PathBuf),
+        Failed(PathBuf, String),
+    }
+
+    let cues = cue_store.snapshot()?;
+    let outcomes: Vec<Outcome> = tracks
+        .par_iter()
+        .map(|track| {
+            let grid_bpm = cues.get(&track.path).and_then(|c| c.grid).map(|grid| grid.bpm);
+            let values = TagValues::for_track(track, grid_bpm, fields);
+            if values.is_empty() {
+                return Outcome::WithoutValues(track.path.clone());
+            }
+            let cache_current =
+                !dry_run && cache_dir.is_some_and(|dir| cache::peek_cached_entry(&track.path, dir).is_some());
+            match update_file(&track.path, &values, dry_run) {
+                Ok(Some(changes)) if changes.is_empty() => Outcome::Unchanged,
+                Ok(Some(changes)) => {
+                    let refreshed = cache_dir
+                        .filter(|_| cache_current)
+                        .map(|dir| cache::refresh_after_tag_write(&track.path, dir));
+                    if let Some(Err(e)) = refreshed {
+                        log::warn!("Failed to refresh cache entry of {}: {}", track.path.display(), e);
+                    }
+                    Outcome::Changed(changes)
+                }
+                Ok(None) => Outcome::Unsupported(track.path.clone()),
+                Err(e) => {
+                    log::warn!("Failed to write analysis tags to {}: {}", track.path.display(), e);
+                    Outcome::Failed(track.path.clone(), e.to_string())
+                }
+            }
+        })
+        .collect();
+
+    let mut report = AnalysisTagReport {
+        dry_run,
+        ..Default::default()
+    };
+    for outcome in outcomes {
+        match outcome {
+            Outcome::Changed(changes) => {
+                report.written += 1;
+                report.changes.extend(changes);
+            }
+            Outcome::Unchanged => report.unchanged += 1,
+            Outcome::WithoutValues(path) => report.without_values.push(path),
+            Outcome::Unsupported(path) => report.unsupported.push(path),
+            Outcome::Failed(path, reason) => report.failed.push((path, reason)),
+        }
+    }
+    log::info!(
+        "{} analysis tags of {} files ({} unchanged, {} without values, {} unsupported, {} failed)",
+        if dry_run { "Dry run: would write" } else { "Wrote" },
+        report.written,
+        report.unchanged,
+        report.without_values.len(),
+        report.unsupported.len(),
+        report.failed.len()
+    );
+    Ok(report)
+}
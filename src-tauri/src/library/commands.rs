@@ -0,0 +1,568 @@
+use super::analysis_tags::{self, AnalysisTagFields, AnalysisTagReport};
+use super::store::LibraryStore;
+use super::watcher::LibraryWatcher;
+use super::cues::{CueStore, TrackCues};
+use super::history::{self, HistoryExportFormat, HistorySession, HistoryStore};
+use super::exchange::serato;
+use super::playlist_files::{self, PlaylistEntry, PlaylistPathMode};
+use super::playlists::{PlaylistKind, PlaylistNode, PlaylistStore};
+use super::query::TrackQuery;
+use super::relocate::{self, RelocationReport};
+use super::suggest::{self, EnergyDirection, TrackSuggestion};
+use super::{scanner, LibraryPage, LibraryTrack, UserFields};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+
+/// Tracks are built and streamed to the frontend in pages of this size by default.
+const DEFAULT_SCAN_PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryScanPageEvent {
+    pub root: String,
+    pub tracks: Vec<LibraryTrack>,
+    pub scanned: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryScanSummary {
+    pub total_tracks: usize,
+    pub analyzed_tracks: usize,
+    /// Tracks whose Serato cues, grid or color were picked up.
+    pub serato_tracks: usize,
+    /// Roots that could not be scanned, with the reason.
+    pub failed_roots: Vec<(String, String)>,
+}
+
+/// Recursively scans library roots. Track records are emitted as
+/// `library://scan-page` events while the scan runs and are kept in the
+/// library database. Tracks no longer found under a root are dropped; tracks
+/// still present keep their play history and user fields. Serato prep data
+/// embedded in files is picked up for tracks without cues of our own.
+#[tauri::command(async)]
+pub fn scan_library(
+    app_handle: tauri::AppHandle,
+    library_store: State<'_, LibraryStore>,
+    cue_store: State<'_, CueStore>,
+    roots: Vec<String>,
+    cache_dir: Option<String>,
+    page_size: Option<usize>,
+) -> Result<LibraryScanSummary, String> {
+    let cache_path = cache_dir.map(PathBuf::from);
+    let page_size = page_size.unwrap_or(DEFAULT_SCAN_PAGE_SIZE).max(1);
+    let mut summary = LibraryScanSummary::default();
+
+    for root in roots {
+        let root_path = Path::new(&root);
+        let files = match scanner::collect_audio_files(root_path) {
+            Ok(files) => files,
+            Err(e) => {
+                log::warn!("Library scan of {} failed: {}", root, e);
+                summary.failed_roots.push((root, e.to_string()));
+                continue;
+            }
+        };
+        log::info!("Library scan: {} audio files under {}", files.len(), root);
+
+        let mut scanned = 0;
+        for chunk in files.chunks(page_size) {
+            let tracks = scanner::build_track_records(chunk, root_path, cache_path.as_deref());
+            scanned += chunk.len();
+            summary.total_tracks += tracks.len();
+            summary.analyzed_tracks += tracks.iter().filter(|t| t.analysis.is_some()).count();
+
+            let event = LibraryScanPageEvent {
+                root: root.clone(),
+                tracks: tracks.clone(),
+                scanned,
+                total: files.len(),
+            };
+            if let Err(e) = app_handle.emit("library://scan-page", event) {
+                log::warn!("Failed to emit library scan page: {}", e);
+            }
+            library_store.upsert_tracks(tracks).map_err(|e| e.to_string())?;
+            match serato::import_file_markers(&library_store, &cue_store, chunk, cache_path.as_deref()) {
+                Ok(count) => summary.serato_tracks += count,
+                Err(e) => log::warn!("Library scan: failed to pick up Serato data: {}", e),
+            }
+        }
+
+        let found: HashSet<PathBuf> = files.into_iter().collect();
+        let pruned = library_store.prune_root(root_path, &found).map_err(|e| e.to_string())?;
+        if pruned > 0 {
+            log::info!("Library scan: dropped {} tracks no longer under {}", pruned, root);
+        }
+    }
+    if let Err(e) = library_store.flush() {
+        log::warn!("Failed to save library database after scan: {}", e);
+    }
+    refresh_smart_playlists_after_change(&app_handle, None);
+
+    log::info!(
+        "Library scan finished: {} tracks, {} with cached analysis",
+        summary.total_tracks,
+        summary.analyzed_tracks
+    );
+    Ok(summary)
+}
+
+#[tauri::command]
+pub async fn get_library_tracks(
+    library_store: State<'_, LibraryStore>,
+    offset: usize,
+    limit: usize,
+) -> Result<LibraryPage, String> {
+    library_store
+        .page(offset, limit)
+        .map_err(|e| format!("Failed to read library tracks: {}", e))
+}
+
+/// Searches, filters, sorts and pages the library database.
+#[tauri::command]
+pub async fn query_library(
+    library_store: State<'_, LibraryStore>,
+    query: TrackQuery,
+) -> Result<LibraryPage, String> {
+    library_store
+        .query(&query)
+        .map_err(|e| format!("Failed to query library: {}", e))
+}
+
+#[tauri::command]
+pub async fn update_track_user_fields(
+    app_handle: tauri::AppHandle,
+    library_store: State<'_, LibraryStore>,
+    path: String,
+    user: UserFields,
+) -> Result<LibraryTrack, String> {
+    let track = library_store
+        .set_user_fields(Path::new(&path), user)
+        .map_err(|e| format!("Failed to update {}: {}", path, e))?;
+    refresh_smart_playlists_after_change(&app_handle, Some(&track));
+    Ok(track)
+}
+
+/// Counts a play of a library track.
+#[tauri::command]
+pub async fn record_track_play(
+    app_handle: tauri::AppHandle,
+    library_store: State<'_, LibraryStore>,
+    path: String,
+) -> Result<LibraryTrack, String> {
+    let track = library_store
+        .record_play(Path::new(&path))
+        .map_err(|e| format!("Failed to record play of {}: {}", path, e))?;
+    refresh_smart_playlists_after_change(&app_handle, Some(&track));
+    Ok(track)
+}
+
+/// Ranks library tracks as next tracks after `path`, the track playing on a
+/// deck at `pitch_rate`, by key compatibility as heard (after pitch), tempo
+/// with half/double-time folding, and energy in the wanted direction. Tracks
+/// already played in the current history session are left out.
+#[tauri::command(async)]
+pub fn suggest_next_tracks(
+    library_store: State<'_, LibraryStore>,
+    history_store: State<'_, HistoryStore>,
+    path: String,
+    original_bpm: Option<f32>,
+    pitch_rate: Option<f32>,
+    energy_direction: Option<EnergyDirection>,
+    count: Option<usize>,
+) -> Result<Vec<TrackSuggestion>, String> {
+    let path = PathBuf::from(path);
+    let track = library_store.get_track(&path).map_err(|e| e.to_string())?;
+    let reference = suggest::reference_for(
+        path,
+        track.as_ref(),
+        original_bpm,
+        pitch_rate.unwrap_or(1.0),
+        energy_direction.unwrap_or_default(),
+    );
+    let played = history_store.session_paths().map_err(|e| e.to_string())?;
+    library_store
+        .suggest(&reference, &played, count.unwrap_or(suggest::DEFAULT_SUGGESTION_COUNT))
+        .map_err(|e| format!("Failed to suggest tracks: {}", e))
+}
+
+#[tauri::command]
+pub async fn get_track_cues(cue_store: State<'_, CueStore>, path: String) -> Result<TrackCues, String> {
+    cue_store
+        .get(Path::new(&path))
+        .map_err(|e| format!("Failed to read cues of {}: {}", path, e))
+}
+
+/// Replaces a track's stored cues, loops and beat grid.
+#[tauri::command]
+pub async fn set_track_cues(cue_store: State<'_, CueStore>, path: String, cues: TrackCues) -> Result<(), String> {
+    cue_store
+        .set(PathBuf::from(&path), cues)
+        .map_err(|e| format!("Failed to save cues of {}: {}", path, e))
+}
+
+/// Watches library roots for added, removed, changed and moved files. With a
+/// cache directory, new and changed files are also queued for analysis.
+#[tauri::command]
+pub async fn watch_library(
+    app_handle: tauri::AppHandle,
+    library_watcher: State<'_, LibraryWatcher>,
+    roots: Vec<String>,
+    cache_dir: Option<String>,
+) -> Result<(), String> {
+    for root in roots {
+        library_watcher
+            .watch(app_handle.clone(), PathBuf::from(&root), cache_dir.as_ref().map(PathBuf::from))
+            .map_err(|e| format!("Failed to watch {}: {}", root, e))?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn unwatch_library(
+    library_watcher: State<'_, LibraryWatcher>,
+    roots: Vec<String>,
+) -> Result<(), String> {
+    for root in roots {
+        library_watcher
+            .unwatch(Path::new(&root))
+            .map_err(|e| format!("Failed to stop watching {}: {}", root, e))?;
+    }
+    Ok(())
+}
+
+/// Looks under `search_root` for library and playlist tracks whose files
+/// are gone, matching them by audio content hash, and moves every reference
+/// (library, playlists, cues and the analysis cache in `cache_dir`) to the
+/// new paths. The report lists what was found and what is still missing.
+#[tauri::command(async)]
+pub fn relocate_missing_tracks(
+    app_handle: tauri::AppHandle,
+    library_store: State<'_, LibraryStore>,
+    playlist_store: State<'_, PlaylistStore>,
+    cue_store: State<'_, CueStore>,
+    search_root: String,
+    cache_dir: Option<String>,
+) -> Result<RelocationReport, String> {
+    let report = relocate::relocate_missing(
+        &library_store,
+        &playlist_store,
+        &cue_store,
+        Path::new(&search_root),
+        cache_dir.as_deref().map(Path::new),
+    )
+    .map_err(|e| format!("Failed to relocate tracks under {}: {}", search_root, e))?;
+    if !report.relocated.is_empty() {
+        refresh_smart_playlists_after_change(&app_handle, None);
+    }
+    Ok(report)
+}
+
+/// Writes analysis results (BPM, key, ReplayGain) and library comments into
+/// the tags of the given tracks, or of every library track when `paths` is
+/// omitted, so other software sees them. Only the fields switched on in
+/// `fields` are written; with `dry_run` nothing is, and the report lists what
+/// would change. Paths not in the library are reported without values.
+#[tauri::command(async)]
+pub fn write_analysis_tags(
+    library_store: State<'_, LibraryStore>,
+    cue_store: State<'_, CueStore>,
+    paths: Option<Vec<String>>,
+    fields: AnalysisTagFields,
+    dry_run: bool,
+    cache_dir: Option<String>,
+) -> Result<AnalysisTagReport, String> {
+    let mut tracks = library_store.all_tracks().map_err(|e| e.to_string())?;
+    let mut unknown = Vec::new();
+    if let Some(paths) = paths {
+        let wanted: HashSet<PathBuf> = paths.into_iter().map(PathBuf::from).collect();
+        tracks.retain(|track| wanted.contains(&track.path));
+        let known: HashSet<&Path> = tracks.iter().map(|track| track.path.as_path()).collect();
+        unknown.extend(wanted.iter().filter(|path| !known.contains(path.as_path())).cloned());
+    }
+    let mut report = analysis_tags::write_analysis_tags(
+        &tracks,
+        &cue_store,
+        fields,
+        dry_run,
+        cache_dir.as_deref().map(Path::new),
+    )
+    .map_err(|e| format!("Failed to write analysis tags: {}", e))?;
+    report.without_values.extend(unknown);
+    report.without_values.sort();
+    Ok(report)
+}
+
+// --- Playlists and Crates ---
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistImportReport {
+    pub playlist: PlaylistNode,
+    /// Entries whose files don't exist. They are kept in the playlist so they
+    /// can be relocated later.
+    pub missing: Vec<PathBuf>,
+}
+
+#[tauri::command]
+pub async fn get_playlists(playlist_store: State<'_, PlaylistStore>) -> Result<Vec<PlaylistNode>, String> {
+    playlist_store
+        .tree()
+        .map_err(|e| format!("Failed to read playlists: {}", e))
+}
+
+/// Re-evaluates smart playlists after the library changed (only those
+/// `changed` affects, when given) and emits the updated ones as a
+/// `library://smart-playlists-updated` event.
+pub(crate) fn refresh_smart_playlists_after_change<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    changed: Option<&LibraryTrack>,
+) -> Vec<PlaylistNode> {
+    let (Some(library_store), Some(playlist_store)) =
+        (app_handle.try_state::<LibraryStore>(), app_handle.try_state::<PlaylistStore>())
+    else {
+        return Vec::new();
+    };
+    match playlist_store.refresh_smart(changed, |rules| library_store.matching_paths(rules)) {
+        Ok(updated) => {
+            if !updated.is_empty() {
+                log::debug!("Refreshed {} smart playlists", updated.len());
+                if let Err(e) = app_handle.emit("library://smart-playlists-updated", &updated) {
+                    log::warn!("Failed to emit smart playlist update: {}", e);
+                }
+            }
+            updated
+        }
+        Err(e) => {
+            log::warn!("Failed to refresh smart playlists: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Creates an empty playlist or crate, at the top level or inside a crate.
+#[tauri::command]
+pub async fn create_playlist(
+    playlist_store: State<'_, PlaylistStore>,
+    name: String,
+    kind: PlaylistKind,
+    parent_id: Option<String>,
+) -> Result<PlaylistNode, String> {
+    playlist_store
+        .create(name, kind, parent_id.as_deref())
+        .map_err(|e| format!("Failed to create playlist: {}", e))
+}
+
+#[tauri::command]
+pub async fn rename_playlist(
+    playlist_store: State<'_, PlaylistStore>,
+    id: String,
+    name: String,
+) -> Result<PlaylistNode, String> {
+    playlist_store
+        .rename(&id, name)
+        .map_err(|e| format!("Failed to rename playlist: {}", e))
+}
+
+/// Deletes a playlist or crate, including everything nested in it.
+#[tauri::command]
+pub async fn delete_playlist(playlist_store: State<'_, PlaylistStore>, id: String) -> Result<(), String> {
+    playlist_store
+        .delete(&id)
+        .map_err(|e| format!("Failed to delete playlist: {}", e))
+}
+
+/// Creates a smart playlist filled by `rules`, a library query such as
+/// "BPM 120-126, compatible with 8A, energy 6+, not played in 30 days".
+/// Its tracks follow the library as tracks are scanned and analyzed.
+#[tauri::command]
+pub async fn create_smart_playlist(
+    library_store: State<'_, LibraryStore>,
+    playlist_store: State<'_, PlaylistStore>,
+    name: String,
+    rules: TrackQuery,
+    parent_id: Option<String>,
+) -> Result<PlaylistNode, String> {
+    let tracks = library_store.matching_paths(&rules).map_err(|e| e.to_string())?;
+    playlist_store
+        .create_smart(name, rules, tracks, parent_id.as_deref())
+        .map_err(|e| format!("Failed to create smart playlist: {}", e))
+}
+
+/// Replaces a smart playlist's rules and re-evaluates it.
+#[tauri::command]
+pub async fn update_smart_playlist(
+    library_store: State<'_, LibraryStore>,
+    playlist_store: State<'_, PlaylistStore>,
+    id: String,
+    rules: TrackQuery,
+) -> Result<PlaylistNode, String> {
+    let tracks = library_store.matching_paths(&rules).map_err(|e| e.to_string())?;
+    playlist_store
+        .set_smart_rules(&id, rules, tracks)
+        .map_err(|e| format!("Failed to update smart playlist: {}", e))
+}
+
+/// Re-evaluates every smart playlist, e.g. so "not played in 30 days" rules
+/// catch up with the clock. Returns the playlists that changed.
+#[tauri::command(async)]
+pub fn refresh_smart_playlists(app_handle: tauri::AppHandle) -> Result<Vec<PlaylistNode>, String> {
+    Ok(refresh_smart_playlists_after_change(&app_handle, None))
+}
+
+/// Moves a playlist or crate into another crate (or to the top level when
+/// `parent_id` is empty) at `index`, appending by default.
+#[tauri::command]
+pub async fn move_playlist(
+    playlist_store: State<'_, PlaylistStore>,
+    id: String,
+    parent_id: Option<String>,
+    index: Option<usize>,
+) -> Result<(), String> {
+    playlist_store
+        .move_node(&id, parent_id.as_deref(), index)
+        .map_err(|e| format!("Failed to move playlist: {}", e))
+}
+
+/// Inserts tracks at `index`, appending by default. Returns how many were
+/// added; crates skip tracks they already hold.
+#[tauri::command]
+pub async fn add_tracks_to_playlist(
+    playlist_store: State<'_, PlaylistStore>,
+    id: String,
+    paths: Vec<String>,
+    index: Option<usize>,
+) -> Result<usize, String> {
+    let paths = paths.into_iter().map(PathBuf::from).collect();
+    playlist_store
+        .add_tracks(&id, paths, index)
+        .map_err(|e| format!("Failed to add tracks to playlist: {}", e))
+}
+
+/// Removes the tracks at the given positions.
+#[tauri::command]
+pub async fn remove_tracks_from_playlist(
+    playlist_store: State<'_, PlaylistStore>,
+    id: String,
+    positions: Vec<usize>,
+) -> Result<usize, String> {
+    playlist_store
+        .remove_tracks(&id, &positions)
+        .map_err(|e| format!("Failed to remove tracks from playlist: {}", e))
+}
+
+/// Moves the tracks at `positions` to `to_index`, keeping their order.
+#[tauri::command]
+pub async fn reorder_playlist_tracks(
+    playlist_store: State<'_, PlaylistStore>,
+    id: String,
+    positions: Vec<usize>,
+    to_index: usize,
+) -> Result<(), String> {
+    playlist_store
+        .move_tracks(&id, &positions, to_index)
+        .map_err(|e| format!("Failed to reorder playlist: {}", e))
+}
+
+/// Imports an M3U, M3U8 or PLS file as a new playlist named after the file.
+#[tauri::command(async)]
+pub fn import_playlist_file(
+    playlist_store: State<'_, PlaylistStore>,
+    file_path: String,
+    parent_id: Option<String>,
+) -> Result<PlaylistImportReport, String> {
+    let path = Path::new(&file_path);
+    let entries = playlist_files::read_playlist_file(path).map_err(|e| format!("Failed to read {}: {}", file_path, e))?;
+
+    let name = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "Imported playlist".to_string());
+    let mut playlist = PlaylistNode::new(name, PlaylistKind::Playlist);
+    playlist.tracks = entries.into_iter().map(|entry| entry.path).collect();
+    let missing: Vec<PathBuf> = playlist.tracks.iter().filter(|p| !p.exists()).cloned().collect();
+    if !missing.is_empty() {
+        log::warn!("Playlist import: {} of {} entries in {} not found", missing.len(), playlist.tracks.len(), file_path);
+    }
+
+    let playlist = playlist_store
+        .insert(playlist, parent_id.as_deref())
+        .map_err(|e| format!("Failed to save imported playlist: {}", e))?;
+    Ok(PlaylistImportReport { playlist, missing })
+}
+
+/// Exports a playlist or crate's tracks to an M3U, M3U8 or PLS file, chosen by
+/// the extension of `file_path`. Titles and durations come from the library.
+#[tauri::command(async)]
+pub fn export_playlist_file(
+    playlist_store: State<'_, PlaylistStore>,
+    library_store: State<'_, LibraryStore>,
+    id: String,
+    file_path: String,
+    path_mode: Option<PlaylistPathMode>,
+) -> Result<usize, String> {
+    let playlist = playlist_store.get(&id).map_err(|e| e.to_string())?;
+    let entries: Vec<PlaylistEntry> = playlist
+        .tracks
+        .iter()
+        .map(|path| {
+            let track = library_store.get_track(path).ok().flatten();
+            let title = track.as_ref().and_then(|t| match (&t.tags.artist, &t.tags.title) {
+                (Some(artist), Some(title)) => Some(format!("{} - {}", artist, title)),
+                (None, Some(title)) => Some(title.clone()),
+                _ => None,
+            });
+            PlaylistEntry {
+                path: path.clone(),
+                title: title.or_else(|| path.file_stem().map(|s| s.to_string_lossy().to_string())),
+                duration_seconds: track
+                    .and_then(|t| t.analysis)
+                    .and_then(|a| a.metadata.duration_seconds),
+            }
+        })
+        .collect();
+
+    playlist_files::write_playlist_file(Path::new(&file_path), &entries, path_mode.unwrap_or_default())
+        .map_err(|e| format!("Failed to export {}: {}", file_path, e))?;
+    Ok(entries.len())
+}
+
+// --- Play History ---
+
+/// Every logged session, newest first.
+#[tauri::command]
+pub async fn get_history_sessions(history_store: State<'_, HistoryStore>) -> Result<Vec<HistorySession>, String> {
+    history_store.sessions().map_err(|e| e.to_string())
+}
+
+/// Closes the session being logged; the next track on the master starts a
+/// new one. Returns the closed session, if one was open.
+#[tauri::command]
+pub async fn end_history_session(history_store: State<'_, HistoryStore>) -> Result<Option<HistorySession>, String> {
+    history_store.end_session().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_history_session(history_store: State<'_, HistoryStore>, id: String) -> Result<(), String> {
+    history_store.delete_session(&id).map_err(|e| e.to_string())
+}
+
+/// Exports a session as CSV, JSON, an M3U playlist or a timestamped
+/// tracklist. Artists and titles come from the library.
+#[tauri::command(async)]
+pub fn export_history_session(
+    history_store: State<'_, HistoryStore>,
+    library_store: State<'_, LibraryStore>,
+    id: String,
+    file_path: String,
+    format: HistoryExportFormat,
+) -> Result<usize, String> {
+    let session = history_store.get(&id).map_err(|e| e.to_string())?;
+    history::export_session(&session, Path::new(&file_path), format, |path| {
+        library_store.get_track(path).ok().flatten()
+    })
+    .map_err(|e| format!("Failed to export {}: {}", file_path, e))
+}
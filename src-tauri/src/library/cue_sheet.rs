@@ -0,0 +1,298 @@
+//! CUE sheets: the `.cue` files that split one audio file (a recorded mix,
+//! a vinyl rip, a whole album) into tracks. Each TRACK's `INDEX 01` becomes
+//! a [`VirtualTrack`] on the parent file's library record.
+
+use crate::audio::decoding;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const SHEET_EXTENSION: &str = "cue";
+/// INDEX times are minutes:seconds:frames, at 75 CD frames per second.
+const FRAMES_PER_SECOND: f64 = 75.0;
+/// Sheets are a few kilobytes; anything far larger isn't one.
+const MAX_SHEET_LEN: u64 = 1024 * 1024;
+
+/// A CUE sheet track: a range of its parent audio file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualTrack {
+    /// Track number from the sheet.
+    pub number: u32,
+    pub title: Option<String>,
+    /// The track's PERFORMER, else the sheet's.
+    pub artist: Option<String>,
+    pub start_seconds: f64,
+    /// Where the next track starts; `None` runs to the end of the file.
+    pub end_seconds: Option<f64>,
+}
+
+/// The tracks a sheet lists for one FILE.
+struct SheetFile {
+    name: String,
+    tracks: Vec<VirtualTrack>,
+}
+
+/// Splits a line into its command and arguments, keeping quoted arguments
+/// whole.
+fn tokens(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut rest = line.trim();
+    while !rest.is_empty() {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            tokens.push(quoted[..end].to_string());
+            rest = quoted.get(end + 1..).unwrap_or_default().trim_start();
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            tokens.push(rest[..end].to_string());
+            rest = rest[end..].trim_start();
+        }
+    }
+    tokens
+}
+
+/// Parses "mm:ss:ff" into seconds.
+fn parse_index_time(value: &str) -> Option<f64> {
+    let mut parts = value.split(':').map(|p| p.parse::<u32>().ok());
+    let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+    Some(minutes as f64 * 60.0 + seconds as f64 + frames as f64 / FRAMES_PER_SECOND)
+}
+
+/// Reads a sheet's text. Older rippers write Latin-1 rather than UTF-8.
+fn read_sheet_text(path: &Path) -> Option<String> {
+    if fs::metadata(path).ok()?.len() > MAX_SHEET_LEN {
+        return None;
+    }
+    let bytes = fs::read(path).ok()?;
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&bytes);
+    Some(match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => bytes.iter().map(|&b| b as char).collect(),
+    })
+}
+
+fn parse_sheet(text: &str) -> Vec<SheetFile> {
+    let mut files: Vec<SheetFile> = Vec::new();
+    let mut sheet_performer: Option<String> = None;
+    // Whether TITLE and PERFORMER lines belong to a track yet
+    let mut in_track = false;
+
+    for line in text.lines() {
+        let tokens = tokens(line);
+        let Some(command) = tokens.first().map(|c| c.to_ascii_uppercase()) else {
+            continue;
+        };
+        let argument = tokens.get(1).cloned().filter(|a| !a.is_empty());
+        match command.as_str() {
+            "FILE" => {
+                files.push(SheetFile {
+                    name: argument.unwrap_or_default(),
+                    tracks: Vec::new(),
+                });
+                in_track = false;
+            }
+            "TRACK" => {
+                let Some(file) = files.last_mut() else {
+                    continue;
+                };
+                file.tracks.push(VirtualTrack {
+                    number: argument.and_then(|n| n.parse().ok()).unwrap_or(file.tracks.len() as u32 + 1),
+                    title: None,
+                    artist: sheet_performer.clone(),
+                    // Negative until the track's INDEX 01 is read
+                    start_seconds: -1.0,
+                    end_seconds: None,
+                });
+                in_track = true;
+            }
+            "PERFORMER" if !in_track => sheet_performer = argument,
+            _ => {
+                let Some(track) = files.last_mut().and_then(|f| f.tracks.last_mut()).filter(|_| in_track) else {
+                    continue;
+                };
+                match command.as_str() {
+                    "TITLE" => track.title = argument,
+                    "PERFORMER" => track.artist = argument.or(track.artist.take()),
+                    "INDEX" if argument.as_deref() == Some("01") => {
+                        if let Some(start) = tokens.get(2).and_then(|t| parse_index_time(t)) {
+                            track.start_seconds = start;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    for file in &mut files {
+        file.tracks.retain(|track| track.start_seconds >= 0.0);
+        file.tracks.sort_by(|a, b| a.start_seconds.total_cmp(&b.start_seconds));
+        let starts: Vec<f64> = file.tracks.iter().skip(1).map(|t| t.start_seconds).collect();
+        for (track, next_start) in file.tracks.iter_mut().zip(starts) {
+            track.end_seconds = Some(next_start);
+        }
+    }
+    files
+}
+
+/// Sheets that may describe an audio file: `<name>.cue` and
+/// `<name>.<ext>.cue` next to it.
+fn sheet_candidates(audio_path: &Path) -> Vec<PathBuf> {
+    let mut with_suffix = audio_path.as_os_str().to_owned();
+    with_suffix.push(".");
+    with_suffix.push(SHEET_EXTENSION);
+    vec![audio_path.with_extension(SHEET_EXTENSION), PathBuf::from(with_suffix)]
+}
+
+/// Whether a path is a CUE sheet.
+pub fn is_sheet(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(SHEET_EXTENSION))
+}
+
+/// The audio files next to a sheet that it may describe, the reverse of
+/// [`sheet_candidates`]. The sheet itself may no longer exist.
+pub fn audio_files_for_sheet(sheet_path: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(sheet_stem)) = (sheet_path.parent(), sheet_path.file_stem()) else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.file_stem() == Some(sheet_stem) || path.file_name() == Some(sheet_stem))
+        .filter(|path| path.is_file() && decoding::is_supported_audio_file(path))
+        .collect()
+}
+
+fn stem(name: &str) -> &str {
+    Path::new(name).file_stem().and_then(|s| s.to_str()).unwrap_or(name)
+}
+
+/// The virtual tracks a sheet next to `audio_path` defines for it. A sheet's
+/// FILE entry matches by name, or by name without extension since rips are
+/// often converted after the sheet was written; a sheet with a single FILE
+/// is taken to describe the file it sits next to. Sheets with fewer than
+/// two tracks add nothing and are ignored.
+pub fn virtual_tracks_for(audio_path: &Path) -> Vec<VirtualTrack> {
+    let Some(file_name) = audio_path.file_name().and_then(|n| n.to_str()) else {
+        return Vec::new();
+    };
+    for sheet_path in sheet_candidates(audio_path).iter().filter(|p| p.is_file()) {
+        let Some(text) = read_sheet_text(sheet_path) else {
+            continue;
+        };
+        let files = parse_sheet(&text);
+        let single_file = files.len() == 1;
+        let matching = files.into_iter().find(|file| {
+            let name = file.name.rsplit(['/', '\\']).next().unwrap_or_default();
+            single_file || name.eq_ignore_ascii_case(file_name) || stem(name).eq_ignore_ascii_case(stem(file_name))
+        });
+        match matching {
+            Some(file) if file.tracks.len() >= 2 => {
+                log::debug!("{} tracks in {}", file.tracks.len(), sheet_path.display());
+                return file.tracks;
+            }
+            _ => log::debug!("{} has no tracks for {}", sheet_path.display(), file_name),
+        }
+    }
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHEET: &str = "REM GENRE House\r
+PERFORMER \"DJ Sheet\"\r
+TITLE \"Live at \"\"Club\"\"\"\r
+FILE \"Mix Recording.wav\" WAVE\r
+  TRACK 01 AUDIO\r
+    TITLE \"Intro\"\r
+    INDEX 00 00:00:00\r
+    INDEX 01 00:00:00\r
+  TRACK 02 AUDIO\r
+    TITLE \"Second Song\"\r
+    PERFORMER \"Guest\"\r
+    INDEX 01 03:25:37\r
+  TRACK 03 AUDIO\r
+    TITLE No Index\r
+  TRACK 04 AUDIO\r
+    INDEX 01 10:00:00\r
+";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("open-dj-cue-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn sheets_parse_into_virtual_tracks() {
+        let files = parse_sheet(SHEET);
+        let [file] = files.as_slice() else {
+            panic!("expected one FILE");
+        };
+        assert_eq!(file.name, "Mix Recording.wav");
+        let summary: Vec<_> = file
+            .tracks
+            .iter()
+            .map(|t| (t.number, t.title.as_deref(), t.artist.as_deref(), t.start_seconds, t.end_seconds))
+            .collect();
+        let second_start = 3.0 * 60.0 + 25.0 + 37.0 / FRAMES_PER_SECOND;
+        assert_eq!(
+            summary,
+            [
+                (1, Some("Intro"), Some("DJ Sheet"), 0.0, Some(second_start)),
+                (2, Some("Second Song"), Some("Guest"), second_start, Some(600.0)),
+                (4, None, Some("DJ Sheet"), 600.0, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_lines_are_skipped() {
+        let text = "TRACK 01 AUDIO\nINDEX 01 00:00:00\nFILE \"unterminated.wav\nTRACK x AUDIO\nINDEX 01 1:2\n\
+                    TRACK 02\nINDEX 01 99999999999:00:00\nTRACK 03\nINDEX 01 00:01:00\nINDEX\n\"\n\u{0}\u{FFFD}";
+        let files = parse_sheet(text);
+        let [file] = files.as_slice() else {
+            panic!("expected one FILE");
+        };
+        assert_eq!(file.name, "unterminated.wav");
+        let numbers: Vec<u32> = file.tracks.iter().map(|t| t.number).collect();
+        assert_eq!(numbers, [3]);
+        assert!(tokens("  \"\"  ").first().is_some_and(String::is_empty));
+    }
+
+    #[test]
+    fn sheets_next_to_audio_files_are_found() {
+        let dir = temp_dir("files");
+        let audio = dir.join("Mix Recording.flac");
+        fs::write(&audio, b"").unwrap();
+
+        // Converted after ripping: matched by name without extension, in Latin-1
+        let latin1: Vec<u8> = SHEET.replace("Guest", "Gäst").chars().map(|c| c as u8).collect();
+        fs::write(dir.join("Mix Recording.cue"), latin1).unwrap();
+        let tracks = virtual_tracks_for(&audio);
+        assert_eq!(tracks.len(), 3);
+        assert_eq!(tracks[1].artist.as_deref(), Some("Gäst"));
+        assert_eq!(audio_files_for_sheet(&dir.join("Mix Recording.cue")), [audio.clone()]);
+
+        // With a BOM and as `<name>.<ext>.cue`
+        fs::remove_file(dir.join("Mix Recording.cue")).unwrap();
+        fs::write(dir.join("Mix Recording.flac.cue"), format!("\u{FEFF}{}", SHEET)).unwrap();
+        assert_eq!(virtual_tracks_for(&audio).len(), 3);
+
+        // Too large to be a sheet
+        let mut oversized = SHEET.to_string();
+        oversized.push_str(&"REM padding\n".repeat(MAX_SHEET_LEN as usize / 10));
+        fs::write(dir.join("Mix Recording.flac.cue"), oversized).unwrap();
+        assert!(virtual_tracks_for(&audio).is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,189 @@
+use super::{write_json_atomic, LibraryError, LibraryResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+/// Bump when the cue file layout changes incompatibly.
+const CUES_DB_VERSION: u32 = 1;
+const CUES_DB_FILE_NAME: &str = "cues.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CueKind {
+    Cue,
+    /// A saved loop from `start_seconds` to `end_seconds`.
+    Loop,
+}
+
+/// A stored cue or loop. Cues with a `hot_cue` slot are hot cues; the rest
+/// are memory cues.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CuePoint {
+    pub kind: CueKind,
+    /// Hot cue slot, 0-based (slot 0 is "A").
+    #[serde(default)]
+    pub hot_cue: Option<u8>,
+    pub start_seconds: f64,
+    #[serde(default)]
+    pub end_seconds: Option<f64>,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Color as "#RRGGBB".
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+/// A beat grid set by hand or imported from other DJ software. It takes
+/// precedence over the analyzed BPM and first beat.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BeatGrid {
+    pub bpm: f32,
+    pub first_beat_sec: f32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TrackCues {
+    pub cues: Vec<CuePoint>,
+    pub grid: Option<BeatGrid>,
+}
+
+impl TrackCues {
+    pub fn is_empty(&self) -> bool {
+        self.cues.is_empty() && self.grid.is_none()
+    }
+}
+
+/// On-disk form of the cue store.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CuesFile {
+    version: u32,
+    tracks: HashMap<PathBuf, TrackCues>,
+}
+
+/// Cues, loops and beat grids per track, managed as Tauri state and written
+/// to disk on every change.
+pub struct CueStore {
+    /// Database file; `None` keeps cues in memory only.
+    db_path: Option<PathBuf>,
+    tracks: Mutex<HashMap<PathBuf, TrackCues>>,
+}
+
+impl CueStore {
+    pub fn new() -> Self {
+        CueStore {
+            db_path: None,
+            tracks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Opens the cue file in `data_dir`. A missing file starts empty; an
+    /// unreadable one is set aside first.
+    pub fn open(data_dir: &Path) -> Self {
+        let db_path = data_dir.join(CUES_DB_FILE_NAME);
+        let read = File::open(&db_path)
+            .map_err(LibraryError::from)
+            .and_then(|file| Ok(serde_json::from_reader::<_, CuesFile>(BufReader::new(file))?));
+        let tracks = match read {
+            Ok(file) if file.version == CUES_DB_VERSION => file.tracks,
+            Ok(file) => {
+                log::warn!("Cue file version {} (expected {}), starting empty", file.version, CUES_DB_VERSION);
+                HashMap::new()
+            }
+            Err(LibraryError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                log::warn!("Cue file {} unreadable ({}), starting empty", db_path.display(), e);
+                if let Err(e) = std::fs::rename(&db_path, db_path.with_extension("json.corrupt")) {
+                    log::warn!("Failed to set aside corrupted cue file: {}", e);
+                }
+                HashMap::new()
+            }
+        };
+        CueStore {
+            db_path: Some(db_path),
+            tracks: Mutex::new(tracks),
+        }
+    }
+
+    fn lock(&self) -> LibraryResult<MutexGuard<'_, HashMap<PathBuf, TrackCues>>> {
+        self.tracks
+            .lock()
+            .map_err(|e| LibraryError::LockPoisoned(format!("Failed to lock cue store: {}", e)))
+    }
+
+    /// Applies a mutation and saves the store.
+    fn update<T>(&self, mutate: impl FnOnce(&mut HashMap<PathBuf, TrackCues>) -> T) -> LibraryResult<T> {
+        let mut tracks = self.lock()?;
+        let result = mutate(&mut tracks);
+        if let Some(db_path) = &self.db_path {
+            write_json_atomic(
+                db_path,
+                &CuesFile {
+                    version: CUES_DB_VERSION,
+                    tracks: tracks.clone(),
+                },
+            )?;
+        }
+        Ok(result)
+    }
+
+    pub fn get(&self, path: &Path) -> LibraryResult<TrackCues> {
+        Ok(self.lock()?.get(path).cloned().unwrap_or_default())
+    }
+
+    /// Every track with cues or a grid.
+    pub fn snapshot(&self) -> LibraryResult<HashMap<PathBuf, TrackCues>> {
+        Ok(self.lock()?.clone())
+    }
+
+    /// Replaces the cues and grid of several tracks in one write. Empty
+    /// entries delete the track's record.
+    pub fn set_many(&self, entries: Vec<(PathBuf, TrackCues)>) -> LibraryResult<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        self.update(|tracks| {
+            for (path, cues) in entries {
+                if cues.is_empty() {
+                    tracks.remove(&path);
+                } else {
+                    tracks.insert(path, cues);
+                }
+            }
+        })
+    }
+
+    pub fn set(&self, path: PathBuf, cues: TrackCues) -> LibraryResult<()> {
+        self.set_many(vec![(path, cues)])
+    }
+
+    /// Moves cues of relocated files to their new paths. Returns how many
+    /// tracks moved.
+    pub fn relocate_tracks(&self, moves: &HashMap<PathBuf, PathBuf>) -> LibraryResult<usize> {
+        if moves.is_empty() {
+            return Ok(0);
+        }
+        self.update(|tracks| {
+            let mut relocated = 0;
+            for (from, to) in moves {
+                if let Some(cues) = tracks.remove(from) {
+                    tracks.insert(to.clone(), cues);
+                    relocated += 1;
+                }
+            }
+            relocated
+        })
+    }
+}
+
+impl Default for CueStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,195 @@
+use super::rekordbox_device::{self, DeviceExportReport};
+use super::serato::{self, SeratoWriteReport};
+use super::{
+    gather_export_source, import_collection, itunes_xml, rekordbox_xml, serato_crates, traktor_nml, ExchangeImportReport, ExternalCollection,
+};
+use crate::library::cues::CueStore;
+use crate::library::playlists::PlaylistStore;
+use crate::library::store::LibraryStore;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+/// Imports a rekordbox XML collection: tracks into the library, grids into
+/// the cache and cue store, cues into the cue store and playlists under a
+/// "rekordbox" crate.
+#[tauri::command(async)]
+pub fn import_rekordbox_xml(
+    library_store: State<'_, LibraryStore>,
+    playlist_store: State<'_, PlaylistStore>,
+    cue_store: State<'_, CueStore>,
+    xml_path: String,
+    cache_dir: Option<String>,
+) -> Result<ExchangeImportReport, String> {
+    let collection = rekordbox_xml::read_rekordbox_xml(Path::new(&xml_path))
+        .map_err(|e| format!("Failed to read {}: {}", xml_path, e))?;
+    let cache_path = cache_dir.map(PathBuf::from);
+    import_collection(
+        &library_store,
+        &playlist_store,
+        &cue_store,
+        collection,
+        cache_path.as_deref(),
+        rekordbox_xml::REKORDBOX_CRATE_NAME,
+    )
+    .map_err(|e| format!("rekordbox import failed: {}", e))
+}
+
+/// Exports the library, grids, cues and playlists as a rekordbox XML
+/// collection. Returns how many tracks were written.
+#[tauri::command(async)]
+pub fn export_rekordbox_xml(
+    library_store: State<'_, LibraryStore>,
+    playlist_store: State<'_, PlaylistStore>,
+    cue_store: State<'_, CueStore>,
+    xml_path: String,
+) -> Result<usize, String> {
+    let source = gather_export_source(&library_store, &playlist_store, &cue_store).map_err(|e| e.to_string())?;
+    rekordbox_xml::write_rekordbox_xml(Path::new(&xml_path), &source)
+        .map_err(|e| format!("Failed to export {}: {}", xml_path, e))
+}
+
+/// Imports a Traktor NML collection the same way, with playlists under a
+/// "Traktor" crate.
+#[tauri::command(async)]
+pub fn import_traktor_nml(
+    library_store: State<'_, LibraryStore>,
+    playlist_store: State<'_, PlaylistStore>,
+    cue_store: State<'_, CueStore>,
+    nml_path: String,
+    cache_dir: Option<String>,
+) -> Result<ExchangeImportReport, String> {
+    let collection = traktor_nml::read_traktor_nml(Path::new(&nml_path))
+        .map_err(|e| format!("Failed to read {}: {}", nml_path, e))?;
+    let cache_path = cache_dir.map(PathBuf::from);
+    import_collection(
+        &library_store,
+        &playlist_store,
+        &cue_store,
+        collection,
+        cache_path.as_deref(),
+        traktor_nml::TRAKTOR_CRATE_NAME,
+    )
+    .map_err(|e| format!("Traktor import failed: {}", e))
+}
+
+/// Exports the library, grids, cues, keys and playlists as a Traktor NML
+/// collection. Returns how many tracks were written.
+#[tauri::command(async)]
+pub fn export_traktor_nml(
+    library_store: State<'_, LibraryStore>,
+    playlist_store: State<'_, PlaylistStore>,
+    cue_store: State<'_, CueStore>,
+    nml_path: String,
+) -> Result<usize, String> {
+    let source = gather_export_source(&library_store, &playlist_store, &cue_store).map_err(|e| e.to_string())?;
+    traktor_nml::write_traktor_nml(Path::new(&nml_path), &source)
+        .map_err(|e| format!("Failed to export {}: {}", nml_path, e))
+}
+
+/// Imports an iTunes / Apple Music `Library.xml`: tracks with their ratings,
+/// play counts and dates into the library, playlists and folders under an
+/// "Apple Music" crate. Entries whose files aren't here are reported as
+/// missing.
+#[tauri::command(async)]
+pub fn import_itunes_library(
+    library_store: State<'_, LibraryStore>,
+    playlist_store: State<'_, PlaylistStore>,
+    cue_store: State<'_, CueStore>,
+    xml_path: String,
+    cache_dir: Option<String>,
+) -> Result<ExchangeImportReport, String> {
+    let collection = itunes_xml::read_itunes_library(Path::new(&xml_path))
+        .map_err(|e| format!("Failed to read {}: {}", xml_path, e))?;
+    let cache_path = cache_dir.map(PathBuf::from);
+    import_collection(
+        &library_store,
+        &playlist_store,
+        &cue_store,
+        collection,
+        cache_path.as_deref(),
+        itunes_xml::ITUNES_CRATE_NAME,
+    )
+    .map_err(|e| format!("Apple Music import failed: {}", e))
+}
+
+/// Imports the crates of a `_Serato_` folder under a "Serato" crate,
+/// together with the cues, grids and colors embedded in their tracks.
+#[tauri::command(async)]
+pub fn import_serato_crates(
+    library_store: State<'_, LibraryStore>,
+    playlist_store: State<'_, PlaylistStore>,
+    cue_store: State<'_, CueStore>,
+    serato_dir: String,
+    cache_dir: Option<String>,
+) -> Result<ExchangeImportReport, String> {
+    let playlists = serato_crates::read_serato_crates(Path::new(&serato_dir))
+        .map_err(|e| format!("Failed to read {}: {}", serato_dir, e))?;
+    let mut paths = Vec::new();
+    serato_crates::crate_tracks(&playlists, &mut paths);
+    paths.sort();
+    paths.dedup();
+    let collection = ExternalCollection {
+        tracks: serato::external_tracks(&paths),
+        playlists,
+    };
+    let cache_path = cache_dir.map(PathBuf::from);
+    import_collection(
+        &library_store,
+        &playlist_store,
+        &cue_store,
+        collection,
+        cache_path.as_deref(),
+        serato_crates::SERATO_CRATE_NAME,
+    )
+    .map_err(|e| format!("Serato import failed: {}", e))
+}
+
+/// Writes the playlist tree as crates into a `_Serato_` folder. Returns how
+/// many crates were written.
+#[tauri::command(async)]
+pub fn export_serato_crates(playlist_store: State<'_, PlaylistStore>, serato_dir: String) -> Result<usize, String> {
+    let playlists = playlist_store.tree().map_err(|e| e.to_string())?;
+    serato_crates::write_serato_crates(Path::new(&serato_dir), &playlists)
+        .map_err(|e| format!("Failed to export crates to {}: {}", serato_dir, e))
+}
+
+/// Writes cues, loops, grids and track colors into the Serato data of the
+/// given files, or of every track with prep data when `paths` is omitted.
+#[tauri::command(async)]
+pub fn write_serato_markers(
+    library_store: State<'_, LibraryStore>,
+    playlist_store: State<'_, PlaylistStore>,
+    cue_store: State<'_, CueStore>,
+    paths: Option<Vec<String>>,
+    cache_dir: Option<String>,
+) -> Result<SeratoWriteReport, String> {
+    let source = gather_export_source(&library_store, &playlist_store, &cue_store).map_err(|e| e.to_string())?;
+    let paths: Option<HashSet<PathBuf>> = paths.map(|paths| paths.into_iter().map(PathBuf::from).collect());
+    let cache_path = cache_dir.map(PathBuf::from);
+    Ok(serato::write_prep_to_files(&source, paths.as_ref(), cache_path.as_deref()))
+}
+
+/// Writes a rekordbox device library (audio, `export.pdb` and ANLZ files)
+/// into `target_dir`, typically a USB drive, for CDJs and XDJs. Exports the
+/// given playlists and their tracks, or everything when `playlist_ids` is
+/// omitted.
+#[tauri::command(async)]
+pub fn export_rekordbox_device(
+    library_store: State<'_, LibraryStore>,
+    playlist_store: State<'_, PlaylistStore>,
+    cue_store: State<'_, CueStore>,
+    target_dir: String,
+    playlist_ids: Option<Vec<String>>,
+    cache_dir: Option<String>,
+) -> Result<DeviceExportReport, String> {
+    let source = gather_export_source(&library_store, &playlist_store, &cue_store).map_err(|e| e.to_string())?;
+    let cache_path = cache_dir.map(PathBuf::from);
+    rekordbox_device::export_device_library(
+        Path::new(&target_dir),
+        &source,
+        playlist_ids.as_deref(),
+        cache_path.as_deref(),
+    )
+    .map_err(|e| format!("Failed to export to {}: {}", target_dir, e))
+}
@@ -0,0 +1,239 @@
+//! The iTunes / Apple Music library export (`Library.xml`, File > Library >
+//! Export Library), an XML property list.
+
+use super::{parse_date, ExternalCollection, ExternalNode, ExternalTrack};
+use crate::library::playlist_files::file_url_to_path;
+use crate::library::{LibraryError, LibraryResult};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Name of the crate imported playlists are placed under.
+pub const ITUNES_CRATE_NAME: &str = "Apple Music";
+
+/// Ratings are stored as 0-100, 20 per star.
+const RATING_STEP: u64 = 20;
+
+// --- Property Lists ---
+
+/// A property list value. Integers, reals and dates are kept as text.
+#[derive(Debug)]
+enum Value {
+    Dict(HashMap<String, Value>),
+    Array(Vec<Value>),
+    Text(String),
+    Bool(bool),
+}
+
+impl Value {
+    fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Dict(entries) => entries.get(key),
+            _ => None,
+        }
+    }
+
+    fn text(&self, key: &str) -> Option<&str> {
+        match self.get(key)? {
+            Value::Text(text) => Some(text.as_str()),
+            _ => None,
+        }
+    }
+
+    fn number(&self, key: &str) -> Option<u64> {
+        self.text(key)?.trim().parse().ok()
+    }
+
+    fn flag(&self, key: &str) -> bool {
+        matches!(self.get(key), Some(Value::Bool(true)))
+    }
+}
+
+/// A dict or array being read.
+enum Frame {
+    Dict(HashMap<String, Value>, Option<String>),
+    Array(Vec<Value>),
+}
+
+impl Frame {
+    fn into_value(self) -> Value {
+        match self {
+            Frame::Dict(entries, _) => Value::Dict(entries),
+            Frame::Array(items) => Value::Array(items),
+        }
+    }
+}
+
+fn add_value(stack: &mut [Frame], root: &mut Option<Value>, value: Value) {
+    match stack.last_mut() {
+        Some(Frame::Dict(entries, key)) => {
+            if let Some(key) = key.take() {
+                entries.insert(key, value);
+            }
+        }
+        Some(Frame::Array(items)) => items.push(value),
+        None => *root = Some(value),
+    }
+}
+
+fn read_plist(xml_path: &Path) -> LibraryResult<Value> {
+    let mut reader = Reader::from_file(xml_path)?;
+    let mut buf = Vec::new();
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut root: Option<Value> = None;
+    let mut text = String::new();
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(element) => match element.name().as_ref() {
+                b"dict" => stack.push(Frame::Dict(HashMap::new(), None)),
+                b"array" => stack.push(Frame::Array(Vec::new())),
+                _ => text.clear(),
+            },
+            Event::Empty(element) => {
+                let value = match element.name().as_ref() {
+                    b"dict" => Value::Dict(HashMap::new()),
+                    b"array" => Value::Array(Vec::new()),
+                    b"true" => Value::Bool(true),
+                    b"false" => Value::Bool(false),
+                    _ => Value::Text(String::new()),
+                };
+                add_value(&mut stack, &mut root, value);
+            }
+            Event::Text(content) => text.push_str(&content.unescape()?),
+            Event::End(element) => match element.name().as_ref() {
+                b"dict" | b"array" => {
+                    if let Some(frame) = stack.pop() {
+                        add_value(&mut stack, &mut root, frame.into_value());
+                    }
+                }
+                b"key" => {
+                    if let Some(Frame::Dict(_, key)) = stack.last_mut() {
+                        *key = Some(std::mem::take(&mut text));
+                    }
+                }
+                b"plist" => {}
+                _ => add_value(&mut stack, &mut root, Value::Text(std::mem::take(&mut text))),
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    root.ok_or_else(|| LibraryError::InvalidCollection(format!("{} is not a property list", xml_path.display())))
+}
+
+// --- Import ---
+
+/// A track entry, if it's a local file.
+fn parse_track(entry: &Value) -> Option<ExternalTrack> {
+    let path = file_url_to_path(entry.text("Location")?)?;
+    // Rating Computed: the album's rating shown on its tracks
+    let rating = if entry.flag("Rating Computed") {
+        None
+    } else {
+        entry.number("Rating").map(|r| (r / RATING_STEP).min(5) as u8)
+    };
+    Some(ExternalTrack {
+        path,
+        rating,
+        comment: entry.text("Comments").map(str::to_string),
+        play_count: entry.number("Play Count").unwrap_or(0) as u32,
+        added_at: entry.text("Date Added").and_then(parse_date),
+        last_played_at: entry.text("Play Date UTC").and_then(parse_date),
+        ..Default::default()
+    })
+}
+
+/// A playlist or folder entry with its place in the folder tree.
+struct PlaylistEntry {
+    persistent_id: String,
+    parent_id: Option<String>,
+    name: String,
+    is_folder: bool,
+    tracks: Vec<PathBuf>,
+}
+
+/// The library-wide and built-in playlists (Library, Music, Podcasts,
+/// Purchased...), which only repeat the collection.
+fn is_builtin_playlist(entry: &Value) -> bool {
+    entry.flag("Master")
+        || entry.get("Distinguished Kind").is_some()
+        || matches!(entry.get("Visible"), Some(Value::Bool(false)))
+}
+
+fn build_nodes(entries: &[PlaylistEntry], parent_id: Option<&str>) -> Vec<ExternalNode> {
+    entries
+        .iter()
+        .filter(|entry| entry.parent_id.as_deref() == parent_id)
+        .map(|entry| {
+            if entry.is_folder {
+                ExternalNode::Folder {
+                    name: entry.name.clone(),
+                    children: build_nodes(entries, Some(&entry.persistent_id)),
+                }
+            } else {
+                ExternalNode::Playlist {
+                    name: entry.name.clone(),
+                    tracks: entry.tracks.clone(),
+                }
+            }
+        })
+        .collect()
+}
+
+/// Reads an iTunes / Apple Music library export. Streams, podcasts and
+/// other non-file entries are left out; smart playlists come in as plain
+/// playlists with their current contents.
+pub fn read_itunes_library(xml_path: &Path) -> LibraryResult<ExternalCollection> {
+    let plist = read_plist(xml_path)?;
+    let Some(Value::Dict(track_entries)) = plist.get("Tracks") else {
+        return Err(LibraryError::InvalidCollection(format!(
+            "{} is not an iTunes library",
+            xml_path.display()
+        )));
+    };
+
+    let mut paths_by_id: HashMap<&str, PathBuf> = HashMap::new();
+    let mut tracks: Vec<ExternalTrack> = Vec::new();
+    for (id, entry) in track_entries {
+        if let Some(track) = parse_track(entry) {
+            paths_by_id.insert(id.as_str(), track.path.clone());
+            tracks.push(track);
+        }
+    }
+    tracks.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut entries: Vec<PlaylistEntry> = Vec::new();
+    if let Some(Value::Array(playlists)) = plist.get("Playlists") {
+        for playlist in playlists.iter().filter(|p| !is_builtin_playlist(p)) {
+            let items = match playlist.get("Playlist Items") {
+                Some(Value::Array(items)) => items.as_slice(),
+                _ => &[],
+            };
+            let tracks = items
+                .iter()
+                .filter_map(|item| item.text("Track ID"))
+                .filter_map(|id| paths_by_id.get(id).cloned())
+                .collect();
+            entries.push(PlaylistEntry {
+                persistent_id: playlist.text("Playlist Persistent ID").unwrap_or_default().to_string(),
+                parent_id: playlist.text("Parent Persistent ID").map(str::to_string),
+                name: playlist.text("Name").unwrap_or_default().to_string(),
+                is_folder: playlist.flag("Folder"),
+                tracks,
+            });
+        }
+    }
+    let playlists = build_nodes(&entries, None);
+
+    log::info!(
+        "Read {} tracks and {} top-level playlist nodes from {}",
+        tracks.len(),
+        playlists.len(),
+        xml_path.display()
+    );
+    Ok(ExternalCollection { tracks, playlists })
+}
@@ -0,0 +1,410 @@
+//! Per-track analysis files, `ANLZ0000.DAT` and `ANLZ0000.EXT`.
+//!
+//! Big-endian files made of tagged sections after a `PMAI` header. The DAT
+//! file holds the beat grid, cue lists and the monochrome previews older
+//! players show; the EXT file holds the scrolling waveforms.
+
+use crate::audio::types::WaveBin;
+use crate::library::cues::{BeatGrid, CueKind, CuePoint};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const FILE_HEADER_LEN: u32 = 0x1C;
+/// Waveform entries per second in the scrolling (detail) waveforms.
+const DETAIL_ENTRIES_PER_SECOND: f64 = 150.0;
+const PREVIEW_LEN: usize = 400;
+const TINY_PREVIEW_LEN: usize = 100;
+/// Durations beyond this (or not finite) are taken as this long, so a bad
+/// duration can't blow up the beat grid or the scrolling waveforms.
+const MAX_DURATION_SECONDS: f64 = 24.0 * 3600.0;
+/// Playable tempo range for the beat grid.
+const GRID_BPM_RANGE: std::ops::RangeInclusive<f32> = 1.0..=999.0;
+/// Hot cues A-H.
+const HOT_CUE_SLOTS: u8 = 8;
+
+const CUE_LIST_MEMORY: u32 = 0;
+const CUE_LIST_HOT_CUES: u32 = 1;
+const CUE_ENTRY_LEN: u32 = 0x38;
+const CUE_TYPE_POINT: u8 = 1;
+const CUE_TYPE_LOOP: u8 = 2;
+
+/// What goes into a track's analysis files.
+pub struct AnlzTrack<'a> {
+    /// Path of the audio file on the device, e.g. "/Contents/a/b.mp3".
+    pub device_path: &'a str,
+    pub duration_seconds: f64,
+    pub grid: Option<BeatGrid>,
+    pub cues: &'a [CuePoint],
+    /// Finest waveform level, spread evenly over the track.
+    pub waveform: Option<&'a [WaveBin]>,
+    pub max_band_energy: f32,
+}
+
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn bytes(&mut self, value: &[u8]) {
+        self.0.extend_from_slice(value);
+    }
+
+    /// Appends a section: tag, header length, total length, then the rest of
+    /// the header and the body.
+    fn section(&mut self, tag: &[u8; 4], header: &[u8], body: &[u8]) {
+        let header_len = 12 + header.len() as u32;
+        self.bytes(tag);
+        self.u32(header_len);
+        self.u32(header_len + body.len() as u32);
+        self.bytes(header);
+        self.bytes(body);
+    }
+}
+
+fn be_u32s(values: &[u32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_be_bytes()).collect()
+}
+
+fn seconds_to_ms(seconds: f64) -> u32 {
+    (seconds.max(0.0) * 1000.0).round() as u32
+}
+
+fn bounded_duration(duration_seconds: f64) -> f64 {
+    if duration_seconds.is_finite() {
+        duration_seconds.clamp(0.0, MAX_DURATION_SECONDS)
+    } else {
+        MAX_DURATION_SECONDS
+    }
+}
+
+// --- Sections ---
+
+fn path_section(out: &mut Writer, device_path: &str) {
+    let mut path: Vec<u8> = device_path.encode_utf16().flat_map(u16::to_be_bytes).collect();
+    path.extend_from_slice(&[0, 0]);
+    out.section(b"PPTH", &be_u32s(&[path.len() as u32]), &path);
+}
+
+/// Every beat of a constant grid, numbered 1-4 from the first beat. The grid
+/// must have a BPM in [`GRID_BPM_RANGE`] and a finite first beat.
+fn beat_grid_section(out: &mut Writer, grid: BeatGrid, duration_seconds: f64) {
+    let interval = 60.0 / grid.bpm as f64;
+    let first_beat = grid.first_beat_sec as f64;
+    // Beats start within one interval of the track start, wherever the first beat is
+    let start = first_beat.rem_euclid(interval);
+    let bar_phase = (-(first_beat / interval).floor()).rem_euclid(4.0) as u32;
+    let tempo = (grid.bpm * 100.0).round() as u16;
+
+    let mut body = Writer::default();
+    let mut count = 0u32;
+    loop {
+        let time = start + count as f64 * interval;
+        if time > duration_seconds {
+            break;
+        }
+        body.u16(((bar_phase + count) % 4 + 1) as u16);
+        body.u16(tempo);
+        body.u32(seconds_to_ms(time));
+        count += 1;
+    }
+    out.section(b"PQTZ", &be_u32s(&[0, 0x0008_0000, count]), &body.0);
+}
+
+fn cue_list_section(out: &mut Writer, list_type: u32, cues: &[(u32, &CuePoint)]) {
+    let mut body = Writer::default();
+    for (position, (hot_cue, cue)) in cues.iter().enumerate() {
+        let is_loop = cue.kind == CueKind::Loop && cue.end_seconds.is_some();
+        body.bytes(b"PCPT");
+        body.u32(0x1C);
+        body.u32(CUE_ENTRY_LEN);
+        body.u32(*hot_cue);
+        // Active
+        body.u32(if is_loop { 4 } else { 1 });
+        body.u32(0x0001_0000);
+        // Memory cues are linked in order
+        body.u16(if position == 0 { 0xFFFF } else { position as u16 - 1 });
+        body.u16(if position + 1 == cues.len() { 0xFFFF } else { position as u16 + 1 });
+        body.u8(if is_loop { CUE_TYPE_LOOP } else { CUE_TYPE_POINT });
+        body.u8(0);
+        body.u16(0x03E8);
+        body.u32(seconds_to_ms(cue.start_seconds));
+        body.u32(match (is_loop, cue.end_seconds) {
+            (true, Some(end)) => seconds_to_ms(end),
+            _ => 0xFFFF_FFFF,
+        });
+        body.bytes(&[0; 16]);
+    }
+    let mut header = Writer::default();
+    header.u32(list_type);
+    header.u16(0);
+    header.u16(cues.len() as u16);
+    header.u32(if list_type == CUE_LIST_MEMORY { cues.len() as u32 } else { 0 });
+    out.section(b"PCOB", &header.0, &body.0);
+}
+
+/// Column heights (0-1) and high-band shares (0-1), max-pooled from the
+/// waveform bins into `columns` columns.
+fn waveform_columns(bins: &[WaveBin], max_energy: f32, columns: usize) -> Vec<(f32, f32, WaveBin)> {
+    let max_energy = max_energy.max(f32::EPSILON);
+    (0..columns)
+        .map(|column| {
+            let start = column * bins.len() / columns;
+            let end = ((column + 1) * bins.len() / columns).max(start + 1).min(bins.len());
+            let mut peak = WaveBin {
+                low: 0.0,
+                mid: 0.0,
+                high: 0.0,
+            };
+            for bin in bins.get(start..end).unwrap_or_default() {
+                peak.low = peak.low.max(bin.low);
+                peak.mid = peak.mid.max(bin.mid);
+                peak.high = peak.high.max(bin.high);
+            }
+            let loudest = peak.low.max(peak.mid).max(peak.high);
+            let total = peak.low + peak.mid + peak.high;
+            let height = (loudest / max_energy).clamp(0.0, 1.0);
+            let brightness = if total > 0.0 { peak.high / total } else { 0.0 };
+            (height, brightness, peak)
+        })
+        .collect()
+}
+
+/// Height in the low five bits, whiteness in the high three.
+fn monochrome_entries(bins: &[WaveBin], max_energy: f32, columns: usize) -> Vec<u8> {
+    waveform_columns(bins, max_energy, columns)
+        .into_iter()
+        .map(|(height, brightness, _)| {
+            let whiteness = (brightness * 7.0).round() as u8;
+            (whiteness << 5) | (height * 31.0).round() as u8
+        })
+        .collect()
+}
+
+fn detail_len(duration_seconds: f64) -> usize {
+    (duration_seconds * DETAIL_ENTRIES_PER_SECOND).ceil().max(1.0) as usize
+}
+
+// --- Files ---
+
+fn file_bytes(sections: Writer) -> Vec<u8> {
+    let mut out = Writer::default();
+    out.bytes(b"PMAI");
+    out.u32(FILE_HEADER_LEN);
+    out.u32(FILE_HEADER_LEN + sections.0.len() as u32);
+    out.u32(1);
+    out.u32(0x0001_0000);
+    out.u32(0x0001_0000);
+    out.u32(0);
+    out.bytes(&sections.0);
+    out.0
+}
+
+/// Writes `ANLZ0000.DAT`: path, beat grid, hot cue and memory cue lists and
+/// the monochrome previews.
+pub fn write_dat(path: &Path, track: &AnlzTrack) -> io::Result<()> {
+    let mut sections = Writer::default();
+    path_section(&mut sections, track.device_path);
+    let grid = track
+        .grid
+        .filter(|grid| GRID_BPM_RANGE.contains(&grid.bpm) && grid.first_beat_sec.is_finite());
+    if let Some(grid) = grid {
+        beat_grid_section(&mut sections, grid, bounded_duration(track.duration_seconds));
+    }
+
+    let mut memory: Vec<(u32, &CuePoint)> = Vec::new();
+    let mut hot_cues: Vec<(u32, &CuePoint)> = Vec::new();
+    for cue in track.cues {
+        match cue.hot_cue.filter(|&slot| slot < HOT_CUE_SLOTS) {
+            Some(slot) => hot_cues.push((slot as u32 + 1, cue)),
+            None => memory.push((0, cue)),
+        }
+    }
+    memory.sort_by(|a, b| a.1.start_seconds.total_cmp(&b.1.start_seconds));
+    hot_cues.sort_by_key(|(slot, _)| *slot);
+    cue_list_section(&mut sections, CUE_LIST_MEMORY, &memory);
+    cue_list_section(&mut sections, CUE_LIST_HOT_CUES, &hot_cues);
+
+    if let Some(bins) = track.waveform.filter(|bins| !bins.is_empty()) {
+        let preview = monochrome_entries(bins, track.max_band_energy, PREVIEW_LEN);
+        sections.section(b"PWAV", &be_u32s(&[PREVIEW_LEN as u32, 0x0001_0000]), &preview);
+        // The tiny preview only has four bits of height
+        let tiny: Vec<u8> = monochrome_entries(bins, track.max_band_energy, TINY_PREVIEW_LEN)
+            .into_iter()
+            .map(|entry| (entry & 0x1F) >> 1)
+            .collect();
+        sections.section(b"PWV2", &be_u32s(&[TINY_PREVIEW_LEN as u32, 0x0001_0000]), &tiny);
+    }
+
+    fs::write(path, file_bytes(sections))
+}
+
+/// Writes `ANLZ0000.EXT`: path and the scrolling waveforms, monochrome and
+/// in color (bass red, mids green, highs blue).
+pub fn write_ext(path: &Path, track: &AnlzTrack) -> io::Result<()> {
+    let mut sections = Writer::default();
+    path_section(&mut sections, track.device_path);
+
+    if let Some(bins) = track.waveform.filter(|bins| !bins.is_empty()) {
+        let entries = detail_len(bounded_duration(track.duration_seconds));
+        let detail = monochrome_entries(bins, track.max_band_energy, entries);
+        sections.section(b"PWV3", &be_u32s(&[1, entries as u32, 0x0096_0000]), &detail);
+
+        let mut color = Writer::default();
+        for (height, _, peak) in waveform_columns(bins, track.max_band_energy, entries) {
+            let loudest = peak.low.max(peak.mid).max(peak.high).max(f32::EPSILON);
+            let channel = |energy: f32| ((energy / loudest) * 7.0).round() as u16;
+            let height = (height * 31.0).round() as u16;
+            color.u16((channel(peak.low) << 13) | (channel(peak.mid) << 10) | (channel(peak.high) << 7) | (height << 2));
+        }
+        sections.section(b"PWV5", &be_u32s(&[2, entries as u32, 0x0096_0305]), &color.0);
+    }
+
+    fs::write(path, file_bytes(sections))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::exchange::test_support::{hot_cue, temp_dir};
+
+    fn be_u32(data: &[u8], at: usize) -> u32 {
+        u32::from_be_bytes(data[at..at + 4].try_into().unwrap())
+    }
+
+    /// Splits an analysis file into (tag, section) pairs, checking that the
+    /// lengths add up.
+    fn sections(file: &[u8]) -> Vec<([u8; 4], &[u8])> {
+        assert_eq!(&file[..4], b"PMAI");
+        assert_eq!(be_u32(file, 8) as usize, file.len());
+        let mut sections = Vec::new();
+        let mut at = FILE_HEADER_LEN as usize;
+        while at < file.len() {
+            let header_len = be_u32(file, at + 4) as usize;
+            let total_len = be_u32(file, at + 8) as usize;
+            assert!(header_len >= 12 && header_len <= total_len);
+            sections.push((file[at..at + 4].try_into().unwrap(), &file[at..at + total_len]));
+            at += total_len;
+        }
+        assert_eq!(at, file.len());
+        sections
+    }
+
+    fn section<'a>(sections: &[([u8; 4], &'a [u8])], tag: &[u8; 4]) -> Vec<&'a [u8]> {
+        sections.iter().filter(|(t, _)| t == tag).map(|(_, s)| *s).collect()
+    }
+
+    fn write(track: &AnlzTrack) -> (Vec<u8>, Vec<u8>) {
+        let dir = temp_dir("anlz");
+        write_dat(&dir.join("ANLZ0000.DAT"), track).unwrap();
+        write_ext(&dir.join("ANLZ0000.EXT"), track).unwrap();
+        let files = (
+            fs::read(dir.join("ANLZ0000.DAT")).unwrap(),
+            fs::read(dir.join("ANLZ0000.EXT")).unwrap(),
+        );
+        let _ = fs::remove_dir_all(&dir);
+        files
+    }
+
+    #[test]
+    fn sections_read_back() {
+        let mut memory_cue = hot_cue(0, 30.0, None);
+        memory_cue.hot_cue = None;
+        let mut loop_cue = hot_cue(1, 4.0, None);
+        loop_cue.kind = CueKind::Loop;
+        loop_cue.end_seconds = Some(8.0);
+        let cues = [hot_cue(0, 2.0, Some("A")), memory_cue, loop_cue, hot_cue(12, 5.0, None)];
+        let bins: Vec<WaveBin> = (0..1000)
+            .map(|i| WaveBin {
+                low: i as f32,
+                mid: 1.0,
+                high: 2.0,
+            })
+            .collect();
+        let track = AnlzTrack {
+            device_path: "/Contents/Ärtist/track.mp3",
+            duration_seconds: 60.0,
+            grid: Some(BeatGrid {
+                bpm: 120.0,
+                first_beat_sec: 0.25,
+            }),
+            cues: &cues,
+            waveform: Some(&bins),
+            max_band_energy: 999.0,
+        };
+        let (dat, ext) = write(&track);
+
+        let dat_sections = sections(&dat);
+        let path = section(&dat_sections, b"PPTH")[0];
+        let units: Vec<u16> = path[16..path.len() - 2].chunks(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+        assert_eq!(String::from_utf16(&units).unwrap(), track.device_path);
+
+        // 120 beats from 0.25s, plus none before it
+        let grid = section(&dat_sections, b"PQTZ")[0];
+        assert_eq!(be_u32(grid, 20), 120);
+        assert_eq!(&grid[24..32], &[0, 1, 0x2E, 0xE0, 0, 0, 0, 250]);
+
+        // Memory cues: the out-of-range hot cue and the plain memory cue, by time
+        let lists = section(&dat_sections, b"PCOB");
+        let starts = |list: &[u8]| -> Vec<(u32, u32)> {
+            list[24..]
+                .chunks(CUE_ENTRY_LEN as usize)
+                .map(|entry| (be_u32(entry, 12), be_u32(entry, 32)))
+                .collect()
+        };
+        assert_eq!(starts(lists[0]), [(0, 5000), (0, 30000)]);
+        assert_eq!(starts(lists[1]), [(1, 2000), (2, 4000)]);
+
+        assert_eq!(section(&dat_sections, b"PWAV")[0].len(), 20 + PREVIEW_LEN);
+        assert_eq!(section(&dat_sections, b"PWV2")[0].len(), 20 + TINY_PREVIEW_LEN);
+        let ext_sections = sections(&ext);
+        assert_eq!(section(&ext_sections, b"PWV3")[0].len(), 24 + 9000);
+        assert_eq!(section(&ext_sections, b"PWV5")[0].len(), 24 + 2 * 9000);
+    }
+
+    #[test]
+    fn bad_grids_and_durations_stay_bounded() {
+        let bins = [WaveBin {
+            low: 1.0,
+            mid: 1.0,
+            high: 1.0,
+        }];
+        for (bpm, first_beat_sec, duration_seconds) in [
+            (f32::INFINITY, 0.0, 60.0),
+            (f32::NAN, 0.0, 60.0),
+            (120.0, f32::NAN, 60.0),
+            (120.0, -1e30, 60.0),
+            (120.0, 0.0, f64::INFINITY),
+            (120.0, 0.0, 1e300),
+            (120.0, 0.0, f64::NAN),
+        ] {
+            let track = AnlzTrack {
+                device_path: "/Contents/track.mp3",
+                duration_seconds,
+                grid: Some(BeatGrid { bpm, first_beat_sec }),
+                cues: &[],
+                waveform: Some(&bins),
+                max_band_energy: 1.0,
+            };
+            let (dat, ext) = write(&track);
+            let dat_sections = sections(&dat);
+            for grid in section(&dat_sections, b"PQTZ") {
+                let beats = be_u32(grid, 20) as f64;
+                assert!(beats <= MAX_DURATION_SECONDS * 2.0 + 1.0, "{} beats", beats);
+            }
+            assert!(ext.len() <= 256 + 3 * detail_len(MAX_DURATION_SECONDS));
+            sections(&ext);
+        }
+    }
+}
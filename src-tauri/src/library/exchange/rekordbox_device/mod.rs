@@ -0,0 +1,361 @@
+//! Export to the device library CDJs and XDJs read from USB drives: audio
+//! under `Contents/`, the `PIONEER/rekordbox/export.pdb` database and an
+//! `ANLZ0000.DAT`/`.EXT` pair per track under `PIONEER/USBANLZ/`.
+
+use super::{format_date, parse_hex_color, track_key_name, track_title, ExportSource};
+use crate::audio::cache::{self, waveform};
+use crate::library::playlists::{PlaylistKind, PlaylistNode};
+use crate::library::{LibraryResult, LibraryTrack};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub mod anlz;
+pub mod pdb;
+
+const CONTENTS_DIR: &str = "Contents";
+const DATABASE_PATH: &str = "PIONEER/rekordbox/export.pdb";
+const ANLZ_DIR: &str = "PIONEER/USBANLZ";
+const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+/// RGB values of rekordbox's track colors, in color id order.
+const DEVICE_COLORS: [(u8, u8, u8); 8] = [
+    (0xFF, 0x00, 0x7F),
+    (0xFF, 0x00, 0x00),
+    (0xFF, 0xA5, 0x00),
+    (0xFF, 0xFF, 0x00),
+    (0x00, 0xFF, 0x00),
+    (0x25, 0xFD, 0xE9),
+    (0x00, 0x00, 0xFF),
+    (0x66, 0x00, 0x99),
+];
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceExportReport {
+    pub tracks: usize,
+    /// Audio files copied; files already on the device are left alone.
+    pub copied: usize,
+    pub playlists: usize,
+    /// Tracks whose files don't exist here, left out of the export.
+    pub missing: Vec<PathBuf>,
+    /// Exported tracks with neither a beat grid nor a cached waveform.
+    pub without_analysis: usize,
+    /// Tracks left out of the export, with the reason.
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+/// The rekordbox color id closest to a "#RRGGBB" color; 0 for none.
+fn nearest_device_color(color: &str) -> u8 {
+    let Some((r, g, b)) = parse_hex_color(color) else {
+        return 0;
+    };
+    let distance = |&(cr, cg, cb): &(u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, cr) + d(g, cg) + d(b, cb)
+    };
+    (0..DEVICE_COLORS.len())
+        .min_by_key(|&i| distance(&DEVICE_COLORS[i]))
+        .map_or(0, |i| i as u8 + 1)
+}
+
+// --- Device Paths ---
+
+/// FAT32 names hold up to 255 characters; this leaves room for the
+/// " (n)" suffix of duplicate names.
+const MAX_DEVICE_NAME_CHARS: usize = 240;
+
+/// A file or folder name FAT32 accepts.
+fn device_name(name: &str, fallback: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let cleaned = cleaned.trim().trim_end_matches('.');
+    if cleaned.is_empty() { fallback.to_string() } else { cleaned.to_string() }
+}
+
+fn capped(name: &str, max_chars: usize) -> String {
+    name.chars().take(max_chars).collect::<String>().trim_end().to_string()
+}
+
+/// `/Contents/<Artist>/<Album>/<file>`, suffixed with a number when another
+/// source file already took that name. FAT32 names are case-insensitive.
+fn device_track_path(track: &LibraryTrack, taken: &mut HashSet<String>) -> String {
+    let artist = capped(&device_name(track.tags.artist.as_deref().unwrap_or_default(), "Unknown Artist"), MAX_DEVICE_NAME_CHARS);
+    let album = capped(&device_name(track.tags.album.as_deref().unwrap_or_default(), "Unknown Album"), MAX_DEVICE_NAME_CHARS);
+    let file_name = device_name(&track.file_name, "track");
+    let (stem, extension) = match file_name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem.to_string(), format!(".{}", extension)),
+        _ => (file_name.clone(), String::new()),
+    };
+    let stem = capped(&stem, MAX_DEVICE_NAME_CHARS.saturating_sub(extension.chars().count()));
+    let file_name = format!("{}{}", stem, extension);
+
+    let mut candidate = format!("/{}/{}/{}/{}", CONTENTS_DIR, artist, album, file_name);
+    let mut counter = 2;
+    while !taken.insert(candidate.to_lowercase()) {
+        candidate = format!("/{}/{}/{}/{} ({}){}", CONTENTS_DIR, artist, album, stem, counter, extension);
+        counter += 1;
+    }
+    candidate
+}
+
+/// Where a track's analysis files go, derived from its device path the
+/// way rekordbox spreads them over `P###/########` folders.
+fn anlz_dir(device_path: &str) -> String {
+    let hash = blake3::hash(device_path.as_bytes());
+    let bytes = hash.as_bytes();
+    let bucket = u16::from_be_bytes([bytes[0], bytes[1]]) & 0x0FFF;
+    let folder = u32::from_be_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]);
+    format!("/{}/P{:03X}/{:08X}", ANLZ_DIR, bucket, folder)
+}
+
+fn on_device(target: &Path, device_path: &str) -> PathBuf {
+    target.join(device_path.trim_start_matches('/'))
+}
+
+/// Copies a file unless a file of the same size is already there. Returns
+/// whether it copied.
+fn copy_to_device(source: &Path, destination: &Path) -> LibraryResult<bool> {
+    let source_len = fs::metadata(source)?.len();
+    if fs::metadata(destination).is_ok_and(|m| m.len() == source_len) {
+        return Ok(false);
+    }
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(source, destination)?;
+    Ok(true)
+}
+
+// --- Playlists ---
+
+fn find_nodes<'a>(nodes: &'a [PlaylistNode], ids: &[String], found: &mut Vec<&'a PlaylistNode>) {
+    for node in nodes {
+        if ids.contains(&node.id) {
+            found.push(node);
+        } else {
+            find_nodes(&node.children, ids, found);
+        }
+    }
+}
+
+fn collect_tracks<'a>(nodes: &[&'a PlaylistNode], paths: &mut HashSet<&'a Path>) {
+    for node in nodes {
+        paths.extend(node.tracks.iter().map(PathBuf::as_path));
+        let children: Vec<&PlaylistNode> = node.children.iter().collect();
+        collect_tracks(&children, paths);
+    }
+}
+
+/// Adds a node to the playlist tree. Crates become folders; a crate's own
+/// tracks go into a playlist of the same name inside its folder.
+fn add_playlist_node(
+    builder: &mut pdb::PdbBuilder,
+    node: &PlaylistNode,
+    parent_id: u32,
+    sort_order: u32,
+    track_ids: &HashMap<&Path, u32>,
+) -> usize {
+    let ids_of = |tracks: &[PathBuf]| -> Vec<u32> {
+        tracks
+            .iter()
+            .filter_map(|path| track_ids.get(path.as_path()).copied())
+            .collect()
+    };
+    match node.kind {
+        PlaylistKind::Playlist | PlaylistKind::Smart => {
+            let id = builder.add_playlist(parent_id, sort_order, false, &node.name);
+            builder.set_playlist_tracks(id, ids_of(&node.tracks));
+            1
+        }
+        PlaylistKind::Crate => {
+            let folder_id = builder.add_playlist(parent_id, sort_order, true, &node.name);
+            let mut count = 0;
+            let mut order = 0;
+            if !node.tracks.is_empty() {
+                let id = builder.add_playlist(folder_id, order, false, &node.name);
+                builder.set_playlist_tracks(id, ids_of(&node.tracks));
+                count += 1;
+                order += 1;
+            }
+            for child in &node.children {
+                count += add_playlist_node(builder, child, folder_id, order, track_ids);
+                order += 1;
+            }
+            count
+        }
+    }
+}
+
+// --- Tracks ---
+
+/// Writes a track's analysis files and returns whether they carry a grid or
+/// a waveform.
+fn write_analysis(
+    target: &Path,
+    source: &ExportSource,
+    track: &LibraryTrack,
+    device_path: &str,
+    analyze_dir: &str,
+    duration_seconds: f64,
+    cache_dir: Option<&Path>,
+) -> LibraryResult<bool> {
+    let waveform = match (cache_dir, &track.analysis) {
+        (Some(cache_dir), Some(analysis)) => waveform::load_waveform_data(cache_dir, &analysis.content_hash)
+            .map_err(|e| log::debug!("No waveform for {}: {}", track.path.display(), e))
+            .ok(),
+        _ => None,
+    };
+    let anlz_track = anlz::AnlzTrack {
+        device_path,
+        duration_seconds,
+        grid: source.grid(track),
+        cues: source.cues(track),
+        waveform: waveform.as_ref().and_then(|w| w.levels.first()).map(Vec::as_slice),
+        max_band_energy: waveform.as_ref().map_or(0.0, |w| w.max_band_energy),
+    };
+
+    let dir = on_device(target, analyze_dir);
+    fs::create_dir_all(&dir)?;
+    anlz::write_dat(&dir.join("ANLZ0000.DAT"), &anlz_track)?;
+    anlz::write_ext(&dir.join("ANLZ0000.EXT"), &anlz_track)?;
+    Ok(anlz_track.grid.is_some() || anlz_track.waveform.is_some())
+}
+
+fn track_row(
+    builder: &mut pdb::PdbBuilder,
+    track: &LibraryTrack,
+    device_path: &str,
+    duration_seconds: f64,
+    sample_rate: u32,
+) -> pdb::TrackRow {
+    let tags = &track.tags;
+    let artist_id = builder.artist(tags.artist.as_deref().unwrap_or_default());
+    let album_id = builder.album(tags.album.as_deref().unwrap_or_default(), artist_id);
+    let kbps = if duration_seconds > 0.0 {
+        (track.file_size as f64 * 8.0 / duration_seconds / 1000.0).round() as u32
+    } else {
+        0
+    };
+    pdb::TrackRow {
+        title: track_title(track),
+        artist_id,
+        album_id,
+        genre_id: builder.genre(tags.genre.as_deref().unwrap_or_default()),
+        label_id: builder.label(tags.label.as_deref().unwrap_or_default()),
+        key_id: builder.key(&track_key_name(track).unwrap_or_default()),
+        color_id: track.user.color.as_deref().map_or(0, nearest_device_color),
+        rating: track.user.rating.unwrap_or(0).min(5),
+        comment: track.user.comment.clone().or_else(|| tags.comment.clone()).unwrap_or_default(),
+        sample_rate,
+        bitrate: kbps,
+        file_size: track.file_size.min(u32::MAX as u64) as u32,
+        duration_seconds: duration_seconds.round().min(u16::MAX as f64) as u16,
+        year: tags.date.as_deref().and_then(|d| d.get(..4)).and_then(|y| y.parse().ok()).unwrap_or(0),
+        play_count: track.play_count.min(u16::MAX as u32) as u16,
+        date_added: format_date(track.added_at),
+        file_name: device_path.rsplit('/').next().unwrap_or_default().to_string(),
+        file_path: device_path.to_string(),
+        analyze_date: format_date(cache::unix_now()),
+        ..Default::default()
+    }
+}
+
+/// Writes a device library into `target` (usually a USB drive's root): the
+/// tracks of the given playlists, or of the whole library and every
+/// playlist when `playlist_ids` is `None`. Grids, cues and waveforms come
+/// from the cue store and the analysis cache in `cache_dir`.
+pub fn export_device_library(
+    target: &Path,
+    source: &ExportSource,
+    playlist_ids: Option<&[String]>,
+    cache_dir: Option<&Path>,
+) -> LibraryResult<DeviceExportReport> {
+    let mut report = DeviceExportReport::default();
+    let mut nodes: Vec<&PlaylistNode> = Vec::new();
+    let tracks: Vec<&LibraryTrack> = match playlist_ids {
+        Some(ids) => {
+            find_nodes(&source.playlists, ids, &mut nodes);
+            let mut wanted = HashSet::new();
+            collect_tracks(&nodes, &mut wanted);
+            source.tracks.iter().filter(|t| wanted.contains(t.path.as_path())).collect()
+        }
+        None => {
+            nodes = source.playlists.iter().collect();
+            source.tracks.iter().collect()
+        }
+    };
+
+    let mut builder = pdb::PdbBuilder::default();
+    let mut track_ids: HashMap<&Path, u32> = HashMap::new();
+    let mut taken = HashSet::new();
+    for track in tracks {
+        if !track.path.is_file() {
+            report.missing.push(track.path.clone());
+            continue;
+        }
+        let device_path = device_track_path(track, &mut taken);
+        let cached = cache_dir.and_then(|dir| cache::peek_cached_entry(&track.path, dir));
+        let metadata = track.analysis.as_ref().map(|a| &a.metadata);
+        let duration_seconds = metadata
+            .and_then(|m| m.duration_seconds)
+            .or_else(|| cached.as_ref().map(|c| c.fingerprint.duration_ms as f64 / 1000.0))
+            .unwrap_or(0.0);
+        let sample_rate = cached.as_ref().map_or(DEFAULT_SAMPLE_RATE, |c| c.fingerprint.sample_rate);
+
+        let analyze_dir = anlz_dir(&device_path);
+
+        let id = track_ids.len() as u32 + 1;
+        let row = pdb::TrackRow {
+            id,
+            tempo: source.grid(track).map_or(0, |g| (g.bpm * 100.0).round() as u32),
+            analyze_path: format!("{}/ANLZ0000.DAT", analyze_dir),
+            ..track_row(&mut builder, track, &device_path, duration_seconds, sample_rate)
+        };
+        if let Err(e) = builder.add_track(row) {
+            log::warn!("Failed to export {}: {}", track.path.display(), e);
+            report.failed.push((track.path.clone(), e.to_string()));
+            continue;
+        }
+
+        if copy_to_device(&track.path, &on_device(target, &device_path))? {
+            report.copied += 1;
+        }
+        let analyzed = write_analysis(target, source, track, &device_path, &analyze_dir, duration_seconds, cache_dir)?;
+        if !analyzed {
+            report.without_analysis += 1;
+        }
+        track_ids.insert(track.path.as_path(), id);
+    }
+    report.tracks = track_ids.len();
+
+    for (order, node) in nodes.iter().enumerate() {
+        report.playlists += add_playlist_node(&mut builder, node, 0, order as u32, &track_ids);
+    }
+
+    let database = target.join(DATABASE_PATH);
+    if let Some(parent) = database.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp_file = database.with_extension("pdb.tmp");
+    fs::write(&temp_file, builder.build())?;
+    fs::rename(&temp_file, &database)?;
+
+    log::info!(
+        "Exported {} tracks ({} copied, {} missing, {} failed) and {} playlists to {}",
+        report.tracks,
+        report.copied,
+        report.missing.len(),
+        report.failed.len(),
+        report.playlists,
+        target.display()
+    );
+    Ok(report)
+}
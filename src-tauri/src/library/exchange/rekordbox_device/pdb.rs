@@ -0,0 +1,834 @@
+//! The device database, `PIONEER/rekordbox/export.pdb`.
+//!
+//! A DeviceSQL file of 4096-byte little-endian pages. Page 0 lists the
+//! tables; every table starts with an index page followed by a chain of
+//! data pages. Rows grow from the top of a data page, and their offsets are
+//! kept in groups of 16 at the bottom. Row layouts follow the community
+//! documentation of the format (Deep Symmetry's analysis, rekordcrate).
+
+use crate::library::{LibraryError, LibraryResult};
+
+const PAGE_LEN: usize = 4096;
+const PAGE_HEADER_LEN: usize = 0x28;
+const ROW_GROUP_LEN: usize = 0x24;
+const ROWS_PER_GROUP: usize = 16;
+/// Rows start on 4-byte boundaries.
+const ROW_ALIGNMENT: usize = 4;
+
+const PAGE_FLAGS_DATA: u8 = 0x34;
+const PAGE_FLAGS_INDEX: u8 = 0x64;
+/// Marks unused slots and missing links in index pages.
+const INDEX_EMPTY: u32 = 0x03FF_FFFF;
+const INDEX_ENTRY_EMPTY: u32 = 0x1FFF_FFF8;
+
+/// Offset of the string offset table in a track row.
+const TRACK_ROW_HEADER_LEN: usize = 0x5E;
+const TRACK_STRING_COUNT: usize = 21;
+/// The largest row that fits on a page of its own, with one row group.
+const MAX_ROW_LEN: usize = PAGE_LEN - PAGE_HEADER_LEN - ROW_GROUP_LEN;
+/// Names of artists, albums, playlists and the like are cut to this length.
+const MAX_NAME_CHARS: usize = 255;
+
+/// Tables in the order rekordbox writes them; the value is the table type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Table {
+    Tracks = 0,
+    Genres = 1,
+    Artists = 2,
+    Albums = 3,
+    Labels = 4,
+    Keys = 5,
+    Colors = 6,
+    PlaylistTree = 7,
+    PlaylistEntries = 8,
+    Unknown9 = 9,
+    Unknown10 = 10,
+    HistoryPlaylists = 11,
+    HistoryEntries = 12,
+    Artwork = 13,
+    Unknown14 = 14,
+    Unknown15 = 15,
+    Columns = 16,
+    Unknown17 = 17,
+    Unknown18 = 18,
+    History = 19,
+}
+
+const TABLES: [Table; 20] = [
+    Table::Tracks,
+    Table::Genres,
+    Table::Artists,
+    Table::Albums,
+    Table::Labels,
+    Table::Keys,
+    Table::Colors,
+    Table::PlaylistTree,
+    Table::PlaylistEntries,
+    Table::Unknown9,
+    Table::Unknown10,
+    Table::HistoryPlaylists,
+    Table::HistoryEntries,
+    Table::Artwork,
+    Table::Unknown14,
+    Table::Unknown15,
+    Table::Columns,
+    Table::Unknown17,
+    Table::Unknown18,
+    Table::History,
+];
+
+/// Browse columns of the device menus: id, unknown code and name.
+const COLUMNS: [(u16, u16, &str); 27] = [
+    (1, 0x80, "GENRE"),
+    (2, 0x81, "ARTIST"),
+    (3, 0x82, "ALBUM"),
+    (4, 0x83, "TRACK"),
+    (5, 0x85, "BPM"),
+    (6, 0x86, "RATING"),
+    (7, 0x87, "YEAR"),
+    (8, 0x88, "REMIXER"),
+    (9, 0x89, "LABEL"),
+    (10, 0x8A, "ORIGINAL ARTIST"),
+    (11, 0x8B, "KEY"),
+    (12, 0x8D, "CUE"),
+    (13, 0x8E, "COLOR"),
+    (14, 0x92, "TIME"),
+    (15, 0x93, "BITRATE"),
+    (16, 0x94, "FILE NAME"),
+    (17, 0x84, "PLAYLIST"),
+    (18, 0x98, "HOT CUE BANK"),
+    (19, 0x95, "HISTORY"),
+    (20, 0x91, "SEARCH"),
+    (21, 0x96, "COMMENTS"),
+    (22, 0x8C, "DATE ADDED"),
+    (23, 0x97, "DJ PLAY COUNT"),
+    (24, 0x90, "FOLDER"),
+    (25, 0xA1, "DEFAULT"),
+    (26, 0xA2, "ALPHABET"),
+    (27, 0xAA, "MATCHING"),
+];
+
+/// rekordbox's eight track colors, by color id.
+const COLORS: [(u8, &str); 8] = [
+    (1, "Pink"),
+    (2, "Red"),
+    (3, "Orange"),
+    (4, "Yellow"),
+    (5, "Green"),
+    (6, "Aqua"),
+    (7, "Blue"),
+    (8, "Purple"),
+];
+
+// --- Rows ---
+
+/// Encodes a DeviceSQL string: short ASCII with a one-byte header, longer
+/// or non-ASCII text with a four-byte header (UTF-16LE for non-ASCII).
+fn device_string(text: &str) -> Vec<u8> {
+    if text.is_ascii() && text.len() <= 126 {
+        let mut out = vec![(((text.len() + 1) << 1) | 1) as u8];
+        out.extend_from_slice(text.as_bytes());
+        return out;
+    }
+    let (flags, data): (u8, Vec<u8>) = if text.is_ascii() {
+        (0x40, text.as_bytes().to_vec())
+    } else {
+        (0x90, text.encode_utf16().flat_map(u16::to_le_bytes).collect())
+    };
+    let mut out = vec![flags];
+    out.extend_from_slice(&((data.len() + 4) as u16).to_le_bytes());
+    out.push(0);
+    out.extend_from_slice(&data);
+    out
+}
+
+/// The longest prefix of `text` whose encoding takes at most `max_len`
+/// bytes.
+fn fit_string(text: &str, max_len: usize) -> &str {
+    if device_string(text).len() <= max_len {
+        return text;
+    }
+    // Counted as the long form, which a prefix never exceeds
+    let budget = max_len.saturating_sub(4);
+    let mut used = 0;
+    let mut end = 0;
+    for (i, c) in text.char_indices() {
+        used += if text.is_ascii() { 1 } else { 2 * c.len_utf16() };
+        if used > budget {
+            break;
+        }
+        end = i + c.len_utf8();
+    }
+    &text[..end]
+}
+
+fn capped_name(name: &str) -> String {
+    name.chars().take(MAX_NAME_CHARS).collect()
+}
+
+#[derive(Default)]
+struct RowBuilder(Vec<u8>);
+
+impl RowBuilder {
+    fn u8(&mut self, value: u8) -> &mut Self {
+        self.0.push(value);
+        self
+    }
+
+    fn u16(&mut self, value: u16) -> &mut Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn u32(&mut self, value: u32) -> &mut Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn string(&mut self, text: &str) -> &mut Self {
+        self.0.extend_from_slice(&device_string(text));
+        self
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.0)
+    }
+}
+
+/// A track row's fields.
+#[derive(Debug, Clone, Default)]
+pub struct TrackRow {
+    pub id: u32,
+    pub title: String,
+    pub artist_id: u32,
+    pub album_id: u32,
+    pub genre_id: u32,
+    pub label_id: u32,
+    pub key_id: u32,
+    pub color_id: u8,
+    /// Stars, 0-5.
+    pub rating: u8,
+    pub comment: String,
+    pub sample_rate: u32,
+    pub bitrate: u32,
+    pub file_size: u32,
+    pub duration_seconds: u16,
+    /// BPM times 100.
+    pub tempo: u32,
+    pub year: u16,
+    pub play_count: u16,
+    /// "YYYY-MM-DD".
+    pub date_added: String,
+    pub file_name: String,
+    /// Path on the device, e.g. "/Contents/Artist/Album/track.mp3".
+    pub file_path: String,
+    /// Path of the track's ANLZ0000.DAT on the device.
+    pub analyze_path: String,
+    pub analyze_date: String,
+}
+
+impl TrackRow {
+    /// Encodes the row. The comment, then the title, are cut short when the
+    /// row would otherwise not fit on a page.
+    fn encode(&self, index_shift: u16) -> Vec<u8> {
+        let mut row = RowBuilder::default();
+        row.u16(0x24)
+            .u16(index_shift)
+            .u32(0x000C_0700)
+            .u32(self.sample_rate)
+            // Composer
+            .u32(0)
+            .u32(self.file_size)
+            .u32(0)
+            .u16(0)
+            .u16(0)
+            // Artwork
+            .u32(0)
+            .u32(self.key_id)
+            // Original artist
+            .u32(0)
+            .u32(self.label_id)
+            // Remixer
+            .u32(0)
+            .u32(self.bitrate)
+            // Track number
+            .u32(0)
+            .u32(self.tempo)
+            .u32(self.genre_id)
+            .u32(self.album_id)
+            .u32(self.artist_id)
+            .u32(self.id)
+            // Disc number
+            .u16(0)
+            .u16(self.play_count)
+            .u16(self.year)
+            // Sample depth
+            .u16(16)
+            .u16(self.duration_seconds)
+            .u16(0x29)
+            .u8(self.color_id)
+            .u8(self.rating)
+            .u16(1)
+            .u16(3);
+        let mut data = row.finish();
+        debug_assert_eq!(data.len(), TRACK_ROW_HEADER_LEN);
+
+        let mut strings = vec![String::new(); TRACK_STRING_COUNT];
+        strings[7] = "ON".to_string();
+        strings[10] = self.date_added.clone();
+        strings[14] = self.analyze_path.clone();
+        strings[15] = self.analyze_date.clone();
+        strings[19] = self.file_name.clone();
+        strings[20] = self.file_path.clone();
+
+        // Title and comment are still empty, one byte each
+        let mut spare = MAX_ROW_LEN.saturating_sub(
+            TRACK_ROW_HEADER_LEN + TRACK_STRING_COUNT * 2 + strings.iter().map(|s| device_string(s).len()).sum::<usize>(),
+        );
+        let title = fit_string(&self.title, spare + 1);
+        spare -= device_string(title).len() - 1;
+        let comment = fit_string(&self.comment, spare + 1);
+        strings[16] = comment.to_string();
+        strings[17] = title.to_string();
+
+        let mut offset = TRACK_ROW_HEADER_LEN + TRACK_STRING_COUNT * 2;
+        let mut heap = Vec::new();
+        for text in &strings {
+            data.extend_from_slice(&(offset as u16).to_le_bytes());
+            let encoded = device_string(text);
+            offset += encoded.len();
+            heap.extend(encoded);
+        }
+        data.extend(heap);
+        data
+    }
+}
+
+/// Rows of every table, built up before the file is laid out.
+#[derive(Default)]
+pub struct PdbBuilder {
+    tracks: Vec<TrackRow>,
+    genres: Vec<String>,
+    artists: Vec<String>,
+    albums: Vec<(String, u32)>,
+    labels: Vec<String>,
+    keys: Vec<String>,
+    /// (id, parent id, sort order, is folder, name)
+    playlists: Vec<(u32, u32, u32, bool, String)>,
+    /// (playlist id, track ids in order)
+    playlist_entries: Vec<(u32, Vec<u32>)>,
+}
+
+/// Finds or appends a name and returns its 1-based id; 0 for empty names.
+fn intern(names: &mut Vec<String>, name: &str) -> u32 {
+    if name.is_empty() {
+        return 0;
+    }
+    let name = capped_name(name);
+    let index = names.iter().position(|n| *n == name).unwrap_or_else(|| {
+        names.push(name);
+        names.len() - 1
+    });
+    index as u32 + 1
+}
+
+impl PdbBuilder {
+    pub fn genre(&mut self, name: &str) -> u32 {
+        intern(&mut self.genres, name)
+    }
+
+    pub fn artist(&mut self, name: &str) -> u32 {
+        intern(&mut self.artists, name)
+    }
+
+    pub fn label(&mut self, name: &str) -> u32 {
+        intern(&mut self.labels, name)
+    }
+
+    pub fn key(&mut self, name: &str) -> u32 {
+        intern(&mut self.keys, name)
+    }
+
+    pub fn album(&mut self, name: &str, artist_id: u32) -> u32 {
+        if name.is_empty() {
+            return 0;
+        }
+        let name = capped_name(name);
+        let index = self
+            .albums
+            .iter()
+            .position(|(n, a)| *n == name && *a == artist_id)
+            .unwrap_or_else(|| {
+                self.albums.push((name, artist_id));
+                self.albums.len() - 1
+            });
+        index as u32 + 1
+    }
+
+    /// Adds a track, unless its paths alone make a row too large for a page.
+    pub fn add_track(&mut self, track: TrackRow) -> LibraryResult<()> {
+        let row_len = TrackRow {
+            title: String::new(),
+            comment: String::new(),
+            ..track.clone()
+        }
+        .encode(0)
+        .len();
+        if row_len > MAX_ROW_LEN {
+            return Err(LibraryError::DeviceExport(format!(
+                "track row of {} bytes doesn't fit on a page: {}",
+                row_len, track.file_path
+            )));
+        }
+        self.tracks.push(track);
+        Ok(())
+    }
+
+    /// Adds a playlist or folder and returns its id.
+    pub fn add_playlist(&mut self, parent_id: u32, sort_order: u32, is_folder: bool, name: &str) -> u32 {
+        let id = self.playlists.len() as u32 + 1;
+        self.playlists.push((id, parent_id, sort_order, is_folder, capped_name(name)));
+        id
+    }
+
+    pub fn set_playlist_tracks(&mut self, playlist_id: u32, track_ids: Vec<u32>) {
+        self.playlist_entries.push((playlist_id, track_ids));
+    }
+
+    fn table_rows(&self, table: Table) -> Vec<Vec<u8>> {
+        let mut row = RowBuilder::default();
+        match table {
+            Table::Tracks => self
+                .tracks
+                .iter()
+                .enumerate()
+                .map(|(i, track)| track.encode(index_shift(i)))
+                .collect(),
+            Table::Genres => named_rows(&self.genres),
+            Table::Labels => named_rows(&self.labels),
+            Table::Artists => self
+                .artists
+                .iter()
+                .enumerate()
+                .map(|(i, name)| {
+                    // Subtype 0x60: the name follows the 10-byte header
+                    row.u16(0x60)
+                        .u16(index_shift(i))
+                        .u32(i as u32 + 1)
+                        .u8(0x03)
+                        .u8(10)
+                        .string(name)
+                        .finish()
+                })
+                .collect(),
+            Table::Albums => self
+                .albums
+                .iter()
+                .enumerate()
+                .map(|(i, (name, artist_id))| {
+                    row.u16(0x80)
+                        .u16(index_shift(i))
+                        .u32(0)
+                        .u32(*artist_id)
+                        .u32(i as u32 + 1)
+                        .u32(0)
+                        .u8(0x03)
+                        .u8(22)
+                        .string(name)
+                        .finish()
+                })
+                .collect(),
+            Table::Keys => self
+                .keys
+                .iter()
+                .enumerate()
+                .map(|(i, name)| row.u32(i as u32 + 1).u32(i as u32 + 1).string(name).finish())
+                .collect(),
+            Table::Colors => COLORS
+                .iter()
+                .map(|(id, name)| {
+                    row.u32(0).u8(0).u16(*id as u16).u8(0).string(name).finish()
+                })
+                .collect(),
+            Table::PlaylistTree => self
+                .playlists
+                .iter()
+                .map(|(id, parent_id, sort_order, is_folder, name)| {
+                    row.u32(*parent_id)
+                        .u32(0)
+                        .u32(*sort_order)
+                        .u32(*id)
+                        .u32(u32::from(*is_folder))
+                        .string(name)
+                        .finish()
+                })
+                .collect(),
+            Table::PlaylistEntries => self
+                .playlist_entries
+                .iter()
+                .flat_map(|(playlist_id, track_ids)| {
+                    track_ids.iter().enumerate().map(move |(i, track_id)| (*playlist_id, i, *track_id))
+                })
+                .map(|(playlist_id, i, track_id)| row.u32(i as u32 + 1).u32(track_id).u32(playlist_id).finish())
+                .collect(),
+            Table::Columns => COLUMNS
+                .iter()
+                .map(|(id, code, name)| {
+                    // Column names are wrapped in U+FFFA/U+FFFB, forcing UTF-16
+                    let wrapped = format!("\u{FFFA}{}\u{FFFB}", name);
+                    row.u16(*id).u16(*code).string(&wrapped).finish()
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Lays out every table into pages and returns the file contents.
+    pub fn build(&self) -> Vec<u8> {
+        let mut next_page: u32 = 1;
+        let mut pages: Vec<(u32, Vec<u8>)> = Vec::new();
+        let mut pointers = Vec::with_capacity(TABLES.len());
+
+        for table in TABLES {
+            let rows = self.table_rows(table);
+            let row_pages = paginate(&rows);
+
+            let index_page = next_page;
+            let data_pages: Vec<u32> = (0..row_pages.len() as u32).map(|i| index_page + 1 + i).collect();
+            let empty_candidate = index_page + 1 + row_pages.len() as u32;
+            next_page = empty_candidate + 1;
+
+            let first_data = data_pages.first().copied().unwrap_or(empty_candidate);
+            pages.push((index_page, index_page_bytes(table, index_page, first_data)));
+            for (i, page_rows) in row_pages.iter().enumerate() {
+                let link = data_pages.get(i + 1).copied().unwrap_or(empty_candidate);
+                pages.push((data_pages[i], data_page_bytes(table, data_pages[i], link, page_rows)));
+            }
+            let last_page = data_pages.last().copied().unwrap_or(index_page);
+            pointers.push((table as u32, empty_candidate, index_page, last_page));
+        }
+
+        let mut file = vec![0u8; next_page as usize * PAGE_LEN];
+        let mut header = RowBuilder::default();
+        header
+            .u32(0)
+            .u32(PAGE_LEN as u32)
+            .u32(TABLES.len() as u32)
+            .u32(next_page)
+            .u32(5)
+            // Sequence number
+            .u32(1)
+            .u32(0);
+        for (table_type, empty_candidate, first_page, last_page) in pointers {
+            header.u32(table_type).u32(empty_candidate).u32(first_page).u32(last_page);
+        }
+        let header = header.finish();
+        file[..header.len()].copy_from_slice(&header);
+        for (index, page) in pages {
+            let start = index as usize * PAGE_LEN;
+            file[start..start + PAGE_LEN].copy_from_slice(&page);
+        }
+        file
+    }
+}
+
+fn index_shift(row: usize) -> u16 {
+    ((row % ROWS_PER_GROUP) as u16) * 0x20
+}
+
+fn named_rows(names: &[String]) -> Vec<Vec<u8>> {
+    let mut row = RowBuilder::default();
+    names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| row.u32(i as u32 + 1).string(name).finish())
+        .collect()
+}
+
+// --- Pages ---
+
+fn aligned(len: usize) -> usize {
+    len.div_ceil(ROW_ALIGNMENT) * ROW_ALIGNMENT
+}
+
+fn row_index_len(row_count: usize) -> usize {
+    row_count.div_ceil(ROWS_PER_GROUP) * ROW_GROUP_LEN
+}
+
+/// Splits rows into the sets that fit on one page each.
+fn paginate(rows: &[Vec<u8>]) -> Vec<Vec<&[u8]>> {
+    let mut pages: Vec<Vec<&[u8]>> = Vec::new();
+    let mut current: Vec<&[u8]> = Vec::new();
+    let mut heap_len = 0;
+    for row in rows {
+        debug_assert!(row.len() <= MAX_ROW_LEN, "row of {} bytes", row.len());
+        let needed = PAGE_HEADER_LEN + heap_len + aligned(row.len()) + row_index_len(current.len() + 1);
+        if needed > PAGE_LEN && !current.is_empty() {
+            pages.push(std::mem::take(&mut current));
+            heap_len = 0;
+        }
+        heap_len += aligned(row.len());
+        current.push(row);
+    }
+    if !current.is_empty() {
+        pages.push(current);
+    }
+    pages
+}
+
+fn page_header(page: &mut [u8], table: Table, page_index: u32, next_page: u32, row_count: usize, flags: u8) {
+    page[4..8].copy_from_slice(&page_index.to_le_bytes());
+    page[8..12].copy_from_slice(&(table as u32).to_le_bytes());
+    page[12..16].copy_from_slice(&next_page.to_le_bytes());
+    page[16..20].copy_from_slice(&1u32.to_le_bytes());
+    // 13 bits of row offsets, then 11 bits of valid rows
+    let packed = (row_count as u32 & 0x1FFF) | ((row_count as u32 & 0x7FF) << 13);
+    page[24..27].copy_from_slice(&packed.to_le_bytes()[..3]);
+    page[27] = flags;
+}
+
+fn data_page_bytes(table: Table, page_index: u32, next_page: u32, rows: &[&[u8]]) -> Vec<u8> {
+    let mut page = vec![0u8; PAGE_LEN];
+    let mut offsets = Vec::with_capacity(rows.len());
+    let mut heap_len = 0;
+    for row in rows {
+        let start = PAGE_HEADER_LEN + heap_len;
+        page[start..start + row.len()].copy_from_slice(row);
+        offsets.push(heap_len as u16);
+        heap_len += aligned(row.len());
+    }
+
+    page_header(&mut page, table, page_index, next_page, rows.len(), PAGE_FLAGS_DATA);
+    let free_len = PAGE_LEN - PAGE_HEADER_LEN - heap_len - row_index_len(rows.len());
+    page[28..30].copy_from_slice(&(free_len as u16).to_le_bytes());
+    page[30..32].copy_from_slice(&(heap_len as u16).to_le_bytes());
+    page[32..34].copy_from_slice(&1u16.to_le_bytes());
+    page[34..36].copy_from_slice(&(rows.len() as u16).to_le_bytes());
+
+    for (group, group_offsets) in offsets.chunks(ROWS_PER_GROUP).enumerate() {
+        let base = PAGE_LEN - group * ROW_GROUP_LEN;
+        let present = ((1u32 << group_offsets.len()) - 1) as u16;
+        page[base - 2..base].copy_from_slice(&present.to_le_bytes());
+        page[base - 4..base - 2].copy_from_slice(&present.to_le_bytes());
+        for (i, offset) in group_offsets.iter().enumerate() {
+            let position = base - 6 - 2 * i;
+            page[position..position + 2].copy_from_slice(&offset.to_le_bytes());
+        }
+    }
+    page
+}
+
+/// The first page of a table. We don't write index entries; the page only
+/// links to the table's data pages.
+fn index_page_bytes(table: Table, page_index: u32, next_page: u32) -> Vec<u8> {
+    let mut page = vec![0u8; PAGE_LEN];
+    page_header(&mut page, table, page_index, next_page, 0, PAGE_FLAGS_INDEX);
+    page[28..30].copy_from_slice(&0u16.to_le_bytes());
+    page[30..32].copy_from_slice(&0u16.to_le_bytes());
+    page[34..36].copy_from_slice(&0x1FFFu16.to_le_bytes());
+
+    let mut content = RowBuilder::default();
+    content
+        .u16(0x1FFF)
+        .u16(0x1FFF)
+        .u16(0x03EC)
+        .u16(0)
+        .u32(page_index)
+        .u32(INDEX_EMPTY)
+        .u32(INDEX_EMPTY)
+        .u32(0)
+        .u16(0)
+        .u16(0x1FFF);
+    let content = content.finish();
+    let mut position = PAGE_HEADER_LEN;
+    page[position..position + content.len()].copy_from_slice(&content);
+    position += content.len();
+    while position + 4 <= PAGE_LEN - 20 {
+        page[position..position + 4].copy_from_slice(&INDEX_ENTRY_EMPTY.to_le_bytes());
+        position += 4;
+    }
+    page
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decodes the DeviceSQL string at the start of `data`.
+    fn read_string(data: &[u8]) -> String {
+        let flags = data[0];
+        if flags & 1 == 1 {
+            let len = (flags >> 1) as usize - 1;
+            return String::from_utf8(data[1..1 + len].to_vec()).unwrap();
+        }
+        let len = u16::from_le_bytes([data[1], data[2]]) as usize - 4;
+        let body = &data[4..4 + len];
+        if flags == 0x90 {
+            let units: Vec<u16> = body.chunks(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+            String::from_utf16(&units).unwrap()
+        } else {
+            String::from_utf8(body.to_vec()).unwrap()
+        }
+    }
+
+    /// The string at `index` of an encoded track row.
+    fn track_string(row: &[u8], index: usize) -> String {
+        let at = TRACK_ROW_HEADER_LEN + index * 2;
+        let offset = u16::from_le_bytes([row[at], row[at + 1]]) as usize;
+        read_string(&row[offset..])
+    }
+
+    fn track(id: u32) -> TrackRow {
+        TrackRow {
+            id,
+            title: format!("Track {}", id),
+            file_name: format!("{}.mp3", id),
+            file_path: format!("/Contents/Artist/Album/{}.mp3", id),
+            analyze_path: "/PIONEER/USBANLZ/P000/00000000/ANLZ0000.DAT".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn le_u16(data: &[u8], at: usize) -> usize {
+        u16::from_le_bytes([data[at], data[at + 1]]) as usize
+    }
+
+    fn le_u32(data: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+    }
+
+    /// Rows of a table, found by following its page chain from the file
+    /// header. Each row runs to the end of its page's heap.
+    fn read_table(file: &[u8], table: Table) -> Vec<&[u8]> {
+        let pointer = 28 + TABLES.iter().position(|&t| t == table).unwrap() * 16;
+        assert_eq!(le_u32(file, pointer), table as u32);
+        let empty_candidate = le_u32(file, pointer + 4);
+        let mut page_index = le_u32(file, pointer + 8);
+        let last_page = le_u32(file, pointer + 12);
+
+        let mut rows = Vec::new();
+        loop {
+            let page = &file[page_index as usize * PAGE_LEN..][..PAGE_LEN];
+            assert_eq!(le_u32(page, 4), page_index);
+            assert_eq!(le_u32(page, 8), table as u32);
+            if page[27] == PAGE_FLAGS_DATA {
+                let row_count = le_u16(page, 34);
+                let heap_end = PAGE_HEADER_LEN + le_u16(page, 30);
+                let free = le_u16(page, 28);
+                assert_eq!(heap_end + free + row_index_len(row_count), PAGE_LEN);
+                for row in 0..row_count {
+                    let base = PAGE_LEN - row / ROWS_PER_GROUP * ROW_GROUP_LEN;
+                    assert!(le_u16(page, base - 2) & (1 << (row % ROWS_PER_GROUP)) != 0);
+                    let start = PAGE_HEADER_LEN + le_u16(page, base - 6 - 2 * (row % ROWS_PER_GROUP));
+                    rows.push(&page[start..heap_end]);
+                }
+            }
+            if page_index == last_page {
+                break;
+            }
+            page_index = le_u32(page, 12);
+        }
+        let last = &file[last_page as usize * PAGE_LEN..][..PAGE_LEN];
+        assert_eq!(le_u32(last, 12), empty_candidate);
+        rows
+    }
+
+    #[test]
+    fn tables_read_back_across_pages() {
+        let mut builder = PdbBuilder::default();
+        let mut ids = Vec::new();
+        for id in 1..=300 {
+            let artist_id = builder.artist(&format!("Ärtist {}", id % 7));
+            builder
+                .add_track(TrackRow {
+                    artist_id,
+                    comment: "c".repeat(id as usize),
+                    ..track(id)
+                })
+                .unwrap();
+            ids.push(id);
+        }
+        let folder = builder.add_playlist(0, 0, true, "Folder");
+        let playlist = builder.add_playlist(folder, 0, false, "Set");
+        builder.set_playlist_tracks(playlist, ids.iter().rev().copied().collect());
+        let file = builder.build();
+
+        let tracks = read_table(&file, Table::Tracks);
+        assert_eq!(tracks.len(), 300);
+        for (row, id) in tracks.iter().zip(1..) {
+            assert_eq!(le_u32(row, 72), id);
+            assert_eq!(track_string(row, 17), format!("Track {}", id));
+            assert_eq!(track_string(row, 16), "c".repeat(id as usize));
+            assert_eq!(track_string(row, 20), format!("/Contents/Artist/Album/{}.mp3", id));
+        }
+
+        let artists = read_table(&file, Table::Artists);
+        assert_eq!(artists.len(), 7);
+        assert_eq!(read_string(&artists[1][10..]), "Ärtist 2");
+
+        let tree = read_table(&file, Table::PlaylistTree);
+        assert_eq!(read_string(&tree[1][20..]), "Set");
+        assert_eq!(le_u32(tree[1], 0), folder);
+        let entries = read_table(&file, Table::PlaylistEntries);
+        assert_eq!(entries.len(), 300);
+        assert_eq!((le_u32(entries[0], 4), le_u32(entries[0], 8)), (300, playlist));
+        assert!(read_table(&file, Table::History).is_empty());
+    }
+
+    #[test]
+    fn oversized_title_and_comment_are_cut_to_fit_a_page() {
+        let row = TrackRow {
+            title: "Tïtle ".repeat(200),
+            comment: "Cömment ".repeat(1000),
+            ..track(1)
+        };
+        let encoded = row.encode(0);
+        assert!(encoded.len() <= MAX_ROW_LEN);
+
+        let title = track_string(&encoded, 17);
+        let comment = track_string(&encoded, 16);
+        assert_eq!(title, row.title, "the title fits, so only the comment is cut");
+        assert!(!comment.is_empty() && row.comment.starts_with(&comment));
+        assert_eq!(track_string(&encoded, 20), row.file_path);
+
+        let mut builder = PdbBuilder::default();
+        builder.add_track(row).unwrap();
+        builder.add_track(track(2)).unwrap();
+        let file = builder.build();
+        assert_eq!(file.len() % PAGE_LEN, 0);
+    }
+
+    #[test]
+    fn track_with_oversized_path_is_rejected() {
+        let long_path = format!("/Contents/{}/track.mp3", "ü".repeat(3000));
+        let mut builder = PdbBuilder::default();
+        let result = builder.add_track(TrackRow {
+            file_path: long_path,
+            ..track(1)
+        });
+        assert!(matches!(result, Err(LibraryError::DeviceExport(_))));
+        assert!(builder.tracks.is_empty());
+    }
+
+    #[test]
+    fn long_names_are_capped() {
+        let mut builder = PdbBuilder::default();
+        let long = "n".repeat(10_000);
+        assert_eq!(builder.artist(&long), 1);
+        assert_eq!(builder.artist(&long), 1, "capped names still intern to the same id");
+        builder.album(&long, 1);
+        builder.add_playlist(0, 0, false, &long);
+        assert_eq!(builder.artists[0].chars().count(), MAX_NAME_CHARS);
+        assert_eq!(builder.albums[0].0.chars().count(), MAX_NAME_CHARS);
+        assert_eq!(builder.playlists[0].4.chars().count(), MAX_NAME_CHARS);
+    }
+
+    #[test]
+    fn fit_string_keeps_whole_characters() {
+        assert_eq!(fit_string("short", 100), "short");
+        let cut = fit_string("日本語のタイトル", 10);
+        assert_eq!(cut, "日本語");
+        assert!(device_string(cut).len() <= 10);
+        assert_eq!(fit_string("anything", 0), "");
+    }
+}
@@ -0,0 +1,408 @@
+//! rekordbox's XML collection format (`rekordbox.xml`, File > Export
+//! Collection in xml format).
+
+use super::{
+    format_date, non_empty, parse_date, parse_hex_color, parse_number, rgb_hex, track_key_name, track_title,
+    xml_attributes, xml_escape, ExportSource, ExternalCollection, ExternalNode, ExternalTrack,
+};
+use crate::library::cues::{BeatGrid, CueKind, CuePoint};
+use crate::library::playlist_files::{file_url_to_path, path_to_file_url};
+use crate::library::playlists::{PlaylistKind, PlaylistNode};
+use crate::library::{LibraryError, LibraryResult, LibraryTrack};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the crate imported playlists are placed under.
+pub const REKORDBOX_CRATE_NAME: &str = "rekordbox";
+
+// POSITION_MARK types; fade-in, fade-out and load points are read as cues
+const MARK_TYPE_CUE: &str = "0";
+const MARK_TYPE_LOOP: &str = "4";
+
+/// rekordbox stores star ratings as 0, 51, 102, 153, 204 or 255.
+const RATING_STEP: u32 = 51;
+
+// --- Import ---
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Other,
+    Collection,
+    Playlists,
+}
+
+/// A NODE being read from the PLAYLISTS tree.
+struct NodeFrame {
+    name: String,
+    is_folder: bool,
+    /// KeyType 1: playlist entries reference tracks by Location, not TrackID.
+    keyed_by_location: bool,
+    children: Vec<ExternalNode>,
+    tracks: Vec<PathBuf>,
+}
+
+impl NodeFrame {
+    fn into_node(self) -> ExternalNode {
+        if self.is_folder {
+            ExternalNode::Folder {
+                name: self.name,
+                children: self.children,
+            }
+        } else {
+            ExternalNode::Playlist {
+                name: self.name,
+                tracks: self.tracks,
+            }
+        }
+    }
+}
+
+fn parse_collection_track(attributes: &HashMap<String, String>) -> Option<(String, ExternalTrack)> {
+    let path = file_url_to_path(attributes.get("Location")?)?;
+    let id = attributes.get("TrackID").cloned().unwrap_or_default();
+    let rating = parse_number::<u32>(attributes, "Rating").map(|r| ((r + RATING_STEP / 2) / RATING_STEP).min(5) as u8);
+    let color = attributes
+        .get("Colour")
+        .and_then(|c| parse_hex_color(c))
+        .map(|(r, g, b)| rgb_hex(r, g, b));
+    let track = ExternalTrack {
+        path,
+        rating,
+        color,
+        comment: non_empty(attributes, "Comments"),
+        key: non_empty(attributes, "Tonality"),
+        play_count: parse_number(attributes, "PlayCount").unwrap_or(0),
+        added_at: attributes.get("DateAdded").and_then(|d| parse_date(d)),
+        ..Default::default()
+    };
+    Some((id, track))
+}
+
+fn parse_position_mark(attributes: &HashMap<String, String>) -> Option<CuePoint> {
+    let start_seconds: f64 = parse_number(attributes, "Start")?;
+    let kind = match attributes.get("Type").map(String::as_str) {
+        Some(MARK_TYPE_LOOP) => CueKind::Loop,
+        _ => CueKind::Cue,
+    };
+    // -1 for memory cues; anything that isn't a slot is one too
+    let hot_cue = parse_number::<u8>(attributes, "Num");
+    let color = match (
+        parse_number::<u8>(attributes, "Red"),
+        parse_number::<u8>(attributes, "Green"),
+        parse_number::<u8>(attributes, "Blue"),
+    ) {
+        (Some(r), Some(g), Some(b)) => Some(rgb_hex(r, g, b)),
+        _ => None,
+    };
+    Some(CuePoint {
+        kind,
+        hot_cue,
+        start_seconds,
+        end_seconds: parse_number(attributes, "End").filter(|_| kind == CueKind::Loop),
+        name: non_empty(attributes, "Name"),
+        color,
+    })
+}
+
+/// Reads a rekordbox XML collection. Only the first TEMPO of each track is
+/// used, since our grids have a single tempo.
+pub fn read_rekordbox_xml(xml_path: &Path) -> LibraryResult<ExternalCollection> {
+    let mut reader = Reader::from_file(xml_path)?;
+    let mut buf = Vec::new();
+
+    let mut section = Section::Other;
+    let mut saw_root = false;
+    let mut tracks: Vec<ExternalTrack> = Vec::new();
+    let mut paths_by_id: HashMap<String, PathBuf> = HashMap::new();
+    let mut current: Option<ExternalTrack> = None;
+    let mut stack: Vec<NodeFrame> = Vec::new();
+    let mut playlists: Vec<ExternalNode> = Vec::new();
+
+    loop {
+        let event = reader.read_event_into(&mut buf)?;
+        match &event {
+            Event::Start(element) | Event::Empty(element) => {
+                let is_empty = matches!(event, Event::Empty(_));
+                match (section, element.name().as_ref()) {
+                    (_, b"DJ_PLAYLISTS") => saw_root = true,
+                    (_, b"COLLECTION") => section = Section::Collection,
+                    (_, b"PLAYLISTS") => section = Section::Playlists,
+                    (Section::Collection, b"TRACK") => {
+                        let attributes = xml_attributes(element)?;
+                        if let Some((id, track)) = parse_collection_track(&attributes) {
+                            paths_by_id.insert(id, track.path.clone());
+                            if is_empty {
+                                tracks.push(track);
+                            } else {
+                                current = Some(track);
+                            }
+                        }
+                    }
+                    (Section::Collection, b"TEMPO") => {
+                        if let Some(track) = current.as_mut().filter(|t| t.grid.is_none()) {
+                            let attributes = xml_attributes(element)?;
+                            if let (Some(bpm), Some(first_beat_sec)) =
+                                (parse_number(&attributes, "Bpm"), parse_number(&attributes, "Inizio"))
+                            {
+                                track.grid = Some(BeatGrid { bpm, first_beat_sec });
+                            }
+                        }
+                    }
+                    (Section::Collection, b"POSITION_MARK") => {
+                        if let Some(track) = current.as_mut()
+                            && let Some(cue) = parse_position_mark(&xml_attributes(element)?)
+                        {
+                            track.cues.push(cue);
+                        }
+                    }
+                    (Section::Playlists, b"NODE") => {
+                        let attributes = xml_attributes(element)?;
+                        let frame = NodeFrame {
+                            name: attributes.get("Name").cloned().unwrap_or_default(),
+                            is_folder: attributes.get("Type").map(String::as_str) == Some("0"),
+                            keyed_by_location: attributes.get("KeyType").map(String::as_str) == Some("1"),
+                            children: Vec::new(),
+                            tracks: Vec::new(),
+                        };
+                        if is_empty {
+                            attach_node(&mut stack, &mut playlists, frame.into_node());
+                        } else {
+                            stack.push(frame);
+                        }
+                    }
+                    (Section::Playlists, b"TRACK") => {
+                        if let Some(frame) = stack.last_mut() {
+                            let attributes = xml_attributes(element)?;
+                            let key = attributes.get("Key").map(String::as_str).unwrap_or_default();
+                            let path = if frame.keyed_by_location {
+                                file_url_to_path(key)
+                            } else {
+                                paths_by_id.get(key).cloned()
+                            };
+                            match path {
+                                Some(path) => frame.tracks.push(path),
+                                None => log::debug!("rekordbox import: unknown playlist entry {}", key),
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(element) => match (section, element.name().as_ref()) {
+                (Section::Collection, b"TRACK") => tracks.extend(current.take()),
+                (Section::Playlists, b"NODE") => {
+                    if let Some(frame) = stack.pop() {
+                        attach_node(&mut stack, &mut playlists, frame.into_node());
+                    }
+                }
+                (_, b"COLLECTION") | (_, b"PLAYLISTS") => section = Section::Other,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    if !saw_root {
+        return Err(LibraryError::InvalidCollection(format!(
+            "{} is not a rekordbox XML collection",
+            xml_path.display()
+        )));
+    }
+
+    // Everything hangs off a single "ROOT" folder
+    if let [ExternalNode::Folder { name, .. }] = playlists.as_slice()
+        && name == "ROOT"
+        && let Some(ExternalNode::Folder { children, .. }) = playlists.pop()
+    {
+        playlists = children;
+    }
+
+    log::info!(
+        "Read {} tracks and {} top-level playlist nodes from {}",
+        tracks.len(),
+        playlists.len(),
+        xml_path.display()
+    );
+    Ok(ExternalCollection { tracks, playlists })
+}
+
+fn attach_node(stack: &mut [NodeFrame], playlists: &mut Vec<ExternalNode>, node: ExternalNode) {
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(node),
+        None => playlists.push(node),
+    }
+}
+
+// --- Export ---
+
+fn file_kind(track: &LibraryTrack) -> &'static str {
+    let extension = track
+        .path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "mp3" => "MP3 File",
+        "flac" => "FLAC File",
+        "wav" | "wave" => "WAV File",
+        "aac" | "m4a" => "M4A File",
+        _ => "Audio File",
+    }
+}
+
+fn push_attribute(out: &mut String, name: &str, value: &str) {
+    let _ = write!(out, " {}=\"{}\"", name, xml_escape(value));
+}
+
+fn write_track(out: &mut String, id: usize, track: &LibraryTrack, source: &ExportSource) {
+    let tags = &track.tags;
+    let duration = track
+        .analysis
+        .as_ref()
+        .and_then(|a| a.metadata.duration_seconds)
+        .unwrap_or(0.0);
+    let grid = source.grid(track);
+
+    out.push_str("    <TRACK");
+    push_attribute(out, "TrackID", &id.to_string());
+    push_attribute(out, "Name", &track_title(track));
+    push_attribute(out, "Artist", tags.artist.as_deref().unwrap_or_default());
+    push_attribute(out, "Album", tags.album.as_deref().unwrap_or_default());
+    push_attribute(out, "Genre", tags.genre.as_deref().unwrap_or_default());
+    push_attribute(out, "Kind", file_kind(track));
+    push_attribute(out, "Size", &track.file_size.to_string());
+    push_attribute(out, "TotalTime", &(duration.round() as u64).to_string());
+    push_attribute(out, "Year", tags.date.as_deref().unwrap_or_default());
+    push_attribute(
+        out,
+        "AverageBpm",
+        &grid.map(|g| format!("{:.2}", g.bpm)).unwrap_or_else(|| "0.00".to_string()),
+    );
+    push_attribute(out, "DateAdded", &format_date(track.added_at));
+    push_attribute(out, "Comments", track.user.comment.as_deref().or(tags.comment.as_deref()).unwrap_or_default());
+    push_attribute(out, "PlayCount", &track.play_count.to_string());
+    push_attribute(out, "Rating", &(track.user.rating.unwrap_or(0).min(5) as u32 * RATING_STEP).to_string());
+    push_attribute(out, "Location", &path_to_file_url(&track.path));
+    push_attribute(out, "Tonality", &track_key_name(track).unwrap_or_default());
+    push_attribute(out, "Label", tags.label.as_deref().unwrap_or_default());
+    if let Some((r, g, b)) = track.user.color.as_deref().and_then(parse_hex_color) {
+        push_attribute(out, "Colour", &format!("0x{:02X}{:02X}{:02X}", r, g, b));
+    }
+
+    let cues = source.cues(track);
+    if grid.is_none() && cues.is_empty() {
+        out.push_str("/>\n");
+        return;
+    }
+    out.push_str(">\n");
+    if let Some(grid) = grid {
+        let _ = writeln!(
+            out,
+            "      <TEMPO Inizio=\"{:.3}\" Bpm=\"{:.2}\" Metro=\"4/4\" Battito=\"1\"/>",
+            grid.first_beat_sec, grid.bpm
+        );
+    }
+    for cue in cues {
+        out.push_str("      <POSITION_MARK");
+        push_attribute(out, "Name", cue.name.as_deref().unwrap_or_default());
+        let mark_type = match cue.kind {
+            CueKind::Cue => MARK_TYPE_CUE,
+            CueKind::Loop => MARK_TYPE_LOOP,
+        };
+        push_attribute(out, "Type", mark_type);
+        push_attribute(out, "Start", &format!("{:.3}", cue.start_seconds));
+        if let (CueKind::Loop, Some(end)) = (cue.kind, cue.end_seconds) {
+            push_attribute(out, "End", &format!("{:.3}", end));
+        }
+        push_attribute(out, "Num", &cue.hot_cue.map(|n| n as i32).unwrap_or(-1).to_string());
+        if let Some((r, g, b)) = cue.color.as_deref().and_then(parse_hex_color) {
+            let _ = write!(out, " Red=\"{}\" Green=\"{}\" Blue=\"{}\"", r, g, b);
+        }
+        out.push_str("/>\n");
+    }
+    out.push_str("    </TRACK>\n");
+}
+
+fn write_playlist_entries(out: &mut String, indent: &str, name: &str, tracks: &[PathBuf], ids: &HashMap<&Path, usize>) {
+    let entries: Vec<usize> = tracks.iter().filter_map(|p| ids.get(p.as_path()).copied()).collect();
+    let _ = writeln!(
+        out,
+        "{}<NODE Name=\"{}\" Type=\"1\" KeyType=\"0\" Entries=\"{}\">",
+        indent,
+        xml_escape(name),
+        entries.len()
+    );
+    for id in entries {
+        let _ = writeln!(out, "{}  <TRACK Key=\"{}\"/>", indent, id);
+    }
+    let _ = writeln!(out, "{}</NODE>", indent);
+}
+
+/// Writes a playlist tree node. rekordbox folders can't hold tracks, so a
+/// crate's own tracks become a playlist of the same name inside its folder.
+fn write_playlist_node(out: &mut String, depth: usize, node: &PlaylistNode, ids: &HashMap<&Path, usize>) {
+    let indent = "  ".repeat(depth);
+    match node.kind {
+        PlaylistKind::Playlist | PlaylistKind::Smart => write_playlist_entries(out, &indent, &node.name, &node.tracks, ids),
+        PlaylistKind::Crate => {
+            let count = node.children.len() + usize::from(!node.tracks.is_empty());
+            let _ = writeln!(
+                out,
+                "{}<NODE Type=\"0\" Name=\"{}\" Count=\"{}\">",
+                indent,
+                xml_escape(&node.name),
+                count
+            );
+            if !node.tracks.is_empty() {
+                write_playlist_entries(out, &format!("{}  ", indent), &node.name, &node.tracks, ids);
+            }
+            for child in &node.children {
+                write_playlist_node(out, depth + 1, child, ids);
+            }
+            let _ = writeln!(out, "{}</NODE>", indent);
+        }
+    }
+}
+
+/// Writes the library, grids, cues and playlists as a rekordbox XML
+/// collection. Returns how many tracks were written. Playlist entries for
+/// files that no longer exist are left out.
+pub fn write_rekordbox_xml(xml_path: &Path, source: &ExportSource) -> LibraryResult<usize> {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<DJ_PLAYLISTS Version=\"1.0.0\">\n");
+    let _ = writeln!(
+        out,
+        "  <PRODUCT Name=\"open-dj\" Version=\"{}\" Company=\"open-dj\"/>",
+        env!("CARGO_PKG_VERSION")
+    );
+
+    let _ = writeln!(out, "  <COLLECTION Entries=\"{}\">", source.tracks.len());
+    let mut ids: HashMap<&Path, usize> = HashMap::new();
+    for (i, track) in source.tracks.iter().enumerate() {
+        ids.insert(track.path.as_path(), i + 1);
+        write_track(&mut out, i + 1, track, source);
+    }
+    out.push_str("  </COLLECTION>\n");
+
+    out.push_str("  <PLAYLISTS>\n");
+    let _ = writeln!(out, "    <NODE Type=\"0\" Name=\"ROOT\" Count=\"{}\">", source.playlists.len());
+    for node in &source.playlists {
+        write_playlist_node(&mut out, 3, node, &ids);
+    }
+    out.push_str("    </NODE>\n  </PLAYLISTS>\n</DJ_PLAYLISTS>\n");
+
+    if let Some(parent) = xml_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(xml_path, out)?;
+    log::info!("Exported {} tracks to {}", source.tracks.len(), xml_path.display());
+    Ok(source.tracks.len())
+}
@@ -0,0 +1,782 @@
+//! Serato's prep data, which lives inside the audio files: cues, saved loops
+//! and the track color in `Serato Markers2`, the beat grid in `Serato
+//! BeatGrid` and the BPM shown in its library in `Serato Autotags`. MP3s
+//! carry them as ID3v2 GEOB frames, FLACs as base64 Vorbis comments.
+
+use super::{parse_hex_color, rgb_hex, ExportSource, ExternalTrack};
+use crate::audio::cache;
+use crate::library::cues::{BeatGrid, CueKind, CuePoint, CueStore, TrackCues};
+use crate::library::store::LibraryStore;
+use crate::library::tag_files::flac::{self, FlacMetadata};
+use crate::library::tag_files::id3v2::{self, Id3Tag};
+use crate::library::LibraryResult;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// One of Serato's embedded objects: its GEOB description in ID3v2 and its
+/// field name in Vorbis comments.
+#[derive(Clone, Copy)]
+struct SeratoObject {
+    description: &'static str,
+    vorbis_field: &'static str,
+}
+
+const MARKERS2: SeratoObject = SeratoObject {
+    description: "Serato Markers2",
+    vorbis_field: "SERATO_MARKERS_V2",
+};
+const BEATGRID: SeratoObject = SeratoObject {
+    description: "Serato BeatGrid",
+    vorbis_field: "SERATO_BEATGRID",
+};
+const AUTOTAGS: SeratoObject = SeratoObject {
+    description: "Serato Autotags",
+    vorbis_field: "SERATO_AUTOTAGS",
+};
+const OBJECT_MIME_TYPE: &str = "application/octet-stream";
+
+const MARKERS2_VERSION: [u8; 2] = [0x01, 0x01];
+const BEATGRID_VERSION: [u8; 2] = [0x01, 0x00];
+const AUTOTAGS_VERSION: [u8; 2] = [0x01, 0x01];
+/// Serato pads Markers2 objects to at least this size.
+const MARKERS2_MIN_LEN: usize = 470;
+const BASE64_LINE_LEN: usize = 72;
+
+/// Serato has eight hot cue and eight saved loop slots.
+const SERATO_SLOTS: u8 = 8;
+const DEFAULT_CUE_COLOR: (u8, u8, u8) = (0xCC, 0x00, 0x00);
+const DEFAULT_LOOP_COLOR: (u8, u8, u8) = (0x27, 0xAA, 0xE1);
+/// Gain fields written to new Autotags objects ("no adjustment").
+const NEUTRAL_GAIN: &str = "0.000";
+
+/// Serato's prep data for one file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SeratoTrackData {
+    pub cues: Vec<CuePoint>,
+    pub grid: Option<BeatGrid>,
+    /// Track color as "#RRGGBB".
+    pub color: Option<String>,
+}
+
+impl SeratoTrackData {
+    pub fn is_empty(&self) -> bool {
+        self.cues.is_empty() && self.grid.is_none() && self.color.is_none()
+    }
+
+    fn into_external_track(self, path: PathBuf) -> ExternalTrack {
+        ExternalTrack {
+            path,
+            grid: self.grid,
+            cues: self.cues,
+            color: self.color,
+            ..Default::default()
+        }
+    }
+}
+
+// --- Base64 ---
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8], padded: bool) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &b)| bits | ((b as u32) << (16 - 8 * i)));
+        let symbols = chunk.len() + 1;
+        for i in 0..4 {
+            if i < symbols {
+                out.push(BASE64_ALPHABET[(bits >> (18 - 6 * i)) as usize & 0x3F] as char);
+            } else if padded {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Wraps base64 text into lines the way Serato writes it.
+fn wrap_lines(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + text.len() / BASE64_LINE_LEN + 1);
+    for (i, c) in text.chars().enumerate() {
+        if i > 0 && i % BASE64_LINE_LEN == 0 {
+            out.push('\n');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Decodes base64, skipping line breaks and padding. Serato sometimes ends
+/// its data with a stray symbol; trailing bits that don't make a full byte
+/// are dropped.
+fn base64_decode(text: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    for &c in text {
+        let Some(value) = BASE64_ALPHABET.iter().position(|&a| a == c) else {
+            continue;
+        };
+        bits = (bits << 6) | value as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            out.push((bits >> bit_count) as u8);
+        }
+    }
+    out
+}
+
+// --- Object Formats ---
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_f32(data: &[u8], offset: usize) -> Option<f32> {
+    read_u32(data, offset).map(f32::from_bits)
+}
+
+/// A NUL-terminated UTF-8 string, or the rest of the data if unterminated.
+fn read_c_string(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+fn color_at(data: &[u8], offset: usize) -> Option<String> {
+    let rgb = data.get(offset..offset + 3)?;
+    Some(rgb_hex(rgb[0], rgb[1], rgb[2]))
+}
+
+/// Markers2 content: typed entries we interpret plus the rest (flips, BPM
+/// lock), kept so that writing back doesn't drop them.
+#[derive(Default)]
+struct Markers2 {
+    color: Option<String>,
+    cues: Vec<CuePoint>,
+    other_entries: Vec<(String, Vec<u8>)>,
+}
+
+fn parse_markers2(object: &[u8]) -> Markers2 {
+    let mut markers = Markers2::default();
+    let Some(encoded) = object.strip_prefix(&MARKERS2_VERSION) else {
+        return markers;
+    };
+    let encoded_len = encoded.iter().position(|&b| b == 0).unwrap_or(encoded.len());
+    let decoded = base64_decode(&encoded[..encoded_len]);
+    let Some(mut entries) = decoded.strip_prefix(&MARKERS2_VERSION) else {
+        return markers;
+    };
+
+    // Each entry: NUL-terminated type name, u32 length, data; an empty name ends the list
+    while let Some(name_len) = entries.iter().position(|&b| b == 0).filter(|&len| len > 0) {
+        let name = String::from_utf8_lossy(&entries[..name_len]).into_owned();
+        let Some(len) = read_u32(entries, name_len + 1) else {
+            break;
+        };
+        let start = name_len + 5;
+        let end = start.saturating_add(len as usize);
+        let Some(data) = entries.get(start..end) else {
+            log::debug!("Serato Markers2 entry {} is truncated", name);
+            break;
+        };
+        entries = &entries[end..];
+
+        match name.as_str() {
+            "COLOR" => markers.color = color_at(data, 1),
+            "CUE" if data.len() >= 12 => markers.cues.push(CuePoint {
+                kind: CueKind::Cue,
+                hot_cue: Some(data[1]),
+                start_seconds: read_u32(data, 2).unwrap_or(0) as f64 / 1000.0,
+                end_seconds: None,
+                name: Some(read_c_string(&data[12..])).filter(|n| !n.is_empty()),
+                color: color_at(data, 7),
+            }),
+            "LOOP" if data.len() >= 19 => markers.cues.push(CuePoint {
+                kind: CueKind::Loop,
+                hot_cue: Some(data[1]),
+                start_seconds: read_u32(data, 2).unwrap_or(0) as f64 / 1000.0,
+                end_seconds: read_u32(data, 6).map(|ms| ms as f64 / 1000.0),
+                name: Some(read_c_string(&data[19..])).filter(|n| !n.is_empty()),
+                color: color_at(data, 15),
+            }),
+            _ => markers.other_entries.push((name, data.to_vec())),
+        }
+    }
+    markers
+}
+
+fn push_markers2_entry(out: &mut Vec<u8>, name: &str, data: &[u8]) {
+    out.extend_from_slice(name.as_bytes());
+    out.push(0);
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(data);
+}
+
+fn rgb_or(color: Option<&str>, default: (u8, u8, u8)) -> [u8; 3] {
+    let (r, g, b) = color.and_then(parse_hex_color).unwrap_or(default);
+    [r, g, b]
+}
+
+fn seconds_to_ms(seconds: f64) -> u32 {
+    (seconds.max(0.0) * 1000.0).round() as u32
+}
+
+fn serialize_markers2(markers: &Markers2) -> Vec<u8> {
+    let mut content = MARKERS2_VERSION.to_vec();
+    if let Some(color) = &markers.color {
+        let [r, g, b] = rgb_or(Some(color), (0xFF, 0xFF, 0xFF));
+        push_markers2_entry(&mut content, "COLOR", &[0, r, g, b]);
+    }
+    for cue in &markers.cues {
+        let slot = cue.hot_cue.unwrap_or(0);
+        let name = cue.name.as_deref().unwrap_or_default().as_bytes();
+        let mut data = vec![0, slot];
+        data.extend_from_slice(&seconds_to_ms(cue.start_seconds).to_be_bytes());
+        match (cue.kind, cue.end_seconds) {
+            (CueKind::Loop, Some(end)) => {
+                data.extend_from_slice(&seconds_to_ms(end).to_be_bytes());
+                data.extend_from_slice(&[0xFF; 4]);
+                data.push(0);
+                data.extend_from_slice(&rgb_or(cue.color.as_deref(), DEFAULT_LOOP_COLOR));
+                // Not locked
+                data.push(0);
+                data.extend_from_slice(name);
+                data.push(0);
+                push_markers2_entry(&mut content, "LOOP", &data);
+            }
+            _ => {
+                data.push(0);
+                data.extend_from_slice(&rgb_or(cue.color.as_deref(), DEFAULT_CUE_COLOR));
+                data.extend_from_slice(&[0, 0]);
+                data.extend_from_slice(name);
+                data.push(0);
+                push_markers2_entry(&mut content, "CUE", &data);
+            }
+        }
+    }
+    for (name, data) in &markers.other_entries {
+        push_markers2_entry(&mut content, name, data);
+    }
+    content.push(0);
+
+    let mut object = MARKERS2_VERSION.to_vec();
+    object.extend_from_slice(wrap_lines(&base64_encode(&content, false)).as_bytes());
+    object.push(0);
+    if object.len() < MARKERS2_MIN_LEN {
+        object.resize(MARKERS2_MIN_LEN, 0);
+    }
+    object
+}
+
+/// Reads the first grid marker as our grid. Serato grids may change tempo
+/// at later markers; the BPM of the first section is used.
+fn parse_beatgrid(object: &[u8]) -> Option<BeatGrid> {
+    let data = object.strip_prefix(&BEATGRID_VERSION)?;
+    let count = read_u32(data, 0)?;
+    let first_position = read_f32(data, 4)?;
+    let bpm = if count == 1 {
+        read_f32(data, 8)?
+    } else {
+        let beats = read_u32(data, 8)? as f32;
+        let next_position = read_f32(data, 12)?;
+        let span = next_position - first_position;
+        if span <= 0.0 {
+            return None;
+        }
+        beats / span * 60.0
+    };
+    (bpm.is_finite() && bpm > 0.0).then_some(BeatGrid {
+        bpm,
+        first_beat_sec: first_position,
+    })
+}
+
+fn serialize_beatgrid(grid: BeatGrid) -> Vec<u8> {
+    let mut object = BEATGRID_VERSION.to_vec();
+    object.extend_from_slice(&1u32.to_be_bytes());
+    object.extend_from_slice(&grid.first_beat_sec.to_be_bytes());
+    object.extend_from_slice(&grid.bpm.to_be_bytes());
+    // Footer
+    object.push(0);
+    object
+}
+
+/// Autotags: NUL-terminated BPM, auto gain and gain strings.
+fn parse_autotags(object: &[u8]) -> Vec<String> {
+    let Some(data) = object.strip_prefix(&AUTOTAGS_VERSION) else {
+        return Vec::new();
+    };
+    data.split(|&b| b == 0)
+        .take(3)
+        .map(|field| String::from_utf8_lossy(field).into_owned())
+        .collect()
+}
+
+/// Autotags with a new BPM, keeping the gain fields of `existing`.
+fn serialize_autotags(bpm: f32, existing: &[String]) -> Vec<u8> {
+    let mut object = AUTOTAGS_VERSION.to_vec();
+    let bpm = format!("{:.2}", bpm);
+    let auto_gain = existing.get(1).map(String::as_str).unwrap_or(NEUTRAL_GAIN);
+    let gain = existing.get(2).map(String::as_str).unwrap_or(NEUTRAL_GAIN);
+    for field in [bpm.as_str(), auto_gain, gain] {
+        object.extend_from_slice(field.as_bytes());
+        object.push(0);
+    }
+    object
+}
+
+// --- Files ---
+
+/// A file's tag block, in whichever container its format uses.
+enum TaggedFile {
+    Mp3(Id3Tag),
+    Flac(FlacMetadata),
+}
+
+/// FLAC comments hold the GEOB fields (MIME type, file name, description)
+/// and the object, base64-encoded together.
+fn unwrap_flac_object(value: &str, description: &str) -> Option<Vec<u8>> {
+    let decoded = base64_decode(value.as_bytes());
+    let mut fields = decoded.splitn(4, |&b| b == 0);
+    let _mime_type = fields.next()?;
+    let _file_name = fields.next()?;
+    if fields.next()? != description.as_bytes() {
+        return None;
+    }
+    fields.next().map(<[u8]>::to_vec)
+}
+
+fn wrap_flac_object(description: &str, object: &[u8]) -> String {
+    let mut data = Vec::with_capacity(object.len() + 64);
+    for field in [OBJECT_MIME_TYPE, "", description] {
+        data.extend_from_slice(field.as_bytes());
+        data.push(0);
+    }
+    data.extend_from_slice(object);
+    wrap_lines(&base64_encode(&data, true))
+}
+
+impl TaggedFile {
+    /// Opens the tags of formats Serato embeds data in; `None` for others.
+    fn open(path: &Path) -> LibraryResult<Option<Self>> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+        Ok(match extension.as_deref() {
+            Some("mp3") => Some(TaggedFile::Mp3(id3v2::read_tag(path)?)),
+            Some("flac") => Some(TaggedFile::Flac(flac::read_metadata(path)?)),
+            _ => None,
+        })
+    }
+
+    fn object(&self, object: SeratoObject) -> Option<Vec<u8>> {
+        match self {
+            TaggedFile::Mp3(tag) => tag.geob(object.description).map(<[u8]>::to_vec),
+            TaggedFile::Flac(metadata) => metadata
+                .comment(object.vorbis_field)
+                .and_then(|value| unwrap_flac_object(value, object.description)),
+        }
+    }
+
+    fn set_object(&mut self, object: SeratoObject, data: &[u8]) {
+        match self {
+            TaggedFile::Mp3(tag) => tag.set_geob(object.description, OBJECT_MIME_TYPE, data),
+            TaggedFile::Flac(metadata) => {
+                metadata.set_comment(object.vorbis_field, Some(wrap_flac_object(object.description, data)))
+            }
+        }
+    }
+
+    fn save(&self, path: &Path) -> LibraryResult<()> {
+        match self {
+            TaggedFile::Mp3(tag) => id3v2::write_tag(path, tag),
+            TaggedFile::Flac(metadata) => flac::write_metadata(path, metadata),
+        }
+    }
+}
+
+/// Reads Serato's cues, loops, grid and color from a file. Returns `None`
+/// for formats Serato doesn't embed data in and for files without any.
+pub fn read_serato_data(path: &Path) -> LibraryResult<Option<SeratoTrackData>> {
+    let Some(file) = TaggedFile::open(path)? else {
+        return Ok(None);
+    };
+    let markers = file.object(MARKERS2).map(|object| parse_markers2(&object)).unwrap_or_default();
+    let data = SeratoTrackData {
+        cues: markers.cues,
+        grid: file.object(BEATGRID).and_then(|object| parse_beatgrid(&object)),
+        color: markers.color,
+    };
+    Ok((!data.is_empty()).then_some(data))
+}
+
+/// Writes cues, loops, a grid and a track color into a file's Serato
+/// objects, keeping Serato entries we don't manage. Serato has no memory
+/// cues, so only cues with a hot cue slot are written; loops without a slot
+/// take the next free one. Returns false for formats Serato doesn't embed
+/// data in.
+pub fn write_serato_data(
+    path: &Path,
+    cues: &[CuePoint],
+    grid: Option<BeatGrid>,
+    color: Option<&str>,
+) -> LibraryResult<bool> {
+    let Some(mut file) = TaggedFile::open(path)? else {
+        return Ok(false);
+    };
+
+    let mut markers = file.object(MARKERS2).map(|object| parse_markers2(&object)).unwrap_or_default();
+    markers.color = color.map(str::to_string).or(markers.color);
+    markers.cues = cues
+        .iter()
+        .filter(|cue| cue.kind == CueKind::Cue && cue.hot_cue.is_some_and(|slot| slot < SERATO_SLOTS))
+        .cloned()
+        .collect();
+    let mut taken_loop_slots: HashSet<u8> = cues
+        .iter()
+        .filter(|cue| cue.kind == CueKind::Loop)
+        .filter_map(|cue| cue.hot_cue)
+        .collect();
+    for cue in cues.iter().filter(|cue| cue.kind == CueKind::Loop && cue.end_seconds.is_some()) {
+        let slot = cue
+            .hot_cue
+            .or_else(|| (0..SERATO_SLOTS).find(|slot| taken_loop_slots.insert(*slot)));
+        if let Some(slot) = slot.filter(|&slot| slot < SERATO_SLOTS) {
+            markers.cues.push(CuePoint {
+                hot_cue: Some(slot),
+                ..cue.clone()
+            });
+        }
+    }
+    file.set_object(MARKERS2, &serialize_markers2(&markers));
+
+    if let Some(grid) = grid {
+        let existing_autotags = file.object(AUTOTAGS).map(|object| parse_autotags(&object)).unwrap_or_default();
+        file.set_object(BEATGRID, &serialize_beatgrid(grid));
+        file.set_object(AUTOTAGS, &serialize_autotags(grid.bpm, &existing_autotags));
+    }
+
+    file.save(path)?;
+    Ok(true)
+}
+
+// --- Library Integration ---
+
+/// Picks up Serato's prep data for files we hold no cues or grid for yet, so
+/// tracks prepared in Serato arrive with their hot cues and grids on first
+/// load. Track colors fill in unset library colors. Returns how many files
+/// had Serato data.
+pub fn import_file_markers(
+    library: &LibraryStore,
+    cue_store: &CueStore,
+    paths: &[PathBuf],
+    cache_dir: Option<&Path>,
+) -> LibraryResult<usize> {
+    let mut unprepared = Vec::new();
+    for path in paths {
+        if cue_store.get(path)?.is_empty() {
+            unprepared.push(path);
+        }
+    }
+
+    let found: Vec<ExternalTrack> = unprepared
+        .par_iter()
+        .filter_map(|path| match read_serato_data(path) {
+            Ok(data) => data.map(|data| data.into_external_track(path.to_path_buf())),
+            Err(e) => {
+                log::debug!("No Serato data read from {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect();
+    if found.is_empty() {
+        return Ok(0);
+    }
+
+    for track in &found {
+        if let (Some(grid), Some(cache_dir)) = (track.grid, cache_dir)
+            && let Err(e) = cache::apply_beat_grid(&track.path, cache_dir, grid.bpm, grid.first_beat_sec)
+        {
+            log::warn!("Failed to apply Serato grid to {}: {}", track.path.display(), e);
+        }
+        if track.color.is_some()
+            && let Err(e) = library.update_track(&track.path, |record| track.merge_into(record))
+        {
+            log::debug!("Serato import: no library record for {}: {}", track.path.display(), e);
+        }
+    }
+    cue_store.set_many(
+        found
+            .iter()
+            .map(|track| {
+                let cues = TrackCues {
+                    cues: track.cues.clone(),
+                    grid: track.grid,
+                };
+                (track.path.clone(), cues)
+            })
+            .collect(),
+    )?;
+
+    log::info!("Picked up Serato cues and grids for {} tracks", found.len());
+    Ok(found.len())
+}
+
+/// Reads the Serato data of a set of files, for importing them through
+/// [`super::import_collection`]. Files without data are included as plain tracks.
+pub fn external_tracks(paths: &[PathBuf]) -> Vec<ExternalTrack> {
+    paths
+        .par_iter()
+        .map(|path| {
+            let data = read_serato_data(path).unwrap_or_else(|e| {
+                log::debug!("No Serato data read from {}: {}", path.display(), e);
+                None
+            });
+            data.unwrap_or_default().into_external_track(path.clone())
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeratoWriteReport {
+    pub written: usize,
+    /// Files in formats Serato doesn't embed data in.
+    pub unsupported: Vec<PathBuf>,
+    /// Files that couldn't be written, with the reason.
+    pub failed: Vec<(PathBuf, String)>,
+    /// Memory cues left out because Serato only has hot cues.
+    pub skipped_cues: usize,
+}
+
+/// Writes our cues, grids and colors into the files' Serato data: every
+/// track with prep data, or only those in `paths`. Cache entries of
+/// rewritten files are kept valid.
+pub fn write_prep_to_files(
+    source: &ExportSource,
+    paths: Option<&HashSet<PathBuf>>,
+    cache_dir: Option<&Path>,
+) -> SeratoWriteReport {
+    enum Outcome {
+        Written(usize),
+        Unsupported(PathBuf),
+        Failed(PathBuf, String),
+    }
+
+    let outcomes: Vec<Outcome> = source
+        .tracks
+        .par_iter()
+        .filter(|track| paths.is_none_or(|paths| paths.contains(&track.path)))
+        .filter_map(|track| {
+            let cues = source.cues(track);
+            let grid = source.grid(track);
+            let color = track.user.color.as_deref();
+            if cues.is_empty() && grid.is_none() && color.is_none() {
+                return None;
+            }
+            let cache_current = cache_dir.is_some_and(|dir| cache::peek_cached_entry(&track.path, dir).is_some());
+            let outcome = match write_serato_data(&track.path, cues, grid, color) {
+                Ok(true) => {
+                    if let (true, Some(cache_dir)) = (cache_current, cache_dir)
+                        && let Err(e) = cache::refresh_after_tag_write(&track.path, cache_dir)
+                    {
+                        log::warn!("Failed to refresh cache entry of {}: {}", track.path.display(), e);
+                    }
+                    let skipped = cues
+                        .iter()
+                        .filter(|cue| cue.kind == CueKind::Cue && cue.hot_cue.is_none_or(|slot| slot >= SERATO_SLOTS))
+                        .count();
+                    Outcome::Written(skipped)
+                }
+                Ok(false) => Outcome::Unsupported(track.path.clone()),
+                Err(e) => {
+                    log::warn!("Failed to write Serato data to {}: {}", track.path.display(), e);
+                    Outcome::Failed(track.path.clone(), e.to_string())
+                }
+            };
+            Some(outcome)
+        })
+        .collect();
+
+    let mut report = SeratoWriteReport::default();
+    for outcome in outcomes {
+        match outcome {
+            Outcome::Written(skipped) => {
+                report.written += 1;
+                report.skipped_cues += skipped;
+            }
+            Outcome::Unsupported(path) => report.unsupported.push(path),
+            Outcome::Failed(path, reason) => report.failed.push((path, reason)),
+        }
+    }
+    log::info!(
+        "Wrote Serato data to {} files ({} unsupported, {} failed)",
+        report.written,
+        report.unsupported.len(),
+        report.failed.len()
+    );
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_support::{hot_cue, temp_dir};
+    use super::*;
+    use std::fs;
+
+    fn markers() -> Markers2 {
+        let mut loop_cue = hot_cue(2, 8.0, Some("Build"));
+        loop_cue.kind = CueKind::Loop;
+        loop_cue.end_seconds = Some(16.0);
+        loop_cue.color = Some("#27AAE1".to_string());
+        let mut cue = hot_cue(0, 1.234, Some("Dröp"));
+        cue.color = Some("#CC0000".to_string());
+        Markers2 {
+            color: Some("#FF99FF".to_string()),
+            cues: vec![cue, loop_cue],
+            other_entries: vec![("BPMLOCK".to_string(), vec![1])],
+        }
+    }
+
+    /// Markers2 content with `entries` in place of the real ones.
+    fn markers2_object(entries: &[u8]) -> Vec<u8> {
+        let mut content = MARKERS2_VERSION.to_vec();
+        content.extend_from_slice(entries);
+        let mut object = MARKERS2_VERSION.to_vec();
+        object.extend_from_slice(base64_encode(&content, false).as_bytes());
+        object.push(0);
+        object
+    }
+
+    #[test]
+    fn base64_round_trips() {
+        for len in 0..8 {
+            let data: Vec<u8> = (0..len).map(|i| (i * 37 + 200) as u8).collect();
+            assert_eq!(base64_decode(base64_encode(&data, true).as_bytes()), data);
+            assert_eq!(base64_decode(wrap_lines(&base64_encode(&data, false)).as_bytes()), data);
+        }
+    }
+
+    #[test]
+    fn markers2_round_trips() {
+        let original = markers();
+        let object = serialize_markers2(&original);
+        assert!(object.len() >= MARKERS2_MIN_LEN);
+
+        let parsed = parse_markers2(&object);
+        assert_eq!(parsed.color, original.color);
+        assert_eq!(parsed.other_entries, original.other_entries);
+        assert_eq!(parsed.cues.len(), 2);
+        assert_eq!(parsed.cues[0].start_seconds, 1.234);
+        assert_eq!(parsed.cues[0].name.as_deref(), Some("Dröp"));
+        assert_eq!(parsed.cues[0].color.as_deref(), Some("#CC0000"));
+        assert_eq!(parsed.cues[1], original.cues[1]);
+    }
+
+    #[test]
+    fn malformed_markers2_are_read_as_far_as_they_go() {
+        assert!(parse_markers2(b"").cues.is_empty());
+        assert!(parse_markers2(b"\x02\x05garbage").cues.is_empty());
+        assert!(parse_markers2(&[0x01, 0x01, 0xFF, 0xFE, b'@', b'!']).cues.is_empty());
+
+        // A valid entry followed by one claiming more data than there is
+        let mut entries = Vec::new();
+        push_markers2_entry(&mut entries, "COLOR", &[0, 1, 2, 3]);
+        entries.extend_from_slice(b"CUE\0");
+        entries.extend_from_slice(&u32::MAX.to_be_bytes());
+        entries.extend_from_slice(&[0; 16]);
+        let parsed = parse_markers2(&markers2_object(&entries));
+        assert_eq!(parsed.color.as_deref(), Some("#010203"));
+        assert!(parsed.cues.is_empty());
+
+        // Entries too short for their type are kept as unknown ones
+        let mut entries = Vec::new();
+        push_markers2_entry(&mut entries, "CUE", &[0, 1, 2]);
+        push_markers2_entry(&mut entries, "LOOP", &[0; 18]);
+        let parsed = parse_markers2(&markers2_object(&entries));
+        assert!(parsed.cues.is_empty());
+        assert_eq!(parsed.other_entries.len(), 2);
+    }
+
+    #[test]
+    fn beatgrid_and_autotags_round_trip() {
+        let grid = BeatGrid {
+            bpm: 123.5,
+            first_beat_sec: 0.042,
+        };
+        assert_eq!(parse_beatgrid(&serialize_beatgrid(grid)), Some(grid));
+        let autotags = serialize_autotags(123.5, &["120.00".to_string(), "-1.5".to_string(), "0.3".to_string()]);
+        assert_eq!(parse_autotags(&autotags), ["123.50", "-1.5", "0.3"]);
+
+        let truncated = serialize_beatgrid(grid);
+        assert_eq!(parse_beatgrid(&truncated[..9]), None);
+        let mut non_finite = BEATGRID_VERSION.to_vec();
+        non_finite.extend_from_slice(&1u32.to_be_bytes());
+        non_finite.extend_from_slice(&0f32.to_be_bytes());
+        non_finite.extend_from_slice(&f32::NAN.to_be_bytes());
+        assert_eq!(parse_beatgrid(&non_finite), None);
+    }
+
+    /// Audio bytes standing in for MPEG or FLAC frames after the tags.
+    fn audio() -> Vec<u8> {
+        (0..4096u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn round_trip_file(path: &Path) {
+        let original = markers();
+        let grid = BeatGrid {
+            bpm: 126.0,
+            first_beat_sec: 0.5,
+        };
+        assert!(write_serato_data(path, &original.cues, Some(grid), original.color.as_deref()).unwrap());
+        let data = read_serato_data(path).unwrap().unwrap();
+        assert_eq!(data.grid, Some(grid));
+        assert_eq!(data.color, original.color);
+        assert_eq!(data.cues[1], original.cues[1]);
+
+        // Rewriting in place keeps the data and the audio after it intact
+        assert!(write_serato_data(path, &original.cues[..1], None, None).unwrap());
+        let data = read_serato_data(path).unwrap().unwrap();
+        assert_eq!(data.cues.len(), 1);
+        assert_eq!(data.grid, Some(grid));
+        assert!(fs::read(path).unwrap().ends_with(&audio()));
+    }
+
+    #[test]
+    fn mp3_files_round_trip() {
+        let dir = temp_dir("serato-mp3");
+        let path = dir.join("track.mp3");
+        fs::write(&path, audio()).unwrap();
+        round_trip_file(&path);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn flac_files_round_trip() {
+        let dir = temp_dir("serato-flac");
+        let path = dir.join("track.flac");
+        let mut file = b"fLaC".to_vec();
+        // Last-block flag, STREAMINFO, 34 bytes
+        file.extend_from_slice(&[0x80, 0, 0, 34]);
+        file.extend_from_slice(&[0x11; 34]);
+        file.extend_from_slice(&audio());
+        fs::write(&path, file).unwrap();
+        round_trip_file(&path);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn other_formats_are_left_alone() {
+        let dir = temp_dir("serato-other");
+        let path = dir.join("track.wav");
+        fs::write(&path, audio()).unwrap();
+        assert!(!write_serato_data(&path, &markers().cues, None, None).unwrap());
+        assert_eq!(read_serato_data(&path).unwrap(), None);
+        assert_eq!(fs::read(&path).unwrap(), audio());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,361 @@
+//! Serato crates: one `_Serato_/Subcrates/<Parent>%%<Child>.crate` file per
+//! crate, listing track paths relative to the drive the `_Serato_` folder
+//! is on.
+
+use super::ExternalNode;
+use crate::library::playlists::PlaylistNode;
+use crate::library::{LibraryError, LibraryResult};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the crate imported Serato crates are placed under.
+pub const SERATO_CRATE_NAME: &str = "Serato";
+
+const SUBCRATES_DIR: &str = "Subcrates";
+const CRATE_EXTENSION: &str = "crate";
+/// Joins parent and child crate names in file names.
+const NESTING_SEPARATOR: &str = "%%";
+const CRATE_VERSION: &str = "1.0/Serato ScratchLive Crate";
+/// Columns shown for exported crates.
+const CRATE_COLUMNS: [&str; 4] = ["song", "artist", "bpm", "key"];
+
+// --- Binary Fields ---
+
+/// Splits crate data into (tag, data) fields: a four-byte tag, a big-endian
+/// u32 length and the data. Stops at the first truncated field.
+fn fields(mut data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut fields = Vec::new();
+    while data.len() >= 8 {
+        let len = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        let end = len.saturating_add(8);
+        let Some(value) = data.get(8..end) else {
+            break;
+        };
+        fields.push((&data[..4], value));
+        data = &data[end..];
+    }
+    fields
+}
+
+fn push_field(out: &mut Vec<u8>, tag: &[u8; 4], value: &[u8]) {
+    out.extend_from_slice(tag);
+    out.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    out.extend_from_slice(value);
+}
+
+fn utf16_be(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+}
+
+fn from_utf16_be(data: &[u8]) -> String {
+    let units: Vec<u16> = data.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+    String::from_utf16_lossy(&units)
+}
+
+// --- Paths ---
+
+/// The folder crate paths are relative to: the drive holding `_Serato_`
+/// for external drives (`/Volumes/<Drive>/_Serato_`, `E:\_Serato_`), the
+/// filesystem root for the home library (`~/Music/_Serato_`).
+fn library_base(serato_dir: &Path) -> PathBuf {
+    let Some(parent) = serato_dir.parent() else {
+        return PathBuf::from("/");
+    };
+    let on_drive_root = match parent.parent() {
+        None => true,
+        Some(grandparent) => grandparent == Path::new("/Volumes"),
+    };
+    if on_drive_root {
+        return parent.to_path_buf();
+    }
+    parent.ancestors().last().unwrap_or(parent).to_path_buf()
+}
+
+fn track_path(base: &Path, relative: &str) -> PathBuf {
+    base.join(relative.trim_start_matches('/'))
+}
+
+/// A track's path as Serato writes it, or `None` if it's on another drive.
+fn relative_track_path(base: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(base).ok()?;
+    let parts: Vec<String> = relative.iter().map(|part| part.to_string_lossy().into_owned()).collect();
+    Some(parts.join("/"))
+}
+
+// --- Import ---
+
+/// A crate and its sub-crates, assembled from file names.
+#[derive(Default)]
+struct CrateTree {
+    name: String,
+    tracks: Vec<PathBuf>,
+    children: Vec<CrateTree>,
+}
+
+impl CrateTree {
+    fn child(&mut self, name: &str) -> &mut CrateTree {
+        let index = match self.children.iter().position(|child| child.name == name) {
+            Some(index) => index,
+            None => {
+                self.children.push(CrateTree {
+                    name: name.to_string(),
+                    ..Default::default()
+                });
+                self.children.len() - 1
+            }
+        };
+        &mut self.children[index]
+    }
+
+    fn into_external_node(self) -> ExternalNode {
+        let children = self.children.into_iter().map(CrateTree::into_external_node).collect();
+        ExternalNode::Crate {
+            name: self.name,
+            tracks: self.tracks,
+            children,
+        }
+    }
+}
+
+fn read_crate_file(path: &Path, base: &Path) -> LibraryResult<Vec<PathBuf>> {
+    let data = fs::read(path)?;
+    let crate_fields = fields(&data);
+    if crate_fields.first().map(|(tag, _)| *tag) != Some(b"vrsn".as_slice()) {
+        return Err(LibraryError::InvalidCollection(format!("{} is not a Serato crate", path.display())));
+    }
+    let mut tracks: Vec<PathBuf> = Vec::new();
+    for (_, track_fields) in crate_fields.into_iter().filter(|(tag, _)| *tag == b"otrk") {
+        for (_, value) in fields(track_fields).into_iter().filter(|(tag, _)| *tag == b"ptrk") {
+            let track = track_path(base, &from_utf16_be(value));
+            if !tracks.contains(&track) {
+                tracks.push(track);
+            }
+        }
+    }
+    Ok(tracks)
+}
+
+/// Reads every crate in a `_Serato_` folder into a crate tree. Unreadable
+/// crate files are logged and skipped.
+pub fn read_serato_crates(serato_dir: &Path) -> LibraryResult<Vec<ExternalNode>> {
+    let subcrates = serato_dir.join(SUBCRATES_DIR);
+    if !subcrates.is_dir() {
+        return Err(LibraryError::InvalidCollection(format!(
+            "{} has no {} folder",
+            serato_dir.display(),
+            SUBCRATES_DIR
+        )));
+    }
+    let base = library_base(serato_dir);
+
+    let mut crate_files: Vec<PathBuf> = fs::read_dir(&subcrates)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| ext.eq_ignore_ascii_case(CRATE_EXTENSION))
+        })
+        .collect();
+    // Parents sort before their children
+    crate_files.sort();
+
+    let mut root = CrateTree::default();
+    for crate_file in crate_files {
+        let Some(stem) = crate_file.file_stem().map(|s| s.to_string_lossy().into_owned()) else {
+            continue;
+        };
+        let tracks = match read_crate_file(&crate_file, &base) {
+            Ok(tracks) => tracks,
+            Err(e) => {
+                log::warn!("Skipping Serato crate {}: {}", crate_file.display(), e);
+                continue;
+            }
+        };
+        let node = stem.split(NESTING_SEPARATOR).fold(&mut root, |node, name| node.child(name));
+        node.tracks = tracks;
+    }
+
+    log::info!("Read {} top-level Serato crates from {}", root.children.len(), serato_dir.display());
+    Ok(root.children.into_iter().map(CrateTree::into_external_node).collect())
+}
+
+/// Every track path in a crate tree.
+pub fn crate_tracks(nodes: &[ExternalNode], tracks: &mut Vec<PathBuf>) {
+    for node in nodes {
+        match node {
+            ExternalNode::Folder { children, .. } => crate_tracks(children, tracks),
+            ExternalNode::Playlist { tracks: own, .. } => tracks.extend(own.iter().cloned()),
+            ExternalNode::Crate {
+                tracks: own, children, ..
+            } => {
+                tracks.extend(own.iter().cloned());
+                crate_tracks(children, tracks);
+            }
+        }
+    }
+}
+
+// --- Export ---
+
+/// Crate names can't contain path separators or the nesting separator.
+fn crate_file_name(name: &str) -> String {
+    name.replace(NESTING_SEPARATOR, "%").replace(['/', '\\', ':'], "-")
+}
+
+fn serialize_crate(tracks: &[String]) -> Vec<u8> {
+    let mut data = Vec::new();
+    push_field(&mut data, b"vrsn", &utf16_be(CRATE_VERSION));
+    for column in CRATE_COLUMNS {
+        let mut column_fields = Vec::new();
+        push_field(&mut column_fields, b"tvcn", &utf16_be(column));
+        push_field(&mut column_fields, b"tvcw", &utf16_be("0"));
+        push_field(&mut data, b"ovct", &column_fields);
+    }
+    for track in tracks {
+        let mut track_fields = Vec::new();
+        push_field(&mut track_fields, b"ptrk", &utf16_be(track));
+        push_field(&mut data, b"otrk", &track_fields);
+    }
+    data
+}
+
+#[derive(Default)]
+struct CrateExport {
+    crates: usize,
+    /// Track entries left out because they're on another drive.
+    skipped: usize,
+}
+
+fn write_crate_node(
+    node: &PlaylistNode,
+    parent_name: Option<&str>,
+    subcrates: &Path,
+    base: &Path,
+    export: &mut CrateExport,
+) -> LibraryResult<()> {
+    let name = match parent_name {
+        Some(parent) => format!("{}{}{}", parent, NESTING_SEPARATOR, crate_file_name(&node.name)),
+        None => crate_file_name(&node.name),
+    };
+    let tracks: Vec<String> = node
+        .tracks
+        .iter()
+        .filter_map(|path| relative_track_path(base, path))
+        .collect();
+    export.skipped += node.tracks.len() - tracks.len();
+    let file_name = format!("{}.{}", name, CRATE_EXTENSION);
+    fs::write(subcrates.join(file_name), serialize_crate(&tracks))?;
+    export.crates += 1;
+
+    for child in &node.children {
+        write_crate_node(child, Some(&name), subcrates, base, export)?;
+    }
+    Ok(())
+}
+
+/// Writes the playlist tree as Serato crates into a `_Serato_` folder,
+/// replacing crates of the same name. Playlists and crates alike become
+/// crates; tracks on other drives than the folder are left out. Returns
+/// how many crates were written.
+pub fn write_serato_crates(serato_dir: &Path, playlists: &[PlaylistNode]) -> LibraryResult<usize> {
+    let subcrates = serato_dir.join(SUBCRATES_DIR);
+    fs::create_dir_all(&subcrates)?;
+    let base = library_base(serato_dir);
+
+    let mut export = CrateExport::default();
+    for node in playlists {
+        write_crate_node(node, None, &subcrates, &base, &mut export)?;
+    }
+    if export.skipped > 0 {
+        log::warn!(
+            "Left {} crate entries out of {}: not on the same drive",
+            export.skipped,
+            serato_dir.display()
+        );
+    }
+    log::info!("Exported {} Serato crates to {}", export.crates, subcrates.display());
+    Ok(export.crates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_support::temp_dir;
+    use super::*;
+    use crate::library::playlists::PlaylistKind;
+
+    fn node(name: &str, kind: PlaylistKind, tracks: &[&str], children: Vec<PlaylistNode>) -> PlaylistNode {
+        let mut node = PlaylistNode::new(name.to_string(), kind);
+        node.tracks = tracks.iter().map(PathBuf::from).collect();
+        node.children = children;
+        node
+    }
+
+    #[test]
+    fn crates_round_trip() {
+        let dir = temp_dir("serato-crates");
+        let serato_dir = dir.join("_Serato_");
+        let playlists = vec![node(
+            "House",
+            PlaylistKind::Crate,
+            &["/music/a.mp3", "/music/ünï cödé.mp3"],
+            vec![node("Deep/Tech%%Minimal", PlaylistKind::Playlist, &["/music/b.flac"], Vec::new())],
+        )];
+        assert_eq!(write_serato_crates(&serato_dir, &playlists).unwrap(), 2);
+
+        let crates = read_serato_crates(&serato_dir).unwrap();
+        let [ExternalNode::Crate { name, tracks, children }] = crates.as_slice() else {
+            panic!("unexpected crates {:?}", crates);
+        };
+        assert_eq!(name, "House");
+        assert_eq!(tracks, &playlists[0].tracks);
+        let [ExternalNode::Crate { name, tracks, children: grandchildren }] = children.as_slice() else {
+            panic!("unexpected sub-crates {:?}", children);
+        };
+        // Separators in names are replaced so the crate stays one level deep
+        assert_eq!(name, "Deep-Tech%Minimal");
+        assert_eq!(tracks, &[PathBuf::from("/music/b.flac")]);
+        assert!(grandchildren.is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn malformed_crates_are_skipped() {
+        let dir = temp_dir("serato-crates-malformed");
+        let serato_dir = dir.join("_Serato_");
+        let subcrates = serato_dir.join(SUBCRATES_DIR);
+        fs::create_dir_all(&subcrates).unwrap();
+        fs::write(subcrates.join("Garbage.crate"), b"\xFF\xFE not a crate").unwrap();
+        fs::write(subcrates.join("Empty.crate"), b"").unwrap();
+
+        // A track entry claiming 4 GiB, after one that is intact
+        let mut data = serialize_crate(&["music/a.mp3".to_string()]);
+        data.extend_from_slice(b"otrk");
+        data.extend_from_slice(&u32::MAX.to_be_bytes());
+        data.extend_from_slice(b"ptrk");
+        fs::write(subcrates.join("Truncated.crate"), &data).unwrap();
+        // A path with an odd byte count
+        let mut track_fields = Vec::new();
+        push_field(&mut track_fields, b"ptrk", b"\0a\0b\0");
+        let mut data = serialize_crate(&[]);
+        push_field(&mut data, b"otrk", &track_fields);
+        fs::write(subcrates.join("Odd.crate"), &data).unwrap();
+
+        let crates = read_serato_crates(&serato_dir).unwrap();
+        let names: Vec<(&str, &[PathBuf])> = crates
+            .iter()
+            .map(|node| match node {
+                ExternalNode::Crate { name, tracks, .. } => (name.as_str(), tracks.as_slice()),
+                other => panic!("expected a crate, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("Odd", [PathBuf::from("/ab")].as_slice()),
+                ("Truncated", [PathBuf::from("/music/a.mp3")].as_slice()),
+            ]
+        );
+        assert!(read_serato_crates(&dir).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,649 @@
+//! Traktor's collection format (`collection.nml`).
+
+use super::{
+    format_date, non_empty, parse_date, parse_hex_color, parse_number, track_title, xml_attributes, xml_escape,
+    ExportSource, ExternalCollection, ExternalNode, ExternalTrack,
+};
+use crate::audio::analysis::key_analyzer;
+use crate::library::cues::{BeatGrid, CueKind, CuePoint};
+use crate::library::playlists::{PlaylistKind, PlaylistNode};
+use crate::library::{LibraryError, LibraryResult, LibraryTrack};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Name of the crate imported playlists are placed under.
+pub const TRAKTOR_CRATE_NAME: &str = "Traktor";
+
+/// Traktor names the volume of every path. Paths on the macOS system volume
+/// are written with this name, since we can't know what the user called it.
+const DEFAULT_VOLUME: &str = "Macintosh HD";
+
+// CUE_V2 types; fade-in, fade-out and load markers are read as cues
+const CUE_TYPE_CUE: &str = "0";
+const CUE_TYPE_GRID: &str = "4";
+const CUE_TYPE_LOOP: &str = "5";
+/// Name Traktor gives cues the user hasn't named.
+const UNNAMED_CUE: &str = "n.n.";
+
+/// Traktor stores star ratings as 0, 51, 102, 153, 204 or 255.
+const RATING_STEP: u32 = 51;
+
+/// Traktor's track colors 1-7.
+const TRAKTOR_COLORS: [(u8, u8, u8); 7] = [
+    (0xFF, 0x00, 0x00),
+    (0xFF, 0x80, 0x00),
+    (0xFF, 0xFF, 0x00),
+    (0x00, 0xFF, 0x00),
+    (0x00, 0x00, 0xFF),
+    (0x80, 0x00, 0xFF),
+    (0xFF, 0x00, 0xFF),
+];
+
+// --- Locations ---
+
+/// Resolves a LOCATION element, e.g. VOLUME="Macintosh HD"
+/// DIR="/:Users/:dj/:Music/:" FILE="track.mp3". Volumes that aren't the
+/// system volume are looked up under `/Volumes`.
+fn location_to_path(volume: &str, dir: &str, file: &str) -> PathBuf {
+    let absolute = format!("{}{}", dir.replace("/:", "/"), file);
+    let is_drive = volume.len() == 2 && volume.ends_with(':');
+    if is_drive {
+        return PathBuf::from(format!("{}{}", volume, absolute));
+    }
+    let direct = PathBuf::from(&absolute);
+    if !volume.is_empty() && !direct.exists() {
+        let mounted = Path::new("/Volumes").join(volume).join(absolute.trim_start_matches('/'));
+        if mounted.exists() {
+            return mounted;
+        }
+    }
+    direct
+}
+
+/// Splits a path into Traktor's VOLUME, DIR and FILE.
+fn path_to_location(path: &Path) -> (String, String, String) {
+    let file = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut volume = DEFAULT_VOLUME.to_string();
+    let mut folders: Vec<String> = Vec::new();
+    for component in path.parent().unwrap_or(Path::new("")).components() {
+        match component {
+            Component::Prefix(prefix) => volume = prefix.as_os_str().to_string_lossy().into_owned(),
+            Component::Normal(name) => folders.push(name.to_string_lossy().into_owned()),
+            _ => {}
+        }
+    }
+    if volume == DEFAULT_VOLUME && folders.len() >= 2 && folders[0] == "Volumes" {
+        volume = folders[1].clone();
+        folders.drain(..2);
+    }
+    let mut dir = String::new();
+    for folder in &folders {
+        let _ = write!(dir, "/:{}", folder);
+    }
+    dir.push_str("/:");
+    (volume, dir, file)
+}
+
+/// The key playlists use to reference a collection entry.
+fn primary_key(volume: &str, dir: &str, file: &str) -> String {
+    format!("{}{}{}", volume, dir, file)
+}
+
+fn traktor_color(index: usize) -> Option<String> {
+    let (r, g, b) = *TRAKTOR_COLORS.get(index.checked_sub(1)?)?;
+    Some(super::rgb_hex(r, g, b))
+}
+
+/// The Traktor color index closest to a "#RRGGBB" color.
+fn nearest_traktor_color(color: &str) -> Option<usize> {
+    let (r, g, b) = parse_hex_color(color)?;
+    let distance = |&(cr, cg, cb): &(u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, cr) + d(g, cg) + d(b, cb)
+    };
+    (0..TRAKTOR_COLORS.len())
+        .min_by_key(|&i| distance(&TRAKTOR_COLORS[i]))
+        .map(|i| i + 1)
+}
+
+fn nml_date(unix_seconds: u64) -> String {
+    format_date(unix_seconds).replace('-', "/")
+}
+
+// --- Import ---
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Other,
+    Collection,
+    Playlists,
+}
+
+/// A collection ENTRY being read.
+#[derive(Default)]
+struct EntryBuilder {
+    track: ExternalTrack,
+    primary_key: Option<String>,
+    bpm: Option<f32>,
+    /// Start of the first grid marker, in seconds.
+    grid_start: Option<f64>,
+}
+
+impl EntryBuilder {
+    fn read_location(&mut self, attributes: &HashMap<String, String>) {
+        let volume = attributes.get("VOLUME").map(String::as_str).unwrap_or_default();
+        let dir = attributes.get("DIR").map(String::as_str).unwrap_or_default();
+        let Some(file) = attributes.get("FILE") else {
+            return;
+        };
+        self.track.path = location_to_path(volume, dir, file);
+        self.primary_key = Some(primary_key(volume, dir, file));
+    }
+
+    fn read_info(&mut self, attributes: &HashMap<String, String>) {
+        let track = &mut self.track;
+        track.comment = non_empty(attributes, "COMMENT");
+        track.play_count = parse_number(attributes, "PLAYCOUNT").unwrap_or(0);
+        track.rating = parse_number::<u32>(attributes, "RANKING").map(|r| ((r + RATING_STEP / 2) / RATING_STEP).min(5) as u8);
+        track.color = parse_number::<usize>(attributes, "COLOR").and_then(traktor_color);
+        track.added_at = attributes.get("IMPORT_DATE").and_then(|d| parse_date(d));
+        track.last_played_at = attributes.get("LAST_PLAYED").and_then(|d| parse_date(d));
+        if track.key.is_none() {
+            track.key = non_empty(attributes, "KEY");
+        }
+    }
+
+    fn read_cue(&mut self, attributes: &HashMap<String, String>) {
+        let Some(start_ms) = parse_number::<f64>(attributes, "START") else {
+            return;
+        };
+        let start_seconds = start_ms / 1000.0;
+        let kind = match attributes.get("TYPE").map(String::as_str) {
+            Some(CUE_TYPE_GRID) => {
+                if self.grid_start.is_none() {
+                    self.grid_start = Some(start_seconds);
+                }
+                return;
+            }
+            Some(CUE_TYPE_LOOP) => CueKind::Loop,
+            _ => CueKind::Cue,
+        };
+        let end_seconds = match kind {
+            CueKind::Loop => parse_number::<f64>(attributes, "LEN").map(|len| start_seconds + len / 1000.0),
+            CueKind::Cue => None,
+        };
+        self.track.cues.push(CuePoint {
+            kind,
+            // -1 for memory cues; anything that isn't a slot is one too
+            hot_cue: parse_number::<u8>(attributes, "HOTCUE"),
+            start_seconds,
+            end_seconds,
+            name: non_empty(attributes, "NAME").filter(|n| n != UNNAMED_CUE),
+            color: None,
+        });
+    }
+
+    fn finish(mut self) -> Option<(Option<String>, ExternalTrack)> {
+        if self.track.path.as_os_str().is_empty() {
+            return None;
+        }
+        if let Some(bpm) = self.bpm.filter(|&bpm| bpm > 0.0) {
+            self.track.grid = Some(BeatGrid {
+                bpm,
+                first_beat_sec: self.grid_start.unwrap_or(0.0) as f32,
+            });
+        }
+        Some((self.primary_key, self.track))
+    }
+}
+
+/// A playlist NODE being read.
+struct NodeFrame {
+    name: String,
+    is_folder: bool,
+    children: Vec<ExternalNode>,
+    tracks: Vec<PathBuf>,
+}
+
+fn attach_node(stack: &mut [NodeFrame], playlists: &mut Vec<ExternalNode>, frame: NodeFrame) {
+    let node = if frame.is_folder {
+        ExternalNode::Folder {
+            name: frame.name,
+            children: frame.children,
+        }
+    } else {
+        ExternalNode::Playlist {
+            name: frame.name,
+            tracks: frame.tracks,
+        }
+    };
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(node),
+        None => playlists.push(node),
+    }
+}
+
+/// Resolves a playlist PRIMARYKEY ("Volume/:dir/:file.mp3") that isn't in the
+/// collection.
+fn primary_key_to_path(key: &str) -> Option<PathBuf> {
+    let dir_start = key.find("/:")?;
+    let (volume, rest) = key.split_at(dir_start);
+    let file_start = rest.rfind("/:")? + 2;
+    let (dir, file) = rest.split_at(file_start);
+    Some(location_to_path(volume, dir, file))
+}
+
+/// Reads a Traktor collection. MUSICAL_KEY values are converted to key names;
+/// the first grid marker and the TEMPO BPM form the beat grid.
+pub fn read_traktor_nml(nml_path: &Path) -> LibraryResult<ExternalCollection> {
+    let mut reader = Reader::from_file(nml_path)?;
+    let mut buf = Vec::new();
+
+    let mut section = Section::Other;
+    let mut saw_root = false;
+    let mut tracks: Vec<ExternalTrack> = Vec::new();
+    let mut paths_by_key: HashMap<String, PathBuf> = HashMap::new();
+    let mut current: Option<EntryBuilder> = None;
+    let mut stack: Vec<NodeFrame> = Vec::new();
+    let mut playlists: Vec<ExternalNode> = Vec::new();
+
+    loop {
+        let event = reader.read_event_into(&mut buf)?;
+        match &event {
+            Event::Start(element) | Event::Empty(element) => {
+                let is_empty = matches!(event, Event::Empty(_));
+                match (section, element.name().as_ref()) {
+                    (_, b"NML") => saw_root = true,
+                    (_, b"COLLECTION") => section = Section::Collection,
+                    (_, b"PLAYLISTS") => section = Section::Playlists,
+                    (Section::Collection, b"ENTRY") if !is_empty => current = Some(EntryBuilder::default()),
+                    (Section::Collection, name) => {
+                        if let Some(builder) = current.as_mut() {
+                            let attributes = xml_attributes(element)?;
+                            match name {
+                                b"LOCATION" => builder.read_location(&attributes),
+                                b"INFO" => builder.read_info(&attributes),
+                                b"TEMPO" => builder.bpm = parse_number(&attributes, "BPM"),
+                                // Takes precedence over the free-text INFO KEY read before it
+                                b"MUSICAL_KEY" => {
+                                    if let Some(value) = parse_number::<usize>(&attributes, "VALUE").filter(|&v| v < 24) {
+                                        let (name, _) = key_analyzer::key_names(value % 12, value >= 12);
+                                        builder.track.key = Some(name);
+                                    }
+                                }
+                                b"CUE_V2" => builder.read_cue(&attributes),
+                                _ => {}
+                            }
+                        }
+                    }
+                    (Section::Playlists, b"NODE") => {
+                        let attributes = xml_attributes(element)?;
+                        let frame = NodeFrame {
+                            name: attributes.get("NAME").cloned().unwrap_or_default(),
+                            is_folder: attributes.get("TYPE").map(String::as_str) == Some("FOLDER"),
+                            children: Vec::new(),
+                            tracks: Vec::new(),
+                        };
+                        if is_empty {
+                            attach_node(&mut stack, &mut playlists, frame);
+                        } else {
+                            stack.push(frame);
+                        }
+                    }
+                    (Section::Playlists, b"PRIMARYKEY") => {
+                        let attributes = xml_attributes(element)?;
+                        if let (Some(frame), Some(key)) = (stack.last_mut(), attributes.get("KEY")) {
+                            match paths_by_key.get(key).cloned().or_else(|| primary_key_to_path(key)) {
+                                Some(path) => frame.tracks.push(path),
+                                None => log::debug!("Traktor import: unknown playlist entry {}", key),
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(element) => match (section, element.name().as_ref()) {
+                (Section::Collection, b"ENTRY") => {
+                    if let Some((key, track)) = current.take().and_then(EntryBuilder::finish) {
+                        if let Some(key) = key {
+                            paths_by_key.insert(key, track.path.clone());
+                        }
+                        tracks.push(track);
+                    }
+                }
+                (Section::Playlists, b"NODE") => {
+                    if let Some(frame) = stack.pop() {
+                        attach_node(&mut stack, &mut playlists, frame);
+                    }
+                }
+                (_, b"COLLECTION") | (_, b"PLAYLISTS") => section = Section::Other,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    if !saw_root {
+        return Err(LibraryError::InvalidCollection(format!(
+            "{} is not a Traktor NML collection",
+            nml_path.display()
+        )));
+    }
+
+    // Everything hangs off a single "$ROOT" folder
+    if let [ExternalNode::Folder { name, .. }] = playlists.as_slice()
+        && name == "$ROOT"
+        && let Some(ExternalNode::Folder { children, .. }) = playlists.pop()
+    {
+        playlists = children;
+    }
+
+    log::info!(
+        "Read {} tracks and {} top-level playlist nodes from {}",
+        tracks.len(),
+        playlists.len(),
+        nml_path.display()
+    );
+    Ok(ExternalCollection { tracks, playlists })
+}
+
+// --- Export ---
+
+fn push_attribute(out: &mut String, name: &str, value: &str) {
+    let _ = write!(out, " {}=\"{}\"", name, xml_escape(value));
+}
+
+fn write_cue(out: &mut String, cue: &CuePoint) {
+    let (cue_type, length_ms) = match (cue.kind, cue.end_seconds) {
+        (CueKind::Loop, Some(end)) => (CUE_TYPE_LOOP, ((end - cue.start_seconds) * 1000.0).max(0.0)),
+        _ => (CUE_TYPE_CUE, 0.0),
+    };
+    out.push_str("<CUE_V2");
+    push_attribute(out, "NAME", cue.name.as_deref().unwrap_or(UNNAMED_CUE));
+    let _ = writeln!(
+        out,
+        " DISPL_ORDER=\"0\" TYPE=\"{}\" START=\"{:.6}\" LEN=\"{:.6}\" REPEATS=\"-1\" HOTCUE=\"{}\"></CUE_V2>",
+        cue_type,
+        cue.start_seconds * 1000.0,
+        length_ms,
+        cue.hot_cue.map(|n| n as i32).unwrap_or(-1)
+    );
+}
+
+fn write_entry(out: &mut String, track: &LibraryTrack, source: &ExportSource) {
+    let tags = &track.tags;
+    let (volume, dir, file) = path_to_location(&track.path);
+    let duration = track
+        .analysis
+        .as_ref()
+        .and_then(|a| a.metadata.duration_seconds)
+        .unwrap_or(0.0);
+    let analyzed_key = track.analysis.as_ref().and_then(|a| a.key.as_ref());
+
+    out.push_str("<ENTRY");
+    push_attribute(out, "MODIFIED_DATE", &nml_date(track.modified_at));
+    push_attribute(out, "TITLE", &track_title(track));
+    push_attribute(out, "ARTIST", tags.artist.as_deref().unwrap_or_default());
+    out.push('>');
+
+    out.push_str("<LOCATION");
+    push_attribute(out, "DIR", &dir);
+    push_attribute(out, "FILE", &file);
+    push_attribute(out, "VOLUME", &volume);
+    push_attribute(out, "VOLUMEID", &volume);
+    out.push_str("></LOCATION>\n");
+
+    if let Some(album) = &tags.album {
+        out.push_str("<ALBUM");
+        push_attribute(out, "TITLE", album);
+        out.push_str("></ALBUM>\n");
+    }
+
+    out.push_str("<INFO");
+    push_attribute(out, "GENRE", tags.genre.as_deref().unwrap_or_default());
+    push_attribute(out, "LABEL", tags.label.as_deref().unwrap_or_default());
+    push_attribute(out, "COMMENT", track.user.comment.as_deref().or(tags.comment.as_deref()).unwrap_or_default());
+    let info_key = analyzed_key.map(|k| k.camelot.clone()).or_else(|| tags.key.clone());
+    push_attribute(out, "KEY", info_key.as_deref().unwrap_or_default());
+    push_attribute(out, "PLAYCOUNT", &track.play_count.to_string());
+    push_attribute(out, "PLAYTIME", &(duration.round() as u64).to_string());
+    push_attribute(out, "PLAYTIME_FLOAT", &format!("{:.6}", duration));
+    push_attribute(out, "RANKING", &(track.user.rating.unwrap_or(0).min(5) as u32 * RATING_STEP).to_string());
+    push_attribute(out, "IMPORT_DATE", &nml_date(track.added_at));
+    if let Some(last_played_at) = track.last_played_at {
+        push_attribute(out, "LAST_PLAYED", &nml_date(last_played_at));
+    }
+    push_attribute(out, "FILESIZE", &(track.file_size / 1024).to_string());
+    if let Some(color) = track.user.color.as_deref().and_then(nearest_traktor_color) {
+        push_attribute(out, "COLOR", &color.to_string());
+    }
+    out.push_str("></INFO>\n");
+
+    let grid = source.grid(track);
+    if let Some(grid) = grid {
+        let _ = writeln!(out, "<TEMPO BPM=\"{:.6}\" BPM_QUALITY=\"100.000000\"></TEMPO>", grid.bpm);
+    }
+    let key_value = analyzed_key
+        .map(|k| k.key.as_str())
+        .or(tags.key.as_deref())
+        .and_then(key_analyzer::parse_key)
+        .map(|(tonic, is_minor)| tonic + if is_minor { 12 } else { 0 });
+    if let Some(value) = key_value {
+        let _ = writeln!(out, "<MUSICAL_KEY VALUE=\"{}\"></MUSICAL_KEY>", value);
+    }
+    if let Some(grid) = grid {
+        let _ = writeln!(
+            out,
+            "<CUE_V2 NAME=\"AutoGrid\" DISPL_ORDER=\"0\" TYPE=\"{}\" START=\"{:.6}\" LEN=\"0.000000\" REPEATS=\"-1\" HOTCUE=\"-1\"></CUE_V2>",
+            CUE_TYPE_GRID,
+            grid.first_beat_sec as f64 * 1000.0
+        );
+    }
+    for cue in source.cues(track) {
+        write_cue(out, cue);
+    }
+    out.push_str("</ENTRY>\n");
+}
+
+fn write_playlist(out: &mut String, id: &str, name: &str, tracks: &[PathBuf], keys: &HashMap<&Path, String>) {
+    let entries: Vec<&String> = tracks.iter().filter_map(|p| keys.get(p.as_path())).collect();
+    out.push_str("<NODE TYPE=\"PLAYLIST\"");
+    push_attribute(out, "NAME", name);
+    let _ = writeln!(out, "><PLAYLIST ENTRIES=\"{}\" TYPE=\"LIST\" UUID=\"{}\">", entries.len(), id);
+    for key in entries {
+        out.push_str("<ENTRY><PRIMARYKEY TYPE=\"TRACK\"");
+        push_attribute(out, "KEY", key);
+        out.push_str("></PRIMARYKEY></ENTRY>\n");
+    }
+    out.push_str("</PLAYLIST></NODE>\n");
+}
+
+/// Writes a playlist tree node. Traktor folders can't hold tracks, so a
+/// crate's own tracks become a playlist of the same name inside its folder.
+fn write_node(out: &mut String, node: &PlaylistNode, keys: &HashMap<&Path, String>) {
+    match node.kind {
+        PlaylistKind::Playlist | PlaylistKind::Smart => write_playlist(out, &node.id, &node.name, &node.tracks, keys),
+        PlaylistKind::Crate => {
+            out.push_str("<NODE TYPE=\"FOLDER\"");
+            push_attribute(out, "NAME", &node.name);
+            let count = node.children.len() + usize::from(!node.tracks.is_empty());
+            let _ = writeln!(out, "><SUBNODES COUNT=\"{}\">", count);
+            if !node.tracks.is_empty() {
+                write_playlist(out, &format!("{}-tracks", node.id), &node.name, &node.tracks, keys);
+            }
+            for child in &node.children {
+                write_node(out, child, keys);
+            }
+            out.push_str("</SUBNODES></NODE>\n");
+        }
+    }
+}
+
+/// Writes the library, grids, cues, keys and playlists as a Traktor
+/// collection. Returns how many tracks were written.
+pub fn write_traktor_nml(nml_path: &Path, source: &ExportSource) -> LibraryResult<usize> {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\" ?>\n");
+    out.push_str("<NML VERSION=\"19\"><HEAD COMPANY=\"www.native-instruments.com\" PROGRAM=\"Traktor\"></HEAD>\n");
+    out.push_str("<MUSICFOLDERS></MUSICFOLDERS>\n");
+
+    let _ = writeln!(out, "<COLLECTION ENTRIES=\"{}\">", source.tracks.len());
+    let mut keys: HashMap<&Path, String> = HashMap::new();
+    for track in &source.tracks {
+        let (volume, dir, file) = path_to_location(&track.path);
+        keys.insert(track.path.as_path(), primary_key(&volume, &dir, &file));
+        write_entry(&mut out, track, source);
+    }
+    out.push_str("</COLLECTION>\n<SETS ENTRIES=\"0\"></SETS>\n");
+
+    out.push_str("<PLAYLISTS><NODE TYPE=\"FOLDER\" NAME=\"$ROOT\">");
+    let _ = writeln!(out, "<SUBNODES COUNT=\"{}\">", source.playlists.len());
+    for node in &source.playlists {
+        write_node(&mut out, node, &keys);
+    }
+    out.push_str("</SUBNODES></NODE></PLAYLISTS>\n</NML>\n");
+
+    if let Some(parent) = nml_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(nml_path, out)?;
+    log::info!("Exported {} tracks to {}", source.tracks.len(), nml_path.display());
+    Ok(source.tracks.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_support::{hot_cue, temp_dir, track};
+    use super::*;
+    use crate::library::cues::TrackCues;
+
+    fn export_source() -> ExportSource {
+        let mut a = track("/music/dj/Ä & <b>.mp3");
+        a.tags.artist = Some("Artist \"quoted\"".to_string());
+        a.tags.key = Some("Am".to_string());
+        a.play_count = 3;
+        a.last_played_at = Some(1_650_000_000);
+        a.user.rating = Some(4);
+        a.user.color = Some("#FF0000".to_string());
+        a.user.comment = Some("warm-up".to_string());
+        let b = track("/music/dj/b.mp3");
+
+        let mut loop_cue = hot_cue(1, 10.0, None);
+        loop_cue.kind = CueKind::Loop;
+        loop_cue.end_seconds = Some(14.0);
+        let cues = TrackCues {
+            cues: vec![hot_cue(0, 1.5, Some("Drop")), loop_cue],
+            grid: Some(BeatGrid {
+                bpm: 128.0,
+                first_beat_sec: 0.25,
+            }),
+        };
+
+        let mut playlist = PlaylistNode::new("Set".to_string(), PlaylistKind::Playlist);
+        playlist.tracks = vec![b.path.clone(), a.path.clone()];
+        let mut folder = PlaylistNode::new("Folder".to_string(), PlaylistKind::Crate);
+        folder.tracks = vec![a.path.clone()];
+        folder.children = vec![playlist];
+
+        ExportSource {
+            cues: HashMap::from([(a.path.clone(), cues)]),
+            tracks: vec![a, b],
+            playlists: vec![folder],
+        }
+    }
+
+    #[test]
+    fn collection_round_trips() {
+        let dir = temp_dir("nml");
+        let nml_path = dir.join("collection.nml");
+        let source = export_source();
+        assert_eq!(write_traktor_nml(&nml_path, &source).unwrap(), 2);
+
+        let collection = read_traktor_nml(&nml_path).unwrap();
+        assert_eq!(collection.tracks.len(), 2);
+        let a = &collection.tracks[0];
+        assert_eq!(a.path, source.tracks[0].path);
+        assert_eq!(a.rating, Some(4));
+        assert_eq!(a.color.as_deref(), Some("#FF0000"));
+        assert_eq!(a.comment.as_deref(), Some("warm-up"));
+        assert_eq!(a.key.as_deref(), Some("A minor"));
+        assert_eq!(a.play_count, 3);
+        assert_eq!(a.last_played_at, Some(1_650_000_000 - 1_650_000_000 % 86_400));
+        assert_eq!(a.grid, source.cues[&a.path].grid);
+        assert_eq!(a.cues, source.cues[&a.path].cues);
+
+        // The folder's own tracks become a playlist of the same name inside it
+        let [ExternalNode::Folder { name, children }] = collection.playlists.as_slice() else {
+            panic!("unexpected playlists {:?}", collection.playlists);
+        };
+        assert_eq!(name, "Folder");
+        let tracks_of = |node: &ExternalNode| match node {
+            ExternalNode::Playlist { name, tracks } => (name.clone(), tracks.clone()),
+            other => panic!("expected a playlist, got {:?}", other),
+        };
+        assert_eq!(tracks_of(&children[0]), ("Folder".to_string(), vec![source.tracks[0].path.clone()]));
+        let set = tracks_of(&children[1]);
+        assert_eq!(set, ("Set".to_string(), vec![source.tracks[1].path.clone(), source.tracks[0].path.clone()]));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn malformed_collections_are_rejected() {
+        let dir = temp_dir("nml-malformed");
+        let not_nml = dir.join("other.xml");
+        fs::write(&not_nml, "<DJ_PLAYLISTS><COLLECTION/></DJ_PLAYLISTS>").unwrap();
+        assert!(matches!(read_traktor_nml(&not_nml), Err(LibraryError::InvalidCollection(_))));
+
+        let broken = dir.join("broken.nml");
+        fs::write(&broken, "<NML><COLLECTION><ENTRY TITLE=\"a></COLLECTION>").unwrap();
+        assert!(read_traktor_nml(&broken).is_err());
+
+        let garbage = dir.join("garbage.nml");
+        fs::write(&garbage, [0xFFu8, 0x00, 0x3C, 0x3C, 0xFE]).unwrap();
+        assert!(read_traktor_nml(&garbage).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn out_of_range_values_are_dropped() {
+        let dir = temp_dir("nml-oversized");
+        let nml_path = dir.join("collection.nml");
+        let huge = "9".repeat(400);
+        let nml = format!(
+            r#"<NML VERSION="19"><COLLECTION ENTRIES="1"><ENTRY TITLE="{long}">
+<LOCATION DIR="/:music/:" FILE="a.mp3" VOLUME=""></LOCATION>
+<INFO RANKING="{huge}" PLAYCOUNT="{huge}" COLOR="{huge}" LAST_PLAYED="{huge}/1/1" IMPORT_DATE="2023/1/1"></INFO>
+<TEMPO BPM="{huge}"></TEMPO>
+<MUSICAL_KEY VALUE="{huge}"></MUSICAL_KEY>
+<CUE_V2 TYPE="0" START="1000" LEN="0" HOTCUE="300"></CUE_V2>
+<CUE_V2 TYPE="0" START="{huge}" LEN="0" HOTCUE="{huge}"></CUE_V2>
+</ENTRY></COLLECTION></NML>"#,
+            long = "x".repeat(1 << 20),
+            huge = huge
+        );
+        fs::write(&nml_path, nml).unwrap();
+
+        let collection = read_traktor_nml(&nml_path).unwrap();
+        let [track] = collection.tracks.as_slice() else {
+            panic!("expected one track");
+        };
+        assert_eq!(track.path, PathBuf::from("/music/a.mp3"));
+        assert_eq!(track.rating, None);
+        assert_eq!(track.play_count, 0);
+        assert_eq!(track.color, None);
+        assert_eq!(track.key, None);
+        assert_eq!(track.last_played_at, None);
+        assert_eq!(track.added_at, Some(1_672_531_200));
+        assert!(track.cues.iter().all(|cue| cue.hot_cue.is_none()));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,688 @@
+//! Play history: the tracks that were heard on the master during a session,
+//! in order. A track is logged once its deck is playing with the channel
+//! fader up, so tracks only previewed in the headphones don't count.
+
+use super::exchange::format_date_time;
+use super::playlist_files::{self, PlaylistEntry, PlaylistPathMode};
+use super::writer::BatchedWriter;
+use super::{write_json_atomic, LibraryError, LibraryResult, LibraryTrack};
+use crate::audio::cache;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Bump when the session file layout changes incompatibly.
+const HISTORY_DB_VERSION: u32 = 1;
+/// One file per session in this directory, named after the session id.
+const HISTORY_DIR_NAME: &str = "history";
+/// Changes are written this long after the first one, batched together.
+const HISTORY_WRITE_DELAY: Duration = Duration::from_secs(2);
+/// Channel fader level at or above which a playing deck is on the master.
+const AUDIBLE_FADER_LEVEL: f32 = 0.05;
+
+/// One track heard during a session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub path: PathBuf,
+    pub deck_id: String,
+    /// Start of the CUE sheet track that was loaded, if one was.
+    pub range_start_seconds: Option<f64>,
+    /// Load, play and fader-up times in Unix seconds.
+    pub loaded_at: u64,
+    pub play_started_at: u64,
+    pub audible_at: u64,
+    /// When the track was last taken off the master.
+    pub ended_at: Option<u64>,
+    /// Pitch rate while on the master (1.0 is the original tempo).
+    pub pitch_rate: f32,
+    /// Length of the loaded track or CUE sheet track.
+    pub duration_seconds: f64,
+    /// Total time the track was on the master.
+    pub audible_seconds: f64,
+}
+
+/// A DJ session: everything played between its start and end.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistorySession {
+    pub id: String,
+    /// Unix seconds.
+    pub started_at: u64,
+    /// `None` while the session is still being logged.
+    pub ended_at: Option<u64>,
+    pub entries: Vec<HistoryEntry>,
+}
+
+/// Unique, and in start order, even for sessions started within the same
+/// second: the start time, then its nanoseconds and a process counter.
+fn new_session_id(started: Duration) -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("session-{}-{:09}-{}", started.as_secs(), started.subsec_nanos(), count)
+}
+
+impl HistorySession {
+    fn new() -> Self {
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        HistorySession {
+            id: new_session_id(started),
+            started_at: started.as_secs(),
+            ended_at: None,
+            entries: Vec::new(),
+        }
+    }
+
+    fn close(&mut self) {
+        let last_end = self.entries.iter().map(|e| e.ended_at.unwrap_or(e.audible_at)).max();
+        self.ended_at = Some(last_end.unwrap_or(self.started_at).max(self.started_at));
+    }
+}
+
+/// What the audio thread reports about a deck when it changes.
+#[derive(Debug, Clone, Copy)]
+pub struct DeckActivity {
+    pub playing: bool,
+    pub fader_level: f32,
+    pub pitch_rate: f32,
+}
+
+impl DeckActivity {
+    fn audible(&self) -> bool {
+        self.playing && self.fader_level >= AUDIBLE_FADER_LEVEL
+    }
+}
+
+/// The track on a deck, kept in memory until it is logged.
+struct LoadedTrack {
+    path: PathBuf,
+    range_start_seconds: Option<f64>,
+    duration_seconds: f64,
+    loaded_at: u64,
+    play_started_at: Option<u64>,
+    /// Index of its entry in the open session once logged.
+    entry: Option<usize>,
+    audible_since: Option<Instant>,
+}
+
+/// On-disk form of one session.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionFile {
+    version: u32,
+    session: HistorySession,
+}
+
+#[derive(Default)]
+struct HistoryState {
+    /// Oldest first; only the last session can be open.
+    sessions: Vec<HistorySession>,
+    decks: HashMap<String, LoadedTrack>,
+    /// Sessions changed or deleted since the last write, by id.
+    unsaved: HashSet<String>,
+}
+
+impl HistoryState {
+    fn open_session(&mut self) -> Option<&mut HistorySession> {
+        self.sessions.last_mut().filter(|s| s.ended_at.is_none())
+    }
+
+    fn open_session_id(&self) -> Option<String> {
+        self.sessions.last().filter(|s| s.ended_at.is_none()).map(|s| s.id.clone())
+    }
+
+    /// Ends the track's current span on the master, if it is on it.
+    fn end_span(&mut self, deck_id: &str) -> bool {
+        let Some(track) = self.decks.get_mut(deck_id) else {
+            return false;
+        };
+        let (Some(since), Some(index)) = (track.audible_since.take(), track.entry) else {
+            return false;
+        };
+        let Some(entry) = self.open_session().and_then(|s| s.entries.get_mut(index)) else {
+            return false;
+        };
+        entry.audible_seconds += since.elapsed().as_secs_f64();
+        entry.ended_at = Some(cache::unix_now());
+        true
+    }
+}
+
+/// Where sessions are written: one file each, so a change rewrites only its
+/// own session.
+struct SessionFiles {
+    dir: PathBuf,
+    /// Held from taking the unsaved sessions until they are written, so an
+    /// older copy never overwrites a newer one.
+    write_lock: Mutex<()>,
+}
+
+impl SessionFiles {
+    fn session_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    /// Every session in the directory, oldest first. Unreadable files are
+    /// set aside.
+    fn read_all(&self) -> Vec<HistorySession> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+            Err(e) => {
+                log::warn!("History directory {} unreadable ({}), starting empty", self.dir.display(), e);
+                return Vec::new();
+            }
+        };
+        let mut sessions = Vec::new();
+        for path in entries.filter_map(|entry| entry.ok().map(|e| e.path())) {
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let read = File::open(&path)
+                .map_err(LibraryError::from)
+                .and_then(|file| Ok(serde_json::from_reader::<_, SessionFile>(BufReader::new(file))?));
+            match read {
+                Ok(file) if file.version == HISTORY_DB_VERSION => sessions.push(file.session),
+                Ok(file) => log::warn!(
+                    "History session {} has version {} (expected {}), skipping it",
+                    path.display(),
+                    file.version,
+                    HISTORY_DB_VERSION
+                ),
+                Err(e) => {
+                    log::warn!("History session {} unreadable ({}), setting it aside", path.display(), e);
+                    if let Err(e) = fs::rename(&path, path.with_extension("json.corrupt")) {
+                        log::warn!("Failed to set aside corrupted history session: {}", e);
+                    }
+                }
+            }
+        }
+        sessions.sort_by(|a, b| (a.started_at, &a.id).cmp(&(b.started_at, &b.id)));
+        sessions
+    }
+
+    fn write(&self, session: &HistorySession) -> LibraryResult<()> {
+        write_json_atomic(
+            &self.session_path(&session.id),
+            &SessionFile {
+                version: HISTORY_DB_VERSION,
+                session: session.clone(),
+            },
+        )
+    }
+
+    /// Writes the sessions changed since the last call and removes deleted
+    /// ones. Only the state lock is held while they are taken, not while
+    /// they are written.
+    fn write_unsaved(&self, state: &Mutex<HistoryState>) -> LibraryResult<()> {
+        let _writing = self
+            .write_lock
+            .lock()
+            .map_err(|e| LibraryError::LockPoisoned(format!("Failed to lock history writes: {}", e)))?;
+        let changes: Vec<(String, Option<HistorySession>)> = {
+            let mut state = state
+                .lock()
+                .map_err(|e| LibraryError::LockPoisoned(format!("Failed to lock history: {}", e)))?;
+            let unsaved = std::mem::take(&mut state.unsaved);
+            unsaved
+                .into_iter()
+                .map(|id| {
+                    let session = state.sessions.iter().find(|s| s.id == id).cloned();
+                    (id, session)
+                })
+                .collect()
+        };
+        for (id, session) in changes {
+            let written = match &session {
+                Some(session) => self.write(session),
+                None => match fs::remove_file(self.session_path(&id)) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                    _ => Ok(()),
+                },
+            };
+            if let Err(e) = written {
+                // Keep it for the next write
+                if let Ok(mut state) = state.lock() {
+                    state.unsaved.insert(id);
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The play history, managed as Tauri state. Live deck state is kept in
+/// memory; a session is written to its own file on a background thread when
+/// a track goes on or off the master.
+pub struct HistoryStore {
+    /// `None` keeps history in memory only.
+    files: Option<Arc<SessionFiles>>,
+    state: Arc<Mutex<HistoryState>>,
+    writer: Option<BatchedWriter>,
+}
+
+impl HistoryStore {
+    pub fn new() -> Self {
+        HistoryStore {
+            files: None,
+            state: Arc::new(Mutex::new(HistoryState::default())),
+            writer: None,
+        }
+    }
+
+    /// Opens the history in `data_dir`. A session left open by a crash or
+    /// quit is closed at its last logged track.
+    pub fn open(data_dir: &Path) -> Self {
+        let files = Arc::new(SessionFiles {
+            dir: data_dir.join(HISTORY_DIR_NAME),
+            write_lock: Mutex::new(()),
+        });
+        let mut sessions = files.read_all();
+        let mut unsaved = HashSet::new();
+        for session in sessions.iter_mut().filter(|s| s.ended_at.is_none()) {
+            session.close();
+            unsaved.insert(session.id.clone());
+        }
+        let state = Arc::new(Mutex::new(HistoryState {
+            sessions,
+            decks: HashMap::new(),
+            unsaved,
+        }));
+
+        let writer = {
+            let files = files.clone();
+            let state = state.clone();
+            BatchedWriter::spawn("history-writer", HISTORY_WRITE_DELAY, move || {
+                if let Err(e) = files.write_unsaved(&state) {
+                    log::error!("Failed to save play history: {}", e);
+                }
+            })
+        };
+        writer.schedule();
+        HistoryStore {
+            files: Some(files),
+            state,
+            writer: Some(writer),
+        }
+    }
+
+    fn lock(&self) -> LibraryResult<MutexGuard<'_, HistoryState>> {
+        self.state
+            .lock()
+            .map_err(|e| LibraryError::LockPoisoned(format!("Failed to lock history: {}", e)))
+    }
+
+    /// Marks a session for the next background write.
+    fn changed(&self, state: &mut HistoryState, session_id: Option<String>) {
+        let (Some(writer), Some(id)) = (&self.writer, session_id) else {
+            return;
+        };
+        state.unsaved.insert(id);
+        writer.schedule();
+    }
+
+    /// Writes pending changes now, e.g. before the app exits.
+    pub fn flush(&self) -> LibraryResult<()> {
+        match &self.files {
+            Some(files) => files.write_unsaved(&self.state),
+            None => Ok(()),
+        }
+    }
+
+    /// Notes a track loaded on a deck, replacing (and ending) whatever was on it.
+    pub fn track_loaded(
+        &self,
+        deck_id: &str,
+        path: &Path,
+        range_start_seconds: Option<f64>,
+        duration_seconds: f64,
+    ) -> LibraryResult<()> {
+        let mut state = self.lock()?;
+        let ended = state.end_span(deck_id);
+        if ended {
+            let id = state.open_session_id();
+            self.changed(&mut state, id);
+        }
+        state.decks.insert(
+            deck_id.to_string(),
+            LoadedTrack {
+                path: path.to_path_buf(),
+                range_start_seconds,
+                duration_seconds,
+                loaded_at: cache::unix_now(),
+                play_started_at: None,
+                entry: None,
+                audible_since: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Updates a deck's play and fader state. Returns the track's path the
+    /// first time it reaches the master, when it should count as a play.
+    pub fn deck_activity(&self, deck_id: &str, activity: DeckActivity) -> LibraryResult<Option<PathBuf>> {
+        let mut state = self.lock()?;
+        let now = cache::unix_now();
+        let Some(track) = state.decks.get_mut(deck_id) else {
+            return Ok(None);
+        };
+        if activity.playing && track.play_started_at.is_none() {
+            track.play_started_at = Some(now);
+        }
+
+        if !activity.audible() {
+            if state.end_span(deck_id) {
+                let id = state.open_session_id();
+                self.changed(&mut state, id);
+            }
+            return Ok(None);
+        }
+        if track.audible_since.is_some() {
+            // Still on the master; keep the latest pitch without saving
+            let index = track.entry;
+            if let (Some(index), Some(session)) = (index, state.open_session())
+                && let Some(entry) = session.entries.get_mut(index)
+            {
+                entry.pitch_rate = activity.pitch_rate;
+            }
+            return Ok(None);
+        }
+
+        track.audible_since = Some(Instant::now());
+        let logged_entry = track.entry;
+        let entry = HistoryEntry {
+            path: track.path.clone(),
+            deck_id: deck_id.to_string(),
+            range_start_seconds: track.range_start_seconds,
+            loaded_at: track.loaded_at,
+            play_started_at: track.play_started_at.unwrap_or(now),
+            audible_at: now,
+            ended_at: None,
+            pitch_rate: activity.pitch_rate,
+            duration_seconds: track.duration_seconds,
+            audible_seconds: 0.0,
+        };
+
+        if state.open_session().is_none() {
+            state.sessions.push(HistorySession::new());
+            log::info!("History: started a new session");
+        }
+        let session = state.open_session().expect("session opened above");
+        let newly_logged = match logged_entry.and_then(|i| session.entries.get_mut(i)) {
+            // Faded back in: the same entry goes on
+            Some(existing) => {
+                existing.ended_at = None;
+                existing.pitch_rate = activity.pitch_rate;
+                None
+            }
+            None => {
+                log::info!("History: {} on the master from deck {}", entry.path.display(), deck_id);
+                let path = entry.path.clone();
+                session.entries.push(entry);
+                Some((session.entries.len() - 1, path))
+            }
+        };
+        let played = newly_logged.map(|(index, path)| {
+            if let Some(track) = state.decks.get_mut(deck_id) {
+                track.entry = Some(index);
+            }
+            path
+        });
+        let id = state.open_session_id();
+        self.changed(&mut state, id);
+        Ok(played)
+    }
+
+    /// Every session, newest first.
+    pub fn sessions(&self) -> LibraryResult<Vec<HistorySession>> {
+        Ok(self.lock()?.sessions.iter().rev().cloned().collect())
+    }
+
+    /// Tracks already logged in the open session.
+    pub fn session_paths(&self) -> LibraryResult<HashSet<PathBuf>> {
+        let mut state = self.lock()?;
+        Ok(state
+            .open_session()
+            .map(|session| session.entries.iter().map(|e| e.path.clone()).collect())
+            .unwrap_or_default())
+    }
+
+    pub fn get(&self, id: &str) -> LibraryResult<HistorySession> {
+        self.lock()?
+            .sessions
+            .iter()
+            .find(|s| s.id == id)
+            .cloned()
+            .ok_or_else(|| LibraryError::HistorySessionNotFound(id.to_string()))
+    }
+
+    /// Closes the open session, if any. The next track on the master starts
+    /// a new one. Tracks still on the master are cut off at this point.
+    pub fn end_session(&self) -> LibraryResult<Option<HistorySession>> {
+        let mut state = self.lock()?;
+        let deck_ids: Vec<String> = state.decks.keys().cloned().collect();
+        for deck_id in &deck_ids {
+            state.end_span(deck_id);
+        }
+        for track in state.decks.values_mut() {
+            track.entry = None;
+        }
+        let Some(session) = state.open_session() else {
+            return Ok(None);
+        };
+        session.close();
+        let session = session.clone();
+        self.changed(&mut state, Some(session.id.clone()));
+        Ok(Some(session))
+    }
+
+    pub fn delete_session(&self, id: &str) -> LibraryResult<()> {
+        let mut state = self.lock()?;
+        let position = state
+            .sessions
+            .iter()
+            .position(|s| s.id == id)
+            .ok_or_else(|| LibraryError::HistorySessionNotFound(id.to_string()))?;
+        if state.sessions[position].ended_at.is_none() {
+            for track in state.decks.values_mut() {
+                track.entry = None;
+                track.audible_since = None;
+            }
+        }
+        state.sessions.remove(position);
+        self.changed(&mut state, Some(id.to_string()));
+        Ok(())
+    }
+}
+
+// --- Export ---
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HistoryExportFormat {
+    Csv,
+    Json,
+    /// M3U or M3U8 (or PLS), chosen by the file extension.
+    M3u,
+    /// "mm:ss Artist - Title" lines timed from the start of the session, as
+    /// radio shows and mix uploads ask for.
+    Tracklist,
+}
+
+/// Artist and title of an entry, from the CUE sheet track it was when there
+/// is one, else from the file's tags.
+fn artist_title(entry: &HistoryEntry, track: Option<&LibraryTrack>) -> (Option<String>, Option<String>) {
+    let Some(track) = track else {
+        return (None, None);
+    };
+    let virtual_track = entry.range_start_seconds.and_then(|start| {
+        track
+            .virtual_tracks
+            .iter()
+            .find(|v| (v.start_seconds - start).abs() < 0.01)
+    });
+    match virtual_track {
+        Some(v) => (v.artist.clone().or(track.tags.artist.clone()), v.title.clone()),
+        None => (track.tags.artist.clone(), track.tags.title.clone()),
+    }
+}
+
+fn display_title(entry: &HistoryEntry, track: Option<&LibraryTrack>) -> String {
+    match artist_title(entry, track) {
+        (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+        (None, Some(title)) => title,
+        _ => entry
+            .path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn format_offset(seconds: u64) -> String {
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    } else {
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+fn format_csv(session: &HistorySession, tracks: &[Option<LibraryTrack>]) -> String {
+    let mut text = String::from(
+        "position,deck,artist,title,path,loaded,play_started,audible,ended,pitch_percent,duration_seconds,audible_seconds\n",
+    );
+    for (i, (entry, track)) in session.entries.iter().zip(tracks).enumerate() {
+        let (artist, title) = artist_title(entry, track.as_ref());
+        let fields = [
+            (i + 1).to_string(),
+            entry.deck_id.clone(),
+            artist.unwrap_or_default(),
+            title.unwrap_or_default(),
+            entry.path.to_string_lossy().to_string(),
+            format_date_time(entry.loaded_at),
+            format_date_time(entry.play_started_at),
+            format_date_time(entry.audible_at),
+            entry.ended_at.map(format_date_time).unwrap_or_default(),
+            format!("{:.2}", (entry.pitch_rate - 1.0) * 100.0),
+            format!("{:.1}", entry.duration_seconds),
+            format!("{:.1}", entry.audible_seconds),
+        ];
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        text.push_str(&row.join(","));
+        text.push('\n');
+    }
+    text
+}
+
+fn format_tracklist(session: &HistorySession, tracks: &[Option<LibraryTrack>]) -> String {
+    let Some(first) = session.entries.first() else {
+        return String::new();
+    };
+    session
+        .entries
+        .iter()
+        .zip(tracks)
+        .map(|(entry, track)| {
+            let offset = entry.audible_at.saturating_sub(first.audible_at);
+            format!("{} {}\n", format_offset(offset), display_title(entry, track.as_ref()))
+        })
+        .collect()
+}
+
+/// Writes a session to `path`. `lookup` supplies library records for artist
+/// and title; tracks no longer in the library fall back to their file name.
+/// Returns the number of entries written.
+pub fn export_session(
+    session: &HistorySession,
+    path: &Path,
+    format: HistoryExportFormat,
+    lookup: impl Fn(&Path) -> Option<LibraryTrack>,
+) -> LibraryResult<usize> {
+    let tracks: Vec<Option<LibraryTrack>> = session.entries.iter().map(|e| lookup(&e.path)).collect();
+    let text = match format {
+        HistoryExportFormat::Csv => format_csv(session, &tracks),
+        HistoryExportFormat::Json => serde_json::to_string_pretty(session)?,
+        HistoryExportFormat::Tracklist => format_tracklist(session, &tracks),
+        HistoryExportFormat::M3u => {
+            let entries: Vec<PlaylistEntry> = session
+                .entries
+                .iter()
+                .zip(&tracks)
+                .map(|(entry, track)| PlaylistEntry {
+                    path: entry.path.clone(),
+                    title: Some(display_title(entry, track.as_ref())),
+                    duration_seconds: Some(entry.duration_seconds),
+                })
+                .collect();
+            playlist_files::write_playlist_file(path, &entries, PlaylistPathMode::default())?;
+            return Ok(entries.len());
+        }
+    };
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, text)?;
+    log::info!("Exported history session {} to {}", session.id, path.display());
+    Ok(session.entries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_data_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("open-dj-history-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn play(store: &HistoryStore, deck_id: &str, path: &str) {
+        let audible = DeckActivity {
+            playing: true,
+            fader_level: 1.0,
+            pitch_rate: 1.0,
+        };
+        store.track_loaded(deck_id, Path::new(path), None, 180.0).unwrap();
+        store.deck_activity(deck_id, audible).unwrap();
+    }
+
+    #[test]
+    fn sessions_are_saved_one_file_each() {
+        let data_dir = temp_data_dir("files");
+        let store = HistoryStore::open(&data_dir);
+        play(&store, "A", "/music/one.mp3");
+        let first = store.end_session().unwrap().unwrap();
+        play(&store, "B", "/music/two.mp3");
+        let second = store.end_session().unwrap().unwrap();
+        store.flush().unwrap();
+
+        assert_ne!(first.id, second.id, "sessions started within a second get distinct ids");
+        assert!(data_dir.join(HISTORY_DIR_NAME).join(format!("{}.json", first.id)).exists());
+
+        let reopened = HistoryStore::open(&data_dir);
+        let sessions = reopened.sessions().unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].id, second.id, "newest first");
+        assert_eq!(sessions[1].entries[0].path, PathBuf::from("/music/one.mp3"));
+
+        reopened.delete_session(&first.id).unwrap();
+        reopened.flush().unwrap();
+        assert!(!data_dir.join(HISTORY_DIR_NAME).join(format!("{}.json", first.id)).exists());
+        let _ = fs::remove_dir_all(&data_dir);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::audio::types::{KeyAnalysis, LoudnessAnalysis, TrackAnalysis, TrackBasicMetadata, TrackTags};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+pub mod analysis_tags;
+pub mod commands;
+pub mod cue_sheet;
+pub mod cues;
+pub mod exchange;
+pub mod history;
+pub mod playlist_files;
+pub mod playlists;
+pub mod query;
+pub mod relocate;
+pub mod scanner;
+pub mod store;
+pub mod suggest;
+pub mod tag_files;
+pub mod watcher;
+mod writer;
+
+/// Analysis results joined onto a track from the cache.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackAnalysisSummary {
+    pub content_hash: String,
+    pub metadata: TrackBasicMetadata,
+    pub loudness: Option<LoudnessAnalysis>,
+    pub key: Option<KeyAnalysis>,
+    #[serde(default)]
+    pub energy: Option<f32>,
+}
+
+impl From<&TrackAnalysis> for TrackAnalysisSummary {
+    fn from(analysis: &TrackAnalysis) -> Self {
+        TrackAnalysisSummary {
+            content_hash: analysis.content_hash.clone(),
+            metadata: analysis.metadata.clone(),
+            loudness: analysis.loudness.clone(),
+            key: analysis.key.clone(),
+            energy: analysis.energy,
+        }
+    }
+}
+
+/// Fields the user edits in the library. They are never read from or written
+/// to the file itself, so rescans and moves keep them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UserFields {
+    /// Star rating from 0 to 5.
+    pub rating: Option<u8>,
+    /// Color label as a CSS color string.
+    pub color: Option<String>,
+    pub comment: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// One audio file found under a library root.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryTrack {
+    pub path: PathBuf,
+    pub root: PathBuf,
+    pub file_name: String,
+    pub file_size: u64,
+    /// Modification time in Unix seconds.
+    pub modified_at: u64,
+    pub tags: TrackTags,
+    /// Cached analysis, if the file has been analyzed and not changed since.
+    pub analysis: Option<TrackAnalysisSummary>,
+    /// When the track entered the library, in Unix seconds.
+    #[serde(default)]
+    pub added_at: u64,
+    #[serde(default)]
+    pub play_count: u32,
+    /// Last time the track was played, in Unix seconds.
+    #[serde(default)]
+    pub last_played_at: Option<u64>,
+    #[serde(default)]
+    pub user: UserFields,
+    /// Tracks a CUE sheet next to the file splits it into.
+    #[serde(default)]
+    pub virtual_tracks: Vec<cue_sheet::VirtualTrack>,
+}
+
+impl LibraryTrack {
+    /// Carries library history and user fields over from an earlier record of
+    /// the same track (e.g. before a rescan or a move).
+    pub fn inherit_from(&mut self, previous: &LibraryTrack) {
+        self.added_at = previous.added_at;
+        self.play_count = previous.play_count;
+        self.last_played_at = previous.last_played_at;
+        self.user = previous.user.clone();
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryPage {
+    pub tracks: Vec<LibraryTrack>,
+    pub offset: usize,
+    pub total: usize,
+}
+
+pub type LibraryResult<T> = Result<T, LibraryError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LibraryError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Library root not found: {0}")]
+    RootNotFound(String),
+
+    #[error("Library lock poisoned: {0}")]
+    LockPoisoned(String),
+
+    #[error("Filesystem watch error: {0}")]
+    Watch(#[from] notify::Error),
+
+    #[error("Library database serialization error: {0}")]
+    Serde(#[from] serde_json::Error),
+
+    /// Boxed, as redb's error would otherwise size every library result.
+    #[error("Library database error: {0}")]
+    Database(Box<redb::Error>),
+
+    #[error("Track not in library: {0}")]
+    TrackNotFound(String),
+
+    #[error("Playlist not found: {0}")]
+    PlaylistNotFound(String),
+
+    #[error("History session not found: {0}")]
+    HistorySessionNotFound(String),
+
+    #[error("Invalid playlist operation: {0}")]
+    InvalidPlaylistOperation(String),
+
+    #[error("Unsupported playlist file: {0}")]
+    UnsupportedPlaylistFormat(String),
+
+    #[error("XML error: {0}")]
+    Xml(#[from] quick_xml::Error),
+
+    #[error("Invalid collection file: {0}")]
+    InvalidCollection(String),
+
+    #[error("Unsupported file for tag editing: {0}")]
+    UnsupportedTagFormat(String),
+
+    #[error("Invalid tag data: {0}")]
+    InvalidTag(String),
+
+    #[error("Device export error: {0}")]
+    DeviceExport(String),
+}
+
+// redb's per-operation errors all widen into `redb::Error`
+macro_rules! impl_from_redb_error {
+    ($($error:ty),*) => {
+        $(impl From<$error> for LibraryError {
+            fn from(e: $error) -> Self {
+                LibraryError::Database(Box::new(e.into()))
+            }
+        })*
+    };
+}
+
+impl_from_redb_error!(
+    redb::Error,
+    redb::DatabaseError,
+    redb::TransactionError,
+    redb::TableError,
+    redb::StorageError,
+    redb::CommitError
+);
+
+/// Writes `value` as JSON through a temporary file and a rename, so a crash
+/// mid-write never leaves a truncated database behind.
+pub(crate) fn write_json_atomic<T: Serialize>(path: &Path, value: &T) -> LibraryResult<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut temp_name = path.as_os_str().to_owned();
+    temp_name.push(".tmp");
+    let temp_file = PathBuf::from(temp_name);
+    {
+        let writer = BufWriter::new(File::create(&temp_file)?);
+        serde_json::to_writer(writer, value)?;
+    }
+    fs::rename(&temp_file, path)?;
+    Ok(())
+}
@@ -0,0 +1,329 @@
+use super::{LibraryError, LibraryResult};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// How track paths are written into exported playlist files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PlaylistPathMode {
+    #[default]
+    Absolute,
+    /// Relative to the playlist file's folder, so the list survives moving a
+    /// music folder together with its playlists. Tracks on another drive
+    /// stay absolute.
+    Relative,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaylistFormat {
+    M3u,
+    M3u8,
+    Pls,
+}
+
+impl PlaylistFormat {
+    pub fn from_path(path: &Path) -> LibraryResult<Self> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "m3u" => Ok(PlaylistFormat::M3u),
+            "m3u8" => Ok(PlaylistFormat::M3u8),
+            "pls" => Ok(PlaylistFormat::Pls),
+            _ => Err(LibraryError::UnsupportedPlaylistFormat(path.display().to_string())),
+        }
+    }
+}
+
+/// One track of a playlist file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistEntry {
+    pub path: PathBuf,
+    /// Display title, usually "Artist - Title".
+    pub title: Option<String>,
+    pub duration_seconds: Option<f64>,
+}
+
+// --- Paths and URLs ---
+
+fn hex_value(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+pub(crate) fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && i + 2 < bytes.len()
+            && let (Some(high), Some(low)) = (hex_value(bytes[i + 1]), hex_value(bytes[i + 2]))
+        {
+            decoded.push(high << 4 | low);
+            i += 3;
+            continue;
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Percent-encodes everything but unreserved characters and `/`.
+pub(crate) fn percent_encode_path(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Converts a `file://` URL (with or without a `localhost` host) to a path.
+pub(crate) fn file_url_to_path(url: &str) -> Option<PathBuf> {
+    let rest = url.strip_prefix("file://")?;
+    let rest = rest.strip_prefix("localhost").unwrap_or(rest);
+    let decoded = percent_decode(rest);
+    // Windows drive paths arrive as "/C:/Music/..."
+    let bytes = decoded.as_bytes();
+    if bytes.len() >= 3 && bytes[0] == b'/' && bytes[1].is_ascii_alphabetic() && bytes[2] == b':' {
+        return Some(PathBuf::from(&decoded[1..]));
+    }
+    Some(PathBuf::from(decoded))
+}
+
+/// Builds a `file://localhost/...` URL, the form rekordbox and iTunes write.
+pub(crate) fn path_to_file_url(path: &Path) -> String {
+    let mut path = path.to_string_lossy().replace('\\', "/");
+    if !path.starts_with('/') {
+        path.insert(0, '/');
+    }
+    format!("file://localhost{}", percent_encode_path(&path))
+}
+
+/// Resolves `.` and `..` components without touching the filesystem.
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push("..");
+                }
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// `to` relative to the directory `from_dir`, or `None` when they share no
+/// root (e.g. different drives).
+pub(crate) fn relative_path(from_dir: &Path, to: &Path) -> Option<PathBuf> {
+    let from: Vec<Component> = from_dir.components().collect();
+    let to_components: Vec<Component> = to.components().collect();
+    if from.first() != to_components.first() {
+        return None;
+    }
+    let common = from
+        .iter()
+        .zip(to_components.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut relative = PathBuf::new();
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    for component in &to_components[common..] {
+        relative.push(component);
+    }
+    Some(relative)
+}
+
+/// Resolves one path as written in a playlist file. Streams and other
+/// non-file URLs yield `None`.
+fn resolve_entry_path(base_dir: &Path, raw: &str) -> Option<PathBuf> {
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return None;
+    }
+    if raw.starts_with("file://") {
+        return file_url_to_path(raw).map(|p| normalize_path(&p));
+    }
+    if raw.contains("://") {
+        log::debug!("Playlist import: skipping non-file entry {}", raw);
+        return None;
+    }
+
+    let mut path = PathBuf::from(raw);
+    // Lists written on Windows use backslashes
+    if !cfg!(windows) && raw.contains('\\') && !base_dir.join(&path).exists() {
+        path = PathBuf::from(raw.replace('\\', "/"));
+    }
+    if path.is_absolute() {
+        Some(normalize_path(&path))
+    } else {
+        Some(normalize_path(&base_dir.join(path)))
+    }
+}
+
+fn entry_path_text(playlist_dir: &Path, path: &Path, mode: PlaylistPathMode) -> String {
+    let written = match mode {
+        PlaylistPathMode::Absolute => path.to_path_buf(),
+        PlaylistPathMode::Relative => relative_path(playlist_dir, path).unwrap_or_else(|| path.to_path_buf()),
+    };
+    written.to_string_lossy().into_owned()
+}
+
+// --- Reading ---
+
+/// Playlist text as UTF-8, falling back to Latin-1 for legacy `.m3u` files.
+fn decode_text(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => bytes.iter().map(|&b| b as char).collect(),
+    }
+}
+
+fn parse_m3u(text: &str, base_dir: &Path) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut pending_info: Option<(Option<f64>, Option<String>)> = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            let (duration, title) = info.split_once(',').unwrap_or((info, ""));
+            let duration = duration
+                .split_whitespace()
+                .next()
+                .and_then(|d| d.parse::<f64>().ok())
+                .filter(|&d| d >= 0.0);
+            let title = Some(title.trim().to_string()).filter(|t| !t.is_empty());
+            pending_info = Some((duration, title));
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (duration_seconds, title) = pending_info.take().unwrap_or_default();
+        if let Some(path) = resolve_entry_path(base_dir, line) {
+            entries.push(PlaylistEntry {
+                path,
+                title,
+                duration_seconds,
+            });
+        }
+    }
+    entries
+}
+
+fn parse_pls(text: &str, base_dir: &Path) -> Vec<PlaylistEntry> {
+    #[derive(Default)]
+    struct PlsEntry {
+        file: Option<String>,
+        title: Option<String>,
+        length: Option<f64>,
+    }
+
+    let mut numbered: BTreeMap<u32, PlsEntry> = BTreeMap::new();
+    for line in text.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let split = key.find(|c: char| c.is_ascii_digit()).unwrap_or(key.len());
+        let (field, number) = key.split_at(split);
+        let Ok(number) = number.parse::<u32>() else {
+            continue;
+        };
+        let entry = numbered.entry(number).or_default();
+        let value = value.trim().to_string();
+        match field {
+            "file" => entry.file = Some(value),
+            "title" => entry.title = Some(value).filter(|t| !t.is_empty()),
+            "length" => entry.length = value.parse::<f64>().ok().filter(|&l| l >= 0.0),
+            _ => {}
+        }
+    }
+
+    numbered
+        .into_values()
+        .filter_map(|entry| {
+            Some(PlaylistEntry {
+                path: resolve_entry_path(base_dir, &entry.file?)?,
+                title: entry.title,
+                duration_seconds: entry.length,
+            })
+        })
+        .collect()
+}
+
+/// Reads an M3U, M3U8 or PLS file. Relative entries are resolved against the
+/// playlist's folder; the files themselves aren't checked.
+pub fn read_playlist_file(path: &Path) -> LibraryResult<Vec<PlaylistEntry>> {
+    let format = PlaylistFormat::from_path(path)?;
+    let text = decode_text(&fs::read(path)?);
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    Ok(match format {
+        PlaylistFormat::M3u | PlaylistFormat::M3u8 => parse_m3u(&text, base_dir),
+        PlaylistFormat::Pls => parse_pls(&text, base_dir),
+    })
+}
+
+// --- Writing ---
+
+fn format_m3u(entries: &[PlaylistEntry], playlist_dir: &Path, mode: PlaylistPathMode) -> String {
+    let mut text = String::from("#EXTM3U\n");
+    for entry in entries {
+        let duration = entry.duration_seconds.map(|d| d.round() as i64).unwrap_or(-1);
+        let title = entry.title.clone().unwrap_or_default();
+        text.push_str(&format!("#EXTINF:{},{}\n", duration, title));
+        text.push_str(&entry_path_text(playlist_dir, &entry.path, mode));
+        text.push('\n');
+    }
+    text
+}
+
+fn format_pls(entries: &[PlaylistEntry], playlist_dir: &Path, mode: PlaylistPathMode) -> String {
+    let mut text = String::from("[playlist]\n");
+    for (i, entry) in entries.iter().enumerate() {
+        let number = i + 1;
+        text.push_str(&format!("File{}={}\n", number, entry_path_text(playlist_dir, &entry.path, mode)));
+        if let Some(title) = &entry.title {
+            text.push_str(&format!("Title{}={}\n", number, title));
+        }
+        let duration = entry.duration_seconds.map(|d| d.round() as i64).unwrap_or(-1);
+        text.push_str(&format!("Length{}={}\n", number, duration));
+    }
+    text.push_str(&format!("NumberOfEntries={}\nVersion=2\n", entries.len()));
+    text
+}
+
+/// Writes entries as M3U, M3U8 or PLS, chosen by the file extension. All
+/// formats are written as UTF-8, which current players expect for `.m3u` too.
+pub fn write_playlist_file(path: &Path, entries: &[PlaylistEntry], mode: PlaylistPathMode) -> LibraryResult<()> {
+    let format = PlaylistFormat::from_path(path)?;
+    let playlist_dir = path.parent().unwrap_or(Path::new(""));
+    let text = match format {
+        PlaylistFormat::M3u | PlaylistFormat::M3u8 => format_m3u(entries, playlist_dir, mode),
+        PlaylistFormat::Pls => format_pls(entries, playlist_dir, mode),
+    };
+    if !playlist_dir.as_os_str().is_empty() {
+        fs::create_dir_all(playlist_dir)?;
+    }
+    fs::write(path, text)?;
+    log::info!("Exported {} tracks to {}", entries.len(), path.display());
+    Ok(())
+}
@@ -0,0 +1,509 @@
+use super::query::{self, TrackQuery};
+use super::{write_json_atomic, LibraryError, LibraryResult, LibraryTrack};
+use crate::audio::cache;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Bump when the playlist file layout changes incompatibly.
+const PLAYLISTS_DB_VERSION: u32 = 1;
+const PLAYLISTS_DB_FILE_NAME: &str = "playlists.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PlaylistKind {
+    /// An ordered track list. Playlists can't hold other nodes.
+    Playlist,
+    /// A folder-like collection: an unordered set of tracks plus nested
+    /// crates and playlists.
+    Crate,
+    /// A playlist filled by a library query. Its tracks are the query's
+    /// latest results and can't be edited by hand.
+    Smart,
+}
+
+/// A playlist or crate in the playlist tree.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistNode {
+    pub id: String,
+    pub name: String,
+    pub kind: PlaylistKind,
+    pub tracks: Vec<PathBuf>,
+    #[serde(default)]
+    pub children: Vec<PlaylistNode>,
+    /// The query a smart playlist is filled by.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rules: Option<TrackQuery>,
+    /// Creation and last change time in Unix seconds.
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl PlaylistNode {
+    pub fn new(name: String, kind: PlaylistKind) -> Self {
+        let now = cache::unix_now();
+        PlaylistNode {
+            id: new_playlist_id(),
+            name,
+            kind,
+            tracks: Vec::new(),
+            children: Vec::new(),
+            rules: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Fails for smart playlists, whose tracks come from their rules.
+    fn check_editable(&self) -> LibraryResult<()> {
+        if self.kind == PlaylistKind::Smart {
+            return Err(LibraryError::InvalidPlaylistOperation(format!(
+                "'{}' is a smart playlist; change its rules instead",
+                self.name
+            )));
+        }
+        Ok(())
+    }
+
+    fn contains_id(&self, id: &str) -> bool {
+        self.id == id || self.children.iter().any(|child| child.contains_id(id))
+    }
+
+    /// Inserts tracks at `index` (or at the end). Crates hold each track once.
+    fn insert_tracks(&mut self, paths: Vec<PathBuf>, index: Option<usize>) -> usize {
+        let paths: Vec<PathBuf> = match self.kind {
+            PlaylistKind::Playlist | PlaylistKind::Smart => paths,
+            PlaylistKind::Crate => {
+                let mut seen: HashSet<PathBuf> = self.tracks.iter().cloned().collect();
+                paths.into_iter().filter(|p| seen.insert(p.clone())).collect()
+            }
+        };
+        let added = paths.len();
+        let index = index.unwrap_or(self.tracks.len()).min(self.tracks.len());
+        self.tracks.splice(index..index, paths);
+        added
+    }
+}
+
+/// Unique enough for local playlist ids: time plus a process counter, hashed.
+fn new_playlist_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    blake3::hash(format!("{}:{}", nanos, count).as_bytes()).to_hex()[..16].to_string()
+}
+
+fn find_mut<'a>(nodes: &'a mut [PlaylistNode], id: &str) -> Option<&'a mut PlaylistNode> {
+    for node in nodes {
+        if node.id == id {
+            return Some(node);
+        }
+        if let Some(found) = find_mut(&mut node.children, id) {
+            return Some(found);
+        }
+    }
+    None
+}
+
+fn find<'a>(nodes: &'a [PlaylistNode], id: &str) -> Option<&'a PlaylistNode> {
+    nodes.iter().find_map(|node| {
+        if node.id == id {
+            Some(node)
+        } else {
+            find(&node.children, id)
+        }
+    })
+}
+
+/// Removes a node (with its children) from the tree and returns it.
+fn take(nodes: &mut Vec<PlaylistNode>, id: &str) -> Option<PlaylistNode> {
+    if let Some(position) = nodes.iter().position(|node| node.id == id) {
+        return Some(nodes.remove(position));
+    }
+    nodes.iter_mut().find_map(|node| take(&mut node.children, id))
+}
+
+fn for_each_node_mut(nodes: &mut [PlaylistNode], visit: &mut impl FnMut(&mut PlaylistNode)) {
+    for node in nodes {
+        visit(node);
+        for_each_node_mut(&mut node.children, visit);
+    }
+}
+
+fn collect_track_paths(nodes: &[PlaylistNode], paths: &mut Vec<PathBuf>) {
+    for node in nodes {
+        paths.extend(node.tracks.iter().cloned());
+        collect_track_paths(&node.children, paths);
+    }
+}
+
+/// Keeps tracks in any playlist or crate out of cache eviction.
+fn pin_playlist_tracks(playlists: &[PlaylistNode]) {
+    let mut paths = Vec::new();
+    collect_track_paths(playlists, &mut paths);
+    cache::eviction::set_playlist_tracks(paths);
+}
+
+/// On-disk form of the playlist tree.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlaylistsFile {
+    version: u32,
+    playlists: Vec<PlaylistNode>,
+}
+
+/// The playlist and crate tree, managed as Tauri state. Every change is
+/// written straight to disk; edits are rare compared to library updates.
+pub struct PlaylistStore {
+    /// Database file; `None` keeps playlists in memory only.
+    db_path: Option<PathBuf>,
+    playlists: Mutex<Vec<PlaylistNode>>,
+}
+
+impl PlaylistStore {
+    pub fn new() -> Self {
+        PlaylistStore {
+            db_path: None,
+            playlists: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Opens the playlist file in `data_dir`. A missing or unreadable file
+    /// starts an empty tree; an unreadable one is set aside first.
+    pub fn open(data_dir: &Path) -> Self {
+        let db_path = data_dir.join(PLAYLISTS_DB_FILE_NAME);
+        let read = File::open(&db_path)
+            .map_err(LibraryError::from)
+            .and_then(|file| Ok(serde_json::from_reader::<_, PlaylistsFile>(BufReader::new(file))?));
+        let playlists = match read {
+            Ok(file) if file.version == PLAYLISTS_DB_VERSION => file.playlists,
+            Ok(file) => {
+                log::warn!("Playlist file version {} (expected {}), starting empty", file.version, PLAYLISTS_DB_VERSION);
+                Vec::new()
+            }
+            Err(LibraryError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                log::warn!("Playlist file {} unreadable ({}), starting empty", db_path.display(), e);
+                if let Err(e) = std::fs::rename(&db_path, db_path.with_extension("json.corrupt")) {
+                    log::warn!("Failed to set aside corrupted playlist file: {}", e);
+                }
+                Vec::new()
+            }
+        };
+        pin_playlist_tracks(&playlists);
+        PlaylistStore {
+            db_path: Some(db_path),
+            playlists: Mutex::new(playlists),
+        }
+    }
+
+    fn lock(&self) -> LibraryResult<MutexGuard<'_, Vec<PlaylistNode>>> {
+        self.playlists
+            .lock()
+            .map_err(|e| LibraryError::LockPoisoned(format!("Failed to lock playlists: {}", e)))
+    }
+
+    /// Writes the tree and pins its tracks in the analysis cache.
+    fn save(&self, playlists: &[PlaylistNode]) -> LibraryResult<()> {
+        pin_playlist_tracks(playlists);
+        if let Some(db_path) = &self.db_path {
+            write_json_atomic(
+                db_path,
+                &PlaylistsFile {
+                    version: PLAYLISTS_DB_VERSION,
+                    playlists: playlists.to_vec(),
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Applies a mutation to the tree and saves it if the mutation succeeded.
+    fn update<T>(&self, mutate: impl FnOnce(&mut Vec<PlaylistNode>) -> LibraryResult<T>) -> LibraryResult<T> {
+        let mut playlists = self.lock()?;
+        let result = mutate(&mut playlists)?;
+        self.save(&playlists)?;
+        Ok(result)
+    }
+
+    /// Applies a mutation to one node, stamping its change time.
+    fn update_node<T>(
+        &self,
+        id: &str,
+        mutate: impl FnOnce(&mut PlaylistNode) -> LibraryResult<T>,
+    ) -> LibraryResult<T> {
+        self.update(|playlists| {
+            let node = find_mut(playlists, id).ok_or_else(|| LibraryError::PlaylistNotFound(id.to_string()))?;
+            let result = mutate(node)?;
+            node.updated_at = cache::unix_now();
+            Ok(result)
+        })
+    }
+
+    pub fn tree(&self) -> LibraryResult<Vec<PlaylistNode>> {
+        Ok(self.lock()?.clone())
+    }
+
+    /// Every track path in any playlist or crate, with repeats.
+    pub fn track_paths(&self) -> LibraryResult<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        collect_track_paths(&self.lock()?, &mut paths);
+        Ok(paths)
+    }
+
+    pub fn get(&self, id: &str) -> LibraryResult<PlaylistNode> {
+        find(&self.lock()?, id)
+            .cloned()
+            .ok_or_else(|| LibraryError::PlaylistNotFound(id.to_string()))
+    }
+
+    /// Adds a node at the end of `parent_id`'s children, or of the top level.
+    pub fn insert(&self, node: PlaylistNode, parent_id: Option<&str>) -> LibraryResult<PlaylistNode> {
+        self.update(|playlists| {
+            let siblings = match parent_id {
+                None => playlists,
+                Some(parent_id) => {
+                    let parent = find_mut(playlists, parent_id)
+                        .ok_or_else(|| LibraryError::PlaylistNotFound(parent_id.to_string()))?;
+                    if parent.kind != PlaylistKind::Crate {
+                        return Err(LibraryError::InvalidPlaylistOperation(format!(
+                            "'{}' is a playlist and can't contain other playlists",
+                            parent.name
+                        )));
+                    }
+                    &mut parent.children
+                }
+            };
+            siblings.push(node.clone());
+            Ok(node)
+        })
+    }
+
+    /// Adds a top-level crate, replacing any top-level crate with the same
+    /// name. Importers use this so re-importing doesn't duplicate playlists.
+    pub fn replace_top_level(&self, node: PlaylistNode) -> LibraryResult<PlaylistNode> {
+        self.update(|playlists| {
+            playlists.retain(|existing| !(existing.kind == PlaylistKind::Crate && existing.name == node.name));
+            playlists.push(node.clone());
+            Ok(node)
+        })
+    }
+
+    pub fn create(&self, name: String, kind: PlaylistKind, parent_id: Option<&str>) -> LibraryResult<PlaylistNode> {
+        if kind == PlaylistKind::Smart {
+            return Err(LibraryError::InvalidPlaylistOperation(
+                "smart playlists are created with their rules".to_string(),
+            ));
+        }
+        self.insert(PlaylistNode::new(name, kind), parent_id)
+    }
+
+    pub fn rename(&self, id: &str, name: String) -> LibraryResult<PlaylistNode> {
+        self.update_node(id, |node| {
+            node.name = name;
+            Ok(node.clone())
+        })
+    }
+
+    /// Deletes a node and everything nested in it.
+    pub fn delete(&self, id: &str) -> LibraryResult<()> {
+        self.update(|playlists| {
+            take(playlists, id)
+                .map(|_| ())
+                .ok_or_else(|| LibraryError::PlaylistNotFound(id.to_string()))
+        })
+    }
+
+    /// Moves a node under another crate (or to the top level) at `index`.
+    pub fn move_node(&self, id: &str, parent_id: Option<&str>, index: Option<usize>) -> LibraryResult<()> {
+        self.update(|playlists| {
+            if let Some(parent_id) = parent_id {
+                let node = find(playlists, id).ok_or_else(|| LibraryError::PlaylistNotFound(id.to_string()))?;
+                if node.contains_id(parent_id) {
+                    return Err(LibraryError::InvalidPlaylistOperation(
+                        "a crate can't be moved into itself".to_string(),
+                    ));
+                }
+                let parent = find(playlists, parent_id)
+                    .ok_or_else(|| LibraryError::PlaylistNotFound(parent_id.to_string()))?;
+                if parent.kind != PlaylistKind::Crate {
+                    return Err(LibraryError::InvalidPlaylistOperation(format!(
+                        "'{}' is a playlist and can't contain other playlists",
+                        parent.name
+                    )));
+                }
+            }
+
+            let node = take(playlists, id).ok_or_else(|| LibraryError::PlaylistNotFound(id.to_string()))?;
+            let siblings = match parent_id {
+                None => playlists,
+                Some(parent_id) => match find_mut(playlists, parent_id) {
+                    Some(parent) => &mut parent.children,
+                    None => return Err(LibraryError::PlaylistNotFound(parent_id.to_string())),
+                },
+            };
+            let index = index.unwrap_or(siblings.len()).min(siblings.len());
+            siblings.insert(index, node);
+            Ok(())
+        })
+    }
+
+    /// Inserts tracks at `index` (or appends). Returns how many were added.
+    pub fn add_tracks(&self, id: &str, paths: Vec<PathBuf>, index: Option<usize>) -> LibraryResult<usize> {
+        self.update_node(id, |node| {
+            node.check_editable()?;
+            Ok(node.insert_tracks(paths, index))
+        })
+    }
+
+    /// Removes the tracks at the given positions.
+    pub fn remove_tracks(&self, id: &str, positions: &[usize]) -> LibraryResult<usize> {
+        let positions: HashSet<usize> = positions.iter().copied().collect();
+        self.update_node(id, |node| {
+            node.check_editable()?;
+            let before = node.tracks.len();
+            let mut position = 0;
+            node.tracks.retain(|_| {
+                let keep = !positions.contains(&position);
+                position += 1;
+                keep
+            });
+            Ok(before - node.tracks.len())
+        })
+    }
+
+    /// Moves the tracks at `positions` so that they form a block starting at
+    /// `to`, counted in the list with those tracks taken out. Their relative
+    /// order is kept.
+    pub fn move_tracks(&self, id: &str, positions: &[usize], to: usize) -> LibraryResult<()> {
+        self.update_node(id, |node| {
+            node.check_editable()?;
+            let mut positions: Vec<usize> = positions.to_vec();
+            positions.sort_unstable();
+            positions.dedup();
+            if positions.last().is_some_and(|&last| last >= node.tracks.len()) {
+                return Err(LibraryError::InvalidPlaylistOperation(format!(
+                    "track position out of range (playlist has {} tracks)",
+                    node.tracks.len()
+                )));
+            }
+            let mut moved = Vec::with_capacity(positions.len());
+            for &position in positions.iter().rev() {
+                moved.push(node.tracks.remove(position));
+            }
+            moved.reverse();
+            let to = to.min(node.tracks.len());
+            node.tracks.splice(to..to, moved);
+            Ok(())
+        })
+    }
+
+    /// Adds a smart playlist with its rules and their current results.
+    pub fn create_smart(
+        &self,
+        name: String,
+        rules: TrackQuery,
+        tracks: Vec<PathBuf>,
+        parent_id: Option<&str>,
+    ) -> LibraryResult<PlaylistNode> {
+        let mut node = PlaylistNode::new(name, PlaylistKind::Smart);
+        node.rules = Some(rules);
+        node.tracks = tracks;
+        self.insert(node, parent_id)
+    }
+
+    /// Replaces a smart playlist's rules and tracks.
+    pub fn set_smart_rules(&self, id: &str, rules: TrackQuery, tracks: Vec<PathBuf>) -> LibraryResult<PlaylistNode> {
+        self.update_node(id, |node| {
+            if node.kind != PlaylistKind::Smart {
+                return Err(LibraryError::InvalidPlaylistOperation(format!(
+                    "'{}' is not a smart playlist",
+                    node.name
+                )));
+            }
+            node.rules = Some(rules);
+            node.tracks = tracks;
+            Ok(node.clone())
+        })
+    }
+
+    /// Re-evaluates smart playlists with `evaluate`, which runs a query over
+    /// the library. With `changed`, only playlists the changed track now
+    /// matches or was listed in are re-evaluated. Returns the playlists whose
+    /// tracks changed; the file is only written when there are any.
+    pub fn refresh_smart(
+        &self,
+        changed: Option<&LibraryTrack>,
+        evaluate: impl Fn(&TrackQuery) -> LibraryResult<Vec<PathBuf>>,
+    ) -> LibraryResult<Vec<PlaylistNode>> {
+        let mut playlists = self.lock()?;
+        let now = cache::unix_now();
+        let mut updated = Vec::new();
+        let mut failure = None;
+        for_each_node_mut(&mut playlists, &mut |node| {
+            let Some(rules) = node.rules.as_ref().filter(|_| node.kind == PlaylistKind::Smart) else {
+                return;
+            };
+            if changed.is_some_and(|track| !query::track_matches(track, rules) && !node.tracks.contains(&track.path)) {
+                return;
+            }
+            match evaluate(rules) {
+                Ok(tracks) if tracks != node.tracks => {
+                    node.tracks = tracks;
+                    node.updated_at = now;
+                    updated.push(node.clone());
+                }
+                Ok(_) => {}
+                Err(e) => failure = Some(e),
+            }
+        });
+        if let Some(e) = failure {
+            return Err(e);
+        }
+        if !updated.is_empty() {
+            self.save(&playlists)?;
+        }
+        Ok(updated)
+    }
+
+    /// Points every playlist entry of a moved file at its new path. Returns
+    /// how many entries changed.
+    pub fn relocate_tracks(&self, moves: &HashMap<PathBuf, PathBuf>) -> LibraryResult<usize> {
+        if moves.is_empty() {
+            return Ok(0);
+        }
+        let now = cache::unix_now();
+        self.update(|playlists| {
+            let mut relocated = 0;
+            for_each_node_mut(playlists, &mut |node| {
+                let mut changed = false;
+                for track in node.tracks.iter_mut() {
+                    if let Some(new_path) = moves.get(track) {
+                        *track = new_path.clone();
+                        changed = true;
+                        relocated += 1;
+                    }
+                }
+                if changed {
+                    node.updated_at = now;
+                }
+            });
+            Ok(relocated)
+        })
+    }
+}
+
+impl Default for PlaylistStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,336 @@
+use super::{LibraryPage, LibraryTrack};
+use crate::audio::analysis::key_analyzer;
+use crate::audio::cache;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::path::PathBuf;
+
+/// Page size when a query doesn't set a limit.
+const DEFAULT_QUERY_LIMIT: usize = 500;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SortField {
+    #[default]
+    Path,
+    FileName,
+    Title,
+    Artist,
+    Album,
+    Genre,
+    Bpm,
+    Key,
+    Energy,
+    Loudness,
+    Duration,
+    Rating,
+    PlayCount,
+    LastPlayedAt,
+    AddedAt,
+}
+
+/// Camelot letter: A for minor keys, B for major keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum KeyMode {
+    Minor,
+    Major,
+}
+
+/// Search, filter, sort and paging options for the library. Every filter is
+/// optional; tracks without the filtered value (e.g. no BPM yet) are excluded
+/// by that filter. Smart playlists store a query as their rules.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TrackQuery {
+    /// Whitespace-separated terms, each of which must appear (case-insensitively)
+    /// in the title, artist, album, genre, label, comment, key, file name or
+    /// user fields.
+    pub text: Option<String>,
+    pub bpm_min: Option<f32>,
+    pub bpm_max: Option<f32>,
+    /// Camelot number range (1-12). A range like 11..=2 wraps around the wheel.
+    pub key_min: Option<u8>,
+    pub key_max: Option<u8>,
+    pub key_mode: Option<KeyMode>,
+    /// A Camelot key such as "8A": keeps tracks in that key, a step either
+    /// way on the wheel, or its relative major/minor.
+    pub compatible_key: Option<String>,
+    pub energy_min: Option<f32>,
+    pub energy_max: Option<f32>,
+    pub min_rating: Option<u8>,
+    /// User tags that must all be set on the track.
+    pub tags: Vec<String>,
+    /// Keeps tracks not played in this many days, including never played ones.
+    pub not_played_within_days: Option<u32>,
+    pub sort: SortField,
+    pub descending: bool,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+// --- Track Field Accessors ---
+
+/// Analyzed BPM, falling back to the tagged BPM.
+pub(crate) fn track_bpm(track: &LibraryTrack) -> Option<f32> {
+    track
+        .analysis
+        .as_ref()
+        .and_then(|a| a.metadata.bpm)
+        .or(track.tags.bpm)
+}
+
+/// Camelot number and letter of the analyzed key, falling back to the tagged
+/// key, e.g. (8, 'A').
+pub(crate) fn track_camelot(track: &LibraryTrack) -> Option<(u8, char)> {
+    if let Some(key) = track.analysis.as_ref().and_then(|a| a.key.as_ref()) {
+        return parse_camelot(&key.camelot);
+    }
+    let (tonic, is_minor) = key_analyzer::parse_key(track.tags.key.as_deref()?)?;
+    let (_, camelot) = key_analyzer::key_names(tonic, is_minor);
+    parse_camelot(&camelot)
+}
+
+pub(crate) fn parse_camelot(camelot: &str) -> Option<(u8, char)> {
+    let last = camelot.chars().last()?;
+    let number: u8 = camelot[..camelot.len() - last.len_utf8()].parse().ok()?;
+    let letter = last.to_ascii_uppercase();
+    ((1..=12).contains(&number) && (letter == 'A' || letter == 'B')).then_some((number, letter))
+}
+
+/// Whether two Camelot keys mix harmonically: the same key, a step either
+/// way with the same letter, or the relative major/minor.
+pub(crate) fn keys_compatible(a: (u8, char), b: (u8, char)) -> bool {
+    let step = (a.0 as i16 - b.0 as i16).rem_euclid(12);
+    if a.1 == b.1 {
+        matches!(step, 0 | 1 | 11)
+    } else {
+        step == 0
+    }
+}
+
+pub(crate) fn track_energy(track: &LibraryTrack) -> Option<f32> {
+    track.analysis.as_ref()?.energy
+}
+
+fn track_loudness(track: &LibraryTrack) -> Option<f32> {
+    Some(track.analysis.as_ref()?.loudness.as_ref()?.integrated_lufs)
+}
+
+fn track_duration(track: &LibraryTrack) -> Option<f64> {
+    track.analysis.as_ref()?.metadata.duration_seconds
+}
+
+// --- Filtering ---
+
+fn in_range(value: Option<f32>, min: Option<f32>, max: Option<f32>) -> bool {
+    if min.is_none() && max.is_none() {
+        return true;
+    }
+    value.is_some_and(|v| min.is_none_or(|min| v >= min) && max.is_none_or(|max| v <= max))
+}
+
+fn matches_key(track: &LibraryTrack, query: &TrackQuery) -> bool {
+    if query.key_min.is_none() && query.key_max.is_none() && query.key_mode.is_none() {
+        return true;
+    }
+    let Some((number, letter)) = track_camelot(track) else {
+        return false;
+    };
+    let mode_matches = match query.key_mode {
+        Some(KeyMode::Minor) => letter == 'A',
+        Some(KeyMode::Major) => letter == 'B',
+        None => true,
+    };
+    let min = query.key_min.unwrap_or(1);
+    let max = query.key_max.unwrap_or(12);
+    let number_matches = if min <= max {
+        (min..=max).contains(&number)
+    } else {
+        number >= min || number <= max
+    };
+    mode_matches && number_matches
+}
+
+fn matches_text(track: &LibraryTrack, terms: &[String]) -> bool {
+    if terms.is_empty() {
+        return true;
+    }
+    let tags = &track.tags;
+    let analyzed_key = track.analysis.as_ref().and_then(|a| a.key.as_ref());
+    let fields: Vec<&str> = [
+        Some(track.file_name.as_str()),
+        tags.title.as_deref(),
+        tags.artist.as_deref(),
+        tags.album.as_deref(),
+        tags.genre.as_deref(),
+        tags.label.as_deref(),
+        tags.comment.as_deref(),
+        tags.key.as_deref(),
+        analyzed_key.map(|k| k.key.as_str()),
+        analyzed_key.map(|k| k.camelot.as_str()),
+        track.user.comment.as_deref(),
+    ]
+    .into_iter()
+    .flatten()
+    .chain(track.user.tags.iter().map(String::as_str))
+    .collect();
+    let haystack = fields.join("\n").to_lowercase();
+    terms.iter().all(|term| haystack.contains(term.as_str()))
+}
+
+/// A query prepared for matching: search terms split, the compatible key
+/// parsed and the last-played cutoff fixed to the current time.
+struct Matcher<'a> {
+    query: &'a TrackQuery,
+    terms: Vec<String>,
+    /// `Some(None)` when the compatible key doesn't parse, which matches nothing.
+    compatible_key: Option<Option<(u8, char)>>,
+    played_before: Option<u64>,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(query: &'a TrackQuery) -> Self {
+        Matcher {
+            query,
+            terms: query
+                .text
+                .as_deref()
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(str::to_lowercase)
+                .collect(),
+            compatible_key: query.compatible_key.as_deref().map(|key| parse_camelot(key.trim())),
+            played_before: query
+                .not_played_within_days
+                .map(|days| cache::unix_now().saturating_sub(days as u64 * 86_400)),
+        }
+    }
+
+    fn matches(&self, track: &LibraryTrack) -> bool {
+        let query = self.query;
+        in_range(track_bpm(track), query.bpm_min, query.bpm_max)
+            && in_range(track_energy(track), query.energy_min, query.energy_max)
+            && matches_key(track, query)
+            && self.compatible_key.is_none_or(|key| {
+                key.zip(track_camelot(track)).is_some_and(|(key, track_key)| keys_compatible(key, track_key))
+            })
+            && query
+                .min_rating
+                .is_none_or(|min| track.user.rating.unwrap_or(0) >= min)
+            && query.tags.iter().all(|tag| track.user.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+            && self
+                .played_before
+                .is_none_or(|before| track.last_played_at.is_none_or(|played| played < before))
+            && matches_text(track, &self.terms)
+    }
+
+    /// Matching tracks in the query's sort order.
+    fn matching<'t>(&self, tracks: &'t [LibraryTrack]) -> Vec<&'t LibraryTrack> {
+        let mut matched: Vec<&LibraryTrack> = tracks.iter().filter(|t| self.matches(t)).collect();
+        if self.query.sort != SortField::Path || self.query.descending {
+            matched.sort_by(|a, b| compare(a, b, self.query.sort, self.query.descending));
+        }
+        matched
+    }
+}
+
+/// Whether one track passes a query's filters.
+pub(crate) fn track_matches(track: &LibraryTrack, query: &TrackQuery) -> bool {
+    Matcher::new(query).matches(track)
+}
+
+// --- Sorting ---
+
+/// Orders present values in the requested direction; missing values always
+/// sort last.
+fn cmp_optional<T: PartialOrd>(a: Option<T>, b: Option<T>, descending: bool) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            let ordering = a.partial_cmp(&b).unwrap_or(Ordering::Equal);
+            if descending { ordering.reverse() } else { ordering }
+        }
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+fn lowercase(value: &Option<String>) -> Option<String> {
+    value.as_ref().map(|v| v.to_lowercase())
+}
+
+fn compare(a: &LibraryTrack, b: &LibraryTrack, sort: SortField, descending: bool) -> Ordering {
+    let ordering = match sort {
+        SortField::Path => cmp_optional(Some(&a.path), Some(&b.path), descending),
+        SortField::FileName => cmp_optional(
+            Some(a.file_name.to_lowercase()),
+            Some(b.file_name.to_lowercase()),
+            descending,
+        ),
+        SortField::Title => cmp_optional(lowercase(&a.tags.title), lowercase(&b.tags.title), descending),
+        SortField::Artist => cmp_optional(lowercase(&a.tags.artist), lowercase(&b.tags.artist), descending),
+        SortField::Album => cmp_optional(lowercase(&a.tags.album), lowercase(&b.tags.album), descending),
+        SortField::Genre => cmp_optional(lowercase(&a.tags.genre), lowercase(&b.tags.genre), descending),
+        SortField::Bpm => cmp_optional(track_bpm(a), track_bpm(b), descending),
+        SortField::Key => cmp_optional(track_camelot(a), track_camelot(b), descending),
+        SortField::Energy => cmp_optional(track_energy(a), track_energy(b), descending),
+        SortField::Loudness => cmp_optional(track_loudness(a), track_loudness(b), descending),
+        SortField::Duration => cmp_optional(track_duration(a), track_duration(b), descending),
+        SortField::Rating => cmp_optional(a.user.rating, b.user.rating, descending),
+        SortField::PlayCount => cmp_optional(Some(a.play_count), Some(b.play_count), descending),
+        SortField::LastPlayedAt => cmp_optional(a.last_played_at, b.last_played_at, descending),
+        SortField::AddedAt => cmp_optional(Some(a.added_at), Some(b.added_at), descending),
+    };
+    // Ties fall back to path order so paging is stable
+    ordering.then_with(|| a.path.cmp(&b.path))
+}
+
+/// Runs a query over the library's tracks.
+pub fn run_query(tracks: &[LibraryTrack], query: &TrackQuery) -> LibraryPage {
+    let matched = Matcher::new(query).matching(tracks);
+    let limit = query.limit.unwrap_or(DEFAULT_QUERY_LIMIT);
+    LibraryPage {
+        tracks: matched.iter().skip(query.offset).take(limit).map(|t| (*t).clone()).collect(),
+        offset: query.offset,
+        total: matched.len(),
+    }
+}
+
+/// Paths of every track a query matches, in its sort order. Paging is
+/// ignored except for an explicit limit, which caps the list.
+pub fn matching_paths(tracks: &[LibraryTrack], query: &TrackQuery) -> Vec<PathBuf> {
+    let matched = Matcher::new(query).matching(tracks);
+    matched
+        .iter()
+        .take(query.limit.unwrap_or(usize::MAX))
+        .map(|t| t.path.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tagged(key: Option<&str>) -> LibraryTrack {
+        serde_json::from_value(serde_json::json!({
+            "path": "/music/a.mp3",
+            "root": "/music",
+            "fileName": "a.mp3",
+            "fileSize": 0,
+            "modifiedAt": 0,
+            "tags": { "key": key },
+            "analysis": null,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn camelot_falls_back_to_the_tagged_key() {
+        assert_eq!(track_camelot(&tagged(Some("Am"))), Some((8, 'A')));
+        assert_eq!(track_camelot(&tagged(Some("8a"))), Some((8, 'A')));
+        assert_eq!(track_camelot(&tagged(Some("C major"))), Some((8, 'B')));
+        assert_eq!(track_camelot(&tagged(Some("not a key"))), None);
+        assert_eq!(track_camelot(&tagged(None)), None);
+    }
+}
@@ -0,0 +1,250 @@
+//! Relocating missing tracks: files moved or renamed while the app wasn't
+//! watching, found again under a search root by their audio content hash.
+
+use super::cues::CueStore;
+use super::playlists::PlaylistStore;
+use super::store::LibraryStore;
+use super::{scanner, LibraryResult, LibraryTrack};
+use crate::audio::cache;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelocationReport {
+    /// Old and new path of every track found again.
+    pub relocated: Vec<(PathBuf, PathBuf)>,
+    /// Missing tracks whose audio wasn't found under the search root.
+    pub not_found: Vec<PathBuf>,
+    /// Missing tracks that were never analyzed, so there is no content hash
+    /// to look for.
+    pub without_hash: Vec<PathBuf>,
+    /// Missing tracks with the same audio as another missing track, so a
+    /// found file can't be told apart between them. They are left as they are.
+    pub ambiguous: Vec<PathBuf>,
+}
+
+/// Replaces a track's library record with one for its new path, keeping its
+/// play history, user fields and analysis (the audio is the same).
+pub(crate) fn move_track_record(store: &LibraryStore, from: &Path, to: &Path, root: &Path) -> LibraryResult<LibraryTrack> {
+    let previous = store.get_track(from)?;
+    store.remove_tracks(&[from])?;
+    let mut track = scanner::build_track_record(to, root, None)?;
+    if let Some(previous) = previous {
+        track.inherit_from(&previous);
+        // Same audio, so the old analysis still applies until the cache adopts the new path
+        track.analysis = previous.analysis;
+    }
+    store.upsert_tracks(vec![track.clone()])?;
+    Ok(track)
+}
+
+fn hash_files(files: &[&PathBuf]) -> Vec<(PathBuf, String)> {
+    let mut hashed: Vec<(PathBuf, String)> = files
+        .par_iter()
+        .filter_map(|path| Some(((*path).clone(), cache::fingerprint::compute_content_hash(path).ok()?)))
+        .collect();
+    hashed.sort();
+    hashed
+}
+
+/// Paths the library and playlists refer to that no longer exist.
+fn missing_paths(library: &[LibraryTrack], playlist_store: &PlaylistStore) -> LibraryResult<Vec<PathBuf>> {
+    let mut missing: Vec<PathBuf> = library.iter().map(|t| t.path.clone()).collect();
+    missing.extend(playlist_store.track_paths()?);
+    missing.sort();
+    missing.dedup();
+    missing.retain(|path| !path.exists());
+    Ok(missing)
+}
+
+/// Finds missing library and playlist tracks under `search_root` and points
+/// the library, playlists, cues and (with `cache_dir`) the analysis cache at
+/// their new paths. Content hashes come from the library's analysis, or the
+/// cache index for paths the library doesn't hold.
+pub fn relocate_missing(
+    library_store: &LibraryStore,
+    playlist_store: &PlaylistStore,
+    cue_store: &CueStore,
+    search_root: &Path,
+    cache_dir: Option<&Path>,
+) -> LibraryResult<RelocationReport> {
+    let library = library_store.all_tracks()?;
+    let missing = missing_paths(&library, playlist_store)?;
+    let mut report = RelocationReport::default();
+    if missing.is_empty() {
+        return Ok(report);
+    }
+
+    let by_path: HashMap<&Path, &LibraryTrack> = library.iter().map(|t| (t.path.as_path(), t)).collect();
+    let cache_index = cache_dir.and_then(|dir| cache::index::open_index(dir).ok());
+    let mut by_hash: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for path in missing {
+        let hash = by_path
+            .get(path.as_path())
+            .and_then(|t| Some(t.analysis.as_ref()?.content_hash.clone()))
+            .or_else(|| Some(cache_index.as_ref()?.get(&path).ok()??.content_hash));
+        match hash {
+            Some(hash) => by_hash.entry(hash).or_default().push(path),
+            None => report.without_hash.push(path),
+        }
+    }
+    // Content hash -> missing path, for hashes only one missing path has
+    let mut wanted: HashMap<String, PathBuf> = HashMap::new();
+    for (hash, mut paths) in by_hash {
+        if paths.len() == 1 {
+            wanted.insert(hash, paths.remove(0));
+        } else {
+            report.ambiguous.extend(paths);
+        }
+    }
+    report.ambiguous.sort();
+    log::info!(
+        "Relocating {} missing tracks under {} ({} without a content hash, {} sharing audio)",
+        wanted.len(),
+        search_root.display(),
+        report.without_hash.len(),
+        report.ambiguous.len()
+    );
+
+    // Files already in the library are tracks of their own, not moved ones.
+    // Files the size of a missing track are hashed first; tag edits change
+    // the size, so the rest are hashed only if tracks are still missing.
+    let candidates: Vec<PathBuf> = scanner::collect_audio_files(search_root)?
+        .into_iter()
+        .filter(|path| !by_path.contains_key(path.as_path()))
+        .collect();
+    let sizes: HashSet<u64> = wanted
+        .values()
+        .filter_map(|path| by_path.get(path.as_path()).map(|t| t.file_size))
+        .collect();
+    let (same_size, other): (Vec<&PathBuf>, Vec<&PathBuf>) = candidates
+        .iter()
+        .partition(|path| std::fs::metadata(path).is_ok_and(|m| sizes.contains(&m.len())));
+
+    let mut moves: HashMap<PathBuf, PathBuf> = HashMap::new();
+    for batch in [same_size, other] {
+        if wanted.is_empty() {
+            break;
+        }
+        for (to, hash) in hash_files(&batch) {
+            if let Some(from) = wanted.remove(&hash) {
+                moves.insert(from, to);
+            }
+        }
+    }
+    report.not_found.extend(wanted.into_values());
+    report.not_found.sort();
+
+    for (from, to) in &moves {
+        let Some(previous) = by_path.get(from.as_path()) else {
+            continue;
+        };
+        let root = if to.starts_with(&previous.root) { previous.root.as_path() } else { search_root };
+        move_track_record(library_store, from, to, root)?;
+        log::info!("Library: {} relocated to {}", from.display(), to.display());
+    }
+    playlist_store.relocate_tracks(&moves)?;
+    cue_store.relocate_tracks(&moves)?;
+    if let Some(Err(e)) = cache_dir.map(|dir| cache::relocate_entries(dir, &moves)) {
+        log::warn!("Failed to relocate cache entries: {}", e);
+    }
+    if let Err(e) = library_store.flush() {
+        log::warn!("Failed to save library database after relocation: {}", e);
+    }
+
+    report.relocated = moves.into_iter().collect();
+    report.relocated.sort();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::types::TrackBasicMetadata;
+    use crate::library::{TrackAnalysisSummary, UserFields};
+    use std::fs;
+
+    /// Writes a short mono 16-bit WAV whose samples all equal `level`.
+    fn write_wav(path: &Path, level: i16) {
+        let data: Vec<u8> = std::iter::repeat_n(level.to_le_bytes(), 4410).flatten().collect();
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&44100u32.to_le_bytes());
+        bytes.extend_from_slice(&88200u32.to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&data);
+        fs::write(path, bytes).unwrap();
+    }
+
+    fn missing_track(path: &Path, content_hash: &str) -> LibraryTrack {
+        LibraryTrack {
+            path: path.to_path_buf(),
+            root: path.parent().unwrap().to_path_buf(),
+            file_name: path.file_name().unwrap().to_string_lossy().into_owned(),
+            file_size: 8864,
+            modified_at: 1,
+            tags: Default::default(),
+            analysis: Some(TrackAnalysisSummary {
+                content_hash: content_hash.to_string(),
+                metadata: TrackBasicMetadata {
+                    duration_seconds: None,
+                    bpm: None,
+                    first_beat_sec: None,
+                },
+                loudness: None,
+                key: None,
+                energy: None,
+            }),
+            added_at: 1,
+            play_count: 0,
+            last_played_at: None,
+            user: UserFields::default(),
+            virtual_tracks: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn tracks_sharing_audio_are_reported_as_ambiguous() {
+        let root = std::env::temp_dir().join(format!("open-dj-relocate-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let old_dir = root.join("old");
+        let search_root = root.join("new");
+        fs::create_dir_all(&search_root).unwrap();
+
+        let shared = search_root.join("shared.wav");
+        let single = search_root.join("single.wav");
+        write_wav(&shared, 1000);
+        write_wav(&single, 2000);
+        let shared_hash = cache::fingerprint::compute_content_hash(&shared).unwrap();
+        let single_hash = cache::fingerprint::compute_content_hash(&single).unwrap();
+
+        let library_store = LibraryStore::new();
+        library_store
+            .upsert_tracks(vec![
+                missing_track(&old_dir.join("a.wav"), &shared_hash),
+                missing_track(&old_dir.join("b.wav"), &shared_hash),
+                missing_track(&old_dir.join("c.wav"), &single_hash),
+            ])
+            .unwrap();
+
+        let report = relocate_missing(&library_store, &PlaylistStore::new(), &CueStore::new(), &search_root, None)
+            .unwrap();
+        assert_eq!(report.ambiguous, [old_dir.join("a.wav"), old_dir.join("b.wav")]);
+        assert_eq!(report.relocated, [(old_dir.join("c.wav"), single.clone())]);
+        assert!(report.not_found.is_empty());
+        assert!(library_store.get_track(&old_dir.join("a.wav")).unwrap().is_some());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,121 @@
+use super::cue_sheet;
+use super::{LibraryError, LibraryResult, LibraryTrack, TrackAnalysisSummary};
+use crate::audio::cache;
+use crate::audio::decoding;
+use rayon::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.starts_with('.'))
+}
+
+/// Lists supported audio files under `dir`, descending into subdirectories in
+/// parallel. Hidden entries (including our own `.open-dj` folders) are skipped,
+/// and symlinked directories are not followed so link cycles can't loop.
+fn walk(dir: &Path) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::warn!("Library scan: cannot read {}: {}", dir.display(), e);
+            return Vec::new();
+        }
+    };
+
+    let mut files = Vec::new();
+    let mut subdirs = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if is_hidden(&path) {
+            continue;
+        }
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            subdirs.push(path);
+        } else if (file_type.is_file() || (file_type.is_symlink() && path.is_file()))
+            && decoding::is_supported_audio_file(&path)
+        {
+            files.push(path);
+        }
+    }
+
+    files.par_extend(subdirs.par_iter().flat_map_iter(|subdir| walk(subdir)));
+    files
+}
+
+/// Every supported audio file under a library root, sorted by path.
+pub fn collect_audio_files(root: &Path) -> LibraryResult<Vec<PathBuf>> {
+    if !root.is_dir() {
+        return Err(LibraryError::RootNotFound(root.display().to_string()));
+    }
+    let mut files = walk(root);
+    files.par_sort();
+    Ok(files)
+}
+
+/// Builds the record for one file: filesystem details, tags, cached
+/// analysis when the cache has a current entry for it, and the tracks of a
+/// CUE sheet next to it. Unreadable tags are logged and left empty rather
+/// than dropping the track.
+pub fn build_track_record(path: &Path, root: &Path, cache_dir: Option<&Path>) -> LibraryResult<LibraryTrack> {
+    let metadata = fs::metadata(path)?;
+    let modified_at = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let tags = decoding::read_tags(&path.to_string_lossy()).unwrap_or_else(|e| {
+        log::debug!("Library scan: no tags for {}: {}", path.display(), e);
+        Default::default()
+    });
+
+    let analysis = cache_dir
+        .and_then(|cache_dir| cache::peek_cached_entry(path, cache_dir))
+        .map(|cached| TrackAnalysisSummary {
+            content_hash: cached.fingerprint.content_hash,
+            metadata: cached.bpm_analysis,
+            loudness: cached.loudness_analysis,
+            key: cached.key_analysis,
+            energy: cached.energy,
+        });
+
+    Ok(LibraryTrack {
+        path: path.to_path_buf(),
+        root: root.to_path_buf(),
+        file_name: path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        file_size: metadata.len(),
+        modified_at,
+        tags,
+        analysis,
+        added_at: cache::unix_now(),
+        play_count: 0,
+        last_played_at: None,
+        user: Default::default(),
+        virtual_tracks: cue_sheet::virtual_tracks_for(path),
+    })
+}
+
+/// Builds records for a batch of files in parallel, skipping files that
+/// disappeared since they were listed.
+pub fn build_track_records(paths: &[PathBuf], root: &Path, cache_dir: Option<&Path>) -> Vec<LibraryTrack> {
+    paths
+        .par_iter()
+        .filter_map(|path| match build_track_record(path, root, cache_dir) {
+            Ok(track) => Some(track),
+            Err(e) => {
+                log::warn!("Library scan: skipping {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect()
+}
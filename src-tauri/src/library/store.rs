@@ -0,0 +1,497 @@
+use super::query::{self, TrackQuery};
+use super::suggest::{self, SuggestionReference, TrackSuggestion};
+use super::writer::BatchedWriter;
+use super::{LibraryError, LibraryPage, LibraryResult, LibraryTrack, TrackAnalysisSummary, UserFields};
+use crate::audio::cache;
+use crate::audio::types::TrackAnalysis;
+use redb::{Database, ReadableTable, TableDefinition};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Bump when the record layout changes incompatibly.
+const LIBRARY_DB_VERSION: u32 = 1;
+const LIBRARY_DB_FILE_NAME: &str = "library.redb";
+/// Changed tracks are written this long after the first change, together.
+const LIBRARY_WRITE_DELAY: Duration = Duration::from_secs(2);
+
+/// Track records as JSON, keyed by the bytes of their path.
+const TRACKS_TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("tracks");
+const META_TABLE: TableDefinition<&str, u32> = TableDefinition::new("meta");
+const VERSION_KEY: &str = "version";
+
+#[derive(Debug, Default)]
+struct LibraryState {
+    /// Tracks of every scanned root, sorted by path.
+    tracks: Vec<LibraryTrack>,
+    /// Paths added, changed or removed since the last write.
+    unsaved: HashSet<PathBuf>,
+}
+
+impl LibraryState {
+    fn with_tracks(mut tracks: Vec<LibraryTrack>) -> Self {
+        tracks.sort_by(|a, b| a.path.cmp(&b.path));
+        LibraryState {
+            tracks,
+            unsaved: HashSet::new(),
+        }
+    }
+
+    fn position(&self, path: &Path) -> Option<usize> {
+        self.tracks.binary_search_by(|t| t.path.as_path().cmp(path)).ok()
+    }
+}
+
+fn track_key(path: &Path) -> &[u8] {
+    path.as_os_str().as_encoded_bytes()
+}
+
+/// The embedded database behind a library store: one record per track, so
+/// a change writes only the tracks it touched.
+struct LibraryDb {
+    db: Database,
+    /// Held from taking the unsaved paths until they are committed, so an
+    /// older copy of a track never overwrites a newer one.
+    write_lock: Mutex<()>,
+}
+
+impl LibraryDb {
+    /// Opens or creates the database. Records of another layout version are
+    /// dropped, as are records that don't parse.
+    fn open(db_path: &Path) -> LibraryResult<(Self, Vec<LibraryTrack>)> {
+        if let Some(parent) = db_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let db = Database::create(db_path)?;
+
+        let txn = db.begin_write()?;
+        {
+            let mut meta = txn.open_table(META_TABLE)?;
+            let mut tracks = txn.open_table(TRACKS_TABLE)?;
+            let version = meta.get(VERSION_KEY)?.map(|v| v.value());
+            if version.is_some_and(|v| v != LIBRARY_DB_VERSION) {
+                log::warn!(
+                    "Library database version {:?} (expected {}), starting a new library",
+                    version,
+                    LIBRARY_DB_VERSION
+                );
+                tracks.retain(|_, _| false)?;
+            }
+            meta.insert(VERSION_KEY, LIBRARY_DB_VERSION)?;
+        }
+        txn.commit()?;
+
+        let txn = db.begin_read()?;
+        let table = txn.open_table(TRACKS_TABLE)?;
+        let mut tracks = Vec::new();
+        for row in table.iter()? {
+            let (key, value) = row?;
+            match serde_json::from_slice::<LibraryTrack>(value.value()) {
+                Ok(track) => tracks.push(track),
+                Err(e) => log::warn!(
+                    "Skipping unreadable library record {}: {}",
+                    String::from_utf8_lossy(key.value()),
+                    e
+                ),
+            }
+        }
+        let library_db = LibraryDb {
+            db,
+            write_lock: Mutex::new(()),
+        };
+        Ok((library_db, tracks))
+    }
+
+    /// Writes (or, for `None`, deletes) records in one transaction.
+    fn commit(&self, changes: &[(PathBuf, Option<Vec<u8>>)]) -> LibraryResult<()> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(TRACKS_TABLE)?;
+            for (path, record) in changes {
+                match record {
+                    Some(record) => table.insert(track_key(path), record.as_slice())?,
+                    None => table.remove(track_key(path))?,
+                };
+            }
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Writes the tracks changed since the last call. Only the changed
+    /// tracks are copied, under the store lock; they are serialized and
+    /// committed after it is released.
+    fn write_unsaved(&self, state: &Mutex<LibraryState>) -> LibraryResult<()> {
+        let _writing = self
+            .write_lock
+            .lock()
+            .map_err(|e| LibraryError::LockPoisoned(format!("Failed to lock library writes: {}", e)))?;
+        let changed: Vec<(PathBuf, Option<LibraryTrack>)> = {
+            let mut state = state
+                .lock()
+                .map_err(|e| LibraryError::LockPoisoned(format!("Failed to lock library store: {}", e)))?;
+            let unsaved = std::mem::take(&mut state.unsaved);
+            unsaved
+                .into_iter()
+                .map(|path| {
+                    let track = state.position(&path).map(|i| state.tracks[i].clone());
+                    (path, track)
+                })
+                .collect()
+        };
+        if changed.is_empty() {
+            return Ok(());
+        }
+
+        let written = changed
+            .iter()
+            .map(|(path, track)| Ok((path.clone(), track.as_ref().map(serde_json::to_vec).transpose()?)))
+            .collect::<LibraryResult<Vec<_>>>()
+            .and_then(|changes| self.commit(&changes));
+        if let Err(e) = written {
+            // Keep them for the next write
+            if let Ok(mut state) = state.lock() {
+                state.unsaved.extend(changed.into_iter().map(|(path, _)| path));
+            }
+            return Err(e);
+        }
+        log::debug!("Saved {} library records", changed.len());
+        Ok(())
+    }
+}
+
+/// Whether an open failed on the file's contents, as opposed to e.g. it being
+/// locked by another instance.
+fn is_unreadable(e: &LibraryError) -> bool {
+    match e {
+        LibraryError::Database(e) => match e.as_ref() {
+            redb::Error::Corrupted(_) | redb::Error::UpgradeRequired(_) => true,
+            redb::Error::Io(e) => e.kind() == io::ErrorKind::InvalidData,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// The track library database shared by the library commands.
+///
+/// Tracks live in memory for querying and are persisted to an embedded
+/// database in the app data directory, one record per track. Changes are
+/// written in batches on a background thread.
+pub struct LibraryStore {
+    /// `None` keeps the library in memory only.
+    db: Option<Arc<LibraryDb>>,
+    state: Arc<Mutex<LibraryState>>,
+    writer: Option<BatchedWriter>,
+}
+
+impl LibraryStore {
+    /// An in-memory library that is never written to disk.
+    pub fn new() -> Self {
+        LibraryStore {
+            db: None,
+            state: Arc::new(Mutex::new(LibraryState::default())),
+            writer: None,
+        }
+    }
+
+    /// Opens the library database in `data_dir`, creating an empty one if none
+    /// exists. A database that can't be opened is set aside rather than
+    /// overwritten.
+    pub fn open(data_dir: &Path) -> Self {
+        let db_path = data_dir.join(LIBRARY_DB_FILE_NAME);
+        let opened = LibraryDb::open(&db_path).or_else(|e| {
+            if !is_unreadable(&e) {
+                return Err(e);
+            }
+            log::warn!("Library database {} unreadable ({}), starting a new library", db_path.display(), e);
+            if let Err(e) = fs::rename(&db_path, db_path.with_extension("redb.corrupt")) {
+                log::warn!("Failed to set aside corrupted library database: {}", e);
+            }
+            LibraryDb::open(&db_path)
+        });
+        let (db, tracks) = match opened {
+            Ok(opened) => opened,
+            Err(e) => {
+                log::error!("Failed to create library database {}, library will not be saved: {}", db_path.display(), e);
+                return Self::new();
+            }
+        };
+        let db = Arc::new(db);
+        let state = Arc::new(Mutex::new(LibraryState::with_tracks(tracks)));
+
+        let writer = {
+            let db = db.clone();
+            let state = state.clone();
+            BatchedWriter::spawn("library-writer", LIBRARY_WRITE_DELAY, move || {
+                if let Err(e) = db.write_unsaved(&state) {
+                    log::error!("Failed to save library database: {}", e);
+                }
+            })
+        };
+        log::info!("Library database {} loaded with {} tracks", db_path.display(), state.lock().map_or(0, |s| s.tracks.len()));
+        LibraryStore {
+            db: Some(db),
+            state,
+            writer: Some(writer),
+        }
+    }
+
+    fn lock(&self) -> LibraryResult<MutexGuard<'_, LibraryState>> {
+        self.state
+            .lock()
+            .map_err(|e| LibraryError::LockPoisoned(format!("Failed to lock library store: {}", e)))
+    }
+
+    /// Applies a mutation under the store lock and schedules a write of the
+    /// paths it marked unsaved.
+    fn update<T>(&self, mutate: impl FnOnce(&mut LibraryState) -> T) -> LibraryResult<T> {
+        let mut state = self.lock()?;
+        let result = mutate(&mut state);
+        match &self.writer {
+            Some(writer) if !state.unsaved.is_empty() => writer.schedule(),
+            Some(_) => {}
+            None => state.unsaved.clear(),
+        }
+        Ok(result)
+    }
+
+    /// Writes any pending updates to disk.
+    pub fn flush(&self) -> LibraryResult<()> {
+        match &self.db {
+            Some(db) => db.write_unsaved(&self.state),
+            None => Ok(()),
+        }
+    }
+
+    /// Drops tracks under `root` that a rescan no longer found. Returns how
+    /// many were removed.
+    pub fn prune_root(&self, root: &Path, found: &HashSet<PathBuf>) -> LibraryResult<usize> {
+        self.update(|state| {
+            let before = state.tracks.len();
+            let mut removed = Vec::new();
+            state.tracks.retain(|track| {
+                let keep = track.root != root || found.contains(&track.path);
+                if !keep {
+                    removed.push(track.path.clone());
+                }
+                keep
+            });
+            state.unsaved.extend(removed);
+            before - state.tracks.len()
+        })
+    }
+
+    /// Adds freshly scanned tracks, replacing any with the same path. Play
+    /// history and user fields of replaced tracks are kept, as is their
+    /// analysis if the file is unchanged and the new record has none.
+    pub fn upsert_tracks(&self, tracks: Vec<LibraryTrack>) -> LibraryResult<()> {
+        if tracks.is_empty() {
+            return Ok(());
+        }
+        self.update(|state| {
+            let incoming: HashSet<PathBuf> = tracks.iter().map(|t| t.path.clone()).collect();
+            state.unsaved.extend(incoming.iter().cloned());
+            let mut previous: HashMap<PathBuf, LibraryTrack> = HashMap::new();
+            state.tracks.retain(|t| {
+                if incoming.contains(&t.path) {
+                    previous.insert(t.path.clone(), t.clone());
+                    false
+                } else {
+                    true
+                }
+            });
+
+            for mut track in tracks {
+                if let Some(old) = previous.remove(&track.path) {
+                    track.inherit_from(&old);
+                    let unchanged = old.file_size == track.file_size && old.modified_at == track.modified_at;
+                    if track.analysis.is_none() && unchanged {
+                        track.analysis = old.analysis;
+                    }
+                }
+                state.tracks.push(track);
+            }
+            state.tracks.sort_by(|a, b| a.path.cmp(&b.path));
+        })
+    }
+
+    pub fn get_track(&self, path: &Path) -> LibraryResult<Option<LibraryTrack>> {
+        let state = self.lock()?;
+        Ok(state.position(path).map(|i| state.tracks[i].clone()))
+    }
+
+    /// Every track in the library, sorted by path.
+    pub fn all_tracks(&self) -> LibraryResult<Vec<LibraryTrack>> {
+        Ok(self.lock()?.tracks.clone())
+    }
+
+    /// The distinct library roots of the stored tracks.
+    pub fn roots(&self) -> LibraryResult<Vec<PathBuf>> {
+        let roots: HashSet<PathBuf> = self.lock()?.tracks.iter().map(|t| t.root.clone()).collect();
+        Ok(roots.into_iter().collect())
+    }
+
+    /// Tracks at `path` or, if it was a directory, anywhere under it.
+    pub fn tracks_under(&self, path: &Path) -> LibraryResult<Vec<LibraryTrack>> {
+        Ok(self
+            .lock()?
+            .tracks
+            .iter()
+            .filter(|t| t.path.starts_with(path))
+            .cloned()
+            .collect())
+    }
+
+    pub fn remove_tracks(&self, paths: &[&Path]) -> LibraryResult<()> {
+        if paths.is_empty() {
+            return Ok(());
+        }
+        let removed: HashSet<&Path> = paths.iter().copied().collect();
+        self.update(|state| {
+            state.tracks.retain(|t| !removed.contains(t.path.as_path()));
+            state.unsaved.extend(removed.iter().map(|path| path.to_path_buf()));
+        })
+    }
+
+    /// Stores a fresh analysis result for a library track. Files outside the
+    /// library are ignored.
+    pub fn record_analysis(&self, path: &Path, analysis: &TrackAnalysis) -> LibraryResult<bool> {
+        if self.lock()?.position(path).is_none() {
+            return Ok(false);
+        }
+        self.update(|state| match state.position(path) {
+            Some(i) => {
+                state.tracks[i].analysis = Some(TrackAnalysisSummary::from(analysis));
+                state.unsaved.insert(path.to_path_buf());
+                true
+            }
+            None => false,
+        })
+    }
+
+    /// Counts one play of a track and stamps its last played time.
+    pub fn record_play(&self, path: &Path) -> LibraryResult<LibraryTrack> {
+        let now = cache::unix_now();
+        self.update_track(path, |track| {
+            track.play_count += 1;
+            track.last_played_at = Some(now);
+        })
+    }
+
+    pub fn set_user_fields(&self, path: &Path, mut user: UserFields) -> LibraryResult<LibraryTrack> {
+        user.rating = user.rating.map(|rating| rating.min(5));
+        self.update_track(path, |track| track.user = user)
+    }
+
+    /// Applies a mutation to one track and returns the updated record.
+    pub fn update_track(&self, path: &Path, mutate: impl FnOnce(&mut LibraryTrack)) -> LibraryResult<LibraryTrack> {
+        self.update(|state| {
+            let i = state.position(path)?;
+            mutate(&mut state.tracks[i]);
+            state.unsaved.insert(path.to_path_buf());
+            Some(state.tracks[i].clone())
+        })?
+        .ok_or_else(|| LibraryError::TrackNotFound(path.display().to_string()))
+    }
+
+    pub fn page(&self, offset: usize, limit: usize) -> LibraryResult<LibraryPage> {
+        let state = self.lock()?;
+        let tracks = state.tracks.iter().skip(offset).take(limit).cloned().collect();
+        Ok(LibraryPage {
+            tracks,
+            offset,
+            total: state.tracks.len(),
+        })
+    }
+
+    /// Filters, sorts and pages the library.
+    pub fn query(&self, track_query: &TrackQuery) -> LibraryResult<LibraryPage> {
+        Ok(query::run_query(&self.lock()?.tracks, track_query))
+    }
+
+    /// Library tracks ranked as next tracks after `reference`.
+    pub fn suggest(
+        &self,
+        reference: &SuggestionReference,
+        exclude: &HashSet<PathBuf>,
+        count: usize,
+    ) -> LibraryResult<Vec<TrackSuggestion>> {
+        Ok(suggest::suggest(&self.lock()?.tracks, reference, exclude, count))
+    }
+
+    /// Paths of every track a query matches, for smart playlists.
+    pub fn matching_paths(&self, track_query: &TrackQuery) -> LibraryResult<Vec<PathBuf>> {
+        Ok(query::matching_paths(&self.lock()?.tracks, track_query))
+    }
+}
+
+impl Default for LibraryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_data_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("open-dj-library-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn track(path: &str) -> LibraryTrack {
+        LibraryTrack {
+            path: PathBuf::from(path),
+            root: PathBuf::from("/music"),
+            file_name: Path::new(path).file_name().unwrap().to_string_lossy().into_owned(),
+            file_size: 1024,
+            modified_at: 1,
+            tags: Default::default(),
+            analysis: None,
+            added_at: 1,
+            play_count: 0,
+            last_played_at: None,
+            user: UserFields::default(),
+            virtual_tracks: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn changes_are_written_per_track() {
+        let data_dir = temp_data_dir("records");
+        let store = LibraryStore::open(&data_dir);
+        store
+            .upsert_tracks(vec![track("/music/a.mp3"), track("/music/b.mp3"), track("/music/c.mp3")])
+            .unwrap();
+        store.record_play(Path::new("/music/b.mp3")).unwrap();
+        store.remove_tracks(&[Path::new("/music/c.mp3")]).unwrap();
+        store.flush().unwrap();
+        drop(store);
+
+        let reopened = LibraryStore::open(&data_dir);
+        let tracks = reopened.all_tracks().unwrap();
+        let paths: Vec<_> = tracks.iter().map(|t| t.path.clone()).collect();
+        assert_eq!(paths, [PathBuf::from("/music/a.mp3"), PathBuf::from("/music/b.mp3")]);
+        assert_eq!(tracks[1].play_count, 1);
+        let _ = fs::remove_dir_all(&data_dir);
+    }
+
+    #[test]
+    fn unreadable_database_is_set_aside() {
+        let data_dir = temp_data_dir("corrupt");
+        fs::write(data_dir.join(LIBRARY_DB_FILE_NAME), b"not a database").unwrap();
+
+        let store = LibraryStore::open(&data_dir);
+        store.upsert_tracks(vec![track("/music/a.mp3")]).unwrap();
+        store.flush().unwrap();
+        assert!(data_dir.join("library.redb.corrupt").exists());
+        let _ = fs::remove_dir_all(&data_dir);
+    }
+}
@@ -0,0 +1,231 @@
+//! Next-track suggestions: library tracks ranked by how well they mix out of
+//! the track playing on a deck, by key, tempo and energy.
+
+use super::query::{keys_compatible, track_bpm, track_camelot, track_energy};
+use super::LibraryTrack;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// Suggestions returned when the caller doesn't set a count.
+pub const DEFAULT_SUGGESTION_COUNT: usize = 20;
+/// Tempo gap, after half/double-time folding, beyond which tracks score
+/// nothing for tempo.
+const MAX_TEMPO_GAP_PERCENT: f32 = 8.0;
+/// Energy steps (on the 1-10 scale) a change in the wanted direction may take
+/// before it counts as a jump.
+const ENERGY_STEP: f32 = 2.0;
+
+const KEY_WEIGHT: f32 = 0.4;
+const TEMPO_WEIGHT: f32 = 0.4;
+const ENERGY_WEIGHT: f32 = 0.2;
+
+/// Where the set should go next.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EnergyDirection {
+    Up,
+    #[default]
+    Hold,
+    Down,
+}
+
+/// The playing track suggestions are made for.
+#[derive(Debug, Clone)]
+pub struct SuggestionReference {
+    pub path: PathBuf,
+    /// BPM of the track at its original speed.
+    pub bpm: Option<f32>,
+    pub pitch_rate: f32,
+    /// Camelot key at the original speed, e.g. (8, 'A').
+    pub camelot: Option<(u8, char)>,
+    pub energy: Option<f32>,
+    pub direction: EnergyDirection,
+}
+
+impl SuggestionReference {
+    /// Tempo as heard, after the deck's pitch.
+    fn playing_bpm(&self) -> Option<f32> {
+        self.bpm.map(|bpm| bpm * self.pitch_rate)
+    }
+}
+
+/// A library track and why it mixes out of the playing one.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackSuggestion {
+    pub track: LibraryTrack,
+    /// 0 to 1; higher mixes better.
+    pub score: f32,
+    /// Pitch rate that matches the playing tempo, when both BPMs are known.
+    pub pitch_rate: Option<f32>,
+    /// The track's Camelot key at that pitch rate.
+    pub effective_key: Option<String>,
+    pub reasons: Vec<String>,
+}
+
+/// Semitones a pitch rate shifts the key by, to the nearest semitone.
+fn semitones(pitch_rate: f32) -> i32 {
+    (12.0 * pitch_rate.max(f32::MIN_POSITIVE).log2()).round() as i32
+}
+
+/// A Camelot key moved by semitones: each semitone is seven steps on the wheel.
+fn shift_camelot((number, letter): (u8, char), semitones: i32) -> (u8, char) {
+    let shifted = (number as i32 - 1 + semitones * 7).rem_euclid(12) + 1;
+    (shifted as u8, letter)
+}
+
+fn camelot_name((number, letter): (u8, char)) -> String {
+    format!("{}{}", number, letter)
+}
+
+/// Folds a BPM to the half, same or double tempo closest to `target`.
+/// Returns the folded BPM and a note when folding was needed.
+fn fold_bpm(bpm: f32, target: f32) -> (f32, Option<&'static str>) {
+    [(bpm, None), (bpm * 2.0, Some("double-time")), (bpm / 2.0, Some("half-time"))]
+        .into_iter()
+        .min_by(|a, b| (a.0 - target).abs().total_cmp(&(b.0 - target).abs()))
+        .unwrap_or((bpm, None))
+}
+
+fn key_score(playing: (u8, char), candidate: (u8, char)) -> (f32, String) {
+    let name = camelot_name(candidate);
+    if playing == candidate {
+        (1.0, format!("Same key ({})", name))
+    } else if playing.1 != candidate.1 && keys_compatible(playing, candidate) {
+        (0.85, format!("Relative key ({})", name))
+    } else if keys_compatible(playing, candidate) {
+        (0.9, format!("Adjacent key ({})", name))
+    } else if playing.1 == candidate.1 && shift_camelot(playing, 2) == candidate {
+        // Two steps up the wheel, the "energy boost" mix
+        (0.5, format!("Energy boost key ({})", name))
+    } else {
+        (0.0, format!("Key clash ({})", name))
+    }
+}
+
+fn tempo_score(gap_percent: f32) -> f32 {
+    (1.0 - gap_percent.abs() / MAX_TEMPO_GAP_PERCENT).max(0.0)
+}
+
+fn energy_score(direction: EnergyDirection, change: f32) -> f32 {
+    let off_course = match direction {
+        EnergyDirection::Up if change >= 0.0 => (change - ENERGY_STEP).max(0.0),
+        EnergyDirection::Down if change <= 0.0 => (-change - ENERGY_STEP).max(0.0),
+        EnergyDirection::Hold => (change.abs() - 1.0).max(0.0),
+        _ => change.abs() + 1.0,
+    };
+    (1.0 - off_course / ENERGY_STEP).max(0.0)
+}
+
+/// A candidate's score, kept by reference until it makes the top list.
+struct Scored<'a> {
+    track: &'a LibraryTrack,
+    score: f32,
+    pitch_rate: Option<f32>,
+    effective_key: Option<(u8, char)>,
+    reasons: Vec<String>,
+}
+
+/// Scores one candidate. Missing analysis scores a neutral half for that part.
+fn score<'a>(reference: &SuggestionReference, track: &'a LibraryTrack) -> Scored<'a> {
+    let mut reasons = Vec::new();
+    let playing_bpm = reference.playing_bpm();
+
+    // Tempo: the pitch the candidate needs, after half/double-time folding
+    let mut pitch_rate = None;
+    let tempo = match (playing_bpm, track_bpm(track)) {
+        (Some(target), Some(bpm)) if bpm > 0.0 && target > 0.0 => {
+            let (folded, fold_note) = fold_bpm(bpm, target);
+            let rate = target / folded;
+            pitch_rate = Some(rate);
+            let gap = (rate - 1.0) * 100.0;
+            reasons.push(match fold_note {
+                Some(note) => format!("{:.1} BPM {} ({:+.1}%)", bpm, note, gap),
+                None => format!("{:.1} BPM ({:+.1}%)", bpm, gap),
+            });
+            tempo_score(gap)
+        }
+        _ => 0.5,
+    };
+
+    // Key: both tracks as heard, the playing one at its pitch and the
+    // candidate at the pitch that matches it
+    let playing_key = reference.camelot.map(|key| shift_camelot(key, semitones(reference.pitch_rate)));
+    let effective_key = track_camelot(track).map(|key| shift_camelot(key, semitones(pitch_rate.unwrap_or(1.0))));
+    let key = match (playing_key, effective_key) {
+        (Some(playing), Some(candidate)) => {
+            let (score, reason) = key_score(playing, candidate);
+            reasons.push(reason);
+            score
+        }
+        _ => 0.5,
+    };
+
+    let energy = match (reference.energy, track_energy(track)) {
+        (Some(current), Some(candidate)) => {
+            let change = candidate - current;
+            if change.abs() >= 0.5 {
+                reasons.push(format!("Energy {:+.0}", change));
+            } else {
+                reasons.push("Same energy".to_string());
+            }
+            energy_score(reference.direction, change)
+        }
+        _ => 0.5,
+    };
+
+    Scored {
+        track,
+        score: key * KEY_WEIGHT + tempo * TEMPO_WEIGHT + energy * ENERGY_WEIGHT,
+        pitch_rate,
+        effective_key,
+        reasons,
+    }
+}
+
+/// Builds the reference from the playing track's library record, with the
+/// deck's BPM taking precedence when the caller knows it.
+pub fn reference_for(
+    path: PathBuf,
+    track: Option<&LibraryTrack>,
+    original_bpm: Option<f32>,
+    pitch_rate: f32,
+    direction: EnergyDirection,
+) -> SuggestionReference {
+    SuggestionReference {
+        path,
+        bpm: original_bpm.or_else(|| track.and_then(track_bpm)),
+        pitch_rate,
+        camelot: track.and_then(track_camelot),
+        energy: track.and_then(track_energy),
+        direction,
+    }
+}
+
+/// Ranks `tracks` as next tracks after the reference, best first. The
+/// reference itself and `exclude` (e.g. tracks already played) are skipped.
+pub fn suggest(
+    tracks: &[LibraryTrack],
+    reference: &SuggestionReference,
+    exclude: &HashSet<PathBuf>,
+    count: usize,
+) -> Vec<TrackSuggestion> {
+    let mut scored: Vec<Scored> = tracks
+        .iter()
+        .filter(|track| track.path != reference.path && !exclude.contains(&track.path))
+        .map(|track| score(reference, track))
+        .collect();
+    scored.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.track.path.cmp(&b.track.path)));
+    scored
+        .into_iter()
+        .take(count)
+        .map(|s| TrackSuggestion {
+            track: s.track.clone(),
+            score: s.score,
+            pitch_rate: s.pitch_rate,
+            effective_key: s.effective_key.map(camelot_name),
+            reasons: s.reasons,
+        })
+        .collect()
+}
@@ -0,0 +1,290 @@
+//! FLAC metadata blocks and the Vorbis comments inside them.
+
+use super::{replace_file_head, TAG_PADDING};
+use crate::library::{LibraryError, LibraryResult};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"fLaC";
+const BLOCK_HEADER_LEN: usize = 4;
+const MAX_BLOCK_LEN: usize = 0xFF_FFFF;
+const LAST_BLOCK_FLAG: u8 = 0x80;
+
+const BLOCK_STREAMINFO: u8 = 0;
+const BLOCK_PADDING: u8 = 1;
+const BLOCK_VORBIS_COMMENT: u8 = 4;
+
+/// A FLAC file's metadata. Blocks other than the Vorbis comments and padding
+/// are kept as read.
+#[derive(Debug, Clone)]
+pub struct FlacMetadata {
+    /// Other blocks in file order as (type, data); STREAMINFO comes first.
+    blocks: Vec<(u8, Vec<u8>)>,
+    pub vendor: String,
+    /// Comments as (field name, value), in file order.
+    pub comments: Vec<(String, String)>,
+    /// Bytes from the start of the file to the first audio frame.
+    existing_len: u64,
+}
+
+impl FlacMetadata {
+    /// The first value of a comment field. Field names are case-insensitive.
+    pub fn comment(&self, name: &str) -> Option<&str> {
+        self.comments
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Replaces every value of a comment field, or removes the field when
+    /// `value` is `None`.
+    pub fn set_comment(&mut self, name: &str, value: Option<String>) {
+        let position = self.comments.iter().position(|(field, _)| field.eq_ignore_ascii_case(name));
+        self.comments.retain(|(field, _)| !field.eq_ignore_ascii_case(name));
+        if let Some(value) = value {
+            let entry = (name.to_ascii_uppercase(), value);
+            self.comments.insert(position.unwrap_or(self.comments.len()), entry);
+        }
+    }
+}
+
+fn invalid(path: &Path, reason: &str) -> LibraryError {
+    LibraryError::InvalidTag(format!("{} in {}", reason, path.display()))
+}
+
+fn read_u32_le(data: &[u8], position: &mut usize) -> Option<u32> {
+    let bytes = data.get(*position..*position + 4)?;
+    *position += 4;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_string(data: &[u8], position: &mut usize) -> Option<String> {
+    let len = read_u32_le(data, position)? as usize;
+    let bytes = data.get(*position..position.checked_add(len)?)?;
+    *position += len;
+    Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+fn parse_vorbis_comments(data: &[u8]) -> Option<(String, Vec<(String, String)>)> {
+    let mut position = 0;
+    let vendor = read_string(data, &mut position)?;
+    let count = read_u32_le(data, &mut position)?;
+    let mut comments = Vec::new();
+    for _ in 0..count {
+        let entry = read_string(data, &mut position)?;
+        if let Some((field, value)) = entry.split_once('=') {
+            comments.push((field.to_string(), value.to_string()));
+        }
+    }
+    Some((vendor, comments))
+}
+
+fn serialize_vorbis_comments(vendor: &str, comments: &[(String, String)]) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    data.extend_from_slice(vendor.as_bytes());
+    data.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for (field, value) in comments {
+        let entry = format!("{}={}", field, value);
+        data.extend_from_slice(&(entry.len() as u32).to_le_bytes());
+        data.extend_from_slice(entry.as_bytes());
+    }
+    data
+}
+
+/// Reads the metadata blocks of a FLAC file.
+pub fn read_metadata(path: &Path) -> LibraryResult<FlacMetadata> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(LibraryError::UnsupportedTagFormat(format!(
+            "{} does not start with a FLAC stream marker",
+            path.display()
+        )));
+    }
+
+    let mut metadata = FlacMetadata {
+        blocks: Vec::new(),
+        vendor: String::new(),
+        comments: Vec::new(),
+        existing_len: MAGIC.len() as u64,
+    };
+    loop {
+        let mut header = [0u8; BLOCK_HEADER_LEN];
+        reader.read_exact(&mut header)?;
+        let block_type = header[0] & !LAST_BLOCK_FLAG;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        let mut data = Vec::new();
+        (&mut reader).take(len as u64).read_to_end(&mut data)?;
+        if data.len() < len {
+            return Err(invalid(path, "metadata block runs past the end of the file"));
+        }
+        metadata.existing_len += (BLOCK_HEADER_LEN + len) as u64;
+
+        match block_type {
+            BLOCK_PADDING => {}
+            BLOCK_VORBIS_COMMENT => {
+                let (vendor, comments) =
+                    parse_vorbis_comments(&data).ok_or_else(|| invalid(path, "truncated Vorbis comment block"))?;
+                metadata.vendor = vendor;
+                metadata.comments = comments;
+            }
+            _ => metadata.blocks.push((block_type, data)),
+        }
+        if header[0] & LAST_BLOCK_FLAG != 0 {
+            break;
+        }
+    }
+
+    if metadata.blocks.first().map(|(block_type, _)| *block_type) != Some(BLOCK_STREAMINFO) {
+        return Err(invalid(path, "missing STREAMINFO block"));
+    }
+    Ok(metadata)
+}
+
+/// Writes metadata back to the file it was read from. The comments follow
+/// STREAMINFO; the leftover space becomes a padding block, or the file is
+/// rewritten with fresh padding when the blocks no longer fit.
+pub fn write_metadata(path: &Path, metadata: &FlacMetadata) -> LibraryResult<()> {
+    let comments = serialize_vorbis_comments(&metadata.vendor, &metadata.comments);
+    let mut blocks: Vec<(u8, &[u8])> = metadata.blocks.iter().map(|(t, d)| (*t, d.as_slice())).collect();
+    blocks.insert(1, (BLOCK_VORBIS_COMMENT, comments.as_slice()));
+
+    let mut head = MAGIC.to_vec();
+    for (block_type, data) in &blocks {
+        if data.len() > MAX_BLOCK_LEN {
+            return Err(invalid(path, "metadata block too large"));
+        }
+        head.push(*block_type);
+        head.extend_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+        head.extend_from_slice(data);
+    }
+
+    let existing_len = metadata.existing_len as usize;
+    let padding_len = match existing_len.checked_sub(head.len() + BLOCK_HEADER_LEN) {
+        Some(fits) if fits <= MAX_BLOCK_LEN => fits,
+        _ => TAG_PADDING,
+    };
+    head.push(BLOCK_PADDING | LAST_BLOCK_FLAG);
+    head.extend_from_slice(&(padding_len as u32).to_be_bytes()[1..]);
+    head.resize(head.len() + padding_len, 0);
+
+    replace_file_head(path, metadata.existing_len, &head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_support::temp_dir;
+    use super::*;
+    use std::fs;
+
+    const BLOCK_APPLICATION: u8 = 2;
+
+    /// Stand-in audio frames; only their bytes matter.
+    fn audio() -> Vec<u8> {
+        [0xFF, 0xF8, 0x69, 0x08, 0, 1, 2, 3].repeat(64)
+    }
+
+    fn streaminfo() -> Vec<u8> {
+        (0..34).collect()
+    }
+
+    fn flac_file(blocks: &[(u8, Vec<u8>)]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        for (index, (block_type, data)) in blocks.iter().enumerate() {
+            let last = if index + 1 == blocks.len() { LAST_BLOCK_FLAG } else { 0 };
+            bytes.push(block_type | last);
+            bytes.extend_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+            bytes.extend_from_slice(data);
+        }
+        bytes.extend(audio());
+        bytes
+    }
+
+    #[test]
+    fn comments_round_trip_around_the_audio() {
+        let dir = temp_dir("flac-round-trip");
+        let path = dir.join("track.flac");
+        let application = b"riff\x01\x02\x03".to_vec();
+        let blocks = vec![(BLOCK_STREAMINFO, streaminfo()), (BLOCK_APPLICATION, application.clone())];
+        fs::write(&path, flac_file(&blocks)).unwrap();
+
+        let mut metadata = read_metadata(&path).unwrap();
+        assert!(metadata.comments.is_empty());
+        metadata.vendor = "reference libFLAC 1.4.3".to_string();
+        metadata.set_comment("bpm", Some("128".to_string()));
+        metadata.set_comment("INITIALKEY", Some("8A".to_string()));
+        write_metadata(&path, &metadata).unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        assert!(bytes.ends_with(&audio()));
+        let mut metadata = read_metadata(&path).unwrap();
+        assert_eq!(metadata.existing_len as usize + audio().len(), bytes.len());
+        assert_eq!(metadata.blocks, blocks);
+        assert_eq!(metadata.vendor, "reference libFLAC 1.4.3");
+        assert_eq!(metadata.comment("BPM"), Some("128"));
+        assert_eq!(metadata.comment("initialkey"), Some("8A"));
+
+        // A small edit fits the padding and leaves the audio where it was
+        metadata.set_comment("BPM", Some("124".to_string()));
+        metadata.set_comment("INITIALKEY", None);
+        write_metadata(&path, &metadata).unwrap();
+        let rewritten = fs::read(&path).unwrap();
+        assert_eq!(rewritten.len(), bytes.len());
+        assert!(rewritten.ends_with(&audio()));
+        let mut metadata = read_metadata(&path).unwrap();
+        assert_eq!(metadata.comments, vec![("BPM".to_string(), "124".to_string())]);
+
+        // Outgrowing it rewrites the file with the audio copied through
+        let long = "x".repeat(3 * TAG_PADDING);
+        metadata.set_comment("COMMENT", Some(long.clone()));
+        write_metadata(&path, &metadata).unwrap();
+        let grown = fs::read(&path).unwrap();
+        assert!(grown.len() > bytes.len());
+        assert!(grown.ends_with(&audio()));
+        let metadata = read_metadata(&path).unwrap();
+        assert_eq!(metadata.blocks, blocks);
+        assert_eq!(metadata.comment("COMMENT"), Some(long.as_str()));
+        assert_eq!(metadata.comment("BPM"), Some("124"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn malformed_and_oversized_blocks_are_rejected() {
+        let dir = temp_dir("flac-malformed");
+        let path = dir.join("track.flac");
+
+        fs::write(&path, audio()).unwrap();
+        assert!(matches!(read_metadata(&path), Err(LibraryError::UnsupportedTagFormat(_))));
+
+        fs::write(&path, flac_file(&[(BLOCK_APPLICATION, vec![1, 2, 3])])).unwrap();
+        assert!(matches!(read_metadata(&path), Err(LibraryError::InvalidTag(_))));
+
+        // A vendor string longer than its block
+        let mut comments = 1000u32.to_le_bytes().to_vec();
+        comments.extend_from_slice(b"vend");
+        let blocks = [(BLOCK_STREAMINFO, streaminfo()), (BLOCK_VORBIS_COMMENT, comments)];
+        fs::write(&path, flac_file(&blocks)).unwrap();
+        assert!(matches!(read_metadata(&path), Err(LibraryError::InvalidTag(_))));
+
+        // A block header claiming the largest length over a few bytes
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(BLOCK_STREAMINFO | LAST_BLOCK_FLAG);
+        bytes.extend_from_slice(&[0xFF, 0xFF, 0xFF]);
+        bytes.extend_from_slice(&streaminfo());
+        fs::write(&path, &bytes).unwrap();
+        assert!(matches!(read_metadata(&path), Err(LibraryError::InvalidTag(_))));
+
+        // Metadata ending without a last block
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[BLOCK_STREAMINFO, 0, 0, 34]);
+        bytes.extend_from_slice(&streaminfo());
+        fs::write(&path, &bytes).unwrap();
+        assert!(read_metadata(&path).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,542 @@
+//! ID3v2.3 and ID3v2.4 tags, as found at the start of MP3 files. Frames are
+//! kept as raw bytes; only the frame types we edit are interpreted.
+
+use super::{replace_file_head, TAG_PADDING};
+use crate::library::{LibraryError, LibraryResult};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+const HEADER_LEN: usize = 10;
+const FLAG_UNSYNCHRONISATION: u8 = 0x80;
+const FLAG_EXTENDED_HEADER: u8 = 0x40;
+const FLAG_FOOTER: u8 = 0x10;
+/// Tag and frame sizes are 28-bit syncsafe integers.
+const MAX_TAG_LEN: usize = 0x0FFF_FFFF;
+
+/// Text encodings of frame strings.
+const ENCODING_LATIN1: u8 = 0;
+const ENCODING_UTF16: u8 = 1;
+const ENCODING_UTF16BE: u8 = 2;
+const ENCODING_UTF8: u8 = 3;
+/// Language code of comment frames we write.
+const COMMENT_LANGUAGE: &[u8; 3] = b"eng";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Id3Frame {
+    /// Four-character frame id, e.g. "TBPM" or "GEOB".
+    pub id: String,
+    pub flags: u16,
+    pub data: Vec<u8>,
+}
+
+/// A file's ID3v2 tag.
+#[derive(Debug, Clone)]
+pub struct Id3Tag {
+    /// Minor version: 3 or 4.
+    pub version: u8,
+    pub frames: Vec<Id3Frame>,
+    /// Bytes the tag occupies in the file, padding included; 0 if it has none.
+    existing_len: u64,
+}
+
+impl Id3Tag {
+    pub fn new() -> Self {
+        Id3Tag {
+            version: 4,
+            frames: Vec::new(),
+            existing_len: 0,
+        }
+    }
+
+    /// The binary object of the GEOB frame with this content description.
+    pub fn geob(&self, description: &str) -> Option<&[u8]> {
+        self.frames
+            .iter()
+            .filter(|frame| frame.id == "GEOB")
+            .find_map(|frame| parse_geob(&frame.data).filter(|(desc, _)| desc == description))
+            .map(|(_, object)| object)
+    }
+
+    /// Replaces the GEOB frame with this content description.
+    pub fn set_geob(&mut self, description: &str, mime_type: &str, object: &[u8]) {
+        let mut data = vec![ENCODING_LATIN1];
+        for field in [mime_type, "", description] {
+            data.extend(field.bytes().map(|b| if b.is_ascii() { b } else { b'?' }));
+            data.push(0);
+        }
+        data.extend_from_slice(object);
+        let frame = Id3Frame {
+            id: "GEOB".to_string(),
+            flags: 0,
+            data,
+        };
+        match self
+            .frames
+            .iter()
+            .position(|f| f.id == "GEOB" && parse_geob(&f.data).is_some_and(|(desc, _)| desc == description))
+        {
+            Some(index) => self.frames[index] = frame,
+            None => self.frames.push(frame),
+        }
+    }
+
+    /// The first string of a text frame such as "TBPM" or "TKEY".
+    pub fn text(&self, id: &str) -> Option<String> {
+        let frame = self.frames.iter().find(|frame| frame.id == id)?;
+        let (&encoding, rest) = frame.data.split_first()?;
+        Some(decode_text(encoding, split_terminated(encoding, rest).0))
+    }
+
+    pub fn set_text(&mut self, id: &str, value: &str) {
+        let encoding = self.text_encoding(value);
+        let mut data = vec![encoding];
+        data.extend(encode_text(encoding, value, false));
+        self.replace_frames(|frame| frame.id == id, id, data);
+    }
+
+    /// The value of the TXXX frame with this description. Descriptions are
+    /// matched case-insensitively, as taggers disagree on "REPLAYGAIN_*" case.
+    pub fn user_text(&self, description: &str) -> Option<String> {
+        self.frames
+            .iter()
+            .filter(|frame| frame.id == "TXXX")
+            .find_map(|frame| parse_described(&frame.data, 0).filter(|(desc, _)| desc.eq_ignore_ascii_case(description)))
+            .map(|(_, value)| value)
+    }
+
+    pub fn set_user_text(&mut self, description: &str, value: &str) {
+        let encoding = self.text_encoding(&format!("{}{}", description, value));
+        let mut data = vec![encoding];
+        data.extend(encode_text(encoding, description, true));
+        data.extend(encode_text(encoding, value, false));
+        let matches = |frame: &Id3Frame| {
+            frame.id == "TXXX" && parse_described(&frame.data, 0).is_some_and(|(desc, _)| desc.eq_ignore_ascii_case(description))
+        };
+        self.replace_frames(matches, "TXXX", data);
+    }
+
+    /// The text of the COMM frame without a content description, the one
+    /// players show as the comment.
+    pub fn comment(&self) -> Option<String> {
+        self.frames
+            .iter()
+            .filter(|frame| frame.id == "COMM")
+            .find_map(|frame| parse_described(&frame.data, COMMENT_LANGUAGE.len()).filter(|(desc, _)| desc.is_empty()))
+            .map(|(_, text)| text)
+    }
+
+    pub fn set_comment(&mut self, value: &str) {
+        let encoding = self.text_encoding(value);
+        let mut data = vec![encoding];
+        data.extend_from_slice(COMMENT_LANGUAGE);
+        data.extend(encode_text(encoding, "", true));
+        data.extend(encode_text(encoding, value, false));
+        let matches = |frame: &Id3Frame| {
+            frame.id == "COMM" && parse_described(&frame.data, COMMENT_LANGUAGE.len()).is_some_and(|(desc, _)| desc.is_empty())
+        };
+        self.replace_frames(matches, "COMM", data);
+    }
+
+    /// Replaces the first frame `matches` selects and drops the others, or
+    /// appends the frame when none matches.
+    fn replace_frames(&mut self, matches: impl Fn(&Id3Frame) -> bool, id: &str, data: Vec<u8>) {
+        let frame = Id3Frame {
+            id: id.to_string(),
+            flags: 0,
+            data,
+        };
+        let position = self.frames.iter().position(&matches);
+        self.frames.retain(|f| !matches(f));
+        self.frames.insert(position.unwrap_or(self.frames.len()), frame);
+    }
+
+    /// UTF-8 where the version allows it; ID3v2.3 gets Latin-1, or UTF-16
+    /// for text Latin-1 can't hold.
+    fn text_encoding(&self, text: &str) -> u8 {
+        if self.version >= 4 {
+            ENCODING_UTF8
+        } else if text.chars().all(|c| (c as u32) < 0x100) {
+            ENCODING_LATIN1
+        } else {
+            ENCODING_UTF16
+        }
+    }
+}
+
+impl Default for Id3Tag {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |value, &b| (value << 7) | (b & 0x7F) as u32)
+}
+
+fn to_syncsafe(value: u32) -> [u8; 4] {
+    [
+        ((value >> 21) & 0x7F) as u8,
+        ((value >> 14) & 0x7F) as u8,
+        ((value >> 7) & 0x7F) as u8,
+        (value & 0x7F) as u8,
+    ]
+}
+
+fn is_wide(encoding: u8) -> bool {
+    encoding == ENCODING_UTF16 || encoding == ENCODING_UTF16BE
+}
+
+/// Splits the next null-terminated string off a frame's fields. A missing
+/// terminator ends the string at the end of the data.
+fn split_terminated(encoding: u8, data: &[u8]) -> (&[u8], &[u8]) {
+    let end = if is_wide(encoding) {
+        data.chunks_exact(2).position(|pair| pair == [0, 0]).map(|index| (index * 2, 2))
+    } else {
+        data.iter().position(|&b| b == 0).map(|index| (index, 1))
+    };
+    match end {
+        Some((end, terminator_len)) => (&data[..end], &data[end + terminator_len..]),
+        None => (data, &[]),
+    }
+}
+
+fn decode_text(encoding: u8, bytes: &[u8]) -> String {
+    match encoding {
+        ENCODING_UTF16 | ENCODING_UTF16BE => {
+            let (big_endian, bytes) = match bytes {
+                [0xFE, 0xFF, rest @ ..] => (true, rest),
+                [0xFF, 0xFE, rest @ ..] => (false, rest),
+                _ => (encoding == ENCODING_UTF16BE, bytes),
+            };
+            let units: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|pair| {
+                    if big_endian {
+                        u16::from_be_bytes([pair[0], pair[1]])
+                    } else {
+                        u16::from_le_bytes([pair[0], pair[1]])
+                    }
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        ENCODING_UTF8 => String::from_utf8_lossy(bytes).into_owned(),
+        _ => bytes.iter().map(|&b| b as char).collect(),
+    }
+}
+
+fn encode_text(encoding: u8, text: &str, terminated: bool) -> Vec<u8> {
+    let mut bytes = match encoding {
+        ENCODING_UTF16 => {
+            let mut bytes = vec![0xFF, 0xFE];
+            bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+            bytes
+        }
+        ENCODING_UTF8 => text.as_bytes().to_vec(),
+        _ => text.chars().map(|c| if (c as u32) < 0x100 { c as u8 } else { b'?' }).collect(),
+    };
+    if terminated {
+        bytes.resize(bytes.len() + if is_wide(encoding) { 2 } else { 1 }, 0);
+    }
+    bytes
+}
+
+/// Splits a TXXX or COMM frame into its description and text. `skip` is the
+/// length of fields between the encoding and the description (COMM's
+/// language code).
+fn parse_described(data: &[u8], skip: usize) -> Option<(String, String)> {
+    let (&encoding, rest) = data.split_first()?;
+    let (description, rest) = split_terminated(encoding, rest.get(skip..)?);
+    let (text, _) = split_terminated(encoding, rest);
+    Some((decode_text(encoding, description), decode_text(encoding, text)))
+}
+
+/// Splits a GEOB frame into its content description and object. Only the
+/// single-byte encodings are read; Serato and most taggers use Latin-1.
+fn parse_geob(data: &[u8]) -> Option<(String, &[u8])> {
+    let (&encoding, mut rest) = data.split_first()?;
+    if encoding != ENCODING_LATIN1 && encoding != ENCODING_UTF8 {
+        return None;
+    }
+    let mut fields = Vec::with_capacity(3);
+    for _ in 0..3 {
+        let end = rest.iter().position(|&b| b == 0)?;
+        fields.push(&rest[..end]);
+        rest = &rest[end + 1..];
+    }
+    let description = match encoding {
+        ENCODING_UTF8 => String::from_utf8_lossy(fields[2]).into_owned(),
+        _ => fields[2].iter().map(|&b| b as char).collect(),
+    };
+    Some((description, rest))
+}
+
+/// Reads the ID3v2 tag at the start of a file. Files without one get an
+/// empty tag that [`write_tag`] will prepend.
+pub fn read_tag(path: &Path) -> LibraryResult<Id3Tag> {
+    let mut file = File::open(path)?;
+    let mut header = [0u8; HEADER_LEN];
+    if file.read_exact(&mut header).is_err() || &header[..3] != b"ID3" {
+        return Ok(Id3Tag::new());
+    }
+    let version = header[3];
+    let flags = header[5];
+    if !(3..=4).contains(&version) {
+        return Err(LibraryError::UnsupportedTagFormat(format!(
+            "ID3v2.{} tag in {}",
+            version,
+            path.display()
+        )));
+    }
+    if flags & FLAG_UNSYNCHRONISATION != 0 {
+        return Err(LibraryError::UnsupportedTagFormat(format!(
+            "unsynchronised ID3v2 tag in {}",
+            path.display()
+        )));
+    }
+
+    let body_len = syncsafe(&header[6..10]) as usize;
+    let footer_len = if version == 4 && flags & FLAG_FOOTER != 0 { HEADER_LEN } else { 0 };
+    let mut body = Vec::new();
+    file.take(body_len as u64).read_to_end(&mut body)?;
+    if body.len() < body_len {
+        return Err(LibraryError::InvalidTag(format!(
+            "ID3v2 tag runs past the end of {}",
+            path.display()
+        )));
+    }
+
+    let mut position = 0;
+    if flags & FLAG_EXTENDED_HEADER != 0 && body.len() >= 4 {
+        position = match version {
+            4 => syncsafe(&body[..4]) as usize,
+            _ => u32::from_be_bytes([body[0], body[1], body[2], body[3]]) as usize + 4,
+        };
+    }
+
+    let mut frames = Vec::new();
+    while position + HEADER_LEN <= body.len() {
+        let id = &body[position..position + 4];
+        // Padding, or garbage we shouldn't try to interpret
+        if !id.iter().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit()) {
+            break;
+        }
+        let size_bytes = &body[position + 4..position + 8];
+        let size = match version {
+            4 => syncsafe(size_bytes),
+            _ => u32::from_be_bytes([size_bytes[0], size_bytes[1], size_bytes[2], size_bytes[3]]),
+        } as usize;
+        let flags = u16::from_be_bytes([body[position + 8], body[position + 9]]);
+        let start = position + HEADER_LEN;
+        let Some(data) = body.get(start..start.saturating_add(size)) else {
+            return Err(LibraryError::InvalidTag(format!(
+                "{} frame runs past the ID3v2 tag in {}",
+                String::from_utf8_lossy(id),
+                path.display()
+            )));
+        };
+        frames.push(Id3Frame {
+            id: String::from_utf8_lossy(id).into_owned(),
+            flags,
+            data: data.to_vec(),
+        });
+        position = start + size;
+    }
+
+    Ok(Id3Tag {
+        version,
+        frames,
+        existing_len: (HEADER_LEN + body_len + footer_len) as u64,
+    })
+}
+
+/// Writes a tag back to the file it was read from. The frames reuse the old
+/// tag's space when they fit; otherwise the file is rewritten with padding.
+pub fn write_tag(path: &Path, tag: &Id3Tag) -> LibraryResult<()> {
+    let mut body = Vec::new();
+    for frame in &tag.frames {
+        if frame.id.len() != 4 {
+            return Err(LibraryError::InvalidTag(format!("frame id '{}'", frame.id)));
+        }
+        let size = frame.data.len() as u32;
+        body.extend_from_slice(frame.id.as_bytes());
+        match tag.version {
+            4 => body.extend_from_slice(&to_syncsafe(size)),
+            _ => body.extend_from_slice(&size.to_be_bytes()),
+        }
+        body.extend_from_slice(&frame.flags.to_be_bytes());
+        body.extend_from_slice(&frame.data);
+    }
+
+    let available = (tag.existing_len as usize).saturating_sub(HEADER_LEN);
+    let body_len = if body.len() <= available { available } else { body.len() + TAG_PADDING };
+    if body_len > MAX_TAG_LEN {
+        return Err(LibraryError::InvalidTag(format!(
+            "ID3v2 tag of {} bytes for {}",
+            body_len,
+            path.display()
+        )));
+    }
+    body.resize(body_len, 0);
+
+    let mut head = Vec::with_capacity(HEADER_LEN + body_len);
+    head.extend_from_slice(b"ID3");
+    head.extend_from_slice(&[tag.version, 0, 0]);
+    head.extend_from_slice(&to_syncsafe(body_len as u32));
+    head.extend_from_slice(&body);
+    replace_file_head(path, tag.existing_len, &head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_support::temp_dir;
+    use super::*;
+    use std::fs;
+
+    /// Stand-in MPEG frames; only their bytes matter.
+    fn audio() -> Vec<u8> {
+        [0xFF, 0xFB, 0x90, 0x64, 1, 2, 3, 4, 5, 6, 7, 8].repeat(64)
+    }
+
+    fn header(version: u8, flags: u8, body_len: u32) -> Vec<u8> {
+        let mut header = vec![b'I', b'D', b'3', version, 0, flags];
+        header.extend_from_slice(&to_syncsafe(body_len));
+        header
+    }
+
+    #[test]
+    fn tags_round_trip_around_the_audio() {
+        let dir = temp_dir("id3-round-trip");
+        let path = dir.join("track.mp3");
+        fs::write(&path, audio()).unwrap();
+
+        let mut tag = read_tag(&path).unwrap();
+        assert!(tag.frames.is_empty());
+        tag.set_text("TBPM", "128");
+        tag.set_text("TKEY", "Ämin");
+        tag.set_user_text("REPLAYGAIN_TRACK_GAIN", "-6.5 dB");
+        tag.set_comment("peak time");
+        tag.set_geob("Serato Markers2", "application/octet-stream", &[0, 1, 2, 0, 3]);
+        write_tag(&path, &tag).unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        assert!(bytes.ends_with(&audio()));
+        let mut tag = read_tag(&path).unwrap();
+        assert_eq!(tag.existing_len as usize + audio().len(), bytes.len());
+        assert_eq!(tag.version, 4);
+        assert_eq!(tag.text("TBPM").as_deref(), Some("128"));
+        assert_eq!(tag.text("TKEY").as_deref(), Some("Ämin"));
+        assert_eq!(tag.user_text("replaygain_track_gain").as_deref(), Some("-6.5 dB"));
+        assert_eq!(tag.comment().as_deref(), Some("peak time"));
+        assert_eq!(tag.geob("Serato Markers2"), Some([0, 1, 2, 0, 3].as_slice()));
+
+        // A small edit fits the padding and leaves the audio where it was
+        tag.set_text("TBPM", "124");
+        write_tag(&path, &tag).unwrap();
+        let rewritten = fs::read(&path).unwrap();
+        assert_eq!(rewritten.len(), bytes.len());
+        assert!(rewritten.ends_with(&audio()));
+        let mut tag = read_tag(&path).unwrap();
+        assert_eq!(tag.text("TBPM").as_deref(), Some("124"));
+        assert_eq!(tag.frames.iter().filter(|frame| frame.id == "TBPM").count(), 1);
+
+        // Outgrowing it rewrites the file with the audio copied through
+        let overview = vec![7u8; 3 * TAG_PADDING];
+        tag.set_geob("Serato Overview", "application/octet-stream", &overview);
+        write_tag(&path, &tag).unwrap();
+        let grown = fs::read(&path).unwrap();
+        assert!(grown.len() > bytes.len());
+        assert!(grown.ends_with(&audio()));
+        let tag = read_tag(&path).unwrap();
+        assert_eq!(tag.geob("Serato Overview"), Some(overview.as_slice()));
+        assert_eq!(tag.text("TBPM").as_deref(), Some("124"));
+        assert_eq!(tag.comment().as_deref(), Some("peak time"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn version_3_tags_keep_their_frame_sizes() {
+        let dir = temp_dir("id3-v3");
+        let path = dir.join("track.mp3");
+        // A frame over 127 bytes reads differently as a syncsafe size
+        let mut title = vec![ENCODING_LATIN1];
+        title.extend(std::iter::repeat_n(b'a', 200));
+        let mut body = b"TIT2".to_vec();
+        body.extend_from_slice(&(title.len() as u32).to_be_bytes());
+        body.extend_from_slice(&[0, 0]);
+        body.extend_from_slice(&title);
+        body.resize(body.len() + 64, 0);
+        let mut bytes = header(3, 0, body.len() as u32);
+        bytes.extend_from_slice(&body);
+        bytes.extend(audio());
+        fs::write(&path, &bytes).unwrap();
+
+        let mut tag = read_tag(&path).unwrap();
+        assert_eq!(tag.version, 3);
+        assert_eq!(tag.text("TIT2").map(|title| title.len()), Some(200));
+        tag.set_text("TKEY", "F♯m");
+        write_tag(&path, &tag).unwrap();
+
+        assert!(fs::read(&path).unwrap().ends_with(&audio()));
+        let tag = read_tag(&path).unwrap();
+        assert_eq!(tag.version, 3);
+        assert_eq!(tag.text("TIT2").map(|title| title.len()), Some(200));
+        assert_eq!(tag.text("TKEY").as_deref(), Some("F♯m"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn malformed_and_oversized_tags_are_rejected() {
+        let dir = temp_dir("id3-malformed");
+        let path = dir.join("track.mp3");
+
+        // A header claiming the largest possible tag over a few bytes
+        let mut bytes = header(4, 0, MAX_TAG_LEN as u32);
+        bytes.extend_from_slice(b"TBPM");
+        fs::write(&path, &bytes).unwrap();
+        assert!(matches!(read_tag(&path), Err(LibraryError::InvalidTag(_))));
+
+        // A frame running past the end of the tag
+        let mut bytes = header(4, 0, 20);
+        bytes.extend_from_slice(b"TBPM");
+        bytes.extend_from_slice(&to_syncsafe(1000));
+        bytes.extend_from_slice(&[0, 0, ENCODING_UTF8, b'1', b'2', b'8']);
+        bytes.resize(30, 0);
+        bytes.extend(audio());
+        fs::write(&path, &bytes).unwrap();
+        assert!(matches!(read_tag(&path), Err(LibraryError::InvalidTag(_))));
+
+        for (version, flags) in [(2, 0), (4, FLAG_UNSYNCHRONISATION)] {
+            let mut bytes = header(version, flags, 0);
+            bytes.extend(audio());
+            fs::write(&path, &bytes).unwrap();
+            assert!(matches!(read_tag(&path), Err(LibraryError::UnsupportedTagFormat(_))));
+        }
+
+        // Files too short for a header just have no tag
+        fs::write(&path, b"ID3").unwrap();
+        assert!(read_tag(&path).unwrap().frames.is_empty());
+
+        // A tag that can't be described in 28 bits is refused, not wrapped
+        fs::write(&path, audio()).unwrap();
+        let tag = Id3Tag {
+            version: 4,
+            frames: Vec::new(),
+            existing_len: (HEADER_LEN + MAX_TAG_LEN + 1) as u64,
+        };
+        assert!(matches!(write_tag(&path, &tag), Err(LibraryError::InvalidTag(_))));
+        let mut tag = Id3Tag::new();
+        tag.frames.push(Id3Frame {
+            id: "TBP".to_string(),
+            flags: 0,
+            data: Vec::new(),
+        });
+        assert!(write_tag(&path, &tag).is_err());
+        assert_eq!(fs::read(&path).unwrap(), audio());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
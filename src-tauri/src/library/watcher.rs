@@ -0,0 +1,354 @@
+use super::cues::CueStore;
+use super::playlists::PlaylistStore;
+use super::store::LibraryStore;
+use super::{cue_sheet, relocate, scanner, LibraryError, LibraryResult, LibraryTrack};
+use crate::audio::{cache, decoding};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+/// Filesystem events are collected until none arrive for this long, so a copy
+/// or a tagger rewriting a file is handled once instead of per write.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(750);
+
+// --- Event Payloads for Frontend ---
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackEventPayload {
+    pub track: LibraryTrack,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackRemovedEventPayload {
+    pub path: PathBuf,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackMovedEventPayload {
+    pub from: PathBuf,
+    pub track: LibraryTrack,
+}
+
+fn emit<S: Serialize + Clone>(app_handle: &AppHandle, event: &str, payload: S) {
+    if let Err(e) = app_handle.emit(event, payload) {
+        log::warn!("Failed to emit {} event: {}", event, e);
+    }
+}
+
+// --- Watcher Registry ---
+
+struct WatchedRoot {
+    // Dropping the watcher closes the event channel, which ends both worker threads
+    _watcher: RecommendedWatcher,
+}
+
+/// Filesystem watchers for the library roots, managed as Tauri state.
+pub struct LibraryWatcher {
+    roots: Mutex<HashMap<PathBuf, WatchedRoot>>,
+}
+
+impl LibraryWatcher {
+    pub fn new() -> Self {
+        LibraryWatcher {
+            roots: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Starts watching `root` recursively. Watching an already watched root
+    /// replaces its watcher (e.g. to change the cache directory).
+    pub fn watch(&self, app_handle: AppHandle, root: PathBuf, cache_dir: Option<PathBuf>) -> LibraryResult<()> {
+        if !root.is_dir() {
+            return Err(LibraryError::RootNotFound(root.display().to_string()));
+        }
+
+        let (event_tx, event_rx) = mpsc::channel::<Event>();
+        let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| match result {
+            Ok(event) => {
+                let _ = event_tx.send(event);
+            }
+            Err(e) => log::warn!("Library watch error: {}", e),
+        })?;
+        watcher.watch(&root, RecursiveMode::Recursive)?;
+
+        let (analysis_tx, analysis_rx) = mpsc::channel::<PathBuf>();
+        if let Some(cache_dir) = cache_dir {
+            let app_handle = app_handle.clone();
+            let root = root.clone();
+            std::thread::spawn(move || run_analysis_queue(app_handle, root, cache_dir, analysis_rx));
+        }
+        {
+            let root = root.clone();
+            std::thread::spawn(move || run_event_loop(app_handle, root, event_rx, analysis_tx));
+        }
+
+        let mut roots = self
+            .roots
+            .lock()
+            .map_err(|e| LibraryError::LockPoisoned(format!("Failed to lock library watchers: {}", e)))?;
+        roots.insert(root.clone(), WatchedRoot { _watcher: watcher });
+        log::info!("Watching library root {}", root.display());
+        Ok(())
+    }
+
+    pub fn unwatch(&self, root: &Path) -> LibraryResult<bool> {
+        let mut roots = self
+            .roots
+            .lock()
+            .map_err(|e| LibraryError::LockPoisoned(format!("Failed to lock library watchers: {}", e)))?;
+        Ok(roots.remove(root).is_some())
+    }
+}
+
+impl Default for LibraryWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// --- Event Processing ---
+
+#[derive(Default)]
+struct PendingChanges {
+    touched: HashSet<PathBuf>,
+    renames: Vec<(PathBuf, PathBuf)>,
+}
+
+impl PendingChanges {
+    fn record(&mut self, event: Event) {
+        match event.kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+                self.renames.push((event.paths[0].clone(), event.paths[1].clone()));
+                self.touched.extend(event.paths);
+            }
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => {
+                self.touched.extend(event.paths);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn is_hidden_under(root: &Path, path: &Path) -> bool {
+    path.strip_prefix(root).is_ok_and(|relative| {
+        relative
+            .components()
+            .any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+    })
+}
+
+fn run_event_loop(app_handle: AppHandle, root: PathBuf, event_rx: Receiver<Event>, analysis_tx: Sender<PathBuf>) {
+    // Blocks until the first event of a burst; ends when the watcher is dropped
+    while let Ok(first) = event_rx.recv() {
+        let mut pending = PendingChanges::default();
+        pending.record(first);
+        loop {
+            match event_rx.recv_timeout(WATCH_DEBOUNCE) {
+                Ok(event) => pending.record(event),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        if let Err(e) = apply_changes(&app_handle, &root, pending, &analysis_tx) {
+            log::warn!("Failed to apply library changes under {}: {}", root.display(), e);
+        }
+    }
+    log::info!("Stopped watching library root {}", root.display());
+}
+
+/// Classifies a debounced burst of filesystem events into added, removed,
+/// changed and moved tracks, updates the library store and emits events.
+fn apply_changes(
+    app_handle: &AppHandle,
+    root: &Path,
+    pending: PendingChanges,
+    analysis_tx: &Sender<PathBuf>,
+) -> LibraryResult<()> {
+    let store = app_handle.state::<LibraryStore>();
+
+    let mut present: HashSet<PathBuf> = HashSet::new();
+    let mut removed: HashMap<PathBuf, LibraryTrack> = HashMap::new();
+    // Audio files whose CUE sheet was added, edited or deleted
+    let mut sheet_changed: HashSet<PathBuf> = HashSet::new();
+    for path in pending.touched {
+        if is_hidden_under(root, &path) {
+            continue;
+        }
+        if cue_sheet::is_sheet(&path) {
+            sheet_changed.extend(cue_sheet::audio_files_for_sheet(&path));
+        } else if path.is_dir() {
+            // A directory moved or copied in arrives as a single event
+            present.extend(scanner::collect_audio_files(&path).unwrap_or_default());
+        } else if path.exists() {
+            if decoding::is_supported_audio_file(&path) {
+                present.insert(path);
+            }
+        } else {
+            for track in store.tracks_under(&path)? {
+                removed.insert(track.path.clone(), track);
+            }
+        }
+    }
+
+    present.extend(sheet_changed.iter().cloned());
+
+    let mut added = Vec::new();
+    let mut changed = Vec::new();
+    for path in present {
+        match store.get_track(&path)? {
+            None => added.push(path),
+            Some(existing) => {
+                let Ok(metadata) = std::fs::metadata(&path) else {
+                    continue;
+                };
+                let modified_at = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0);
+                if metadata.len() != existing.file_size
+                    || modified_at != existing.modified_at
+                    || sheet_changed.contains(&path)
+                {
+                    changed.push(path);
+                }
+            }
+        }
+    }
+
+    let moves = detect_moves(&pending.renames, &mut removed, &mut added);
+
+    let relocations: HashMap<PathBuf, PathBuf> = moves.iter().cloned().collect();
+    if let Err(e) = app_handle.state::<PlaylistStore>().relocate_tracks(&relocations) {
+        log::warn!("Failed to update playlists for moved tracks: {}", e);
+    }
+    if let Err(e) = app_handle.state::<CueStore>().relocate_tracks(&relocations) {
+        log::warn!("Failed to update cues for moved tracks: {}", e);
+    }
+    for (from, to) in moves {
+        let track = relocate::move_track_record(&store, &from, &to, root)?;
+        log::info!("Library: {} moved to {}", from.display(), to.display());
+        emit(app_handle, "library://track-moved", TrackMovedEventPayload { from, track });
+        let _ = analysis_tx.send(to);
+    }
+
+    let removed_paths: Vec<&Path> = removed.keys().map(PathBuf::as_path).collect();
+    store.remove_tracks(&removed_paths)?;
+    for path in removed.into_keys() {
+        emit(app_handle, "library://track-removed", TrackRemovedEventPayload { path });
+    }
+
+    for (paths, event) in [(added, "library://track-added"), (changed, "library://track-changed")] {
+        let tracks = scanner::build_track_records(&paths, root, None);
+        store.upsert_tracks(tracks.clone())?;
+        for track in tracks {
+            let path = track.path.clone();
+            emit(app_handle, event, TrackEventPayload { track });
+            let _ = analysis_tx.send(path);
+        }
+    }
+    super::commands::refresh_smart_playlists_after_change(app_handle, None);
+    Ok(())
+}
+
+/// Pairs removed tracks with added files that are the same audio. Renames
+/// reported by the OS are taken as-is; otherwise new files are hashed and
+/// matched against the removed tracks' cached content hashes. Matched paths
+/// are taken out of `removed` and `added`.
+fn detect_moves(
+    renames: &[(PathBuf, PathBuf)],
+    removed: &mut HashMap<PathBuf, LibraryTrack>,
+    added: &mut Vec<PathBuf>,
+) -> Vec<(PathBuf, PathBuf)> {
+    let mut moves = Vec::new();
+
+    // A renamed directory moves every track under it
+    for (from, to) in renames {
+        let renamed: Vec<PathBuf> = removed.keys().filter(|p| p.starts_with(from)).cloned().collect();
+        for old_path in renamed {
+            let new_path = match old_path.strip_prefix(from) {
+                Ok(relative) if !relative.as_os_str().is_empty() => to.join(relative),
+                _ => to.clone(),
+            };
+            if let Some(position) = added.iter().position(|p| *p == new_path) {
+                removed.remove(&old_path);
+                added.swap_remove(position);
+                moves.push((old_path, new_path));
+            }
+        }
+    }
+
+    let by_hash: HashMap<String, PathBuf> = removed
+        .values()
+        .filter_map(|t| Some((t.analysis.as_ref()?.content_hash.clone(), t.path.clone())))
+        .collect();
+    if by_hash.is_empty() || added.is_empty() {
+        return moves;
+    }
+
+    let hashed: Vec<(PathBuf, Option<String>)> = added
+        .par_iter()
+        .map(|path| (path.clone(), cache::fingerprint::compute_content_hash(path).ok()))
+        .collect();
+    for (to, hash) in hashed {
+        let Some(from) = hash.and_then(|h| by_hash.get(&h)) else {
+            continue;
+        };
+        if removed.remove(from).is_some() {
+            added.retain(|p| *p != to);
+            moves.push((from.clone(), to));
+        }
+    }
+    moves
+}
+
+// --- Analysis Queue ---
+
+/// Analyzes queued files through the cache, draining whatever has queued up
+/// into one parallel batch, then refreshes their records with the results.
+fn run_analysis_queue(app_handle: AppHandle, root: PathBuf, cache_dir: PathBuf, analysis_rx: Receiver<PathBuf>) {
+    while let Ok(first) = analysis_rx.recv() {
+        let mut batch: HashSet<PathBuf> = HashSet::from([first]);
+        batch.extend(analysis_rx.try_iter());
+
+        let analyzed: Vec<PathBuf> = batch
+            .into_par_iter()
+            .filter(|path| path.exists())
+            .filter(|path| {
+                match cache::analyze_bpm_with_cache(&path.to_string_lossy(), Some(&cache_dir)) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        log::warn!("Library: analysis of {} failed: {}", path.display(), e);
+                        false
+                    }
+                }
+            })
+            .collect();
+
+        if let Err(e) = cache::index::flush_index(&cache_dir) {
+            log::warn!("Failed to flush cache index after library analysis: {}", e);
+        }
+        if let Err(e) = cache::eviction::enforce_size_limit(&cache_dir) {
+            log::warn!("Cache eviction after library analysis failed: {}", e);
+        }
+
+        let store = app_handle.state::<LibraryStore>();
+        let tracks = scanner::build_track_records(&analyzed, &root, Some(&cache_dir));
+        if let Err(e) = store.upsert_tracks(tracks.clone()) {
+            log::warn!("Failed to store analyzed library tracks: {}", e);
+        }
+        for track in tracks {
+            emit(&app_handle, "library://track-changed", TrackEventPayload { track });
+        }
+    }
+}
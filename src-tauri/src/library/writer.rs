@@ -0,0 +1,73 @@
+//! A background thread that persists a store shortly after it changes, so
+//! callers (the audio thread among them) never wait on the disk and a burst
+//! of changes turns into one write.
+
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub(crate) struct BatchedWriter {
+    /// `None` if the thread couldn't be started; stores then only persist
+    /// on an explicit flush.
+    schedule_tx: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl BatchedWriter {
+    /// Starts a thread that calls `write` `delay` after the first `schedule`
+    /// since its last write. Once every handle is dropped it writes one last
+    /// time and exits; dropping the writer waits for that.
+    pub(crate) fn spawn(name: &str, delay: Duration, write: impl Fn() + Send + 'static) -> Self {
+        let (schedule_tx, schedule_rx) = mpsc::channel::<()>();
+        let spawned = thread::Builder::new().name(name.to_string()).spawn(move || {
+            while schedule_rx.recv().is_ok() {
+                let deadline = Instant::now() + delay;
+                // Later changes within the window ride along with this write
+                let disconnected = loop {
+                    match schedule_rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                        Ok(()) => continue,
+                        Err(RecvTimeoutError::Timeout) => break false,
+                        Err(RecvTimeoutError::Disconnected) => break true,
+                    }
+                };
+                write();
+                if disconnected {
+                    return;
+                }
+            }
+        });
+        match spawned {
+            Ok(thread) => BatchedWriter {
+                schedule_tx: Some(schedule_tx),
+                thread: Some(thread),
+            },
+            Err(e) => {
+                log::error!("Failed to start {} thread, changes are saved on exit only: {}", name, e);
+                BatchedWriter {
+                    schedule_tx: None,
+                    thread: None,
+                }
+            }
+        }
+    }
+
+    /// Asks for a write soon. Never blocks.
+    pub(crate) fn schedule(&self) {
+        if let Some(schedule_tx) = &self.schedule_tx {
+            // Only fails once the thread is gone, which ends with a write anyway
+            let _ = schedule_tx.send(());
+        }
+    }
+}
+
+impl Drop for BatchedWriter {
+    fn drop(&mut self) {
+        // Disconnecting ends the thread after its final write
+        self.schedule_tx.take();
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            log::error!("Store writer thread panicked");
+        }
+    }
+}
@@ -1,6 +1,7 @@
 import type { EqParams, TrackInfo, VolumeAnalysis } from '$lib/types';
 import { invoke } from '@tauri-apps/api/core';
 import { derived, writable } from 'svelte/store';
+import { libraryStore } from './libraryStore';
 
 export interface DeckState {
     // Core track data
@@ -94,9 +95,10 @@ function createDeckStore(deckId: 'A' | 'B') {
         try {
             console.log(`[DeckStore ${deckId}] Loading waveform on-demand: ${track.path}`);
 
+            const { cacheDir } = get(libraryStore);
             const result = await invoke<VolumeAnalysis>(
                 'get_track_volume_analysis',
-                { path: track.path }
+                { path: track.path, cacheDir }
             );
 
             // Batch the final updates
@@ -1,13 +1,13 @@
-import type { BasicMetadataBatchResult, LibraryState, TrackBasicMetadata, TrackInfo } from '$lib/types';
+import type { BasicMetadataBatchResult, LibraryScanPageEvent, LibraryState, LibraryTrack, TrackBasicMetadata, TrackInfo } from '$lib/types';
 import { invoke } from '@tauri-apps/api/core';
-import { join } from '@tauri-apps/api/path';
+import { listen } from '@tauri-apps/api/event';
 import { open } from '@tauri-apps/plugin-dialog';
-import { readDir } from '@tauri-apps/plugin-fs';
-import { writable } from 'svelte/store';
+import { get, writable } from 'svelte/store';
 
 function createLibraryStore() {
     const { subscribe, update } = writable<LibraryState>({
         selectedFolder: null,
+        cacheDir: null,
         audioFiles: [],
         selectedTrack: null,
         isLoading: false,
@@ -15,6 +15,58 @@ function createLibraryStore() {
         error: null,
     });
 
+    let stopWatching: (() => Promise<void>) | null = null;
+
+    function toTrackInfo(track: LibraryTrack): TrackInfo {
+        return {
+            path: track.path,
+            name: track.fileName,
+            tags: track.tags,
+            metadata: track.analysis?.metadata,
+            volumeAnalysisData: undefined,
+            virtualTracks: track.virtualTracks,
+        };
+    }
+
+    // Keeps `audioFiles` in sync with the backend's filesystem watcher
+    async function watchLibraryFolder(folderPath: string, cacheDir: string | null) {
+        if (stopWatching) {
+            await stopWatching();
+        }
+
+        const upsert = (track: LibraryTrack) => update(state => {
+            const next = toTrackInfo(track);
+            const exists = state.audioFiles.some(file => file.path === track.path);
+            const audioFiles = exists
+                ? state.audioFiles.map(file => file.path === track.path ? { ...file, ...next, metadata: next.metadata ?? file.metadata } : file)
+                : [...state.audioFiles, next];
+            return { ...state, audioFiles };
+        });
+        const remove = (path: string) => update(state => ({
+            ...state,
+            audioFiles: state.audioFiles.filter(file => file.path !== path),
+            selectedTrack: state.selectedTrack?.path === path ? null : state.selectedTrack,
+        }));
+
+        const unlisteners = await Promise.all([
+            listen<{ track: LibraryTrack }>('library://track-added', e => upsert(e.payload.track)),
+            listen<{ track: LibraryTrack }>('library://track-changed', e => upsert(e.payload.track)),
+            listen<{ path: string }>('library://track-removed', e => remove(e.payload.path)),
+            listen<{ from: string; track: LibraryTrack }>('library://track-moved', e => {
+                remove(e.payload.from);
+                upsert(e.payload.track);
+            }),
+        ]);
+        await invoke('watch_library', { roots: [folderPath], cacheDir });
+
+        stopWatching = async () => {
+            unlisteners.forEach(unlisten => unlisten());
+            await invoke('unwatch_library', { roots: [folderPath] }).catch(err =>
+                console.warn("[LibraryStore] Failed to stop watching library:", err));
+            stopWatching = null;
+        };
+    }
+
     async function selectLibraryFolder() {
         update(state => ({
             ...state,
@@ -24,6 +76,7 @@ function createLibraryStore() {
             audioFiles: [],
             selectedTrack: null,
             selectedFolder: null,
+            cacheDir: null,
         }));
 
         let folderPath: string | null = null;
@@ -44,48 +97,51 @@ function createLibraryStore() {
             update(state => ({ ...state, selectedFolder: folderPath }));
             console.log(`[LibraryStore] Selected folder: ${folderPath}`);
 
-            const entries = await readDir(folderPath);
-            const initialFiles: TrackInfo[] = [];
-            const filePaths: string[] = [];
-
-            for (const entry of entries) {
-                if (entry.isFile && entry.name?.toLowerCase().match(/\.(mp3|mpeg|wav|flac|ogg)$/)) {
-                    const fullPath = await join(folderPath, entry.name);
-                    initialFiles.push({
-                        path: fullPath,
-                        name: entry.name,
-                        metadata: undefined,
-                        volumeAnalysisData: undefined
-                    });
-                    filePaths.push(fullPath);
-                }
+            // Resolve the cache first so the scan can attach cached analysis
+            let cacheDir: string | null = null;
+            try {
+                cacheDir = await invoke<string>('ensure_cache_directory', { musicDir: folderPath });
+                console.log(`[LibraryStore] Cache directory: ${cacheDir}`);
+                update(state => ({ ...state, cacheDir }));
+            } catch (cacheError) {
+                console.warn("[LibraryStore] Failed to create cache directory, proceeding without cache:", cacheError);
+            }
+
+            // The backend scans recursively and streams tracks in pages
+            const unlistenScan = await listen<LibraryScanPageEvent>('library://scan-page', (event) => {
+                const pageFiles = event.payload.tracks.map(toTrackInfo);
+                update(state => ({ ...state, audioFiles: [...state.audioFiles, ...pageFiles] }));
+            });
+            try {
+                await invoke('scan_library', { roots: [folderPath], cacheDir });
+            } finally {
+                unlistenScan();
+            }
+
+            try {
+                await watchLibraryFolder(folderPath, cacheDir);
+            } catch (watchError) {
+                console.warn("[LibraryStore] Failed to watch library folder for changes:", watchError);
             }
 
-            console.log(`[LibraryStore] Found ${initialFiles.length} audio files.`);
-            if (initialFiles.length === 0) {
-                console.warn("[LibraryStore] No compatible audio files found in the selected folder. Skipping analysis.");
+            const initialFiles = get({ subscribe }).audioFiles;
+            // Only files without cached analysis still need the batch
+            const filePaths = initialFiles.filter(file => !file.metadata).map(file => file.path);
+
+            console.log(`[LibraryStore] Found ${initialFiles.length} audio files, ${filePaths.length} need analysis.`);
+            if (filePaths.length === 0) {
                 update(state => ({ ...state, isLoading: false, isAnalyzing: false }));
                 return;
             }
 
             update(state => ({
                 ...state,
-                audioFiles: initialFiles,
                 isLoading: false,
                 isAnalyzing: true,
             }));
 
             const runBackgroundAnalysis = async () => {
                 try {
-                    // First, ensure cache directory exists
-                    let cacheDir: string | null = null;
-                    try {
-                        cacheDir = await invoke<string>('ensure_cache_directory', { musicDir: folderPath });
-                        console.log(`[LibraryStore] Cache directory: ${cacheDir}`);
-                    } catch (cacheError) {
-                        console.warn("[LibraryStore] Failed to create cache directory, proceeding without cache:", cacheError);
-                    }
-
                     console.log("[LibraryStore] Invoking BPM analysis with cache...");
                     const results = await invoke<BasicMetadataBatchResult>(
                         'analyze_features_batch_with_cache',
@@ -98,6 +154,9 @@ function createLibraryStore() {
 
                     update(state => {
                         const updatedFiles = state.audioFiles.map((file) => {
+                            if (file.metadata) {
+                                return file;
+                            }
                             const result = results[file.path];
                             let trackMetadata: TrackBasicMetadata | null | undefined = undefined;
 
@@ -24,6 +24,7 @@ interface PlaybackLoadPayload {
     deckId: string;
     duration: number;
     cuePointSeconds: number | null;
+    playableRange: [number, number] | null;
     originalBpm: number | null;
     firstBeatSec: number | null;
 }
@@ -46,6 +47,7 @@ export function createPlayerStore(deckId: string) {
         isLoading: false,
         error: null,
         cuePointTime: null,
+        playableRange: null,
         isSyncActive: false,
         isMaster: false,
         pitchRate: 1.0,
@@ -71,11 +73,13 @@ export function createPlayerStore(deckId: string) {
             "playback://load-update",
             (event) => {
                 if (event.payload.deckId === deckId) {
-                    const { duration, cuePointSeconds, originalBpm, firstBeatSec } = event.payload;
+                    const { duration, cuePointSeconds, playableRange, originalBpm, firstBeatSec } = event.payload;
                     update(s => ({
                         ...initialState,
                         duration: duration,
                         cuePointTime: cuePointSeconds,
+                        playableRange: playableRange,
+                        currentTime: playableRange ? playableRange[0] : 0,
                         isLoading: false,
                         error: null,
                     }));
@@ -162,7 +166,13 @@ export function createPlayerStore(deckId: string) {
         }
     }
 
-    async function loadTrack(path: string, originalBpm?: number | null, firstBeatSec?: number | null) {
+    // `range` plays only part of the file, for CUE sheet tracks
+    async function loadTrack(
+        path: string,
+        originalBpm?: number | null,
+        firstBeatSec?: number | null,
+        range?: { startSeconds: number; endSeconds: number | null },
+    ) {
         set({
             ...initialState,
             isLoading: true,
@@ -173,6 +183,8 @@ export function createPlayerStore(deckId: string) {
                 path,
                 originalBpm: originalBpm === null ? undefined : originalBpm,
                 firstBeatSec: firstBeatSec === null ? undefined : firstBeatSec,
+                startSeconds: range?.startSeconds,
+                endSeconds: range?.endSeconds ?? undefined,
             });
         } catch (err) {
             const errorMsg = `Failed to load track: ${err}`;
@@ -35,8 +35,231 @@ export interface VolumeAnalysis {
 export interface TrackInfo {
     path: string;
     name: string;
+    tags?: TrackTags;
     metadata?: TrackBasicMetadata | null | undefined;
     volumeAnalysisData?: VolumeAnalysis | null | undefined;
+    // CUE sheet tracks inside the file
+    virtualTracks?: VirtualTrack[];
+    // Set on an entry for one of those tracks; only this part of the file plays
+    range?: { startSeconds: number; endSeconds: number | null };
+}
+
+// Tags read from the file. Matches Rust struct TrackTags.
+export interface TrackTags {
+    title: string | null;
+    artist: string | null;
+    album: string | null;
+    genre: string | null;
+    label: string | null;
+    comment: string | null;
+    date: string | null;
+    bpm: number | null;
+    key: string | null;
+}
+
+// A scanned library file. Matches Rust struct LibraryTrack.
+export interface LibraryTrack {
+    path: string;
+    root: string;
+    fileName: string;
+    fileSize: number;
+    modifiedAt: number;
+    tags: TrackTags;
+    analysis: {
+        contentHash: string;
+        metadata: TrackBasicMetadata;
+        loudness: { integratedLufs: number; peakDbfs: number; replayGainDb: number } | null;
+        key: { key: string; camelot: string; confidence: number } | null;
+        energy: number | null;
+    } | null;
+    addedAt: number;
+    playCount: number;
+    lastPlayedAt: number | null;
+    user: UserFields;
+    virtualTracks: VirtualTrack[];
+}
+
+// A CUE sheet track inside its parent file. Matches Rust struct VirtualTrack.
+export interface VirtualTrack {
+    number: number;
+    title: string | null;
+    artist: string | null;
+    startSeconds: number;
+    endSeconds: number | null;
+}
+
+// User-edited library fields. Matches Rust struct UserFields.
+export interface UserFields {
+    rating: number | null;
+    color: string | null;
+    comment: string | null;
+    tags: string[];
+}
+
+// A playlist, crate or smart playlist. Matches Rust struct PlaylistNode.
+export interface PlaylistNode {
+    id: string;
+    name: string;
+    kind: 'playlist' | 'crate' | 'smart';
+    tracks: string[];
+    children: PlaylistNode[];
+    // Only set on smart playlists
+    rules?: TrackQuery;
+    createdAt: number;
+    updatedAt: number;
+}
+
+export interface PlaylistImportReport {
+    playlist: PlaylistNode;
+    missing: string[];
+}
+
+// A stored cue or loop. Matches Rust struct CuePoint.
+export interface CuePoint {
+    kind: 'cue' | 'loop';
+    hotCue: number | null;
+    startSeconds: number;
+    endSeconds: number | null;
+    name: string | null;
+    color: string | null;
+}
+
+// Cues and beat grid override of a track. Matches Rust struct TrackCues.
+export interface TrackCues {
+    cues: CuePoint[];
+    grid: { bpm: number; firstBeatSec: number } | null;
+}
+
+// Result of importing another application's collection.
+export interface ExchangeImportReport {
+    tracks: number;
+    missing: string[];
+    cues: number;
+    grids: number;
+    gridsCached: number;
+    playlists: number;
+}
+
+// Result of writing prep data into files' Serato tags.
+export interface SeratoWriteReport {
+    written: number;
+    unsupported: string[];
+    failed: [string, string][];
+    skippedCues: number;
+}
+
+// Result of the export_rekordbox_device command. Matches Rust struct DeviceExportReport.
+export interface DeviceExportReport {
+    tracks: number;
+    copied: number;
+    playlists: number;
+    missing: string[];
+    withoutAnalysis: number;
+    failed: [string, string][];
+}
+
+// Result of the relocate_missing_tracks command. Matches Rust struct RelocationReport.
+export interface RelocationReport {
+    relocated: [string, string][];
+    notFound: string[];
+    withoutHash: string[];
+    ambiguous: string[];
+}
+
+// Fields the write_analysis_tags command writes. Matches Rust struct AnalysisTagFields.
+export interface AnalysisTagFields {
+    bpm?: boolean;
+    key?: boolean;
+    replayGain?: boolean;
+    comment?: boolean;
+}
+
+// Matches Rust enum TagField.
+export type TagField = 'bpm' | 'key' | 'replayGainTrackGain' | 'replayGainTrackPeak' | 'comment';
+
+// Matches Rust struct TagChange.
+export interface TagChange {
+    path: string;
+    field: TagField;
+    oldValue: string | null;
+    newValue: string;
+}
+
+// Result of the write_analysis_tags command. Matches Rust struct AnalysisTagReport.
+export interface AnalysisTagReport {
+    dryRun: boolean;
+    written: number;
+    unchanged: number;
+    changes: TagChange[];
+    withoutValues: string[];
+    unsupported: string[];
+    failed: [string, string][];
+}
+
+// A next-track suggestion from suggest_next_tracks. Matches Rust struct TrackSuggestion.
+export interface TrackSuggestion {
+    track: LibraryTrack;
+    score: number;
+    pitchRate: number | null;
+    effectiveKey: string | null;
+    reasons: string[];
+}
+
+// Matches Rust enum EnergyDirection.
+export type EnergyDirection = 'up' | 'hold' | 'down';
+
+// A track heard on the master. Matches Rust struct HistoryEntry.
+export interface HistoryEntry {
+    path: string;
+    deckId: string;
+    rangeStartSeconds: number | null;
+    loadedAt: number;
+    playStartedAt: number;
+    audibleAt: number;
+    endedAt: number | null;
+    pitchRate: number;
+    durationSeconds: number;
+    audibleSeconds: number;
+}
+
+// A logged DJ session. Matches Rust struct HistorySession.
+export interface HistorySession {
+    id: string;
+    startedAt: number;
+    endedAt: number | null;
+    entries: HistoryEntry[];
+}
+
+// Matches Rust enum HistoryExportFormat.
+export type HistoryExportFormat = 'csv' | 'json' | 'm3u' | 'tracklist';
+
+// Options for the query_library command. Matches Rust struct TrackQuery.
+export interface TrackQuery {
+    text?: string;
+    bpmMin?: number;
+    bpmMax?: number;
+    keyMin?: number;
+    keyMax?: number;
+    keyMode?: 'minor' | 'major';
+    compatibleKey?: string;
+    energyMin?: number;
+    energyMax?: number;
+    minRating?: number;
+    tags?: string[];
+    notPlayedWithinDays?: number;
+    sort?: 'path' | 'fileName' | 'title' | 'artist' | 'album' | 'genre' | 'bpm' | 'key' | 'energy'
+        | 'loudness' | 'duration' | 'rating' | 'playCount' | 'lastPlayedAt' | 'addedAt';
+    descending?: boolean;
+    offset?: number;
+    limit?: number;
+}
+
+// Payload of the `library://scan-page` event.
+export interface LibraryScanPageEvent {
+    root: string;
+    tracks: LibraryTrack[];
+    scanned: number;
+    total: number;
 }
 
 // Structure for the result of the new batch features analysis command
@@ -58,6 +281,7 @@ export interface CacheStats {
 // State for the library store
 export interface LibraryState {
     selectedFolder: string | null;
+    cacheDir: string | null;
     audioFiles: TrackInfo[];
     selectedTrack: TrackInfo | null;
     isLoading: boolean;
@@ -75,6 +299,8 @@ export interface PlayerState {
     isLoading: boolean;
     error: string | null;
     cuePointTime: number | null;
+    // [start, end] in seconds when a CUE sheet track is loaded
+    playableRange: [number, number] | null;
     isSyncActive: boolean;
     isMaster: boolean;
     pitchRate: number | null;